@@ -1,7 +1,9 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use serde::Deserialize;
 use std::fs;
 
+use crate::world::map_data::MapTileData;
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct ServerConfig {
     pub server: ServerSection,
@@ -9,11 +11,16 @@ pub struct ServerConfig {
     pub game: GameSection,
     #[serde(default = "default_paths")]
     pub paths: PathsSection,
+    #[serde(default)]
+    pub start_locations: StartLocationsSection,
 }
 
 fn default_paths() -> PathsSection {
     PathsSection {
         maps_dir: "../L1J-TW_3.80c/maps".to_string(),
+        audit_log: default_audit_log(),
+        game_data: default_game_data(),
+        world_state: default_world_state(),
     }
 }
 
@@ -21,13 +28,104 @@ fn default_paths() -> PathsSection {
 pub struct ServerSection {
     pub host: String,
     pub port: u16,
+    /// Pending-connection queue length for the listening socket.
+    #[serde(default = "default_listen_backlog")]
+    pub listen_backlog: u32,
     pub max_online_users: u32,
+    /// Create accounts on first login with an unknown name.
+    #[serde(default = "default_true")]
+    pub auto_create_account: bool,
+    /// Hold back logins from an IP the account has never used until a GM
+    /// confirms it (`.confirmip`).
+    #[serde(default)]
+    pub reject_new_ip: bool,
+    /// Simultaneous connections allowed from one IP (0 = unlimited).
+    #[serde(default = "default_max_connections_per_ip")]
+    pub max_connections_per_ip: u32,
+    /// Accepted client version numbers (empty = any).
+    #[serde(default)]
+    pub allowed_client_versions: Vec<u32>,
+    /// Accepted client language codes (empty = any; 3 = Taiwan).
+    #[serde(default)]
+    pub allowed_client_languages: Vec<i32>,
+    /// Client protocol to speak; picks the handshake payload
+    /// (see `protocol::server::init::first_packet`).
+    #[serde(default = "default_protocol_version")]
+    pub protocol_version: String,
+    /// Write packet bursts (the login init packets) in one go rather
+    /// than one write per packet. The 3.80c client has no compressed
+    /// packets, so this is the only way to shrink the burst. Off by
+    /// default.
+    #[serde(default)]
+    pub coalesce_packet_bursts: bool,
+    /// Seconds a dropped in-game connection stays resumable (0 = off).
+    #[serde(default = "default_reconnect_grace")]
+    pub reconnect_grace_secs: u64,
+    /// Disable Nagle on client sockets; most packets are a few bytes.
+    #[serde(default = "default_true")]
+    pub tcp_nodelay: bool,
+    /// Idle seconds before the OS starts keepalive probes (0 = no
+    /// keepalive), so dead peers are noticed without waiting on a read.
+    #[serde(default = "default_keepalive_idle")]
+    pub tcp_keepalive_idle_secs: u64,
+    /// Seconds between keepalive probes.
+    #[serde(default = "default_keepalive_interval")]
+    pub tcp_keepalive_interval_secs: u64,
+    /// Unanswered probes before the connection is dropped.
+    #[serde(default = "default_keepalive_count")]
+    pub tcp_keepalive_count: u32,
+}
+
+fn default_protocol_version() -> String {
+    crate::protocol::server::init::DEFAULT_PROTOCOL_VERSION.to_string()
+}
+
+fn default_listen_backlog() -> u32 {
+    1024
+}
+
+fn default_keepalive_idle() -> u64 {
+    60
+}
+
+fn default_keepalive_interval() -> u64 {
+    10
+}
+
+fn default_keepalive_count() -> u32 {
+    5
+}
+
+fn default_reconnect_grace() -> u64 {
+    10
+}
+
+fn default_max_connections_per_ip() -> u32 {
+    10
+}
+
+fn default_true() -> bool {
+    true
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct DatabaseSection {
     pub url: String,
     pub max_connections: u32,
+    /// Connections the pool keeps open even when idle.
+    #[serde(default)]
+    pub min_connections: u32,
+    /// Seconds a query waits for a free connection before failing.
+    #[serde(default = "default_acquire_timeout")]
+    pub acquire_timeout_secs: u64,
+    /// Refuse to start when the database is unreachable, instead of
+    /// running without one.
+    #[serde(default)]
+    pub required: bool,
+}
+
+fn default_acquire_timeout() -> u64 {
+    5
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -35,11 +133,146 @@ pub struct GameSection {
     pub tick_interval_ms: u64,
     pub npc_ai_sleep_range: u32,
     pub packet_batch_flush: bool,
+    /// Spatial grid cell size in tiles (see `WorldGrid::occupancy`).
+    #[serde(default = "default_grid_cell_size")]
+    pub grid_cell_size: i32,
+    /// Seconds between stats log lines (0 disables).
+    #[serde(default = "default_stats_interval")]
+    pub stats_log_interval_secs: u64,
+    /// Save long-running buffs (speed potions, blessing of eva,
+    /// cooking) at logout and restore them, minus the time away, at the
+    /// next login.
+    #[serde(default)]
+    pub persist_buffs: bool,
+    /// Seconds between saves of castle door states, which are also
+    /// saved at shutdown and restored on boot (0 = off).
+    #[serde(default)]
+    pub world_state_save_secs: u64,
+    /// Seconds between in-game character autosaves.
+    #[serde(default = "default_autosave_interval")]
+    pub autosave_interval_secs: u64,
+    /// Seconds a character must wait between vulcan crafts (0 = off).
+    #[serde(default)]
+    pub vulcan_craft_cooldown_secs: i64,
+    /// Vulcan crafts allowed per character per day (0 = unlimited).
+    #[serde(default)]
+    pub vulcan_daily_craft_limit: u32,
+}
+
+impl GameSection {
+    /// Vulcan crafting pace limits.
+    pub fn craft_limits(&self) -> crate::ecs::vulcan::CraftLimits {
+        crate::ecs::vulcan::CraftLimits {
+            cooldown_secs: self.vulcan_craft_cooldown_secs,
+            daily_limit: self.vulcan_daily_craft_limit,
+        }
+    }
+}
+
+fn default_grid_cell_size() -> i32 {
+    crate::world::grid::REGION_SIZE
+}
+
+fn default_stats_interval() -> u64 {
+    60
+}
+
+fn default_autosave_interval() -> u64 {
+    300
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct PathsSection {
     pub maps_dir: String,
+    /// Append-only audit trail (logins, account creation, GM actions).
+    #[serde(default = "default_audit_log")]
+    pub audit_log: String,
+    /// Reloadable gameplay data (`.reload`); built-in data if missing.
+    #[serde(default = "default_game_data")]
+    pub game_data: String,
+    /// Saved castle doors (`game.world_state_save_secs`).
+    #[serde(default = "default_world_state")]
+    pub world_state: String,
+}
+
+fn default_audit_log() -> String {
+    "logs/audit.log".to_string()
+}
+
+fn default_game_data() -> String {
+    "config/gamedata.toml".to_string()
+}
+
+fn default_world_state() -> String {
+    "data/world_state.toml".to_string()
+}
+
+/// Where a new character appears.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct StartLocation {
+    pub x: i32,
+    pub y: i32,
+    pub map_id: i32,
+}
+
+/// Starting location used for any class without its own entry.
+pub const DEFAULT_START: StartLocation = StartLocation {
+    x: crate::protocol::client::char_create::START_X,
+    y: crate::protocol::client::char_create::START_Y,
+    map_id: crate::protocol::client::char_create::START_MAP,
+};
+
+/// Per-class start towns for new characters. Unset classes use `DEFAULT_START`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct StartLocationsSection {
+    pub royal: Option<StartLocation>,
+    pub knight: Option<StartLocation>,
+    pub elf: Option<StartLocation>,
+    pub mage: Option<StartLocation>,
+    pub darkelf: Option<StartLocation>,
+    pub dragon_knight: Option<StartLocation>,
+    pub illusionist: Option<StartLocation>,
+}
+
+impl StartLocationsSection {
+    /// (class name, configured location) in char_type order.
+    fn entries(&self) -> [(&'static str, Option<StartLocation>); 7] {
+        [
+            ("royal", self.royal),
+            ("knight", self.knight),
+            ("elf", self.elf),
+            ("mage", self.mage),
+            ("darkelf", self.darkelf),
+            ("dragon_knight", self.dragon_knight),
+            ("illusionist", self.illusionist),
+        ]
+    }
+
+    /// Start location for a char_type (0=Prince .. 6=Illusionist).
+    pub fn for_class(&self, char_type: i32) -> StartLocation {
+        usize::try_from(char_type)
+            .ok()
+            .and_then(|i| self.entries().get(i).and_then(|(_, loc)| *loc))
+            .unwrap_or(DEFAULT_START)
+    }
+
+    /// Maps referenced by configured start locations.
+    pub fn map_ids(&self) -> Vec<i32> {
+        self.entries().into_iter().filter_map(|(_, loc)| loc.map(|l| l.map_id)).collect()
+    }
+
+    /// Check every configured tile is walkable. Returns the offending
+    /// class names. Maps `map_for` can't provide are not checked.
+    pub fn validate<'a>(&self, map_for: impl Fn(i32) -> Option<&'a MapTileData>) -> Vec<&'static str> {
+        self.entries()
+            .into_iter()
+            .filter_map(|(name, loc)| {
+                let loc = loc?;
+                let map = map_for(loc.map_id)?;
+                (!map.is_passable(loc.x, loc.y)).then_some(name)
+            })
+            .collect()
+    }
 }
 
 impl ServerConfig {
@@ -48,6 +281,111 @@ impl ServerConfig {
             .with_context(|| format!("Failed to read config file: {}", path))?;
         let config: ServerConfig =
             toml::from_str(&content).with_context(|| "Failed to parse config file")?;
+        if crate::protocol::server::init::first_packet(&config.server.protocol_version).is_none() {
+            bail!("Unknown protocol_version {:?}", config.server.protocol_version);
+        }
         Ok(config)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"
+        [server]
+        host = "0.0.0.0"
+        port = 7000
+        max_online_users = 200
+        {extra}
+
+        [database]
+        url = "mysql://root@localhost/l1jtw"
+        max_connections = 10
+
+        [game]
+        tick_interval_ms = 200
+        npc_ai_sleep_range = 30
+        packet_batch_flush = true
+    "#;
+
+    fn parse(extra: &str) -> ServerConfig {
+        toml::from_str(&BASE.replace("{extra}", extra)).unwrap()
+    }
+
+    #[test]
+    fn test_auto_create_account_defaults_on() {
+        assert!(parse("").server.auto_create_account);
+        assert!(parse("auto_create_account = true").server.auto_create_account);
+    }
+
+    #[test]
+    fn test_auto_create_account_disabled() {
+        assert!(!parse("auto_create_account = false").server.auto_create_account);
+    }
+
+    #[test]
+    fn test_client_allowlists() {
+        let cfg = parse("");
+        assert!(cfg.server.allowed_client_versions.is_empty());
+        let cfg = parse("allowed_client_versions = [0x07cbf4dd]\nallowed_client_languages = [3]");
+        assert_eq!(cfg.server.allowed_client_versions, vec![0x07cbf4dd]);
+        assert_eq!(cfg.server.allowed_client_languages, vec![3]);
+    }
+
+    #[test]
+    fn test_each_race_starts_at_configured_town() {
+        let cfg: ServerConfig = toml::from_str(&format!(
+            "{}\n[start_locations]\n\
+             elf = {{ x = 33051, y = 32337, map_id = 4 }}\n\
+             darkelf = {{ x = 32877, y = 32905, map_id = 304 }}\n",
+            BASE.replace("{extra}", ""),
+        ))
+        .unwrap();
+        let starts = &cfg.start_locations;
+
+        assert_eq!(starts.for_class(2), StartLocation { x: 33051, y: 32337, map_id: 4 });
+        assert_eq!(starts.for_class(4), StartLocation { x: 32877, y: 32905, map_id: 304 });
+        for unset in [0, 1, 3, 5, 6, 99, -1] {
+            assert_eq!(starts.for_class(unset), DEFAULT_START);
+        }
+    }
+
+    #[test]
+    fn test_start_location_must_be_walkable() {
+        use crate::world::map_data::{v1_flags, MapProperties};
+
+        // 2x1 map at (100,100): first tile walkable, second blocked
+        let map = MapTileData::from_v1(
+            4, 100, 100, 2, 1, vec![0, v1_flags::IMPASSABLE], MapProperties::default(),
+        );
+        let lookup = |id: i32| (id == 4).then_some(&map);
+
+        let mut starts = StartLocationsSection {
+            elf: Some(StartLocation { x: 100, y: 100, map_id: 4 }),
+            ..Default::default()
+        };
+        assert!(starts.validate(lookup).is_empty());
+
+        starts.knight = Some(StartLocation { x: 101, y: 100, map_id: 4 });
+        starts.mage = Some(StartLocation { x: 1, y: 1, map_id: 99 }); // map not loaded
+        assert_eq!(starts.validate(lookup), vec!["knight"]);
+    }
+
+    #[test]
+    fn test_protocol_version_defaults_to_380c() {
+        assert_eq!(parse("").server.protocol_version, "3.80c-tw");
+    }
+
+    #[test]
+    fn test_reject_new_ip_defaults_off() {
+        assert!(!parse("").server.reject_new_ip);
+        assert!(parse("reject_new_ip = true").server.reject_new_ip);
+    }
+
+    #[test]
+    fn test_coalesce_packet_bursts_defaults_off() {
+        assert!(!parse("").server.coalesce_packet_bursts);
+        assert!(parse("coalesce_packet_bursts = true").server.coalesce_packet_bursts);
+    }
+}
@@ -7,7 +7,9 @@ use std::collections::HashMap;
 
 use anyhow::Result;
 use sqlx::{MySqlPool, Row};
-use tracing::info;
+use tracing::{info, warn};
+
+use crate::db::schema::{self, Migration};
 
 /// A single dungeon portal entry.
 #[derive(Debug, Clone)]
@@ -19,10 +21,35 @@ pub struct DungeonEntry {
     pub new_y: i32,
     pub new_map_id: i32,
     pub new_heading: i32,
+    /// Lowest character level allowed through (0 = any).
+    pub min_level: i32,
+    /// Item the player must carry to pass (0 = none).
+    pub key_item_id: i32,
+}
+
+/// Why a gated portal turned a player back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortalError {
+    UnderLevel { needed: i32 },
+    MissingKey { item_id: i32 },
+}
+
+impl DungeonEntry {
+    /// Check the portal's level and key requirements.
+    pub fn check_gate(&self, level: i32, has_key: bool) -> Result<(), PortalError> {
+        if level < self.min_level {
+            return Err(PortalError::UnderLevel { needed: self.min_level });
+        }
+        if self.key_item_id != 0 && !has_key {
+            return Err(PortalError::MissingKey { item_id: self.key_item_id });
+        }
+        Ok(())
+    }
 }
 
 /// Dungeon portal lookup table.
 /// Key format: "{map_id}_{x}_{y}" (matches Java's `srcMapId + srcX + srcY`)
+#[derive(Debug, Default)]
 pub struct DungeonTable {
     portals: HashMap<String, DungeonEntry>,
 }
@@ -33,32 +60,59 @@ impl DungeonTable {
         format!("{}_{}", map_id, x * 10000 + y)
     }
 
+    /// Add the gating columns to the `dungeon` table.
+    pub async fn migrate_schema(pool: &MySqlPool) -> Result<()> {
+        let mut m = Migration::new("dungeon");
+        for column in ["min_level", "key_item_id"] {
+            m.step(
+                &format!("add {}", column),
+                schema::add_column(pool, "dungeon", column, "INT NOT NULL DEFAULT 0").await,
+            );
+        }
+        m.finish()
+    }
+
     /// Load all portals from the database.
+    ///
+    /// If the gating columns couldn't be added, portals still load
+    /// ungated rather than not at all.
     pub async fn load(pool: &MySqlPool) -> Result<Self> {
-        let rows = sqlx::query(
-            "SELECT src_mapid, src_x, src_y, new_x, new_y, new_mapid, new_heading FROM dungeon",
-        )
+        let gated = schema::column_exists(pool, "dungeon", "min_level").await?
+            && schema::column_exists(pool, "dungeon", "key_item_id").await?;
+        // Both forms come back as BIGINT
+        let gates = if gated { "CAST(min_level AS SIGNED), CAST(key_item_id AS SIGNED)" } else { "0, 0" };
+        let rows = sqlx::query(&format!(
+            "SELECT src_mapid, src_x, src_y, new_x, new_y, new_mapid, new_heading, {} FROM dungeon",
+            gates
+        ))
         .fetch_all(pool)
         .await?;
 
-        let mut portals = HashMap::with_capacity(rows.len());
-
-        for r in &rows {
-            let entry = DungeonEntry {
-                src_map_id: r.get(0),
-                src_x: r.get(1),
-                src_y: r.get(2),
-                new_x: r.get(3),
-                new_y: r.get(4),
-                new_map_id: r.get(5),
-                new_heading: r.get(6),
-            };
-            let key = Self::make_key(entry.src_map_id, entry.src_x, entry.src_y);
-            portals.insert(key, entry);
+        let table = Self::from_entries(rows.iter().map(|r| DungeonEntry {
+            src_map_id: r.get(0),
+            src_x: r.get(1),
+            src_y: r.get(2),
+            new_x: r.get(3),
+            new_y: r.get(4),
+            new_map_id: r.get(5),
+            new_heading: r.get(6),
+            min_level: r.get::<i64, _>(7) as i32,
+            key_item_id: r.get::<i64, _>(8) as i32,
+        }));
+
+        if !gated {
+            warn!("dungeon table has no min_level/key_item_id; portals load ungated");
         }
+        info!("Loaded {} dungeon portals", table.portals.len());
+        Ok(table)
+    }
 
-        info!("Loaded {} dungeon portals", portals.len());
-        Ok(DungeonTable { portals })
+    pub fn from_entries(entries: impl IntoIterator<Item = DungeonEntry>) -> Self {
+        let portals = entries
+            .into_iter()
+            .map(|e| (Self::make_key(e.src_map_id, e.src_x, e.src_y), e))
+            .collect();
+        DungeonTable { portals }
     }
 
     /// Look up a portal at the given source coordinates.
@@ -66,6 +120,22 @@ impl DungeonTable {
         let key = Self::make_key(map_id, x, y);
         self.portals.get(&key)
     }
+
+    /// A player of `level` steps onto (map_id, x, y): the portal there, if
+    /// any, and whether its gate lets them through. `has_item` reports
+    /// whether they carry a given item.
+    pub fn step_on(
+        &self,
+        map_id: i32,
+        x: i32,
+        y: i32,
+        level: i32,
+        has_item: impl Fn(i32) -> bool,
+    ) -> Option<Result<&DungeonEntry, PortalError>> {
+        let entry = self.find_portal(map_id, x, y)?;
+        let has_key = entry.key_item_id == 0 || has_item(entry.key_item_id);
+        Some(entry.check_gate(level, has_key).map(|()| entry))
+    }
 }
 
 #[cfg(test)]
@@ -85,4 +155,37 @@ mod tests {
         };
         assert!(table.find_portal(4, 100, 200).is_none());
     }
+
+    fn portal(src_x: i32, min_level: i32, key_item_id: i32) -> DungeonEntry {
+        DungeonEntry {
+            src_map_id: 4, src_x, src_y: 32800,
+            new_x: 32700, new_y: 32800, new_map_id: 7, new_heading: 0,
+            min_level, key_item_id,
+        }
+    }
+
+    #[test]
+    fn test_step_on_portal() {
+        let table = DungeonTable::from_entries([portal(32750, 0, 0)]);
+        let dest = table.step_on(4, 32750, 32800, 1, |_| false).unwrap().unwrap();
+        assert_eq!((dest.new_x, dest.new_y, dest.new_map_id), (32700, 32800, 7));
+        // A neighbouring tile is plain ground
+        assert!(table.step_on(4, 32751, 32800, 1, |_| false).is_none());
+    }
+
+    #[test]
+    fn test_gated_portal() {
+        let table = DungeonTable::from_entries([portal(32750, 45, 0), portal(32760, 0, 40200)]);
+        assert_eq!(
+            table.step_on(4, 32750, 32800, 44, |_| true).unwrap().err(),
+            Some(PortalError::UnderLevel { needed: 45 })
+        );
+        assert!(table.step_on(4, 32750, 32800, 45, |_| false).unwrap().is_ok());
+
+        assert_eq!(
+            table.step_on(4, 32760, 32800, 99, |_| false).unwrap().err(),
+            Some(PortalError::MissingKey { item_id: 40200 })
+        );
+        assert!(table.step_on(4, 32760, 32800, 1, |id| id == 40200).unwrap().is_ok());
+    }
 }
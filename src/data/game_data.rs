@@ -0,0 +1,277 @@
+//! Gameplay data that can be reloaded while the server runs: vulcan
+//! recipes, monster drops, spawn points and server rates.
+//!
+//! Read from a TOML file at startup and again on the `.reload` GM
+//! command. A new file is parsed and validated in full before it
+//! replaces the live data, so a bad edit leaves the old data in place.
+//! Sessions take an `Arc` snapshot per use and never see a half-swap.
+
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::ecs::components::npc::SpawnInfo;
+use crate::ecs::vulcan::{craft_recipes, CraftRecipe};
+
+/// Drop chances are out of this many (Java `droplist.chance`).
+pub const DROP_CHANCE_SCALE: i32 = 1_000_000;
+
+/// Server-wide multipliers.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Rates {
+    pub exp: f64,
+    pub drop: f64,
+    pub adena: f64,
+}
+
+impl Default for Rates {
+    fn default() -> Self {
+        Rates { exp: 1.0, drop: 1.0, adena: 1.0 }
+    }
+}
+
+/// One item a monster may drop (a `droplist` row).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DropEntry {
+    pub mob_id: i32,
+    pub item_id: i32,
+    pub min: i32,
+    pub max: i32,
+    /// Out of `DROP_CHANCE_SCALE`.
+    pub chance: i32,
+}
+
+/// One consistent set of reloadable tables.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GameData {
+    #[serde(default = "craft_recipes")]
+    pub vulcan_recipes: Vec<CraftRecipe>,
+    #[serde(default)]
+    pub drops: Vec<DropEntry>,
+    #[serde(default)]
+    pub spawns: Vec<SpawnInfo>,
+    #[serde(default)]
+    pub rates: Rates,
+}
+
+impl Default for GameData {
+    fn default() -> Self {
+        GameData {
+            vulcan_recipes: craft_recipes(),
+            drops: Vec::new(),
+            spawns: Vec::new(),
+            rates: Rates::default(),
+        }
+    }
+}
+
+impl GameData {
+    /// Parse and validate a data file's contents.
+    pub fn parse(text: &str) -> Result<Self> {
+        let data: GameData = toml::from_str(text)?;
+        data.validate()?;
+        Ok(data)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let mut seen = HashSet::new();
+        for r in &self.vulcan_recipes {
+            if !seen.insert(r.result_item_id) {
+                bail!("duplicate vulcan recipe for item {}", r.result_item_id);
+            }
+            if r.contract_cost < 0 || r.crystal_cost < 0 {
+                bail!("vulcan recipe {} has a negative cost", r.result_item_id);
+            }
+            if !(0..=100).contains(&r.base_success_rate) || !(0..=100).contains(&r.hammer_bonus_rate) {
+                bail!("vulcan recipe {} rate out of 0..=100", r.result_item_id);
+            }
+        }
+        for d in &self.drops {
+            if d.min < 1 || d.max < d.min {
+                bail!("drop of item {} by mob {} has count {}..={}", d.item_id, d.mob_id, d.min, d.max);
+            }
+            if !(0..=DROP_CHANCE_SCALE).contains(&d.chance) {
+                bail!("drop of item {} by mob {} chance out of 0..={}", d.item_id, d.mob_id, DROP_CHANCE_SCALE);
+            }
+        }
+        let mut spawn_ids = HashSet::new();
+        for sp in &self.spawns {
+            if !spawn_ids.insert(sp.spawn_id) {
+                bail!("duplicate spawn {}", sp.spawn_id);
+            }
+            if sp.count < 1 || sp.min_respawn_delay < 0 || sp.max_respawn_delay < sp.min_respawn_delay {
+                bail!("spawn {} has a bad count or respawn delay", sp.spawn_id);
+            }
+        }
+        let Rates { exp, drop, adena } = self.rates;
+        if [exp, drop, adena].iter().any(|r| !r.is_finite() || *r < 0.0) {
+            bail!("rates must be finite and not negative");
+        }
+        Ok(())
+    }
+
+    /// Read and validate a data file.
+    pub fn load_file(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+        Self::parse(&text).with_context(|| format!("loading {}", path))
+    }
+
+    pub fn vulcan_recipe(&self, result_item_id: i32) -> Option<&CraftRecipe> {
+        crate::ecs::vulcan::find_recipe(&self.vulcan_recipes, result_item_id)
+    }
+
+    /// Drops of one monster.
+    pub fn drops_for(&self, mob_id: i32) -> impl Iterator<Item = &DropEntry> {
+        self.drops.iter().filter(move |d| d.mob_id == mob_id)
+    }
+}
+
+/// The live `GameData`, swapped whole on reload.
+#[derive(Debug, Default)]
+pub struct GameDataStore {
+    current: RwLock<Arc<GameData>>,
+}
+
+impl GameDataStore {
+    pub fn new(data: GameData) -> Self {
+        GameDataStore { current: RwLock::new(Arc::new(data)) }
+    }
+
+    /// Snapshot of the current data.
+    pub fn get(&self) -> Arc<GameData> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Swap in `text` if it parses and validates; otherwise keep the
+    /// current data and return the error.
+    pub fn reload_str(&self, text: &str) -> Result<()> {
+        let data = GameData::parse(text)?;
+        *self.current.write().unwrap() = Arc::new(data);
+        Ok(())
+    }
+
+    /// Swap in the data file at `path`, read and parsed off the async
+    /// runtime; a file that fails to load leaves the current data.
+    pub async fn reload_file(&self, path: &str) -> Result<()> {
+        let owned = path.to_string();
+        let data = tokio::task::spawn_blocking(move || GameData::load_file(&owned)).await??;
+        *self.current.write().unwrap() = Arc::new(data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEW_RECIPES: &str = r#"
+        [[vulcan_recipes]]
+        result_item_name = "武官之刃"
+        result_item_id = 80
+        contract_cost = 4
+        crystal_cost = 20
+        base_success_rate = 90
+        hammer_bonus_rate = 5
+    "#;
+
+    #[test]
+    fn test_reload_swaps_recipes() {
+        let store = GameDataStore::default();
+        let before = store.get();
+        assert_eq!(before.vulcan_recipe(80).unwrap().contract_cost, 8);
+
+        store.reload_str(NEW_RECIPES).unwrap();
+        let after = store.get();
+        assert_eq!(after.vulcan_recipes.len(), 1);
+        assert_eq!(after.vulcan_recipe(80).unwrap().contract_cost, 4);
+        assert!(after.vulcan_recipe(81).is_none());
+        // A snapshot taken before the reload is unchanged
+        assert_eq!(before.vulcan_recipe(80).unwrap().contract_cost, 8);
+    }
+
+    #[test]
+    fn test_bad_file_keeps_old_data() {
+        let store = GameDataStore::default();
+        store.reload_str(NEW_RECIPES).unwrap();
+
+        // Syntax error
+        assert!(store.reload_str("[[vulcan_recipes]\nresult_item_id = ").is_err());
+        // Parses but fails validation
+        let bad_rate = NEW_RECIPES.replace("base_success_rate = 90", "base_success_rate = 150");
+        assert!(store.reload_str(&bad_rate).is_err());
+        let duplicate = format!("{}{}", NEW_RECIPES, NEW_RECIPES);
+        assert!(store.reload_str(&duplicate).is_err());
+
+        assert_eq!(*store.get(), GameData::parse(NEW_RECIPES).unwrap());
+    }
+
+    const NEW_TABLES: &str = r#"
+        [rates]
+        exp = 2.0
+
+        [[drops]]
+        mob_id = 45000
+        item_id = 40308
+        min = 10
+        max = 50
+        chance = 500000
+
+        [[spawns]]
+        spawn_id = 1
+        npc_template_id = 45000
+        loc_x = 32800
+        loc_y = 32800
+        map_id = 4
+        count = 3
+        min_respawn_delay = 60
+        max_respawn_delay = 120
+        is_boss = true
+    "#;
+
+    #[test]
+    fn test_reload_swaps_drops_spawns_and_rates() {
+        let store = GameDataStore::default();
+        store.reload_str(NEW_TABLES).unwrap();
+        let data = store.get();
+
+        assert_eq!(data.rates, Rates { exp: 2.0, ..Rates::default() });
+        assert_eq!(data.drops_for(45000).map(|d| d.item_id).collect::<Vec<_>>(), vec![40308]);
+        assert_eq!(data.spawns.len(), 1);
+        assert!(data.spawns[0].is_boss);
+        // Tables left out of the file fall back as usual
+        assert_eq!(data.vulcan_recipes, craft_recipes());
+
+        for bad in [
+            NEW_TABLES.replace("chance = 500000", "chance = 2000000"),
+            NEW_TABLES.replace("max = 50", "max = 5"),
+            NEW_TABLES.replace("exp = 2.0", "exp = -1.0"),
+            NEW_TABLES.replace("max_respawn_delay = 120", "max_respawn_delay = 30"),
+        ] {
+            assert!(store.reload_str(&bad).is_err());
+        }
+        assert_eq!(*store.get(), GameData::parse(NEW_TABLES).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_reload_file() {
+        let path = std::env::temp_dir().join(format!("gamedata-{}.toml", std::process::id()));
+        let path = path.to_str().unwrap();
+        let store = GameDataStore::default();
+
+        std::fs::write(path, NEW_RECIPES).unwrap();
+        store.reload_file(path).await.unwrap();
+        assert_eq!(store.get().vulcan_recipes.len(), 1);
+
+        std::fs::remove_file(path).unwrap();
+        assert!(store.reload_file(path).await.is_err());
+        assert_eq!(store.get().vulcan_recipes.len(), 1);
+    }
+
+    #[test]
+    fn test_missing_table_uses_builtin() {
+        assert_eq!(GameData::parse("").unwrap(), GameData::default());
+    }
+}
@@ -11,31 +11,45 @@ pub async fn load_item_templates(pool: &MySqlPool) -> Result<HashMap<i32, ItemTe
     let mut templates = HashMap::new();
 
     // Load etcitems
-    let count = load_etcitems(pool, &mut templates).await?;
+    let count = load_etcitems(pool, "", &mut templates).await?;
     info!("Loaded {} etcitems", count);
 
     // Load weapons
-    let count = load_weapons(pool, &mut templates).await?;
+    let count = load_weapons(pool, "", &mut templates).await?;
     info!("Loaded {} weapons", count);
 
     // Load armors
-    let count = load_armors(pool, &mut templates).await?;
+    let count = load_armors(pool, "", &mut templates).await?;
     info!("Loaded {} armors", count);
 
     info!("Total item templates: {}", templates.len());
     Ok(templates)
 }
 
-async fn load_etcitems(pool: &MySqlPool, map: &mut HashMap<i32, ItemTemplate>) -> Result<usize> {
-    let rows = sqlx::query(
-        "SELECT item_id, name, unidentified_name_id, identified_name_id, \
+/// Load only the templates for `item_ids` (e.g. one character's
+/// equipment) instead of the whole table.
+pub async fn load_item_templates_for(pool: &MySqlPool, item_ids: &[i32]) -> Result<HashMap<i32, ItemTemplate>> {
+    let mut templates = HashMap::new();
+    if item_ids.is_empty() {
+        return Ok(templates);
+    }
+    let ids: Vec<String> = item_ids.iter().map(i32::to_string).collect();
+    let filter = format!(" WHERE item_id IN ({})", ids.join(","));
+    load_etcitems(pool, &filter, &mut templates).await?;
+    load_weapons(pool, &filter, &mut templates).await?;
+    load_armors(pool, &filter, &mut templates).await?;
+    Ok(templates)
+}
+
+async fn load_etcitems(pool: &MySqlPool, filter: &str, map: &mut HashMap<i32, ItemTemplate>) -> Result<usize> {
+    let select = "SELECT item_id, name, unidentified_name_id, identified_name_id, \
          item_type, use_type, material, weight, invgfx, grdgfx, itemdesc_id, \
          dmg_small, dmg_large, min_lvl, max_lvl, bless, trade, cant_delete, \
          stackable, max_charge_count, food_volume, save_at_once \
-         FROM etcitem"
-    )
-    .fetch_all(pool)
-    .await?;
+         FROM etcitem";
+    let rows = sqlx::query(&format!("{}{}", select, filter))
+        .fetch_all(pool)
+        .await?;
 
     let count = rows.len();
     for r in &rows {
@@ -68,9 +82,8 @@ async fn load_etcitems(pool: &MySqlPool, map: &mut HashMap<i32, ItemTemplate>) -
     Ok(count)
 }
 
-async fn load_weapons(pool: &MySqlPool, map: &mut HashMap<i32, ItemTemplate>) -> Result<usize> {
-    let rows = sqlx::query(
-        "SELECT item_id, name, unidentified_name_id, identified_name_id, \
+async fn load_weapons(pool: &MySqlPool, filter: &str, map: &mut HashMap<i32, ItemTemplate>) -> Result<usize> {
+    let select = "SELECT item_id, name, unidentified_name_id, identified_name_id, \
          type, material, weight, invgfx, grdgfx, itemdesc_id, \
          dmg_small, dmg_large, `range`, safenchant, \
          use_royal, use_knight, use_elf, use_mage, use_darkelf, use_dragonknight, use_illusionist, \
@@ -79,10 +92,10 @@ async fn load_weapons(pool: &MySqlPool, map: &mut HashMap<i32, ItemTemplate>) ->
          add_hp, add_mp, add_hpr, add_mpr, add_sp, m_def, \
          double_dmg_chance, magicdmgmodifier, canbedmg, \
          min_lvl, max_lvl, bless, trade, cant_delete, haste_item, max_use_time \
-         FROM weapon"
-    )
-    .fetch_all(pool)
-    .await?;
+         FROM weapon";
+    let rows = sqlx::query(&format!("{}{}", select, filter))
+        .fetch_all(pool)
+        .await?;
 
     let count = rows.len();
     for r in &rows {
@@ -138,9 +151,8 @@ async fn load_weapons(pool: &MySqlPool, map: &mut HashMap<i32, ItemTemplate>) ->
     Ok(count)
 }
 
-async fn load_armors(pool: &MySqlPool, map: &mut HashMap<i32, ItemTemplate>) -> Result<usize> {
-    let rows = sqlx::query(
-        "SELECT item_id, name, unidentified_name_id, identified_name_id, \
+async fn load_armors(pool: &MySqlPool, filter: &str, map: &mut HashMap<i32, ItemTemplate>) -> Result<usize> {
+    let select = "SELECT item_id, name, unidentified_name_id, identified_name_id, \
          type, material, weight, invgfx, grdgfx, itemdesc_id, \
          ac, safenchant, \
          use_royal, use_knight, use_elf, use_mage, use_darkelf, use_dragonknight, use_illusionist, \
@@ -150,10 +162,10 @@ async fn load_armors(pool: &MySqlPool, map: &mut HashMap<i32, ItemTemplate>) ->
          hit_modifier, dmg_modifier, bow_hit_modifier, bow_dmg_modifier, \
          haste_item, bless, trade, cant_delete, \
          min_lvl, max_lvl, max_use_time, grade \
-         FROM armor"
-    )
-    .fetch_all(pool)
-    .await?;
+         FROM armor";
+    let rows = sqlx::query(&format!("{}{}", select, filter))
+        .fetch_all(pool)
+        .await?;
 
     let count = rows.len();
     for r in &rows {
@@ -0,0 +1,67 @@
+//! Map bounds loaded from the `mapids` MySQL table.
+//!
+//! Only the bounds are read for now; the flag columns keep their
+//! `MapProperties` defaults until something consults them.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use sqlx::{MySqlPool, Row};
+use tracing::warn;
+
+use crate::world::map_data::{MapProperties, MapTileData};
+
+/// Load the `mapids` row for one map, if present.
+pub async fn load_properties(pool: &MySqlPool, map_id: i32) -> Result<Option<MapProperties>> {
+    let row = sqlx::query(
+        "SELECT mapid, locationname, startX, endX, startY, endY FROM mapids WHERE mapid = ?",
+    )
+    .bind(map_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| MapProperties {
+        map_id: r.get(0),
+        locationname: r.get(1),
+        startx: r.get(2),
+        endx: r.get(3),
+        starty: r.get(4),
+        endy: r.get(5),
+        ..Default::default()
+    }))
+}
+
+/// Load the V1 text maps for `map_ids` from `maps_dir`. Maps that
+/// can't be loaded are logged and left out.
+pub async fn load_maps(
+    pool: &MySqlPool,
+    maps_dir: &str,
+    map_ids: impl IntoIterator<Item = i32>,
+) -> HashMap<i32, MapTileData> {
+    let mut maps = HashMap::new();
+    for map_id in map_ids {
+        if maps.contains_key(&map_id) {
+            continue;
+        }
+        let props = match load_properties(pool, map_id).await {
+            Ok(Some(props)) => props,
+            Ok(None) => {
+                warn!("Map {} has no mapids row", map_id);
+                continue;
+            }
+            Err(e) => {
+                warn!("Map {} bounds unavailable: {}", map_id, e);
+                continue;
+            }
+        };
+        let dir = maps_dir.to_string();
+        match tokio::task::spawn_blocking(move || MapTileData::load_v1_text(&dir, props)).await {
+            Ok(Ok(map)) => {
+                maps.insert(map_id, map);
+            }
+            Ok(Err(e)) => warn!("Map {} not loaded: {:#}", map_id, e),
+            Err(e) => warn!("Map {} loader failed: {}", map_id, e),
+        }
+    }
+    maps
+}
@@ -1,6 +1,9 @@
 pub mod bookmark_table;
 pub mod dungeon_table;
+pub mod game_data;
 pub mod item_table;
+pub mod map_table;
 pub mod npc_table;
 pub mod skill_table;
 pub mod spawn_table;
+pub mod starter_kit;
@@ -4,7 +4,18 @@ use anyhow::Result;
 use sqlx::{MySqlPool, Row};
 use tracing::info;
 
-use crate::ecs::components::skill::SkillTemplate;
+use crate::ecs::components::skill::{class_mask, SkillTemplate};
+
+/// A skill's display name and client icon number (`skill_number`).
+pub async fn load_skill_entry(pool: &MySqlPool, skill_id: i32) -> Result<Option<(String, i32)>> {
+    let row: Option<(String, i32)> = sqlx::query_as(
+        "SELECT name, CAST(skill_number AS SIGNED) FROM skills WHERE skill_id = ?",
+    )
+    .bind(skill_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
 
 /// Load all skill templates from the `skills` database table.
 pub async fn load_skill_templates(pool: &MySqlPool) -> Result<HashMap<i32, SkillTemplate>> {
@@ -25,10 +36,11 @@ pub async fn load_skill_templates(pool: &MySqlPool) -> Result<HashMap<i32, Skill
 
     for r in &rows {
         let skill_id: i32 = r.get(0);
+        let skill_level: i32 = r.get(2);
         templates.insert(skill_id, SkillTemplate {
             skill_id,
             name: r.get(1),
-            skill_level: r.get(2),
+            skill_level,
             skill_number: r.get(3),
             mp_consume: r.get(4),
             hp_consume: r.get(5),
@@ -53,6 +65,7 @@ pub async fn load_skill_templates(pool: &MySqlPool) -> Result<HashMap<i32, Skill
             sys_msg_id_happen: r.get(24),
             sys_msg_id_stop: r.get(25),
             sys_msg_id_fail: r.get(26),
+            allowed_classes: class_mask::for_skill(skill_id, skill_level),
         });
     }
 
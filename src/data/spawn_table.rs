@@ -1,56 +1,57 @@
 use anyhow::Result;
 use sqlx::{MySqlPool, Row};
-use tracing::info;
+use tracing::{info, warn};
 
+use crate::db::schema::{self, Migration};
 use crate::ecs::components::npc::SpawnInfo;
 
+/// Add the `is_boss` column to both spawn tables.
+pub async fn migrate_schema(pool: &MySqlPool) -> Result<()> {
+    let mut m = Migration::new("spawnlist");
+    for table in ["spawnlist", "spawnlist_npc"] {
+        m.step(
+            &format!("add {}.is_boss", table),
+            schema::add_column(pool, table, "is_boss", "TINYINT NOT NULL DEFAULT 0").await,
+        );
+    }
+    m.finish()
+}
+
 /// Load all monster spawn data from the `spawnlist` database table.
 ///
 /// Returns a Vec of SpawnInfo, each representing a spawn point.
 pub async fn load_spawn_table(pool: &MySqlPool) -> Result<Vec<SpawnInfo>> {
-    let rows = sqlx::query(
-        "SELECT id, count, npc_templateid, locx, locy, mapid, heading, \
-         randomx, randomy, min_respawn_delay, max_respawn_delay, \
-         movement_distance \
-         FROM spawnlist",
-    )
-    .fetch_all(pool)
-    .await?;
-
-    let mut spawns = Vec::with_capacity(rows.len());
-
-    for r in &rows {
-        spawns.push(SpawnInfo {
-            spawn_id: r.get(0),
-            count: r.get(1),
-            npc_template_id: r.get(2),
-            loc_x: r.get(3),
-            loc_y: r.get(4),
-            map_id: r.get::<i16, _>(5) as i32,
-            heading: r.get(6),
-            randomx: r.get(7),
-            randomy: r.get(8),
-            min_respawn_delay: r.get(9),
-            max_respawn_delay: r.get(10),
-            movement_distance: r.get(11),
-        });
-    }
-
+    let spawns = load_from(pool, "spawnlist").await?;
     info!("Loaded {} spawn entries", spawns.len());
     Ok(spawns)
 }
 
 /// Load NPC spawn data from the `spawnlist_npc` database table.
 pub async fn load_npc_spawn_table(pool: &MySqlPool) -> Result<Vec<SpawnInfo>> {
-    let rows = sqlx::query(
+    let spawns = load_from(pool, "spawnlist_npc").await?;
+    info!("Loaded {} NPC spawn entries", spawns.len());
+    Ok(spawns)
+}
+
+/// Read one spawn table. Without the `is_boss` column (migration
+/// failed) every spawn loads as a regular one.
+async fn load_from(pool: &MySqlPool, table: &str) -> Result<Vec<SpawnInfo>> {
+    let has_boss = schema::column_exists(pool, table, "is_boss").await?;
+    let boss = if has_boss { "CAST(is_boss AS SIGNED)" } else { "0" };
+    let rows = sqlx::query(&format!(
         "SELECT id, count, npc_templateid, locx, locy, mapid, heading, \
          randomx, randomy, min_respawn_delay, max_respawn_delay, \
-         movement_distance \
-         FROM spawnlist_npc",
-    )
+         movement_distance, {} \
+         FROM {}",
+        boss, table
+    ))
     .fetch_all(pool)
     .await?;
 
+    if !has_boss {
+        warn!("{} has no is_boss column; no spawn is treated as a boss", table);
+    }
+
     let mut spawns = Vec::with_capacity(rows.len());
 
     for r in &rows {
@@ -67,9 +68,9 @@ pub async fn load_npc_spawn_table(pool: &MySqlPool) -> Result<Vec<SpawnInfo>> {
             min_respawn_delay: r.get(9),
             max_respawn_delay: r.get(10),
             movement_distance: r.get(11),
+            is_boss: r.get::<i64, _>(12) != 0,
         });
     }
 
-    info!("Loaded {} NPC spawn entries", spawns.len());
     Ok(spawns)
 }
@@ -0,0 +1,118 @@
+//! Starting equipment and spells granted on character creation.
+//!
+//! Mirrors the Java `beginner` table: each row carries a class mask
+//! (the `activate` column) and an optional sex filter, so the grant
+//! set is driven by data rather than per-class code paths.
+
+use crate::ecs::components::skill::class_mask::{
+    self, ALL, DARKELF, DRAGONKNIGHT, ELF, ILLUSIONIST, KNIGHT, MAGE, ROYAL,
+};
+use crate::ecs::components::skill::skill_ids;
+
+/// A single starter item row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StarterItem {
+    pub item_id: i32,
+    pub name: &'static str,
+    pub count: i32,
+    pub enchant: i32,
+    pub equipped: bool,
+    pub classes: u8,
+    /// `None` = both sexes, otherwise 0=male / 1=female only.
+    pub sex: Option<i32>,
+}
+
+/// A single starter spell row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StarterSkill {
+    pub skill_id: i32,
+    pub name: &'static str,
+    pub classes: u8,
+}
+
+const fn item(item_id: i32, name: &'static str, count: i32, equipped: bool, classes: u8) -> StarterItem {
+    StarterItem { item_id, name, count, enchant: 0, equipped, classes, sex: None }
+}
+
+/// Starter items (象牙塔 beginner gear + consumables).
+pub const STARTER_ITEMS: &[StarterItem] = &[
+    // Common consumables
+    item(40010, "治癒藥水", 10, false, ALL),
+    item(40100, "瞬間移動卷軸", 5, false, ALL),
+    item(40005, "蠟燭", 1, false, ALL),
+    item(crate::ecs::components::clan::ADENA_ITEM_ID, "金幣", 500, false, ALL),
+    // Weapons
+    item(35, "象牙塔單手劍", 1, true, ROYAL | DARKELF),
+    item(48, "象牙塔雙手劍", 1, true, KNIGHT | DRAGONKNIGHT),
+    item(175, "象牙塔弓", 1, true, ELF),
+    item(40743, "箭", 500, false, ELF),
+    item(120, "象牙塔魔杖", 1, true, MAGE | ILLUSIONIST),
+    // Armor
+    item(20322, "象牙塔皮盔", 1, true, ALL),
+    item(20028, "象牙塔皮甲", 1, true, ALL & !MAGE),
+    item(20082, "象牙塔長袍", 1, true, MAGE),
+    // Mana recovery for casters
+    item(40015, "藍色藥水", 5, false, MAGE | ELF | ILLUSIONIST),
+];
+
+/// Level-1 spells granted on creation.
+pub const STARTER_SKILLS: &[StarterSkill] = &[
+    StarterSkill { skill_id: skill_ids::LIGHT, name: "日光術", classes: ROYAL | ELF | MAGE },
+    StarterSkill { skill_id: skill_ids::ENERGY_BOLT, name: "光箭", classes: MAGE },
+    StarterSkill { skill_id: skill_ids::SHIELD, name: "保護罩", classes: MAGE },
+];
+
+/// Items granted to a new character of the given class and sex.
+pub fn starter_items(char_type: i32, sex: i32) -> Vec<StarterItem> {
+    let flag = class_mask::flag(char_type);
+    STARTER_ITEMS
+        .iter()
+        .filter(|i| i.classes & flag != 0 && i.sex.is_none_or(|s| s == sex))
+        .copied()
+        .collect()
+}
+
+/// Spells granted to a new character of the given class.
+pub fn starter_skills(char_type: i32) -> Vec<StarterSkill> {
+    let flag = class_mask::flag(char_type);
+    STARTER_SKILLS.iter().filter(|s| s.classes & flag != 0).copied().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item_ids(char_type: i32, sex: i32) -> Vec<i32> {
+        starter_items(char_type, sex).iter().map(|i| i.item_id).collect()
+    }
+
+    #[test]
+    fn test_mage_starter_set() {
+        let items = item_ids(3, 1);
+        assert!(items.contains(&120)); // wand
+        assert!(items.contains(&20082)); // robe
+        assert!(items.contains(&40015)); // mana potion
+        assert!(!items.contains(&48));
+        assert!(!items.contains(&20028));
+
+        let skills: Vec<i32> = starter_skills(3).iter().map(|s| s.skill_id).collect();
+        assert_eq!(skills, vec![skill_ids::LIGHT, skill_ids::ENERGY_BOLT, skill_ids::SHIELD]);
+    }
+
+    #[test]
+    fn test_knight_starter_set() {
+        let items = item_ids(1, 0);
+        assert!(items.contains(&48)); // two-handed sword
+        assert!(items.contains(&20028)); // leather armor
+        assert!(items.contains(&40010)); // healing potions
+        assert!(!items.contains(&120));
+        assert!(!items.contains(&40015));
+        assert!(starter_skills(1).is_empty());
+    }
+
+    #[test]
+    fn test_unknown_class_gets_nothing() {
+        assert!(starter_items(9, 0).is_empty());
+        assert!(starter_skills(-1).is_empty());
+    }
+}
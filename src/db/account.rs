@@ -1,7 +1,14 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
 use base64::Engine;
+use rand::RngExt;
 use sha1::{Digest, Sha1};
 use sqlx::MySqlPool;
+use tracing::warn;
+
+use crate::db::retry::with_retry;
+use crate::db::schema::{self, Migration};
 
 /// Account data loaded from the `accounts` MySQL table.
 #[derive(Debug, Clone)]
@@ -15,19 +22,49 @@ pub struct AccountData {
     pub online_status: i32,
 }
 
-/// Load an account from the database by login name.
-/// Uses CAST to handle INT UNSIGNED columns safely.
-pub async fn load_account(pool: &MySqlPool, login: &str) -> Result<Option<AccountData>> {
-    let row: Option<(String, String, i32, i32, i32, i32, i32)> = sqlx::query_as(
-        "SELECT login, password, CAST(access_level AS SIGNED), CAST(online AS SIGNED), \
-         CAST(banned AS SIGNED), CAST(character_slot AS SIGNED), CAST(OnlineStatus AS SIGNED) \
-         FROM accounts WHERE login = ? LIMIT 1"
-    )
-    .bind(login)
-    .fetch_optional(pool)
-    .await?;
+impl AccountData {
+    /// Privilege tier for this account.
+    pub fn access(&self) -> AccessLevel {
+        AccessLevel::from_level(self.access_level)
+    }
+}
 
-    Ok(row.map(|r| AccountData {
+/// Account privilege tiers, derived from `accounts.access_level`.
+///
+/// Ordered so a higher tier passes every check a lower one does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AccessLevel {
+    Player,
+    Gm,
+    Admin,
+}
+
+impl AccessLevel {
+    /// Minimum `access_level` value for a GM (L1J "monitor" level).
+    pub const GM_LEVEL: i32 = 100;
+    /// Minimum `access_level` value for an administrator (L1J full GM).
+    pub const ADMIN_LEVEL: i32 = 200;
+
+    pub fn from_level(level: i32) -> Self {
+        if level >= Self::ADMIN_LEVEL {
+            AccessLevel::Admin
+        } else if level >= Self::GM_LEVEL {
+            AccessLevel::Gm
+        } else {
+            AccessLevel::Player
+        }
+    }
+
+    /// Does this tier meet `required`?
+    pub fn allows(self, required: AccessLevel) -> bool {
+        self >= required
+    }
+}
+
+type AccountRow = (String, String, i32, i32, i32, i32, i32);
+
+fn account_from_row(r: AccountRow) -> AccountData {
+    AccountData {
         login: r.0,
         password: r.1,
         access_level: r.2,
@@ -35,52 +72,274 @@ pub async fn load_account(pool: &MySqlPool, login: &str) -> Result<Option<Accoun
         banned: r.4,
         character_slot: r.5,
         online_status: r.6,
-    }))
+    }
+}
+
+/// Load an account from the database by login name.
+/// Uses CAST to handle INT UNSIGNED columns safely.
+pub async fn load_account(pool: &MySqlPool, login: &str) -> Result<Option<AccountData>> {
+    let row: Option<AccountRow> = with_retry(|| {
+        sqlx::query_as(
+            "SELECT login, password, CAST(access_level AS SIGNED), CAST(online AS SIGNED), \
+             CAST(banned AS SIGNED), CAST(character_slot AS SIGNED), CAST(OnlineStatus AS SIGNED) \
+             FROM accounts WHERE login = ? LIMIT 1",
+        )
+        .bind(login)
+        .fetch_optional(pool)
+    })
+    .await?;
+
+    Ok(row.map(account_from_row))
+}
+
+/// Set an account's raw access level.
+pub async fn set_access_level(pool: &MySqlPool, login: &str, level: i32) -> Result<()> {
+    sqlx::query("UPDATE accounts SET access_level = ? WHERE login = ?")
+        .bind(level)
+        .bind(login)
+        .execute(pool)
+        .await?;
+    Ok(())
 }
 
-/// Validate a raw password against the stored SHA-1 + Base64 hash.
+/// Widen columns this server writes beyond the stock L1J schema.
 ///
-/// Algorithm (matching Java):
-///   1. SHA-1 hash the raw password bytes
-///   2. Base64 encode the hash
-///   3. Compare with stored value
-pub fn validate_password(raw_password: &str, stored_hash: &str) -> bool {
+/// Argon2 PHC strings are ~100 characters; the Java schema sized
+/// `password` for a 28-character SHA-1 digest. Older dumps also lack
+/// `access_level`, which GM checks depend on. `login_history` is created
+/// first: logins write to it, so it must not depend on an ALTER.
+pub async fn migrate_schema(pool: &MySqlPool) -> Result<()> {
+    let mut m = Migration::new("accounts");
+    m.step(
+        "create login_history",
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS login_history (\
+               id INT AUTO_INCREMENT PRIMARY KEY, \
+               login VARCHAR(45) NOT NULL, \
+               ip VARCHAR(45) NOT NULL, \
+               accepted TINYINT NOT NULL DEFAULT 1, \
+               login_time DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP, \
+               KEY idx_login (login, login_time))",
+        )
+        .execute(pool)
+        .await,
+    );
+    m.step("widen password", widen_password(pool).await);
+    m.step(
+        "add access_level",
+        schema::add_column(pool, "accounts", "access_level", "INT NOT NULL DEFAULT 0").await,
+    );
+    m.finish()
+}
+
+/// Widen `password` to hold Argon2 hashes. Skipped once it is wide
+/// enough: MODIFY rebuilds the whole table.
+async fn widen_password(pool: &MySqlPool) -> Result<()> {
+    if schema::column_length(pool, "accounts", "password").await?.is_some_and(|len| len >= 255) {
+        return Ok(());
+    }
+    sqlx::query("ALTER TABLE accounts MODIFY password VARCHAR(255) NOT NULL").execute(pool).await?;
+    Ok(())
+}
+
+/// Result of checking a password against the stored value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordCheck {
+    /// Matches a current (Argon2) hash.
+    Valid,
+    /// Matches, but the stored value is a legacy SHA-1 digest or plaintext
+    /// and should be replaced with `hash_password`.
+    ValidLegacy,
+    Invalid,
+}
+
+/// Hash a password for storage (Argon2id, PHC string format).
+pub fn hash_password(raw_password: &str) -> Result<String> {
+    let salt_bytes: [u8; 16] = rand::rng().random();
+    let salt = SaltString::encode_b64(&salt_bytes).map_err(|e| anyhow!("salt: {}", e))?;
+    let hash = Argon2::default()
+        .hash_password(raw_password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("hash: {}", e))?;
+    Ok(hash.to_string())
+}
+
+/// `hash_password` on the blocking pool; Argon2 takes tens of
+/// milliseconds and would otherwise stall an async worker.
+pub async fn hash_password_blocking(raw_password: &str) -> Result<String> {
+    let raw = raw_password.to_string();
+    tokio::task::spawn_blocking(move || hash_password(&raw)).await?
+}
+
+/// Java-compatible SHA-1 + Base64 digest.
+fn legacy_sha1(raw_password: &str) -> String {
     let mut hasher = Sha1::new();
     hasher.update(raw_password.as_bytes());
-    let hash = hasher.finalize();
-    let encoded = base64::engine::general_purpose::STANDARD.encode(hash);
-    encoded == stored_hash
+    base64::engine::general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+/// Does a stored value look like a Java SHA-1 + Base64 digest?
+fn is_legacy_sha1(stored: &str) -> bool {
+    stored.len() == 28 && stored.ends_with('=')
+        && base64::engine::general_purpose::STANDARD.decode(stored).is_ok_and(|b| b.len() == 20)
+}
+
+/// Check a raw password against any stored format.
+///
+/// Stored values are tried as:
+///   1. Argon2 PHC string (`$argon2...`)
+///   2. SHA-1 + Base64 digest (Java L1J)
+///   3. Plaintext (very old or hand-edited rows)
+///
+/// A SHA-1 digest is never compared as plaintext, so knowing the
+/// digest is not enough to log in.
+pub fn verify_password(raw_password: &str, stored: &str) -> PasswordCheck {
+    if stored.starts_with("$argon2") {
+        let ok = PasswordHash::new(stored)
+            .map(|h| Argon2::default().verify_password(raw_password.as_bytes(), &h).is_ok())
+            .unwrap_or(false);
+        return if ok { PasswordCheck::Valid } else { PasswordCheck::Invalid };
+    }
+    let matches = if is_legacy_sha1(stored) {
+        legacy_sha1(raw_password) == stored
+    } else {
+        !stored.is_empty() && raw_password == stored
+    };
+    if matches { PasswordCheck::ValidLegacy } else { PasswordCheck::Invalid }
+}
+
+/// `verify_password` on the blocking pool (see `hash_password_blocking`).
+pub async fn check_password(raw_password: &str, stored: &str) -> Result<PasswordCheck> {
+    let (raw, stored) = (raw_password.to_string(), stored.to_string());
+    Ok(tokio::task::spawn_blocking(move || verify_password(&raw, &stored)).await?)
+}
+
+/// Validate a raw password against the stored hash (any format).
+pub fn validate_password(raw_password: &str, stored_hash: &str) -> bool {
+    verify_password(raw_password, stored_hash) != PasswordCheck::Invalid
+}
+
+/// Replace a stored password with a fresh Argon2 hash.
+pub async fn update_password(pool: &MySqlPool, login: &str, raw_password: &str) -> Result<()> {
+    let hash = hash_password_blocking(raw_password).await?;
+    sqlx::query("UPDATE accounts SET password = ? WHERE login = ?")
+        .bind(&hash)
+        .bind(login)
+        .execute(pool)
+        .await?;
+    Ok(())
 }
 
-/// Update account online status after successful login.
+/// Update account online status after successful login and record it
+/// in the login history.
+///
+/// The UPDATE only sets absolute values, so retrying it (or calling this
+/// twice) is harmless. The history row is written once, after it lands,
+/// and is best-effort: a history failure is logged but never fails the
+/// login.
 pub async fn set_online(pool: &MySqlPool, login: &str, ip: &str) -> Result<()> {
-    sqlx::query("UPDATE accounts SET online = 1, ip = ?, lastactive = NOW() WHERE login = ?")
-        .bind(ip)
+    with_retry(|| {
+        sqlx::query("UPDATE accounts SET online = 1, ip = ?, lastactive = NOW() WHERE login = ?")
+            .bind(ip)
+            .bind(login)
+            .execute(pool)
+    })
+    .await?;
+    if let Err(e) = record_login(pool, login, ip, true).await {
+        warn!("Login history not recorded for {}: {}", login, e);
+    }
+    Ok(())
+}
+
+/// One row of `login_history`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginRecord {
+    pub ip: String,
+    /// False for logins held back because the IP was new.
+    pub accepted: bool,
+    /// Unix timestamp (seconds).
+    pub login_time: i64,
+}
+
+/// Append a login attempt to the history.
+pub async fn record_login(pool: &MySqlPool, login: &str, ip: &str, accepted: bool) -> Result<()> {
+    sqlx::query("INSERT INTO login_history (login, ip, accepted) VALUES (?, ?, ?)")
         .bind(login)
+        .bind(ip)
+        .bind(accepted as i32)
         .execute(pool)
         .await?;
     Ok(())
 }
 
-/// Clear account online status on disconnect.
-pub async fn set_offline(pool: &MySqlPool, login: &str) -> Result<()> {
-    sqlx::query("UPDATE accounts SET online = 0, OnlineStatus = 0 WHERE login = ?")
+/// Most recent login attempts for an account, newest first.
+pub async fn recent_logins(pool: &MySqlPool, login: &str, limit: u32) -> Result<Vec<LoginRecord>> {
+    let rows: Vec<(String, i32, i64)> = sqlx::query_as(
+        "SELECT ip, CAST(accepted AS SIGNED), CAST(UNIX_TIMESTAMP(login_time) AS SIGNED) \
+         FROM login_history WHERE login = ? ORDER BY login_time DESC, id DESC LIMIT ?",
+    )
+    .bind(login)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(ip, accepted, login_time)| LoginRecord { ip, accepted: accepted != 0, login_time })
+        .collect())
+}
+
+/// Mark held-back logins from `ip` as accepted so the next login passes.
+pub async fn confirm_ip(pool: &MySqlPool, login: &str, ip: &str) -> Result<u64> {
+    let res = sqlx::query("UPDATE login_history SET accepted = 1 WHERE login = ? AND ip = ?")
         .bind(login)
+        .bind(ip)
         .execute(pool)
         .await?;
+    Ok(res.rows_affected())
+}
+
+/// How many history rows to consider when deciding if an IP is known.
+pub const LOGIN_HISTORY_WINDOW: u32 = 50;
+
+/// Should a login from `ip` be held back as a new IP?
+///
+/// Only applies when the toggle is on and the account has logged in
+/// before; the very first login always establishes a trusted IP.
+pub fn is_new_ip_rejected(enforce: bool, history: &[LoginRecord], ip: &str) -> bool {
+    if !enforce {
+        return false;
+    }
+    let mut trusted = history.iter().filter(|r| r.accepted).peekable();
+    trusted.peek().is_some() && !trusted.any(|r| r.ip == ip)
+}
+
+/// Clear account online status on disconnect.
+pub async fn set_offline(pool: &MySqlPool, login: &str) -> Result<()> {
+    with_retry(|| {
+        sqlx::query("UPDATE accounts SET online = 0, OnlineStatus = 0 WHERE login = ?")
+            .bind(login)
+            .execute(pool)
+    })
+    .await?;
     Ok(())
 }
 
-/// Create a new account with SHA-1 + Base64 hashed password.
+/// Clear every account's online flag. Run once at startup: no session
+/// can be live yet, so any flag still set was left by a crash and would
+/// otherwise lock the account out. Returns how many accounts were reset.
+pub async fn clear_all_online(pool: &MySqlPool) -> Result<u64> {
+    let done = sqlx::query("UPDATE accounts SET online = 0, OnlineStatus = 0 WHERE online <> 0 OR OnlineStatus <> 0")
+        .execute(pool)
+        .await?;
+    Ok(done.rows_affected())
+}
+
+/// Create a new account with an Argon2 hashed password.
 pub async fn create_account(
     pool: &MySqlPool,
     login: &str,
     raw_password: &str,
 ) -> Result<()> {
-    let mut hasher = Sha1::new();
-    hasher.update(raw_password.as_bytes());
-    let hash = hasher.finalize();
-    let encoded = base64::engine::general_purpose::STANDARD.encode(hash);
+    let encoded = hash_password_blocking(raw_password).await?;
 
     sqlx::query("INSERT INTO accounts (login, password, access_level, online, banned, character_slot) VALUES (?, ?, 0, 0, 0, 0)")
         .bind(login)
@@ -106,4 +365,101 @@ mod tests {
         assert!(validate_password(password, &encoded));
         assert!(!validate_password("wrong", &encoded));
     }
+
+    fn login(ip: &str, accepted: bool, login_time: i64) -> LoginRecord {
+        LoginRecord { ip: ip.to_string(), accepted, login_time }
+    }
+
+    #[test]
+    fn test_new_ip_rejection_toggle() {
+        let history = vec![login("10.0.0.5", true, 200), login("10.0.0.9", false, 100)];
+
+        // Known IP always passes; unknown IP only held back when enforced
+        assert!(!is_new_ip_rejected(true, &history, "10.0.0.5"));
+        assert!(is_new_ip_rejected(true, &history, "192.168.1.1"));
+        assert!(!is_new_ip_rejected(false, &history, "192.168.1.1"));
+
+        // A held-back attempt doesn't make the IP trusted until confirmed
+        assert!(is_new_ip_rejected(true, &history, "10.0.0.9"));
+        let confirmed = vec![login("10.0.0.5", true, 200), login("10.0.0.9", true, 100)];
+        assert!(!is_new_ip_rejected(true, &confirmed, "10.0.0.9"));
+    }
+
+    #[test]
+    fn test_first_login_establishes_ip() {
+        assert!(!is_new_ip_rejected(true, &[], "10.0.0.5"));
+        // Only rejected attempts so far - nothing trusted yet
+        assert!(!is_new_ip_rejected(true, &[login("10.0.0.9", false, 100)], "10.0.0.5"));
+    }
+
+    #[test]
+    fn test_account_row_carries_access_level() {
+        let row = ("gm01".to_string(), String::new(), 200, 0, 0, 0, 0);
+        let account = account_from_row(row);
+        assert_eq!(account.access_level, 200);
+        assert_eq!(account.access(), AccessLevel::Admin);
+    }
+
+    #[test]
+    fn test_access_tiers() {
+        assert_eq!(AccessLevel::from_level(0), AccessLevel::Player);
+        assert_eq!(AccessLevel::from_level(99), AccessLevel::Player);
+        assert_eq!(AccessLevel::from_level(100), AccessLevel::Gm);
+        assert_eq!(AccessLevel::from_level(200), AccessLevel::Admin);
+
+        assert!(AccessLevel::Admin.allows(AccessLevel::Gm));
+        assert!(AccessLevel::Gm.allows(AccessLevel::Gm));
+        assert!(!AccessLevel::Gm.allows(AccessLevel::Admin));
+        assert!(!AccessLevel::Player.allows(AccessLevel::Gm));
+    }
+
+    #[test]
+    fn test_argon2_hash_verification() {
+        let hash = hash_password("test123").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert_ne!(hash, hash_password("test123").unwrap(), "salt must differ");
+        assert_eq!(verify_password("test123", &hash), PasswordCheck::Valid);
+        assert_eq!(verify_password("test124", &hash), PasswordCheck::Invalid);
+    }
+
+    #[tokio::test]
+    async fn test_off_thread_hash_and_check() {
+        let hash = hash_password_blocking("test123").await.unwrap();
+        assert_eq!(check_password("test123", &hash).await.unwrap(), PasswordCheck::Valid);
+        assert_eq!(check_password("nope", &hash).await.unwrap(), PasswordCheck::Invalid);
+    }
+
+    #[test]
+    fn test_legacy_passwords_flagged_for_migration() {
+        let sha1 = legacy_sha1("test123");
+        assert_eq!(verify_password("test123", &sha1), PasswordCheck::ValidLegacy);
+        assert_eq!(verify_password("wrong", &sha1), PasswordCheck::Invalid);
+        // Knowing the digest is not the same as knowing the password
+        assert_eq!(verify_password(&sha1, &sha1), PasswordCheck::Invalid);
+
+        assert_eq!(verify_password("plain", "plain"), PasswordCheck::ValidLegacy);
+        assert_eq!(verify_password("", ""), PasswordCheck::Invalid);
+
+        // After migration the new hash verifies as current
+        let upgraded = hash_password("test123").unwrap();
+        assert_eq!(verify_password("test123", &upgraded), PasswordCheck::Valid);
+    }
+
+    /// Runs against a scratch database named by `L1J_TEST_DATABASE_URL`.
+    #[tokio::test]
+    #[ignore = "needs a MySQL database (L1J_TEST_DATABASE_URL)"]
+    async fn test_startup_sweep_clears_stuck_online() {
+        let url = std::env::var("L1J_TEST_DATABASE_URL").expect("L1J_TEST_DATABASE_URL");
+        let pool = MySqlPool::connect(&url).await.unwrap();
+        let login = "sweep_test";
+        sqlx::query("DELETE FROM accounts WHERE login = ?").bind(login).execute(&pool).await.unwrap();
+        create_account(&pool, login, "pw").await.unwrap();
+        set_online(&pool, login, "127.0.0.1").await.unwrap();
+        assert_eq!(load_account(&pool, login).await.unwrap().unwrap().online, 1);
+
+        assert!(clear_all_online(&pool).await.unwrap() >= 1);
+        assert_eq!(load_account(&pool, login).await.unwrap().unwrap().online, 0);
+
+        sqlx::query("DELETE FROM accounts WHERE login = ?").bind(login).execute(&pool).await.unwrap();
+    }
 }
@@ -0,0 +1,179 @@
+//! Buffs kept across logout (character_buff table).
+//!
+//! Only long-running buffs that official servers keep (speed potions,
+//! blessing of eva, cooking) are saved; everything else ends with the
+//! session. Each row records when it was saved so the time spent
+//! offline comes off the remaining duration on the next login.
+
+use anyhow::Result;
+use sqlx::MySqlPool;
+
+use crate::db::schema::{self, Migration};
+use crate::ecs::components::skill::{skill_ids, SkillEffects, TICK_MS};
+
+/// Buffs that survive logout.
+pub const PERSISTENT_BUFFS: &[i32] = &[
+    skill_ids::STATUS_HASTE,
+    skill_ids::STATUS_BRAVE,
+    skill_ids::STATUS_THIRD_SPEED,
+    skill_ids::STATUS_UNDERWATER_BREATH,
+    skill_ids::COOKING_1_7_N,
+    skill_ids::COOKING_1_7_S,
+];
+
+/// One saved buff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SavedBuff {
+    pub skill_id: i32,
+    pub remaining_secs: i64,
+    pub value: i32,
+}
+
+/// The persistent, timed buffs in `effects`, ready to save.
+pub fn buffs_to_save(effects: &SkillEffects) -> Vec<SavedBuff> {
+    let mut saved: Vec<SavedBuff> = effects
+        .effects
+        .values()
+        .filter(|e| e.remaining_ticks > 0 && PERSISTENT_BUFFS.contains(&e.skill_id))
+        .map(|e| SavedBuff {
+            skill_id: e.skill_id,
+            remaining_secs: (e.remaining_ticks as u128 * TICK_MS / 1000) as i64,
+            value: e.value,
+        })
+        .filter(|b| b.remaining_secs > 0)
+        .collect();
+    saved.sort_by_key(|b| b.skill_id);
+    saved
+}
+
+/// Rebuild effects from saved buffs after `offline_secs` away; buffs
+/// that ran out in the meantime are dropped.
+pub fn restore_buffs(saved: &[SavedBuff], offline_secs: i64) -> SkillEffects {
+    let mut effects = SkillEffects::new();
+    for b in saved {
+        let left = b.remaining_secs - offline_secs.max(0);
+        if left > 0 {
+            let ticks = (left as u128 * 1000 / TICK_MS).min(u32::MAX as u128) as u32;
+            effects.add_effect(b.skill_id, ticks, b.value);
+        }
+    }
+    effects
+}
+
+/// Create `character_buff` if the database predates it, and add the
+/// save timestamp the stock L1J table lacks.
+pub async fn migrate_schema(pool: &MySqlPool) -> Result<()> {
+    let mut m = Migration::new("character_buff");
+    m.step(
+        "create character_buff",
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS character_buff (\
+             char_obj_id INT UNSIGNED NOT NULL, skill_id INT UNSIGNED NOT NULL, \
+             remaining_time INT NOT NULL, poly_id INT NOT NULL DEFAULT 0, \
+             PRIMARY KEY (char_obj_id, skill_id))",
+        )
+        .execute(pool)
+        .await,
+    );
+    m.step(
+        "add saved_at",
+        schema::add_column(pool, "character_buff", "saved_at", "BIGINT NOT NULL DEFAULT 0").await,
+    );
+    m.step(
+        "add value",
+        schema::add_column(pool, "character_buff", "value", "INT NOT NULL DEFAULT 0").await,
+    );
+    m.finish()
+}
+
+/// Replace a character's saved buffs with `buffs`, stamped `now` (unix
+/// seconds).
+pub async fn save_buffs(pool: &MySqlPool, char_id: i32, buffs: &[SavedBuff], now: i64) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM character_buff WHERE char_obj_id = ?")
+        .bind(char_id)
+        .execute(&mut *tx)
+        .await?;
+    for b in buffs {
+        sqlx::query(
+            "INSERT INTO character_buff SET char_obj_id=?, skill_id=?, remaining_time=?, \
+             poly_id=0, saved_at=?, value=?",
+        )
+        .bind(char_id)
+        .bind(b.skill_id)
+        .bind(b.remaining_secs)
+        .bind(now)
+        .bind(b.value)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Take a character's saved buffs and the seconds since they were saved
+/// (`save_buffs` writes them all at once). The rows are removed so a
+/// buff is only restored once.
+pub async fn take_buffs(pool: &MySqlPool, char_id: i32, now: i64) -> Result<(Vec<SavedBuff>, i64)> {
+    let mut tx = pool.begin().await?;
+    let rows: Vec<(i32, i64, i32, i64)> = sqlx::query_as(
+        "SELECT CAST(skill_id AS SIGNED), CAST(remaining_time AS SIGNED), value, saved_at \
+         FROM character_buff WHERE char_obj_id = ?",
+    )
+    .bind(char_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM character_buff WHERE char_obj_id = ?")
+        .bind(char_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    let offline_secs = rows.iter().map(|r| now - r.3).min().unwrap_or(0);
+    let buffs = rows
+        .into_iter()
+        .map(|(skill_id, remaining_secs, value, _)| SavedBuff { skill_id, remaining_secs, value })
+        .collect();
+    Ok((buffs, offline_secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECS: u32 = (1000 / TICK_MS) as u32;
+
+    #[test]
+    fn test_round_trip_with_offline_decay() {
+        let mut effects = SkillEffects::new();
+        effects.add_effect(skill_ids::STATUS_HASTE, 600 * SECS, 1);
+        effects.add_effect(skill_ids::STATUS_UNDERWATER_BREATH, 1800 * SECS, 0);
+        effects.add_effect(skill_ids::STATUS_BRAVE, 60 * SECS, 1);
+        // Not persistent: a spell buff and a poison
+        effects.add_effect(skill_ids::HOLY_WALK, 300 * SECS, 0);
+        effects.add_effect(skill_ids::STATUS_POISON, 30 * SECS, 5);
+
+        let saved = buffs_to_save(&effects);
+        assert_eq!(
+            saved.iter().map(|b| b.skill_id).collect::<Vec<_>>(),
+            [skill_ids::STATUS_HASTE, skill_ids::STATUS_BRAVE, skill_ids::STATUS_UNDERWATER_BREATH]
+        );
+
+        // Two minutes offline: brave has run out, the rest lose 120s
+        let restored = restore_buffs(&saved, 120);
+        assert!(!restored.has_effect(skill_ids::STATUS_BRAVE));
+        assert!(!restored.has_effect(skill_ids::HOLY_WALK));
+        assert_eq!(restored.effects[&skill_ids::STATUS_HASTE].remaining_ticks, 480 * SECS);
+        assert_eq!(restored.effects[&skill_ids::STATUS_HASTE].value, 1);
+        assert_eq!(restored.effects[&skill_ids::STATUS_UNDERWATER_BREATH].remaining_ticks, 1680 * SECS);
+    }
+
+    #[test]
+    fn test_untimed_and_expired_not_saved() {
+        let mut effects = SkillEffects::new();
+        effects.add_effect(skill_ids::STATUS_HASTE, 0, 1); // until removed
+        effects.add_effect(skill_ids::STATUS_BRAVE, 1, 1); // under a second left
+        assert!(buffs_to_save(&effects).is_empty());
+        assert!(restore_buffs(&[], 0).effects.is_empty());
+    }
+}
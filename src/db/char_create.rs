@@ -1,64 +1,187 @@
 /// Character creation DB operations.
 
 use anyhow::Result;
-use sqlx::MySqlPool;
+use sqlx::{MySql, MySqlPool, Transaction};
 
+use crate::config::StartLocation;
+use crate::data::starter_kit::{self, StarterItem, StarterSkill};
+use crate::ecs::components::item::ItemInstance;
 use crate::protocol::client::char_create::{self, NewChar};
+use crate::protocol::server::char_create as server_char_create;
 
-/// Create a new character in the database.
+/// The writes that make up a new character. Nothing is visible to other
+/// connections until `commit`; dropping it uncommitted discards them.
+trait CreationTx {
+    async fn insert_character(
+        &mut self,
+        account_name: &str,
+        nc: &NewChar,
+        objid: i32,
+        start: StartLocation,
+    ) -> Result<()>;
+    async fn next_item_id(&mut self) -> Result<i32>;
+    async fn insert_item(&mut self, id: i32, char_id: i32, item: &StarterItem) -> Result<()>;
+    async fn add_skill(&mut self, char_id: i32, skill: &StarterSkill) -> Result<()>;
+    async fn commit(self) -> Result<()>;
+}
+
+/// Why `create_character` failed.
+#[derive(Debug)]
+pub enum CreateCharError {
+    /// Another character has the name; it won the race past `name_exists`
+    /// and the unique index on `char_name` rejected this insert.
+    NameTaken,
+    Db(anyhow::Error),
+}
+
+impl CreateCharError {
+    /// S_CHARCREATESTATUS reason to send the client.
+    pub fn status(&self) -> u8 {
+        match self {
+            CreateCharError::NameTaken => server_char_create::REASON_ALREADY_EXISTS,
+            CreateCharError::Db(_) => server_char_create::REASON_INVALID_NAME,
+        }
+    }
+}
+
+/// Unique index on `characters.char_name` (see `character::migrate_schema`).
+/// Other duplicate keys, e.g. an item id collision, are plain DB errors.
+pub const NAME_INDEX: &str = "char_name_unique";
+
+impl From<anyhow::Error> for CreateCharError {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast_ref::<sqlx::Error>() {
+            Some(sqlx::Error::Database(db))
+                if db.is_unique_violation() && db.message().contains(NAME_INDEX) =>
+            {
+                CreateCharError::NameTaken
+            }
+            _ => CreateCharError::Db(e),
+        }
+    }
+}
+
+impl std::fmt::Display for CreateCharError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CreateCharError::NameTaken => write!(f, "name already taken"),
+            CreateCharError::Db(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Create a new character with its starter items and spells.
 ///
-/// Returns the new objid, or an error string.
+/// Everything is written in one transaction, so a failure part way
+/// through leaves no character, items or skills behind. Returns the
+/// new objid.
 pub async fn create_character(
     pool: &MySqlPool,
     account_name: &str,
     nc: &NewChar,
     objid: i32,
+    start: StartLocation,
+) -> Result<i32, CreateCharError> {
+    let tx = pool.begin().await.map_err(anyhow::Error::from)?;
+    Ok(write_character(tx, account_name, nc, objid, start).await?)
+}
+
+async fn write_character(
+    mut tx: impl CreationTx,
+    account_name: &str,
+    nc: &NewChar,
+    objid: i32,
+    start: StartLocation,
 ) -> Result<i32> {
-    let hp = char_create::get_init_hp(nc.char_type);
-    let mp = char_create::calc_init_mp(nc.char_type, nc.wis_stat);
-    let _gfxid = char_create::get_gfx_id(nc.char_type, nc.sex);
-
-    // Birthday as yyyyMMdd integer
-    let now = chrono_free_birthday();
-
-    sqlx::query(
-        "INSERT INTO characters SET \
-         account_name=?, objid=?, char_name=?, birthday=?, level=1, HighLevel=1, \
-         Exp=0, MaxHp=?, CurHp=?, MaxMp=?, CurMp=?, Ac=10, \
-         Str=?, Con=?, Dex=?, Cha=?, Intel=?, Wis=?, \
-         Status=0, Class=0, Sex=?, Type=?, Heading=0, \
-         LocX=?, LocY=?, MapID=?, Food=40, Lawful=0, Title='', \
-         ClanID=0, Clanname='', ClanRank=0, BonusStatus=0, ElixirStatus=0, \
-         ElfAttr=0, PKcount=0, PkCountForElf=0, ExpRes=0, PartnerID=0, \
-         AccessLevel=0, OnlineStatus=0, HomeTownID=0, Contribution=0, \
-         Pay=0, HellTime=0, Banned=0, Karma=0, LastPk=NULL, LastPkForElf=NULL, \
-         DeleteTime=NULL"
-    )
-    .bind(account_name)
-    .bind(objid)
-    .bind(&nc.name)
-    .bind(now)
-    .bind(hp)
-    .bind(hp)
-    .bind(mp)
-    .bind(mp)
-    .bind(nc.str_stat)
-    .bind(nc.con_stat)
-    .bind(nc.dex_stat)
-    .bind(nc.cha_stat)
-    .bind(nc.int_stat)
-    .bind(nc.wis_stat)
-    .bind(nc.sex)
-    .bind(nc.char_type)
-    .bind(char_create::START_X)
-    .bind(char_create::START_Y)
-    .bind(char_create::START_MAP)
-    .execute(pool)
-    .await?;
+    tx.insert_character(account_name, nc, objid, start).await?;
 
+    for it in starter_kit::starter_items(nc.char_type, nc.sex) {
+        let item_id = tx.next_item_id().await?;
+        tx.insert_item(item_id, objid, &it).await?;
+    }
+    for sk in starter_kit::starter_skills(nc.char_type) {
+        tx.add_skill(objid, &sk).await?;
+    }
+
+    tx.commit().await?;
     Ok(objid)
 }
 
+impl CreationTx for Transaction<'_, MySql> {
+    async fn insert_character(
+        &mut self,
+        account_name: &str,
+        nc: &NewChar,
+        objid: i32,
+        start: StartLocation,
+    ) -> Result<()> {
+        let hp = char_create::get_init_hp(nc.char_type);
+        let mp = char_create::calc_init_mp(nc.char_type, nc.wis_stat);
+
+        // Birthday as yyyyMMdd integer
+        let now = chrono_free_birthday();
+
+        sqlx::query(
+            "INSERT INTO characters SET \
+             account_name=?, objid=?, char_name=?, birthday=?, level=1, HighLevel=1, \
+             Exp=0, MaxHp=?, CurHp=?, MaxMp=?, CurMp=?, Ac=10, \
+             Str=?, Con=?, Dex=?, Cha=?, Intel=?, Wis=?, \
+             Status=0, Class=0, Sex=?, Type=?, Heading=0, \
+             LocX=?, LocY=?, MapID=?, Food=40, Lawful=0, Title='', \
+             ClanID=0, Clanname='', ClanRank=0, BonusStatus=0, ElixirStatus=0, \
+             ElfAttr=0, PKcount=0, PkCountForElf=0, ExpRes=0, PartnerID=0, \
+             AccessLevel=0, OnlineStatus=0, HomeTownID=0, Contribution=0, \
+             Pay=0, HellTime=0, Banned=0, Karma=0, LastPk=NULL, LastPkForElf=NULL, \
+             DeleteTime=NULL"
+        )
+        .bind(account_name)
+        .bind(objid)
+        .bind(&nc.name)
+        .bind(now)
+        .bind(hp)
+        .bind(hp)
+        .bind(mp)
+        .bind(mp)
+        .bind(nc.str_stat)
+        .bind(nc.con_stat)
+        .bind(nc.dex_stat)
+        .bind(nc.cha_stat)
+        .bind(nc.int_stat)
+        .bind(nc.wis_stat)
+        .bind(nc.sex)
+        .bind(nc.char_type)
+        .bind(start.x)
+        .bind(start.y)
+        .bind(start.map_id)
+        .execute(&mut **self)
+        .await?;
+        Ok(())
+    }
+
+    async fn next_item_id(&mut self) -> Result<i32> {
+        super::inventory::next_item_id(&mut **self).await
+    }
+
+    async fn insert_item(&mut self, id: i32, char_id: i32, item: &StarterItem) -> Result<()> {
+        let row = ItemInstance {
+            count: item.count,
+            is_equipped: item.equipped,
+            enchant_level: item.enchant,
+            ..ItemInstance::new(id as u32, item.item_id)
+        };
+        super::inventory::insert_item(&mut **self, char_id, &row, item.name).await
+    }
+
+    async fn add_skill(&mut self, char_id: i32, skill: &StarterSkill) -> Result<()> {
+        super::skill::add_skill(&mut **self, char_id, skill.skill_id, skill.name).await
+    }
+
+    async fn commit(self) -> Result<()> {
+        Transaction::commit(self).await?;
+        Ok(())
+    }
+}
+
 /// Check if a character name already exists.
 pub async fn name_exists(pool: &MySqlPool, name: &str) -> Result<bool> {
     let (count,): (i64,) = sqlx::query_as(
@@ -85,3 +208,144 @@ fn chrono_free_birthday() -> i32 {
     let day = remaining % 30 + 1;
     (years as i32) * 10000 + (month as i32) * 100 + (day as i32)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Row {
+        Character(i32),
+        Item(i32),
+        Skill(i32),
+    }
+
+    /// Stands in for the database: rows written through a transaction
+    /// only reach `committed` on commit.
+    #[derive(Default)]
+    struct FakeDb {
+        committed: RefCell<Vec<Row>>,
+        /// Fail the nth write (0-based) of the transaction.
+        fail_at: Option<usize>,
+    }
+
+    struct FakeTx<'a> {
+        db: &'a FakeDb,
+        staged: Vec<Row>,
+    }
+
+    impl FakeTx<'_> {
+        fn write(&mut self, row: Row) -> Result<()> {
+            if self.db.fail_at == Some(self.staged.len()) {
+                anyhow::bail!("connection lost");
+            }
+            self.staged.push(row);
+            Ok(())
+        }
+    }
+
+    impl CreationTx for FakeTx<'_> {
+        async fn insert_character(&mut self, _: &str, _: &NewChar, objid: i32, _: StartLocation) -> Result<()> {
+            self.write(Row::Character(objid))
+        }
+        async fn next_item_id(&mut self) -> Result<i32> {
+            Ok(1000)
+        }
+        async fn insert_item(&mut self, id: i32, _: i32, _: &StarterItem) -> Result<()> {
+            self.write(Row::Item(id))
+        }
+        async fn add_skill(&mut self, _: i32, skill: &StarterSkill) -> Result<()> {
+            self.write(Row::Skill(skill.skill_id))
+        }
+        async fn commit(self) -> Result<()> {
+            self.db.committed.borrow_mut().extend(self.staged);
+            Ok(())
+        }
+    }
+
+    fn mage() -> NewChar {
+        NewChar {
+            name: "Merlin".to_string(),
+            char_type: 3,
+            sex: 0,
+            str_stat: 8,
+            dex_stat: 7,
+            con_stat: 12,
+            wis_stat: 12,
+            cha_stat: 8,
+            int_stat: 12,
+        }
+    }
+
+    const START: StartLocation = StartLocation { x: 32689, y: 32842, map_id: 2005 };
+
+    #[tokio::test]
+    async fn test_creation_commits_everything() {
+        let db = FakeDb::default();
+        let tx = FakeTx { db: &db, staged: Vec::new() };
+        assert_eq!(write_character(tx, "alice", &mage(), 42, START).await.unwrap(), 42);
+
+        let rows = db.committed.borrow();
+        assert_eq!(rows[0], Row::Character(42));
+        let items = starter_kit::starter_items(3, 0).len();
+        let skills = starter_kit::starter_skills(3).len();
+        assert!(items > 0 && skills > 0);
+        assert_eq!(rows.len(), 1 + items + skills);
+    }
+
+    /// A MySQL duplicate-key error (1062) as the driver reports it.
+    #[derive(Debug)]
+    struct DuplicateKey(&'static str);
+
+    impl std::fmt::Display for DuplicateKey {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl std::error::Error for DuplicateKey {}
+
+    impl sqlx::error::DatabaseError for DuplicateKey {
+        fn message(&self) -> &str {
+            self.0
+        }
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+        fn kind(&self) -> sqlx::error::ErrorKind {
+            sqlx::error::ErrorKind::UniqueViolation
+        }
+    }
+
+    #[test]
+    fn test_duplicate_key_maps_to_already_exists() {
+        let dup = |msg| -> anyhow::Error { sqlx::Error::Database(Box::new(DuplicateKey(msg))).into() };
+        let err = CreateCharError::from(dup("Duplicate entry 'Merlin' for key 'characters.char_name_unique'"));
+        assert!(matches!(err, CreateCharError::NameTaken));
+        assert_eq!(err.status(), server_char_create::REASON_ALREADY_EXISTS);
+
+        // Two creations racing for the same item id is not a name clash
+        let item = CreateCharError::from(dup("Duplicate entry '1001' for key 'character_items.PRIMARY'"));
+        assert!(matches!(item, CreateCharError::Db(_)));
+
+        let other = CreateCharError::from(anyhow::Error::from(sqlx::Error::PoolTimedOut));
+        assert!(matches!(other, CreateCharError::Db(_)));
+        assert_eq!(other.status(), server_char_create::REASON_INVALID_NAME);
+    }
+
+    #[tokio::test]
+    async fn test_mid_creation_failure_leaves_no_rows() {
+        // Fail after the character row and first starter item are written
+        let db = FakeDb { fail_at: Some(2), ..Default::default() };
+        let tx = FakeTx { db: &db, staged: Vec::new() };
+        assert!(write_character(tx, "alice", &mage(), 42, START).await.is_err());
+        assert!(db.committed.borrow().is_empty());
+    }
+}
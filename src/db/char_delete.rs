@@ -0,0 +1,147 @@
+//! Character deletion and restore (characters.DeleteTime).
+//!
+//! Ported from Java C_DeleteChar.java. Characters at level 30 or above
+//! are not removed right away: a deletion deadline is stored and the
+//! character stays on the list until it passes. Deleting a pending
+//! character again before the deadline restores it.
+
+use anyhow::Result;
+use sqlx::MySqlPool;
+
+/// Grace period before a scheduled deletion takes effect (7 days).
+pub const DELETE_GRACE_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Characters below this level are deleted immediately.
+pub const DELAYED_DELETE_MIN_LEVEL: i32 = 30;
+
+/// What a delete request should do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteDecision {
+    Immediate,
+    /// Remove once `deadline` (unix seconds) has passed.
+    Scheduled { deadline: i64 },
+}
+
+/// Decide how to delete a character of `level` at time `now`.
+pub fn plan_delete(level: i32, now: i64) -> DeleteDecision {
+    if level >= DELAYED_DELETE_MIN_LEVEL {
+        DeleteDecision::Scheduled { deadline: now + DELETE_GRACE_SECS }
+    } else {
+        DeleteDecision::Immediate
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreError {
+    /// No deletion is scheduled.
+    NotPending,
+    /// The grace period is over; the character is gone.
+    DeadlinePassed,
+}
+
+/// Check that a scheduled deletion can still be cancelled.
+pub fn check_restore(deadline: Option<i64>, now: i64) -> Result<(), RestoreError> {
+    match deadline {
+        None => Err(RestoreError::NotPending),
+        Some(d) if now >= d => Err(RestoreError::DeadlinePassed),
+        Some(_) => Ok(()),
+    }
+}
+
+/// Deletion state of one of an account's characters.
+#[derive(Debug, Clone)]
+pub struct DeleteState {
+    pub objid: i32,
+    pub level: i32,
+    /// Scheduled deletion deadline (unix seconds), if any.
+    pub deadline: Option<i64>,
+}
+
+/// Load a character's deletion state, scoped to the owning account.
+pub async fn load_delete_state(
+    pool: &MySqlPool,
+    account_name: &str,
+    char_name: &str,
+) -> Result<Option<DeleteState>> {
+    let row: Option<(i32, i32, Option<i64>)> = sqlx::query_as(
+        "SELECT CAST(objid AS SIGNED), CAST(level AS SIGNED), \
+         CAST(UNIX_TIMESTAMP(DeleteTime) AS SIGNED) \
+         FROM characters WHERE account_name = ? AND char_name = ? LIMIT 1",
+    )
+    .bind(account_name)
+    .bind(char_name)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|(objid, level, deadline)| DeleteState { objid, level, deadline }))
+}
+
+/// Set (or clear, with `None`) a character's deletion deadline.
+pub async fn set_delete_time(pool: &MySqlPool, objid: i32, deadline: Option<i64>) -> Result<()> {
+    sqlx::query("UPDATE characters SET DeleteTime = FROM_UNIXTIME(?) WHERE objid = ?")
+        .bind(deadline)
+        .bind(objid)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Remove a character and the rows that belong to it.
+pub async fn delete_character(pool: &MySqlPool, objid: i32) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for sql in [
+        "DELETE FROM character_items WHERE char_id = ?",
+        "DELETE FROM character_skills WHERE char_obj_id = ?",
+        "DELETE FROM characters WHERE objid = ?",
+    ] {
+        sqlx::query(sql).bind(objid).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Delete an account's characters whose grace period has run out.
+/// Returns how many were removed.
+pub async fn purge_expired(pool: &MySqlPool, account_name: &str, now: i64) -> Result<usize> {
+    let expired: Vec<(i32,)> = sqlx::query_as(
+        "SELECT CAST(objid AS SIGNED) FROM characters \
+         WHERE account_name = ? AND DeleteTime IS NOT NULL AND DeleteTime <= FROM_UNIXTIME(?)",
+    )
+    .bind(account_name)
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+    for (objid,) in &expired {
+        delete_character(pool, *objid).await?;
+    }
+    Ok(expired.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_760_000_000;
+
+    #[test]
+    fn test_high_level_deletion_is_delayed() {
+        assert_eq!(plan_delete(29, NOW), DeleteDecision::Immediate);
+        assert_eq!(
+            plan_delete(30, NOW),
+            DeleteDecision::Scheduled { deadline: NOW + DELETE_GRACE_SECS }
+        );
+    }
+
+    #[test]
+    fn test_restore_within_window() {
+        let deadline = NOW + DELETE_GRACE_SECS;
+        assert_eq!(check_restore(Some(deadline), NOW + 60), Ok(()));
+        assert_eq!(check_restore(Some(deadline), deadline - 1), Ok(()));
+    }
+
+    #[test]
+    fn test_restore_after_deadline_fails() {
+        let deadline = NOW + DELETE_GRACE_SECS;
+        assert_eq!(check_restore(Some(deadline), deadline), Err(RestoreError::DeadlinePassed));
+        assert_eq!(check_restore(None, NOW), Err(RestoreError::NotPending));
+    }
+}
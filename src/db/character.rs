@@ -1,5 +1,8 @@
-use anyhow::Result;
-use sqlx::{MySqlPool, Row};
+use anyhow::{bail, Result};
+use sqlx::{MySql, MySqlPool, QueryBuilder, Row};
+
+use crate::db::retry::with_retry;
+use crate::db::schema::{self, Migration};
 
 /// Character data from the `characters` MySQL table.
 /// Contains only the fields needed for the character list screen.
@@ -26,7 +29,7 @@ pub struct CharacterListData {
 }
 
 /// Character data loaded for entering the game world.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct CharacterFullData {
     pub objid: i32,
     pub char_name: String,
@@ -59,48 +62,72 @@ pub struct CharacterFullData {
     pub mons_kill: i32,
 }
 
+/// Everything the character select screen shows for one account.
+#[derive(Debug, Clone, Default)]
+pub struct CharList {
+    pub chars: Vec<CharacterListData>,
+    /// Purchased slots beyond the base count (`accounts.character_slot`).
+    pub extra_slots: i32,
+}
+
 /// Load character list for account (character select screen).
-pub async fn load_char_list(
-    pool: &MySqlPool,
-    account_name: &str,
-) -> Result<Vec<CharacterListData>> {
-    let rows = sqlx::query(
-        "SELECT CAST(objid AS SIGNED), char_name, IFNULL(Clanname,''), \
-         CAST(Type AS SIGNED), CAST(Sex AS SIGNED), CAST(Lawful AS SIGNED), \
-         CAST(CurHp AS SIGNED), CAST(CurMp AS SIGNED), CAST(Ac AS SIGNED), \
-         CAST(level AS SIGNED), CAST(Str AS SIGNED), CAST(Dex AS SIGNED), \
-         CAST(Con AS SIGNED), CAST(Wis AS SIGNED), CAST(Cha AS SIGNED), \
-         CAST(Intel AS SIGNED), CAST(AccessLevel AS SIGNED), CAST(IFNULL(birthday,0) AS SIGNED) \
-         FROM characters WHERE account_name = ? ORDER BY objid",
-    )
-    .bind(account_name)
-    .fetch_all(pool)
+///
+/// One round trip: the account row is LEFT JOINed to its characters, so
+/// the slot count and every field `build_char_pack` needs come back
+/// together, and an account with no characters still yields its row.
+pub async fn load_char_list(pool: &MySqlPool, account_name: &str) -> Result<CharList> {
+    let rows = with_retry(|| {
+        sqlx::query(
+            "SELECT CAST(a.character_slot AS SIGNED), CAST(c.objid AS SIGNED), c.char_name, \
+             IFNULL(c.Clanname,''), CAST(c.Type AS SIGNED), CAST(c.Sex AS SIGNED), \
+             CAST(c.Lawful AS SIGNED), CAST(c.CurHp AS SIGNED), CAST(c.CurMp AS SIGNED), \
+             CAST(c.Ac AS SIGNED), CAST(c.level AS SIGNED), CAST(c.Str AS SIGNED), \
+             CAST(c.Dex AS SIGNED), CAST(c.Con AS SIGNED), CAST(c.Wis AS SIGNED), \
+             CAST(c.Cha AS SIGNED), CAST(c.Intel AS SIGNED), CAST(c.AccessLevel AS SIGNED), \
+             CAST(IFNULL(c.birthday,0) AS SIGNED) \
+             FROM accounts a LEFT JOIN characters c ON c.account_name = a.login \
+             WHERE a.login = ? ORDER BY c.objid",
+        )
+        .bind(account_name)
+        .fetch_all(pool)
+    })
     .await?;
 
-    let mut chars = Vec::with_capacity(rows.len());
-    for r in &rows {
-        chars.push(CharacterListData {
-            objid: r.get(0),
-            char_name: r.get(1),
-            clanname: r.get(2),
-            char_type: r.get(3),
-            sex: r.get(4),
-            lawful: r.get(5),
-            cur_hp: r.get(6),
-            cur_mp: r.get(7),
-            ac: r.get(8),
-            level: r.get(9),
-            str_stat: r.get(10),
-            dex_stat: r.get(11),
-            con_stat: r.get(12),
-            wis_stat: r.get(13),
-            cha_stat: r.get(14),
-            int_stat: r.get(15),
-            access_level: r.get(16),
-            birthday: r.get(17),
+    Ok(collect_char_list(rows.iter().map(|r| {
+        let objid: Option<i32> = r.get(1);
+        let ch = objid.map(|objid| CharacterListData {
+            objid,
+            char_name: r.get(2),
+            clanname: r.get(3),
+            char_type: r.get(4),
+            sex: r.get(5),
+            lawful: r.get(6),
+            cur_hp: r.get(7),
+            cur_mp: r.get(8),
+            ac: r.get(9),
+            level: r.get(10),
+            str_stat: r.get(11),
+            dex_stat: r.get(12),
+            con_stat: r.get(13),
+            wis_stat: r.get(14),
+            cha_stat: r.get(15),
+            int_stat: r.get(16),
+            access_level: r.get(17),
+            birthday: r.get(18),
         });
+        (r.get(0), ch)
+    })))
+}
+
+/// Fold joined `(character_slot, character)` rows into a `CharList`.
+/// A character-less account comes back as a single row with no character.
+fn collect_char_list(rows: impl IntoIterator<Item = (i32, Option<CharacterListData>)>) -> CharList {
+    let mut list = CharList::default();
+    for (extra_slots, ch) in rows {
+        list.extra_slots = extra_slots;
+        list.chars.extend(ch);
     }
-    Ok(chars)
+    list
 }
 
 /// Load full character data for entering the game world.
@@ -109,21 +136,23 @@ pub async fn load_character(
     char_name: &str,
     account_name: &str,
 ) -> Result<Option<CharacterFullData>> {
-    let row = sqlx::query(
-        "SELECT CAST(objid AS SIGNED), char_name, account_name, IFNULL(Clanname,''), CAST(IFNULL(ClanID,0) AS SIGNED), \
-         CAST(Type AS SIGNED), CAST(Sex AS SIGNED), CAST(Lawful AS SIGNED), \
-         CAST(CurHp AS SIGNED), CAST(MaxHp AS SIGNED), CAST(CurMp AS SIGNED), CAST(MaxMp AS SIGNED), \
-         CAST(Ac AS SIGNED), CAST(level AS SIGNED), CAST(Exp AS SIGNED), \
-         CAST(Str AS SIGNED), CAST(Dex AS SIGNED), CAST(Con AS SIGNED), \
-         CAST(Wis AS SIGNED), CAST(Cha AS SIGNED), CAST(Intel AS SIGNED), \
-         CAST(LocX AS SIGNED), CAST(LocY AS SIGNED), CAST(MapID AS SIGNED), \
-         CAST(Heading AS SIGNED), CAST(AccessLevel AS SIGNED), \
-         CAST(IFNULL(Food, 40) AS SIGNED) \
-         FROM characters WHERE char_name = ? AND account_name = ? LIMIT 1",
-    )
-    .bind(char_name)
-    .bind(account_name)
-    .fetch_optional(pool)
+    let row = with_retry(|| {
+        sqlx::query(
+            "SELECT CAST(objid AS SIGNED), char_name, account_name, IFNULL(Clanname,''), CAST(IFNULL(ClanID,0) AS SIGNED), \
+             CAST(Type AS SIGNED), CAST(Sex AS SIGNED), CAST(Lawful AS SIGNED), \
+             CAST(CurHp AS SIGNED), CAST(MaxHp AS SIGNED), CAST(CurMp AS SIGNED), CAST(MaxMp AS SIGNED), \
+             CAST(Ac AS SIGNED), CAST(level AS SIGNED), CAST(Exp AS SIGNED), \
+             CAST(Str AS SIGNED), CAST(Dex AS SIGNED), CAST(Con AS SIGNED), \
+             CAST(Wis AS SIGNED), CAST(Cha AS SIGNED), CAST(Intel AS SIGNED), \
+             CAST(LocX AS SIGNED), CAST(LocY AS SIGNED), CAST(MapID AS SIGNED), \
+             CAST(Heading AS SIGNED), CAST(AccessLevel AS SIGNED), \
+             CAST(IFNULL(Food, 40) AS SIGNED) \
+             FROM characters WHERE char_name = ? AND account_name = ? LIMIT 1",
+        )
+        .bind(char_name)
+        .bind(account_name)
+        .fetch_optional(pool)
+    })
     .await?;
 
     Ok(row.map(|r| {
@@ -173,3 +202,278 @@ pub async fn count_characters(pool: &MySqlPool, account_name: &str) -> Result<i6
             .await?;
     Ok(count)
 }
+
+/// Add columns this server tracks beyond the stock L1J schema.
+pub async fn migrate_schema(pool: &MySqlPool) -> Result<()> {
+    let mut m = Migration::new("characters");
+    m.step(
+        "add played_seconds",
+        schema::add_column(pool, "characters", "played_seconds", "BIGINT NOT NULL DEFAULT 0").await,
+    );
+    // Names are checked before creation, but two sessions can pass the
+    // check together; the index makes the second insert fail instead.
+    m.step("add char_name_unique", add_name_index(pool).await);
+    m.finish()
+}
+
+/// Add the unique index on `char_name` unless it exists. Rows that
+/// already share a name would make the ALTER fail; they are reported by
+/// name so an operator can rename them, and the index is added on the
+/// next start.
+async fn add_name_index(pool: &MySqlPool) -> Result<()> {
+    use crate::db::char_create::NAME_INDEX;
+
+    if schema::index_exists(pool, "characters", NAME_INDEX).await? {
+        return Ok(());
+    }
+    let dups: Vec<(String,)> =
+        sqlx::query_as("SELECT char_name FROM characters GROUP BY char_name HAVING COUNT(*) > 1")
+            .fetch_all(pool)
+            .await?;
+    if !dups.is_empty() {
+        let names: Vec<String> = dups.into_iter().map(|(n,)| n).collect();
+        bail!("duplicate character names must be renamed first: {}", names.join(", "));
+    }
+    sqlx::query(&format!("ALTER TABLE characters ADD UNIQUE INDEX {} (char_name)", NAME_INDEX))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// The character columns saved during play, as last written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharSnapshot {
+    pub x: i32,
+    pub y: i32,
+    pub map_id: i32,
+    pub heading: i32,
+}
+
+impl CharSnapshot {
+    /// Columns whose value differs from `saved` (all of them if nothing
+    /// is known to be saved yet).
+    pub fn changed_columns(&self, saved: Option<&CharSnapshot>) -> Vec<(&'static str, i32)> {
+        [
+            ("LocX", self.x, saved.map(|s| s.x)),
+            ("LocY", self.y, saved.map(|s| s.y)),
+            ("MapID", self.map_id, saved.map(|s| s.map_id)),
+            ("Heading", self.heading, saved.map(|s| s.heading)),
+        ]
+        .into_iter()
+        .filter(|&(_, now, before)| before != Some(now))
+        .map(|(column, now, _)| (column, now))
+        .collect()
+    }
+}
+
+/// UPDATE for just the position columns in `changes`; None when
+/// nothing changed.
+fn save_position_query(objid: i32, changes: &[(&'static str, i32)]) -> Option<QueryBuilder<'static, MySql>> {
+    if changes.is_empty() {
+        return None;
+    }
+    let mut q = QueryBuilder::new("UPDATE characters SET ");
+    let mut set = q.separated(", ");
+    for &(column, value) in changes {
+        set.push(column).push_unseparated(" = ").push_bind_unseparated(value);
+    }
+    q.push(" WHERE objid = ").push_bind(objid);
+    Some(q)
+}
+
+/// Write the columns of `now` that changed since `saved`. Returns false
+/// if there was nothing to write.
+///
+/// Play time goes through `add_played_seconds` on its own, so a
+/// problem with `played_seconds` never costs the player their position.
+pub async fn save_position(
+    pool: &MySqlPool,
+    objid: i32,
+    now: &CharSnapshot,
+    saved: Option<&CharSnapshot>,
+) -> Result<bool> {
+    match save_position_query(objid, &now.changed_columns(saved)) {
+        Some(mut q) => {
+            q.build().execute(pool).await?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Most characters written by one batched UPDATE.
+pub const SAVE_BATCH_MAX: usize = 500;
+
+/// A character's position and unsaved play time, for `save_batch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharSave {
+    pub objid: i32,
+    pub x: i32,
+    pub y: i32,
+    pub map_id: i32,
+    pub heading: i32,
+    /// Seconds to add to `played_seconds`.
+    pub played_secs: u64,
+}
+
+type SaveColumn = fn(&CharSave) -> i32;
+
+/// One UPDATE writing the position of every character in `saves`,
+/// using a CASE on objid per column.
+fn save_batch_query(saves: &[CharSave]) -> QueryBuilder<'static, MySql> {
+    let mut q = QueryBuilder::new("UPDATE characters SET ");
+    let columns: [(&str, SaveColumn); 4] = [
+        ("LocX", |c| c.x),
+        ("LocY", |c| c.y),
+        ("MapID", |c| c.map_id),
+        ("Heading", |c| c.heading),
+    ];
+    for (i, (column, value)) in columns.into_iter().enumerate() {
+        if i > 0 {
+            q.push(", ");
+        }
+        q.push(column).push(" = CASE objid");
+        for c in saves {
+            q.push(" WHEN ").push_bind(c.objid).push(" THEN ").push_bind(value(c));
+        }
+        q.push(" END");
+    }
+    push_objid_filter(&mut q, saves);
+    q
+}
+
+/// One UPDATE adding each character's `played_secs`.
+fn played_batch_query(saves: &[CharSave]) -> QueryBuilder<'static, MySql> {
+    let mut q = QueryBuilder::new("UPDATE characters SET played_seconds = played_seconds + CASE objid");
+    for c in saves {
+        q.push(" WHEN ").push_bind(c.objid).push(" THEN ").push_bind(c.played_secs);
+    }
+    q.push(" ELSE 0 END");
+    push_objid_filter(&mut q, saves);
+    q
+}
+
+fn push_objid_filter(q: &mut QueryBuilder<'static, MySql>, saves: &[CharSave]) {
+    q.push(" WHERE objid IN (");
+    let mut ids = q.separated(", ");
+    for c in saves {
+        ids.push_bind(c.objid);
+    }
+    q.push(")");
+}
+
+/// Write many characters' positions, one statement per `SAVE_BATCH_MAX`
+/// characters. Returns the rows updated.
+pub async fn save_batch(pool: &MySqlPool, saves: &[CharSave]) -> Result<u64> {
+    let mut updated = 0;
+    for chunk in saves.chunks(SAVE_BATCH_MAX) {
+        updated += save_batch_query(chunk).build().execute(pool).await?.rows_affected();
+    }
+    Ok(updated)
+}
+
+/// Add many characters' play time, kept apart from `save_batch` so a
+/// play-time failure can't hold back positions.
+pub async fn add_played_batch(pool: &MySqlPool, saves: &[CharSave]) -> Result<()> {
+    let played: Vec<CharSave> = saves.iter().filter(|s| s.played_secs > 0).copied().collect();
+    for chunk in played.chunks(SAVE_BATCH_MAX) {
+        played_batch_query(chunk).build().execute(pool).await?;
+    }
+    Ok(())
+}
+
+/// Add to a character's total play time.
+pub async fn add_played_seconds(pool: &MySqlPool, objid: i32, seconds: u64) -> Result<()> {
+    sqlx::query("UPDATE characters SET played_seconds = played_seconds + ? WHERE objid = ?")
+        .bind(seconds)
+        .bind(objid)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ch(objid: i32, name: &str) -> CharacterListData {
+        CharacterListData {
+            objid,
+            char_name: name.to_string(),
+            clanname: "Knights".to_string(),
+            char_type: 1,
+            sex: 0,
+            lawful: 32767,
+            cur_hp: 50,
+            cur_mp: 10,
+            ac: -5,
+            level: 52,
+            str_stat: 18,
+            dex_stat: 12,
+            con_stat: 14,
+            wis_stat: 9,
+            cha_stat: 8,
+            int_stat: 9,
+            access_level: 0,
+            birthday: 20250101,
+        }
+    }
+
+    #[test]
+    fn test_joined_rows_fill_char_list() {
+        let list = collect_char_list([(2, Some(ch(10, "Alice"))), (2, Some(ch(11, "Bob")))]);
+        assert_eq!(list.extra_slots, 2);
+        let names: Vec<_> = list.chars.iter().map(|c| c.char_name.as_str()).collect();
+        assert_eq!(names, ["Alice", "Bob"]);
+        // Every display field comes straight from the joined row
+        let alice = &list.chars[0];
+        assert_eq!((alice.clanname.as_str(), alice.level, alice.ac, alice.birthday), ("Knights", 52, -5, 20250101));
+    }
+
+    #[test]
+    fn test_save_only_changed_columns() {
+        let saved = CharSnapshot { x: 32800, y: 32800, map_id: 4, heading: 2 };
+        let moved = CharSnapshot { x: 32805, y: 32799, ..saved };
+        let changes = moved.changed_columns(Some(&saved));
+        assert_eq!(changes, vec![("LocX", 32805), ("LocY", 32799)]);
+
+        let mut q = save_position_query(1, &changes).unwrap();
+        assert_eq!(q.sql(), "UPDATE characters SET LocX = ?, LocY = ? WHERE objid = ?");
+        let _ = q.build();
+
+        // Unchanged: no write at all
+        assert!(save_position_query(1, &saved.changed_columns(Some(&saved))).is_none());
+        assert_eq!(saved.changed_columns(None).len(), 4);
+    }
+
+    #[test]
+    fn test_save_batch_is_one_statement() {
+        let saves: Vec<CharSave> = (0..3)
+            .map(|i| CharSave { objid: 100 + i, x: 32800 + i, y: 32700, map_id: 4, heading: 2, played_secs: 300 })
+            .collect();
+        let mut q = save_batch_query(&saves);
+        let sql = q.sql().to_string();
+        assert_eq!(sql.matches("UPDATE characters").count(), 1);
+        // 4 position columns of one WHEN per character, plus the IN list
+        assert_eq!(sql.matches("WHEN ?").count(), 4 * saves.len());
+        assert!(!sql.contains("played_seconds"));
+        assert!(sql.ends_with("END WHERE objid IN (?, ?, ?)"), "{}", sql);
+        let _ = q.build();
+
+        let mut q = played_batch_query(&saves);
+        let sql = q.sql().to_string();
+        assert!(sql.starts_with("UPDATE characters SET played_seconds = played_seconds + CASE objid"));
+        assert_eq!(sql.matches("WHEN ?").count(), saves.len());
+        let _ = q.build();
+    }
+
+    #[test]
+    fn test_account_without_characters() {
+        let list = collect_char_list([(1, None)]);
+        assert_eq!(list.extra_slots, 1);
+        assert!(list.chars.is_empty());
+        // Unknown account: no rows at all
+        let list = collect_char_list([]);
+        assert_eq!((list.extra_slots, list.chars.len()), (0, 0));
+    }
+}
@@ -64,6 +64,15 @@ pub async fn load_all_clans(pool: &MySqlPool) -> Result<Vec<ClanRow>> {
     Ok(clans)
 }
 
+/// Object ID of a clan's leader, if the clan exists.
+pub async fn leader_of(pool: &MySqlPool, clan_id: i32) -> Result<Option<i32>> {
+    let row = sqlx::query("SELECT leader_id FROM clan_data WHERE clan_id = ?")
+        .bind(clan_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|r| r.get(0)))
+}
+
 /// Create a new clan.
 pub async fn create_clan(
     pool: &MySqlPool,
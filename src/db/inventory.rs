@@ -0,0 +1,324 @@
+//! Character inventory DB operations (character_items table).
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use anyhow::Result;
+use sqlx::{MySqlConnection, MySqlExecutor, MySqlPool};
+
+use crate::db::schema::{self, Migration};
+use crate::ecs::components::item::{InventoryChanges, ItemInstance, ItemTemplate};
+
+/// Add columns this server needs to `character_items`.
+pub async fn migrate_schema(pool: &MySqlPool) -> Result<()> {
+    let mut m = Migration::new("character_items");
+    m.step(
+        "add expires_at",
+        schema::add_column(pool, "character_items", "expires_at", "BIGINT NULL DEFAULT NULL").await,
+    );
+    m.finish()
+}
+
+/// Item object IDs are handed out in-process, after the highest ID in
+/// `character_items` as of the first allocation. Asking the table for
+/// MAX(id) on every allocation let two sessions get the same ID.
+static NEXT_ITEM_ID: tokio::sync::OnceCell<AtomicI32> = tokio::sync::OnceCell::const_new();
+
+/// Seed the item ID allocator (call at boot; otherwise the first
+/// `next_item_id` does it).
+pub async fn seed_item_ids<'e>(pool: impl MySqlExecutor<'e>) -> Result<()> {
+    item_ids(pool).await.map(|_| ())
+}
+
+async fn item_ids<'e>(pool: impl MySqlExecutor<'e>) -> Result<&'static AtomicI32> {
+    NEXT_ITEM_ID
+        .get_or_try_init(|| async {
+            let (max,): (Option<i32>,) = sqlx::query_as("SELECT MAX(id) FROM character_items")
+                .fetch_one(pool)
+                .await?;
+            Ok::<_, anyhow::Error>(AtomicI32::new(max.unwrap_or(0) + 1))
+        })
+        .await
+}
+
+/// Allocate the next free item object ID.
+pub async fn next_item_id<'e>(pool: impl MySqlExecutor<'e>) -> Result<i32> {
+    Ok(item_ids(pool).await?.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Insert a new item into a character's inventory.
+pub async fn insert_item<'e>(
+    pool: impl MySqlExecutor<'e>,
+    char_id: i32,
+    item: &ItemInstance,
+    item_name: &str,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO character_items SET id=?, item_id=?, char_id=?, item_name=?, \
+         count=?, is_equipped=?, enchantlvl=?, is_id=?, durability=0, bless=?",
+    )
+    .bind(item.object_id as i32)
+    .bind(item.item_id)
+    .bind(char_id)
+    .bind(item_name)
+    .bind(item.count)
+    .bind(item.is_equipped as i32)
+    .bind(item.enchant_level)
+    .bind(item.is_identified as i32)
+    .bind(item.bless)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Total count of an item_id held by a character.
+pub async fn count_item(pool: &MySqlPool, char_id: i32, item_id: i32) -> Result<i32> {
+    let (total,): (Option<i64>,) = sqlx::query_as(
+        "SELECT SUM(count) FROM character_items WHERE char_id = ? AND item_id = ?",
+    )
+    .bind(char_id)
+    .bind(item_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(total.unwrap_or(0) as i32)
+}
+
+/// Remove `count` of a stackable item. Returns false if the character holds too few.
+/// Takes a connection so it can run inside the caller's transaction.
+pub async fn consume_item(conn: &mut MySqlConnection, char_id: i32, item_id: i32, count: i32) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE character_items SET count = count - ? \
+         WHERE char_id = ? AND item_id = ? AND count >= ? LIMIT 1",
+    )
+    .bind(count)
+    .bind(char_id)
+    .bind(item_id)
+    .bind(count)
+    .execute(&mut *conn)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    sqlx::query("DELETE FROM character_items WHERE char_id = ? AND item_id = ? AND count <= 0")
+        .bind(char_id)
+        .bind(item_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(true)
+}
+
+type ItemRow = (i32, i32, i32, i32, i32, Option<i64>, i32, i32);
+
+const ITEM_COLUMNS: &str = "SELECT CAST(id AS SIGNED), CAST(item_id AS SIGNED), CAST(count AS SIGNED), \
+     CAST(is_equipped AS SIGNED), CAST(enchantlvl AS SIGNED), expires_at, CAST(bless AS SIGNED), \
+     CAST(is_id AS SIGNED) FROM character_items";
+
+fn item_from_row((id, item_id, count, equipped, enchant, expires_at, bless, identified): ItemRow) -> ItemInstance {
+    ItemInstance {
+        count,
+        is_equipped: equipped != 0,
+        enchant_level: enchant,
+        expires_at,
+        bless,
+        is_identified: identified != 0,
+        ..ItemInstance::new(id as u32, item_id)
+    }
+}
+
+/// Load one of a character's items by object ID.
+pub async fn load_item(pool: &MySqlPool, char_id: i32, object_id: i32) -> Result<Option<ItemInstance>> {
+    let row: Option<ItemRow> = sqlx::query_as(&format!("{} WHERE id = ? AND char_id = ? LIMIT 1", ITEM_COLUMNS))
+        .bind(object_id)
+        .bind(char_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(item_from_row))
+}
+
+/// Load all of a character's items.
+pub async fn load_items(pool: &MySqlPool, char_id: i32) -> Result<Vec<ItemInstance>> {
+    let rows: Vec<ItemRow> = sqlx::query_as(&format!("{} WHERE char_id = ?", ITEM_COLUMNS))
+        .bind(char_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(item_from_row).collect())
+}
+
+/// Remove an item row. Returns false if the character doesn't own it.
+pub async fn delete_item(pool: &MySqlPool, char_id: i32, object_id: i32) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM character_items WHERE id = ? AND char_id = ?")
+        .bind(object_id)
+        .bind(char_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Store whether an item is worn. Returns false if the character
+/// doesn't own it.
+pub async fn set_equipped(pool: &MySqlPool, char_id: i32, object_id: i32, equipped: bool) -> Result<bool> {
+    let result = sqlx::query("UPDATE character_items SET is_equipped = ? WHERE id = ? AND char_id = ?")
+        .bind(equipped as i32)
+        .bind(object_id)
+        .bind(char_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Add `count` of a stackable item, topping up an existing stack if the
+/// character has one.
+pub async fn add_stackable(pool: &MySqlPool, char_id: i32, item_id: i32, item_name: &str, count: i32) -> Result<()> {
+    let result = sqlx::query(
+        "UPDATE character_items SET count = count + ? WHERE char_id = ? AND item_id = ? LIMIT 1",
+    )
+    .bind(count)
+    .bind(char_id)
+    .bind(item_id)
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        let id = next_item_id(pool).await?;
+        let item = ItemInstance { count, ..ItemInstance::new(id as u32, item_id) };
+        insert_item(pool, char_id, &item, item_name).await?;
+    }
+    Ok(())
+}
+
+/// Write an inventory change to a character's items in one transaction.
+/// New rows are named from `templates`. Returns false, writing nothing,
+/// if an item to remove or update is no longer stored.
+pub async fn apply_changes(
+    pool: &MySqlPool,
+    char_id: i32,
+    changes: &InventoryChanges,
+    templates: &HashMap<i32, ItemTemplate>,
+) -> Result<bool> {
+    apply_changes_for(pool, &[(char_id, changes)], templates).await
+}
+
+/// `apply_changes` for several characters at once, all or nothing.
+/// Every removal and update is written before any new row, so an item
+/// handed from one character to another can keep its object ID.
+pub async fn apply_changes_for(
+    pool: &MySqlPool,
+    changes: &[(i32, &InventoryChanges)],
+    templates: &HashMap<i32, ItemTemplate>,
+) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+    for &(char_id, changes) in changes {
+        for &object_id in &changes.removed {
+            let result = sqlx::query("DELETE FROM character_items WHERE id = ? AND char_id = ?")
+                .bind(object_id as i32)
+                .bind(char_id)
+                .execute(&mut *tx)
+                .await?;
+            if result.rows_affected() == 0 {
+                return Ok(false);
+            }
+        }
+        for item in &changes.updated {
+            let result = sqlx::query(
+                "UPDATE character_items SET count = ?, enchantlvl = ?, bless = ?, is_id = ? WHERE id = ? AND char_id = ?",
+            )
+                .bind(item.count)
+                .bind(item.enchant_level)
+                .bind(item.bless)
+                .bind(item.is_identified as i32)
+                .bind(item.object_id as i32)
+                .bind(char_id)
+                .execute(&mut *tx)
+                .await?;
+            if result.rows_affected() == 0 {
+                return Ok(false);
+            }
+        }
+    }
+    for &(char_id, changes) in changes {
+        for item in &changes.added {
+            let name = templates.get(&item.item_id).map_or("", |t| t.name.as_str());
+            insert_item(&mut *tx, char_id, item, name).await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(true)
+}
+
+type NamedItemRow = (i32, i32, i32, i32, i32, Option<i64>, i32, i32, String);
+
+/// Delete a character's items whose expiry (unix seconds) has passed
+/// and return them with their stored names.
+pub async fn remove_expired(pool: &MySqlPool, char_id: i32, now: i64) -> Result<Vec<(ItemInstance, String)>> {
+    let rows: Vec<NamedItemRow> = sqlx::query_as(
+        "SELECT CAST(id AS SIGNED), CAST(item_id AS SIGNED), CAST(count AS SIGNED), \
+         CAST(is_equipped AS SIGNED), CAST(enchantlvl AS SIGNED), expires_at, CAST(bless AS SIGNED), \
+         CAST(is_id AS SIGNED), item_name \
+         FROM character_items WHERE char_id = ? AND expires_at IS NOT NULL AND expires_at <= ?",
+    )
+    .bind(char_id)
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+    let mut expired = Vec::with_capacity(rows.len());
+    for (id, item_id, count, equipped, enchant, expires_at, bless, identified, name) in rows {
+        if delete_item(pool, char_id, id).await? {
+            let row = (id, item_id, count, equipped, enchant, expires_at, bless, identified);
+            expired.push((item_from_row(row), name));
+        }
+    }
+    Ok(expired)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs against a scratch database named by `L1J_TEST_DATABASE_URL`.
+    #[tokio::test]
+    #[ignore = "needs a MySQL database (L1J_TEST_DATABASE_URL)"]
+    async fn test_bless_survives_reload() {
+        let url = std::env::var("L1J_TEST_DATABASE_URL").expect("L1J_TEST_DATABASE_URL");
+        let pool = MySqlPool::connect(&url).await.unwrap();
+        migrate_schema(&pool).await.unwrap();
+        let char_id = 0x7fff_0001;
+        sqlx::query("DELETE FROM character_items WHERE char_id = ?").bind(char_id).execute(&pool).await.unwrap();
+
+        // A cursed item stays cursed across a relog
+        let id = next_item_id(&pool).await.unwrap();
+        let cursed = ItemInstance { bless: 2, ..ItemInstance::new(id as u32, 20) };
+        insert_item(&pool, char_id, &cursed, "test").await.unwrap();
+        let loaded = load_item(&pool, char_id, id).await.unwrap().unwrap();
+        assert_eq!(loaded.bless, 2);
+
+        // Removing the curse is written back
+        let uncursed = ItemInstance { bless: 1, ..loaded };
+        let changes = InventoryChanges { updated: vec![uncursed], ..Default::default() };
+        assert!(apply_changes(&pool, char_id, &changes, &HashMap::new()).await.unwrap());
+        assert_eq!(load_item(&pool, char_id, id).await.unwrap().unwrap().bless, 1);
+
+        sqlx::query("DELETE FROM character_items WHERE char_id = ?").bind(char_id).execute(&pool).await.unwrap();
+    }
+
+    /// Runs against a scratch database named by `L1J_TEST_DATABASE_URL`.
+    #[tokio::test]
+    #[ignore = "needs a MySQL database (L1J_TEST_DATABASE_URL)"]
+    async fn test_identify_survives_reload() {
+        let url = std::env::var("L1J_TEST_DATABASE_URL").expect("L1J_TEST_DATABASE_URL");
+        let pool = MySqlPool::connect(&url).await.unwrap();
+        migrate_schema(&pool).await.unwrap();
+        let char_id = 0x7fff_0002;
+        sqlx::query("DELETE FROM character_items WHERE char_id = ?").bind(char_id).execute(&pool).await.unwrap();
+
+        let id = next_item_id(&pool).await.unwrap();
+        insert_item(&pool, char_id, &ItemInstance::unidentified(id as u32, 20), "test").await.unwrap();
+        let loaded = load_item(&pool, char_id, id).await.unwrap().unwrap();
+        assert!(!loaded.is_identified);
+
+        let identified = ItemInstance { is_identified: true, ..loaded };
+        let changes = InventoryChanges { updated: vec![identified], ..Default::default() };
+        assert!(apply_changes(&pool, char_id, &changes, &HashMap::new()).await.unwrap());
+        assert!(load_item(&pool, char_id, id).await.unwrap().unwrap().is_identified);
+
+        sqlx::query("DELETE FROM character_items WHERE char_id = ?").bind(char_id).execute(&pool).await.unwrap();
+    }
+}
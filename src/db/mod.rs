@@ -1,5 +1,11 @@
 pub mod account;
+pub mod buff;
 pub mod char_create;
+pub mod char_delete;
 pub mod character;
 pub mod clan;
+pub mod inventory;
 pub mod pool;
+pub mod retry;
+pub mod schema;
+pub mod skill;
@@ -1,23 +1,71 @@
-use anyhow::Result;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
 use sqlx::mysql::MySqlPoolOptions;
 use sqlx::MySqlPool;
 
 use crate::config::DatabaseSection;
 
+/// Pool settings from `config`: connection limits and how long a query
+/// may wait for a free connection.
+pub fn pool_options(config: &DatabaseSection) -> MySqlPoolOptions {
+    MySqlPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections.min(config.max_connections))
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+}
+
 /// Create an async MySQL connection pool.
 ///
-/// Uses sqlx with tokio runtime. The pool lazily creates connections
-/// up to `max_connections`.
+/// Uses sqlx with tokio runtime. The pool keeps `min_connections` open
+/// and grows up to `max_connections`. Fails within the acquire timeout
+/// if the database can't be reached.
 pub async fn create_pool(config: &DatabaseSection) -> Result<MySqlPool> {
-    let pool = MySqlPoolOptions::new()
-        .max_connections(config.max_connections)
+    let pool = pool_options(config)
         .connect(&config.url)
-        .await?;
+        .await
+        .context("connecting to the database")?;
+    health_check(&pool).await?;
+    Ok(pool)
+}
 
-    // Verify the connection works
+/// Verify the database answers a trivial query.
+pub async fn health_check(pool: &MySqlPool) -> Result<()> {
     sqlx::query("SELECT 1")
-        .execute(&pool)
-        .await?;
+        .execute(pool)
+        .await
+        .context("database health check")?;
+    Ok(())
+}
 
-    Ok(pool)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(extra: &str) -> DatabaseSection {
+        toml::from_str(&format!("url = \"mysql://root@127.0.0.1:1/l1jtw\"\nmax_connections = 20\n{extra}")).unwrap()
+    }
+
+    #[test]
+    fn test_pool_options_use_config() {
+        let opts = pool_options(&section("min_connections = 4\nacquire_timeout_secs = 3"));
+        assert_eq!(opts.get_max_connections(), 20);
+        assert_eq!(opts.get_min_connections(), 4);
+        assert_eq!(opts.get_acquire_timeout(), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_pool_options_defaults() {
+        let opts = pool_options(&section("min_connections = 50"));
+        // min is clamped to max
+        assert_eq!(opts.get_min_connections(), 20);
+        assert_eq!(opts.get_acquire_timeout(), Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_database_fails_fast() {
+        let started = std::time::Instant::now();
+        assert!(create_pool(&section("acquire_timeout_secs = 2")).await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
 }
@@ -0,0 +1,119 @@
+//! Bounded retry for transient database errors.
+//!
+//! A dropped connection or a deadlock during login shouldn't disconnect
+//! the player, so the login-path queries go through `with_retry`. Only
+//! errors that can succeed on a second try are retried; anything else
+//! (bad SQL, a missing row, a decode error) is returned at once.
+
+use std::future::Future;
+use std::time::Duration;
+
+use sqlx::mysql::MySqlDatabaseError;
+use tracing::warn;
+
+/// MySQL error numbers worth retrying: lock wait timeout and deadlock.
+const RETRYABLE_MYSQL_ERRORS: [u16; 2] = [1205, 1213];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total tries, including the first.
+    pub attempts: u32,
+    /// Wait before the first retry; doubled for each one after.
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub const DEFAULT: RetryPolicy = RetryPolicy { attempts: 3, base_delay: Duration::from_millis(100) };
+}
+
+/// Could `err` go away if the query is simply run again?
+pub fn is_transient(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::WorkerCrashed => true,
+        sqlx::Error::Database(db) => db
+            .try_downcast_ref::<MySqlDatabaseError>()
+            .is_some_and(|e| RETRYABLE_MYSQL_ERRORS.contains(&e.number())),
+        _ => false,
+    }
+}
+
+/// Run `op` with `RetryPolicy::DEFAULT`.
+pub async fn with_retry<T, F, Fut>(op: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    with_retry_policy(RetryPolicy::DEFAULT, op).await
+}
+
+/// Run `op`, retrying transient failures with exponential backoff. `op`
+/// must be safe to run more than once.
+pub async fn with_retry_policy<T, F, Fut>(policy: RetryPolicy, mut op: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut delay = policy.base_delay;
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Err(e) if attempt < policy.attempts && is_transient(&e) => {
+                warn!("Transient DB error (attempt {}/{}), retrying: {}", attempt, policy.attempts, e);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    const FAST: RetryPolicy = RetryPolicy { attempts: 3, base_delay: Duration::from_millis(1) };
+
+    fn io_error() -> sqlx::Error {
+        sqlx::Error::Io(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset"))
+    }
+
+    #[tokio::test]
+    async fn test_transient_failure_retries() {
+        let calls = Cell::new(0);
+        let result = with_retry_policy(FAST, || {
+            calls.set(calls.get() + 1);
+            let n = calls.get();
+            async move { if n < 3 { Err(io_error()) } else { Ok(n) } }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_attempts() {
+        let calls = Cell::new(0);
+        let result: Result<(), _> = with_retry_policy(FAST, || {
+            calls.set(calls.get() + 1);
+            async { Err(sqlx::Error::PoolTimedOut) }
+        })
+        .await;
+        assert!(matches!(result, Err(sqlx::Error::PoolTimedOut)));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_failure_surfaces_immediately() {
+        let calls = Cell::new(0);
+        let result: Result<(), _> = with_retry_policy(FAST, || {
+            calls.set(calls.get() + 1);
+            async { Err(sqlx::Error::RowNotFound) }
+        })
+        .await;
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(calls.get(), 1);
+        assert!(!is_transient(&sqlx::Error::ColumnNotFound("x".into())));
+    }
+}
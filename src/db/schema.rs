@@ -0,0 +1,112 @@
+//! Schema migration helpers.
+//!
+//! MySQL 8 has no `ADD COLUMN IF NOT EXISTS` / `ADD INDEX IF NOT EXISTS`
+//! (only MariaDB does), so each migration looks the column or index up in
+//! `information_schema` and issues a plain ALTER only when it's missing.
+//! Steps are independent: `Migration` logs a failed step and carries on,
+//! so one bad ALTER can't skip the ones after it.
+
+use anyhow::{bail, Result};
+use sqlx::MySqlPool;
+use tracing::warn;
+
+/// Does `table.column` exist in the current database?
+pub async fn column_exists(pool: &MySqlPool, table: &str, column: &str) -> Result<bool> {
+    let found: Option<(i32,)> = sqlx::query_as(
+        "SELECT 1 FROM information_schema.COLUMNS \
+         WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? AND COLUMN_NAME = ? LIMIT 1",
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_optional(pool)
+    .await?;
+    Ok(found.is_some())
+}
+
+/// Declared character length of `table.column`, if it exists and is a
+/// string column.
+pub async fn column_length(pool: &MySqlPool, table: &str, column: &str) -> Result<Option<i64>> {
+    let row: Option<(Option<i64>,)> = sqlx::query_as(
+        "SELECT CAST(CHARACTER_MAXIMUM_LENGTH AS SIGNED) FROM information_schema.COLUMNS \
+         WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? AND COLUMN_NAME = ? LIMIT 1",
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.and_then(|(len,)| len))
+}
+
+/// Does `table` have an index named `index`?
+pub async fn index_exists(pool: &MySqlPool, table: &str, index: &str) -> Result<bool> {
+    let found: Option<(i32,)> = sqlx::query_as(
+        "SELECT 1 FROM information_schema.STATISTICS \
+         WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? AND INDEX_NAME = ? LIMIT 1",
+    )
+    .bind(table)
+    .bind(index)
+    .fetch_optional(pool)
+    .await?;
+    Ok(found.is_some())
+}
+
+/// Add `column` to `table` unless it's already there. Returns whether
+/// it was added.
+pub async fn add_column(pool: &MySqlPool, table: &str, column: &str, definition: &str) -> Result<bool> {
+    if column_exists(pool, table, column).await? {
+        return Ok(false);
+    }
+    sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+        .execute(pool)
+        .await?;
+    Ok(true)
+}
+
+/// Collects the outcome of independent migration steps.
+#[derive(Debug)]
+pub struct Migration {
+    name: &'static str,
+    failed: Vec<String>,
+}
+
+impl Migration {
+    pub fn new(name: &'static str) -> Self {
+        Migration { name, failed: Vec::new() }
+    }
+
+    /// Record one step's result; a failure is logged and kept, and the
+    /// caller moves on to the next step.
+    pub fn step<T, E: Into<anyhow::Error>>(&mut self, what: &str, result: Result<T, E>) {
+        if let Err(e) = result.map_err(Into::into) {
+            warn!("{} schema: {} failed: {:#}", self.name, what, e);
+            self.failed.push(what.to_string());
+        }
+    }
+
+    /// Ok if every step succeeded, otherwise an error naming the failures.
+    pub fn finish(self) -> Result<()> {
+        if self.failed.is_empty() {
+            Ok(())
+        } else {
+            bail!("{} step(s) failed: {}", self.failed.len(), self.failed.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finish_reports_failed_steps() {
+        let mut m = Migration::new("test");
+        for (what, ok) in [("create table", true), ("add column", false), ("add index", true)] {
+            let result: Result<()> = if ok { Ok(()) } else { Err(anyhow::anyhow!("syntax error")) };
+            m.step(what, result);
+        }
+        let err = m.finish().unwrap_err().to_string();
+        assert_eq!(err, "1 step(s) failed: add column");
+
+        assert!(Migration::new("test").finish().is_ok());
+    }
+}
@@ -0,0 +1,49 @@
+//! Learned skill DB operations (character_skills table).
+
+use anyhow::Result;
+use sqlx::{MySqlExecutor, MySqlPool};
+
+/// Record a learned skill for a character.
+pub async fn add_skill<'e>(pool: impl MySqlExecutor<'e>, char_id: i32, skill_id: i32, skill_name: &str) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO character_skills SET char_obj_id=?, skill_id=?, skill_name=?, \
+         is_active=0, activetimeleft=0",
+    )
+    .bind(char_id)
+    .bind(skill_id)
+    .bind(skill_name)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Buy a skill: take `price` of `adena_id` and record the skill in one
+/// transaction. Returns false (and changes nothing) if the character
+/// can't pay.
+pub async fn buy_skill(
+    pool: &MySqlPool,
+    char_id: i32,
+    skill_id: i32,
+    skill_name: &str,
+    adena_id: i32,
+    price: i32,
+) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+    if !crate::db::inventory::consume_item(&mut tx, char_id, adena_id, price).await? {
+        return Ok(false);
+    }
+    add_skill(&mut *tx, char_id, skill_id, skill_name).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Load the skill IDs a character has learned.
+pub async fn load_skills(pool: &MySqlPool, char_id: i32) -> Result<Vec<i32>> {
+    let rows: Vec<(i32,)> = sqlx::query_as(
+        "SELECT skill_id FROM character_skills WHERE char_obj_id = ?",
+    )
+    .bind(char_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
@@ -0,0 +1,195 @@
+//! Generational arena for world entities.
+//!
+//! Entities live in a dense `Vec` of slots so the tick walks contiguous
+//! memory instead of hashing every key. Freed slots are reused; each reuse
+//! bumps the slot's generation, so a stale `Handle` never resolves to the
+//! entity that took its place. Lookups by `ObjectId` (what packets and
+//! other systems refer to) go through a side index.
+
+use std::collections::HashMap;
+use std::ops::Index;
+
+use crate::world::grid::ObjectId;
+
+/// Index into an `Arena`, valid until that entity is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    slot: u32,
+    generation: u32,
+}
+
+#[derive(Debug)]
+struct Slot<T> {
+    generation: u32,
+    entry: Option<(ObjectId, T)>,
+}
+
+/// Slot storage keyed by generational `Handle`, with `ObjectId` lookup.
+#[derive(Debug)]
+pub struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    by_id: HashMap<ObjectId, Handle>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Arena { slots: Vec::new(), free: Vec::new(), by_id: HashMap::new() }
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let grow = additional.saturating_sub(self.free.len());
+        self.slots.reserve(grow);
+        self.by_id.reserve(additional);
+    }
+
+    /// Store `value` under `id`, replacing any entity that already had it.
+    pub fn insert(&mut self, id: ObjectId, value: T) -> Handle {
+        self.remove(id);
+        let handle = match self.free.pop() {
+            Some(slot) => {
+                let s = &mut self.slots[slot as usize];
+                s.entry = Some((id, value));
+                Handle { slot, generation: s.generation }
+            }
+            None => {
+                self.slots.push(Slot { generation: 0, entry: Some((id, value)) });
+                Handle { slot: self.slots.len() as u32 - 1, generation: 0 }
+            }
+        };
+        self.by_id.insert(id, handle);
+        handle
+    }
+
+    /// Remove the entity with `id`, freeing its slot for reuse.
+    pub fn remove(&mut self, id: ObjectId) -> Option<T> {
+        let handle = self.by_id.remove(&id)?;
+        let slot = &mut self.slots[handle.slot as usize];
+        let (_, value) = slot.entry.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.slot);
+        Some(value)
+    }
+
+    pub fn handle_of(&self, id: ObjectId) -> Option<Handle> {
+        self.by_id.get(&id).copied()
+    }
+
+    pub fn get(&self, id: ObjectId) -> Option<&T> {
+        self.resolve(self.handle_of(id)?)
+    }
+
+    pub fn get_mut(&mut self, id: ObjectId) -> Option<&mut T> {
+        let handle = self.handle_of(id)?;
+        self.resolve_mut(handle)
+    }
+
+    /// The entity behind `handle`, unless it has since been removed.
+    pub fn resolve(&self, handle: Handle) -> Option<&T> {
+        let slot = self.slots.get(handle.slot as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        slot.entry.as_ref().map(|(_, v)| v)
+    }
+
+    pub fn resolve_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        let slot = self.slots.get_mut(handle.slot as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        slot.entry.as_mut().map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, id: ObjectId) -> bool {
+        self.by_id.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_id.is_empty()
+    }
+
+    /// Number of slots, occupied or free.
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Handles of the stored entities, in slot order.
+    pub fn handles(&self) -> impl Iterator<Item = Handle> + '_ {
+        self.slots.iter().enumerate().filter(|(_, s)| s.entry.is_some()).map(|(slot, s)| Handle {
+            slot: slot as u32,
+            generation: s.generation,
+        })
+    }
+
+    /// Entities in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (ObjectId, &T)> {
+        self.slots.iter().filter_map(|s| s.entry.as_ref().map(|(id, v)| (*id, v)))
+    }
+}
+
+impl<T> Index<&ObjectId> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id: &ObjectId) -> &T {
+        self.get(*id).expect("no entity with this object id")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut arena = Arena::new();
+        arena.insert(100, "orc");
+        arena.insert(101, "goblin");
+        assert_eq!(arena.len(), 2);
+        assert_eq!(arena[&100], "orc");
+
+        assert_eq!(arena.remove(100), Some("orc"));
+        assert_eq!(arena.remove(100), None);
+        assert!(arena.get(100).is_none());
+        assert!(!arena.contains_key(100));
+        assert_eq!(arena.iter().collect::<Vec<_>>(), vec![(101, &"goblin")]);
+        let handles: Vec<_> = arena.handles().collect();
+        assert_eq!(handles, vec![arena.handle_of(101).unwrap()]);
+    }
+
+    #[test]
+    fn test_reused_slot_rejects_stale_handle() {
+        let mut arena = Arena::new();
+        let old = arena.insert(100, "orc");
+        arena.remove(100);
+
+        // The next spawn takes the freed slot
+        let new = arena.insert(200, "wolf");
+        assert_eq!(arena.slot_count(), 1);
+        assert_ne!(old, new);
+        assert!(arena.resolve(old).is_none());
+        assert!(arena.resolve_mut(old).is_none());
+        assert_eq!(arena.resolve(new), Some(&"wolf"));
+        assert!(arena.get(100).is_none());
+    }
+
+    #[test]
+    fn test_reinsert_same_id_replaces() {
+        let mut arena = Arena::new();
+        let first = arena.insert(100, 1);
+        arena.insert(100, 2);
+        assert_eq!(arena.len(), 1);
+        assert_eq!(arena[&100], 2);
+        assert!(arena.resolve(first).is_none());
+    }
+}
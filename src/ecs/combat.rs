@@ -5,6 +5,11 @@
 
 use rand::{Rng, RngExt};
 
+use crate::ecs::components::npc::NpcTemplate;
+use crate::ecs::components::position::{direction_from_delta, heading_delta, Position};
+use crate::protocol::server::movement::build_move_char;
+use crate::world::map_data::MapTileData;
+
 /// Attack types for calculation branching.
 #[derive(Debug, Clone, Copy)]
 pub enum AttackType {
@@ -14,8 +19,195 @@ pub enum AttackType {
     NpcVsNpc,
 }
 
+/// Weapon families that change how an attack resolves.
+///
+/// `from_item_type` maps the weapon table's `type` column
+/// (Java L1ItemTemplate weapon types).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WeaponType {
+    /// Bare hands.
+    #[default]
+    None,
+    Sword,
+    Dagger,
+    Bow,
+    Spear,
+    /// Maces and other crushing weapons, one- or two-handed.
+    Blunt,
+    Staff,
+    Other,
+}
+
+impl WeaponType {
+    pub fn from_item_type(item_type: i32) -> Self {
+        match item_type {
+            0 => WeaponType::None,
+            1 | 3 => WeaponType::Sword,   // sword, two-hand sword
+            2 => WeaponType::Dagger,
+            4 | 13 => WeaponType::Bow,    // bow, single bow
+            5 | 14 => WeaponType::Spear,  // spear, single spear
+            6 | 15 => WeaponType::Blunt,  // blunt, two-hand blunt
+            7 | 16 => WeaponType::Staff,  // staff, two-hand staff
+            _ => WeaponType::Other,
+        }
+    }
+
+    /// Melee reach in tiles; spears strike from 2 tiles away.
+    /// Ranged weapons use their own range instead.
+    pub fn melee_reach(self) -> i32 {
+        match self {
+            WeaponType::Spear => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// Weapon attribute (element), using the skill table's `attr` bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Element {
+    #[default]
+    None,
+    Earth,
+    Fire,
+    Water,
+    Wind,
+}
+
+impl Element {
+    /// 0 = none, 1 = earth, 2 = fire, 4 = water, 8 = wind.
+    pub fn from_attr(attr: i32) -> Self {
+        match attr {
+            1 => Element::Earth,
+            2 => Element::Fire,
+            4 => Element::Water,
+            8 => Element::Wind,
+            _ => Element::None,
+        }
+    }
+}
+
+/// Which of the attacker's pools a drain refills.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeechPool {
+    Hp,
+    Mp,
+}
+
+/// Drain on a weapon or skill: the attacker regains `pct`% of the
+/// effective damage into `pool`, whose current/max values are `cur`/`max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Leech {
+    pub pool: LeechPool,
+    pub pct: i32,
+    pub cur: i32,
+    pub max: i32,
+}
+
+/// Amount regained by draining `pct`% of `effective_damage`, capped at
+/// the room left between `cur` and `max`.
+pub fn leech_gain(effective_damage: i32, pct: i32, cur: i32, max: i32) -> i32 {
+    let drained = effective_damage.max(0) * pct.max(0) / 100;
+    drained.min((max - cur).max(0))
+}
+
+/// Can a melee attack with `weapon` land on a target `distance` tiles away?
+pub fn in_melee_reach(weapon: WeaponType, distance: i32) -> bool {
+    (1..=weapon.melee_reach()).contains(&distance)
+}
+
+/// A hit taking at least this percent of the target's max HP knocks it
+/// back a tile.
+pub const KNOCKBACK_DAMAGE_PCT: i32 = 25;
+
+/// Is `effective_damage` a heavy enough hit to knock its target back?
+pub fn is_heavy_hit(effective_damage: i32, target_max_hp: i32) -> bool {
+    target_max_hp > 0 && effective_damage * 100 >= target_max_hp * KNOCKBACK_DAMAGE_PCT
+}
+
+/// A target pushed one tile straight away from its attacker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Knockback {
+    pub x: i32,
+    pub y: i32,
+    /// Direction of the push (away from the attacker).
+    pub heading: i32,
+}
+
+impl Knockback {
+    /// Where `target` lands when knocked back by `attacker`, or None if
+    /// that tile is not walkable (a wall stops the push) or the two share
+    /// a tile so there is no direction to push in.
+    pub fn resolve(attacker: &Position, target: &Position, map: &MapTileData) -> Option<Knockback> {
+        let dx = (target.x - attacker.x).signum();
+        let dy = (target.y - attacker.y).signum();
+        if dx == 0 && dy == 0 {
+            return None;
+        }
+        let heading = direction_from_delta(dx, dy);
+        let (sx, sy) = heading_delta(heading);
+        let (x, y) = (target.x + sx, target.y + sy);
+        map.is_passable(x, y).then_some(Knockback { x, y, heading })
+    }
+
+    /// S_MOVECHARPACKET sliding `object_id` onto the knockback tile, for
+    /// broadcast to everyone who can see it.
+    pub fn packet(&self, object_id: i32) -> Vec<u8> {
+        build_move_char(object_id, self.x, self.y, self.heading)
+    }
+}
+
+/// Status effect an NPC's hit can leave on its target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusRider {
+    /// Damage over time.
+    Poison,
+    /// Silence poison: no spellcasting.
+    Silence,
+    Paralysis,
+}
+
+/// Percent chance that a landed NPC hit carries its special attack
+/// (Java L1Attack.addNpcPoisonAttack).
+pub const NPC_SPECIAL_ATTACK_CHANCE: i32 = 15;
+
+/// An NPC template's special-attack flags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NpcSpecialAttack {
+    /// 1 = damage poison, 2 = silence poison, 4 = paralysis poison.
+    pub poison_atk: i32,
+    /// Non-zero: hits can paralyze.
+    pub paralysis_atk: i32,
+}
+
+impl NpcSpecialAttack {
+    pub const NONE: NpcSpecialAttack = NpcSpecialAttack { poison_atk: 0, paralysis_atk: 0 };
+
+    pub fn of(template: &NpcTemplate) -> Self {
+        NpcSpecialAttack {
+            poison_atk: template.poison_atk,
+            paralysis_atk: template.paralysis_atk,
+        }
+    }
+
+    /// The effect these flags inflict, before the chance roll.
+    pub fn rider(&self) -> Option<StatusRider> {
+        match self.poison_atk {
+            1 => Some(StatusRider::Poison),
+            2 => Some(StatusRider::Silence),
+            4 => Some(StatusRider::Paralysis),
+            _ if self.paralysis_atk != 0 => Some(StatusRider::Paralysis),
+            _ => None,
+        }
+    }
+
+    fn roll(&self, rng: &mut impl Rng) -> Option<StatusRider> {
+        let rider = self.rider()?;
+        (rng.random_range(1..=100) <= NPC_SPECIAL_ATTACK_CHANCE).then_some(rider)
+    }
+}
+
 /// Attacker stats needed for combat calculation.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct AttackerStats {
     pub level: i32,
     pub str_stat: i32,
@@ -25,10 +217,16 @@ pub struct AttackerStats {
     pub weapon_max_damage: i32,
     pub weapon_enchant: i32,
     pub is_ranged: bool,
+    pub weapon_type: WeaponType,
+    /// Attribute-enchanted weapons deal `element_damage` extra of this element.
+    pub weapon_element: Element,
+    pub element_damage: i32,
+    /// HP/MP drain from the weapon, if any.
+    pub leech: Option<Leech>,
 }
 
 /// Defender stats needed for combat calculation.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct DefenderStats {
     pub level: i32,
     pub ac: i32,
@@ -37,6 +235,29 @@ pub struct DefenderStats {
     pub damage_reduction: i32,
     pub cur_hp: i32,
     pub max_hp: i32,
+    /// Undead take extra damage from blunt weapons.
+    pub undead: bool,
+    /// Elemental resistances in percent; negative means a weakness.
+    pub fire_resist: i32,
+    pub water_resist: i32,
+    pub wind_resist: i32,
+    pub earth_resist: i32,
+    /// Percent of melee damage bounced back to the attacker by a reflect
+    /// buff (see `skill_executor::calc_reflect_pct`).
+    pub reflect_pct: i32,
+}
+
+impl DefenderStats {
+    /// Resistance against `element` (0 for neutral attacks).
+    pub fn resist(&self, element: Element) -> i32 {
+        match element {
+            Element::None => 0,
+            Element::Earth => self.earth_resist,
+            Element::Fire => self.fire_resist,
+            Element::Water => self.water_resist,
+            Element::Wind => self.wind_resist,
+        }
+    }
 }
 
 /// Result of a single attack calculation.
@@ -45,6 +266,91 @@ pub struct AttackResult {
     pub hit: bool,
     pub damage: i32,
     pub is_critical: bool,
+    /// Damage components; only filled by `calculate_attack_detailed`.
+    pub breakdown: Option<DamageBreakdown>,
+    /// Armor absorbed the whole hit; `damage` is only the 1-damage minimum.
+    pub was_absorbed: bool,
+    /// Damage the target can actually lose: `damage` capped at its
+    /// remaining HP. Drain effects are based on this, not on overkill.
+    pub effective_damage: i32,
+    /// Amount to restore to the attacker's `leech` pool (already capped).
+    pub leeched: i32,
+    /// Damage the caller applies to the attacker from a reflect buff.
+    pub reflected_damage: i32,
+    /// Status effect the world layer applies to the target (NPC hits only).
+    pub status: Option<StatusRider>,
+    /// Heavy hit: the world layer pushes the target back (see `Knockback`).
+    pub knockback: bool,
+}
+
+impl AttackResult {
+    fn miss() -> Self {
+        AttackResult {
+            hit: false,
+            damage: 0,
+            is_critical: false,
+            breakdown: None,
+            was_absorbed: false,
+            effective_damage: 0,
+            leeched: 0,
+            reflected_damage: 0,
+            status: None,
+            knockback: false,
+        }
+    }
+
+    /// Damage dealt beyond the target's remaining HP.
+    pub fn overkill(&self) -> i32 {
+        self.damage - self.effective_damage
+    }
+}
+
+/// Damage bounced back by a reflect buff. Only melee reflects.
+fn reflected_damage(effective_damage: i32, is_ranged: bool, reflect_pct: i32) -> i32 {
+    if is_ranged {
+        return 0;
+    }
+    effective_damage * reflect_pct.clamp(0, 100) / 100
+}
+
+/// Damage a target with `cur_hp` left can actually lose.
+fn effective_damage(damage: i32, cur_hp: i32) -> i32 {
+    damage.min(cur_hp.max(0))
+}
+
+/// Every contribution to one hit's damage, for balance debugging.
+///
+/// The fields sum to the final damage (see `total`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DamageBreakdown {
+    pub weapon_roll: i32,
+    pub dmg_modifier: i32,
+    pub stat_bonus: i32,
+    pub enchant_bonus: i32,
+    /// Extra damage from a critical (the doubled half), 0 otherwise.
+    pub critical_bonus: i32,
+    /// Weapon-type rules (e.g. blunt vs undead).
+    pub weapon_type_bonus: i32,
+    /// Elemental weapon damage left after the defender's resistance.
+    pub element_bonus: i32,
+    /// Armor reduction, as a negative number.
+    pub reduction: i32,
+    /// Damage added to reach the 1-damage minimum.
+    pub minimum_adjust: i32,
+}
+
+impl DamageBreakdown {
+    pub fn total(&self) -> i32 {
+        self.weapon_roll
+            + self.dmg_modifier
+            + self.stat_bonus
+            + self.enchant_bonus
+            + self.critical_bonus
+            + self.weapon_type_bonus
+            + self.element_bonus
+            + self.reduction
+            + self.minimum_adjust
+    }
 }
 
 /// Calculate a melee/ranged attack.
@@ -56,6 +362,25 @@ pub fn calculate_attack(
     attacker: &AttackerStats,
     defender: &DefenderStats,
     attack_type: AttackType,
+) -> AttackResult {
+    resolve_attack(attacker, defender, attack_type, false)
+}
+
+/// Same as `calculate_attack`, but also returns the damage breakdown
+/// (for GM inspection and balance work, not the hot path).
+pub fn calculate_attack_detailed(
+    attacker: &AttackerStats,
+    defender: &DefenderStats,
+    attack_type: AttackType,
+) -> AttackResult {
+    resolve_attack(attacker, defender, attack_type, true)
+}
+
+fn resolve_attack(
+    attacker: &AttackerStats,
+    defender: &DefenderStats,
+    attack_type: AttackType,
+    with_breakdown: bool,
 ) -> AttackResult {
     let mut rng = rand::rng();
 
@@ -63,46 +388,63 @@ pub fn calculate_attack(
     let hit = calc_hit(&mut rng, attacker, defender, attack_type);
 
     if !hit {
-        return AttackResult {
-            hit: false,
-            damage: 0,
-            is_critical: false,
-        };
+        return AttackResult::miss();
     }
 
     // Damage calculation
-    let (damage, is_critical) = calc_damage(&mut rng, attacker, defender, attack_type);
+    let breakdown = calc_damage(&mut rng, attacker, defender, attack_type);
 
+    let damage = breakdown.total();
+    let effective_damage = effective_damage(damage, defender.cur_hp);
     AttackResult {
         hit: true,
-        damage: damage.max(0),
-        is_critical,
+        damage,
+        is_critical: breakdown.critical_bonus != 0,
+        breakdown: with_breakdown.then_some(breakdown),
+        was_absorbed: breakdown.minimum_adjust > 0,
+        effective_damage,
+        leeched: attacker.leech
+            .map_or(0, |l| leech_gain(effective_damage, l.pct, l.cur, l.max)),
+        reflected_damage: reflected_damage(effective_damage, attacker.is_ranged, defender.reflect_pct),
+        status: None,
+        knockback: is_heavy_hit(effective_damage, defender.max_hp),
     }
 }
 
-/// Hit roll calculation.
-fn calc_hit(
-    rng: &mut impl Rng,
+/// Probability (0.0..=1.0) that an attack hits.
+///
+/// The hit roll is d20 + hit_modifier + (STR or DEX bonus) + level/2
+/// against a dodge of 10 - AC + DEX/3, so the chance is the share of the
+/// 20 faces that reach the dodge. Pure, so tools can display it.
+pub fn hit_chance(
     attacker: &AttackerStats,
     defender: &DefenderStats,
     _attack_type: AttackType,
-) -> bool {
-    // Attacker roll: d20 + hit_modifier + (STR or DEX bonus) + level/2
+) -> f32 {
     let stat_bonus = if attacker.is_ranged {
         (attacker.dex_stat - 10) / 2
     } else {
         (attacker.str_stat - 10) / 2
     };
-
-    let attacker_roll = rng.random_range(1..=20)
-        + attacker.hit_modifier
-        + stat_bonus
-        + attacker.level / 2;
+    let attack_bonus = attacker.hit_modifier + stat_bonus + attacker.level / 2;
 
     // Defender dodge: 10 + AC + DEX bonus
     let defender_dodge = 10 - defender.ac + (defender.dex_stat - 10) / 3;
 
-    attacker_roll >= defender_dodge
+    // Lowest d20 face that still hits
+    let needed = defender_dodge - attack_bonus;
+    let faces = (21 - needed).clamp(0, 20);
+    faces as f32 / 20.0
+}
+
+/// Hit roll calculation.
+fn calc_hit(
+    rng: &mut impl Rng,
+    attacker: &AttackerStats,
+    defender: &DefenderStats,
+    attack_type: AttackType,
+) -> bool {
+    rng.random::<f32>() < hit_chance(attacker, defender, attack_type)
 }
 
 /// Damage calculation.
@@ -111,7 +453,7 @@ fn calc_damage(
     attacker: &AttackerStats,
     defender: &DefenderStats,
     _attack_type: AttackType,
-) -> (i32, bool) {
+) -> DamageBreakdown {
     // Base weapon damage
     let weapon_damage = if attacker.weapon_max_damage > 0 {
         rng.random_range(1..=attacker.weapon_max_damage)
@@ -131,27 +473,52 @@ fn calc_damage(
 
     // Critical hit (5% chance, double damage)
     let is_critical = rng.random_range(1..=20) == 20;
-    let crit_multiplier = if is_critical { 2 } else { 1 };
 
-    // Total damage
-    let raw_damage = (weapon_damage + attacker.dmg_modifier + stat_bonus + enchant_bonus)
-        * crit_multiplier;
+    let base = weapon_damage + attacker.dmg_modifier + stat_bonus + enchant_bonus;
+    let critical_bonus = if is_critical { base } else { 0 };
+
+    // Blunt weapons crush undead: +half the weapon roll (rounded up)
+    let weapon_type_bonus = if attacker.weapon_type == WeaponType::Blunt && defender.undead {
+        (weapon_damage + 1) / 2
+    } else {
+        0
+    };
+
+    // Elemental damage, scaled by the matching resistance (capped at 100%)
+    let element_bonus = if attacker.weapon_element == Element::None {
+        0
+    } else {
+        let resist = defender.resist(attacker.weapon_element).min(100);
+        attacker.element_damage * (100 - resist) / 100
+    };
 
     // Armor reduction (simplified: higher level = better reduction)
     let reduction = defender.damage_reduction + (defender.ac.abs() / 3);
 
-    let final_damage = (raw_damage - reduction).max(1); // minimum 1 damage
-
-    (final_damage, is_critical)
+    let raw = base + critical_bonus + weapon_type_bonus + element_bonus - reduction;
+    DamageBreakdown {
+        weapon_roll: weapon_damage,
+        dmg_modifier: attacker.dmg_modifier,
+        stat_bonus,
+        enchant_bonus,
+        critical_bonus,
+        weapon_type_bonus,
+        element_bonus,
+        reduction: -reduction,
+        minimum_adjust: (1 - raw).max(0), // minimum 1 damage
+    }
 }
 
 /// Calculate NPC auto-attack damage (NPC vs PC or NPC vs NPC).
 ///
 /// Simplified from Java L1Attack.calcNpcPcDamage():
 ///   damage = random(level) + STR/2 + 1 + modifiers
+///
+/// A hit rolls for the NPC's `special` attack and reports it in `status`.
 pub fn calculate_npc_attack(
     npc_level: i32,
     npc_str: i32,
+    special: NpcSpecialAttack,
     defender: &DefenderStats,
 ) -> AttackResult {
     let mut rng = rand::rng();
@@ -161,11 +528,7 @@ pub fn calculate_npc_attack(
     let dodge = 10 - defender.ac;
 
     if hit_roll < dodge {
-        return AttackResult {
-            hit: false,
-            damage: 0,
-            is_critical: false,
-        };
+        return AttackResult::miss();
     }
 
     // NPC damage formula
@@ -176,10 +539,18 @@ pub fn calculate_npc_attack(
     };
     let damage = base_damage + npc_str / 2 + 1 - defender.damage_reduction;
 
+    let effective_damage = effective_damage(damage.max(1), defender.cur_hp);
     AttackResult {
         hit: true,
         damage: damage.max(1),
         is_critical: false,
+        breakdown: None,
+        was_absorbed: damage < 1,
+        effective_damage,
+        leeched: 0,
+        reflected_damage: reflected_damage(effective_damage, false, defender.reflect_pct),
+        status: special.roll(&mut rng),
+        knockback: is_heavy_hit(effective_damage, defender.max_hp),
     }
 }
 
@@ -192,12 +563,13 @@ mod tests {
         let attacker = AttackerStats {
             level: 1, str_stat: 10, dex_stat: 10,
             hit_modifier: 100, // guaranteed hit
-            dmg_modifier: 0, weapon_max_damage: 1,
-            weapon_enchant: 0, is_ranged: false,
+            weapon_max_damage: 1, weapon_type: WeaponType::Sword,
+            ..Default::default()
         };
         let defender = DefenderStats {
             level: 99, ac: -50, dex_stat: 30, mr: 100,
             damage_reduction: 100, cur_hp: 9999, max_hp: 9999,
+            ..Default::default()
         };
 
         let result = calculate_attack(&attacker, &defender, AttackType::PcVsNpc);
@@ -209,14 +581,13 @@ mod tests {
     #[test]
     fn test_high_stats_attacker() {
         let attacker = AttackerStats {
-            level: 50, str_stat: 30, dex_stat: 20,
-            hit_modifier: 10, dmg_modifier: 15,
-            weapon_max_damage: 20, weapon_enchant: 7,
-            is_ranged: false,
+            level: 50, str_stat: 30, dex_stat: 20, hit_modifier: 10, dmg_modifier: 15,
+            weapon_max_damage: 20, weapon_enchant: 7, weapon_type: WeaponType::Sword,
+            ..Default::default()
         };
         let defender = DefenderStats {
-            level: 10, ac: 5, dex_stat: 10, mr: 0,
-            damage_reduction: 0, cur_hp: 100, max_hp: 100,
+            level: 10, ac: 5, dex_stat: 10, cur_hp: 100, max_hp: 100,
+            ..Default::default()
         };
 
         // Run 100 attacks, most should hit with decent damage
@@ -237,12 +608,377 @@ mod tests {
     #[test]
     fn test_npc_attack() {
         let defender = DefenderStats {
-            level: 10, ac: 0, dex_stat: 12, mr: 0,
-            damage_reduction: 0, cur_hp: 200, max_hp: 200,
+            level: 10, dex_stat: 12, cur_hp: 200, max_hp: 200,
+            ..Default::default()
         };
 
-        let result = calculate_npc_attack(20, 14, &defender);
+        let result = calculate_npc_attack(20, 14, NpcSpecialAttack::NONE, &defender);
         // Just verify it doesn't panic
         assert!(result.damage >= 0 || !result.hit);
     }
+
+    #[test]
+    fn test_poison_npc_sometimes_poisons() {
+        let defender = DefenderStats {
+            level: 10, ac: 10, dex_stat: 12, cur_hp: 200, max_hp: 200,
+            ..Default::default()
+        };
+        let poison = NpcSpecialAttack { poison_atk: 1, paralysis_atk: 0 };
+
+        let results: Vec<_> = (0..1000).map(|_| calculate_npc_attack(20, 14, poison, &defender)).collect();
+        let poisoned = results.iter().filter(|r| r.status == Some(StatusRider::Poison)).count();
+        assert!(poisoned > 0, "a poison NPC should poison some of the time");
+        assert!(poisoned < 500, "poison should be a chance, got {poisoned}/1000");
+        assert!(results.iter().all(|r| r.hit || r.status.is_none()), "misses carry no rider");
+
+        for _ in 0..1000 {
+            let plain = calculate_npc_attack(20, 14, NpcSpecialAttack::NONE, &defender);
+            assert_eq!(plain.status, None);
+        }
+    }
+
+    #[test]
+    fn test_heavy_hit_knocks_back() {
+        let attacker = AttackerStats {
+            level: 50, str_stat: 30, dex_stat: 20, hit_modifier: 100, dmg_modifier: 20,
+            weapon_max_damage: 10, weapon_type: WeaponType::Sword,
+            ..Default::default()
+        };
+        let mut defender = DefenderStats {
+            level: 1, ac: 10, dex_stat: 10, cur_hp: 40, max_hp: 40,
+            ..Default::default()
+        };
+        let result = calculate_attack(&attacker, &defender, AttackType::PcVsNpc);
+        assert!(result.hit && result.knockback);
+
+        defender.cur_hp = 10_000;
+        defender.max_hp = 10_000;
+        assert!(!calculate_attack(&attacker, &defender, AttackType::PcVsNpc).knockback);
+        assert!(is_heavy_hit(25, 100) && !is_heavy_hit(24, 100) && !is_heavy_hit(5, 0));
+    }
+
+    fn open_field_with_wall(wall: (i32, i32)) -> MapTileData {
+        use crate::world::map_data::{v1_flags, MapProperties};
+        let mut tiles = vec![0u8; 100];
+        tiles[((wall.1 - 100) * 10 + (wall.0 - 100)) as usize] = v1_flags::IMPASSABLE;
+        MapTileData::from_v1(4, 100, 100, 10, 10, tiles, MapProperties::default())
+    }
+
+    #[test]
+    fn test_knockback_pushes_away_from_attacker() {
+        let map = open_field_with_wall((109, 109));
+        let target = Position::new(105, 105, 4);
+
+        // Attacker to the west: pushed one tile east
+        let kb = Knockback::resolve(&Position::new(104, 105, 4), &target, &map).unwrap();
+        assert_eq!((kb.x, kb.y, kb.heading), (106, 105, 2));
+        // Attacker two tiles to the northwest (spear): pushed southeast
+        let kb = Knockback::resolve(&Position::new(103, 103, 4), &target, &map).unwrap();
+        assert_eq!((kb.x, kb.y), (106, 106));
+
+        // The broadcast move ends on the new tile
+        let pkt = kb.packet(1234);
+        assert_eq!(pkt, build_move_char(1234, 106, 106, kb.heading));
+    }
+
+    #[test]
+    fn test_knockback_blocked_by_wall() {
+        let map = open_field_with_wall((106, 105));
+        let target = Position::new(105, 105, 4);
+
+        assert_eq!(Knockback::resolve(&Position::new(104, 105, 4), &target, &map), None);
+        // Off the edge of the map counts as a wall too
+        let edge = Position::new(109, 105, 4);
+        assert_eq!(Knockback::resolve(&Position::new(108, 105, 4), &edge, &map), None);
+        // Same tile: no direction to push
+        assert_eq!(Knockback::resolve(&target, &target, &map), None);
+    }
+
+    #[test]
+    fn test_special_attack_flags() {
+        let rider = |poison_atk, paralysis_atk| NpcSpecialAttack { poison_atk, paralysis_atk }.rider();
+        assert_eq!(rider(0, 0), None);
+        assert_eq!(rider(1, 0), Some(StatusRider::Poison));
+        assert_eq!(rider(2, 0), Some(StatusRider::Silence));
+        assert_eq!(rider(4, 0), Some(StatusRider::Paralysis));
+        assert_eq!(rider(0, 1), Some(StatusRider::Paralysis));
+    }
+
+    #[test]
+    fn test_hit_chance_matches_rolls() {
+        let mut attacker = AttackerStats {
+            level: 10, str_stat: 14, dex_stat: 10, hit_modifier: 7, weapon_max_damage: 8,
+            weapon_type: WeaponType::Sword,
+            ..Default::default()
+        };
+        let defender = DefenderStats {
+            level: 10, ac: -5, dex_stat: 13, cur_hp: 100, max_hp: 100,
+            ..Default::default()
+        };
+
+        // d20 + 7 + 2 + 5 vs dodge 16: faces 2..=20 hit
+        assert_eq!(hit_chance(&attacker, &defender, AttackType::PcVsNpc), 0.95);
+        // d20 - 1 + 2 + 5 vs dodge 16: faces 10..=20 hit
+        attacker.hit_modifier = -1;
+        let chance = hit_chance(&attacker, &defender, AttackType::PcVsNpc);
+        assert_eq!(chance, 0.55);
+
+        let mut rng = rand::rng();
+        let n = 100_000;
+        let hits = (0..n)
+            .filter(|_| calc_hit(&mut rng, &attacker, &defender, AttackType::PcVsNpc))
+            .count();
+        let rate = hits as f32 / n as f32;
+        assert!((rate - chance).abs() < 0.01, "empirical {rate} vs {chance}");
+
+        // Clamped at both ends
+        attacker.hit_modifier = 100;
+        assert_eq!(hit_chance(&attacker, &defender, AttackType::PcVsNpc), 1.0);
+        attacker.hit_modifier = -100;
+        assert_eq!(hit_chance(&attacker, &defender, AttackType::PcVsNpc), 0.0);
+    }
+
+    #[test]
+    fn test_breakdown_sums_to_damage() {
+        let attacker = AttackerStats {
+            level: 50, str_stat: 18, dex_stat: 12, hit_modifier: 100, dmg_modifier: 3,
+            weapon_max_damage: 12, weapon_enchant: 5, weapon_type: WeaponType::Sword,
+            ..Default::default()
+        };
+        let mut defender = DefenderStats {
+            level: 10, ac: -9, dex_stat: 10, damage_reduction: 2, cur_hp: 100, max_hp: 100,
+            ..Default::default()
+        };
+
+        let mut crits = 0;
+        for _ in 0..2_000 {
+            let result = calculate_attack_detailed(&attacker, &defender, AttackType::PcVsNpc);
+            let b = result.breakdown.expect("detailed attack records a breakdown");
+            assert_eq!(b.total(), result.damage, "{b:?}");
+            assert_eq!((b.stat_bonus, b.enchant_bonus, b.reduction), (4, 5, -5));
+            assert!((1..=12).contains(&b.weapon_roll));
+            if result.is_critical {
+                crits += 1;
+                assert_eq!(b.critical_bonus, b.weapon_roll + 3 + 4 + 5);
+            }
+        }
+        assert!(crits > 0);
+
+        // Heavy armor: the 1-damage floor shows up as its own entry
+        defender.damage_reduction = 100;
+        let result = calculate_attack_detailed(&attacker, &defender, AttackType::PcVsNpc);
+        let b = result.breakdown.unwrap();
+        assert_eq!(result.damage, 1);
+        assert!(b.minimum_adjust > 0);
+        assert_eq!(b.total(), 1);
+
+        // The plain path skips it
+        assert!(calculate_attack(&attacker, &defender, AttackType::PcVsNpc).breakdown.is_none());
+    }
+
+    #[test]
+    fn test_blunt_bonus_vs_undead() {
+        let mut attacker = AttackerStats {
+            level: 30, str_stat: 10, dex_stat: 10, hit_modifier: 100, weapon_max_damage: 10,
+            weapon_type: WeaponType::from_item_type(6),
+            ..Default::default()
+        };
+        let mut defender = DefenderStats {
+            level: 30, dex_stat: 10, cur_hp: 100, max_hp: 100, undead: true,
+            ..Default::default()
+        };
+        assert_eq!(attacker.weapon_type, WeaponType::Blunt);
+
+        for _ in 0..500 {
+            let b = calculate_attack_detailed(&attacker, &defender, AttackType::PcVsNpc)
+                .breakdown.unwrap();
+            assert_eq!(b.weapon_type_bonus, (b.weapon_roll + 1) / 2);
+            assert!(b.weapon_type_bonus >= 1);
+        }
+
+        // No bonus against the living, or with a sword
+        defender.undead = false;
+        let b = calculate_attack_detailed(&attacker, &defender, AttackType::PcVsNpc).breakdown.unwrap();
+        assert_eq!(b.weapon_type_bonus, 0);
+        defender.undead = true;
+        attacker.weapon_type = WeaponType::Sword;
+        let b = calculate_attack_detailed(&attacker, &defender, AttackType::PcVsNpc).breakdown.unwrap();
+        assert_eq!(b.weapon_type_bonus, 0);
+    }
+
+    #[test]
+    fn test_spear_reach() {
+        let spear = WeaponType::from_item_type(5);
+        assert_eq!(spear, WeaponType::Spear);
+        assert!(in_melee_reach(spear, 1));
+        assert!(in_melee_reach(spear, 2));
+        assert!(!in_melee_reach(spear, 3));
+
+        assert!(in_melee_reach(WeaponType::Sword, 1));
+        assert!(!in_melee_reach(WeaponType::Sword, 2));
+        assert!(!in_melee_reach(WeaponType::Blunt, 0));
+    }
+
+    #[test]
+    fn test_element_resist_reduces_matching_weapon() {
+        let mut attacker = AttackerStats {
+            level: 30, str_stat: 10, dex_stat: 10, hit_modifier: 100, weapon_max_damage: 10,
+            weapon_type: WeaponType::Sword,
+            weapon_element: Element::from_attr(2), element_damage: 20,
+            ..Default::default()
+        };
+        let mut defender = DefenderStats {
+            level: 30, dex_stat: 10, cur_hp: 100, max_hp: 100, fire_resist: 50,
+            ..Default::default()
+        };
+        let element = |a: &AttackerStats, d: &DefenderStats| {
+            calculate_attack_detailed(a, d, AttackType::PcVsNpc).breakdown.unwrap().element_bonus
+        };
+
+        assert_eq!(attacker.weapon_element, Element::Fire);
+        assert_eq!(element(&attacker, &defender), 10);
+
+        // Other resistances don't apply to fire
+        defender.fire_resist = 0;
+        defender.water_resist = 80;
+        assert_eq!(element(&attacker, &defender), 20);
+
+        // Weakness adds, full resistance cancels
+        defender.fire_resist = -50;
+        assert_eq!(element(&attacker, &defender), 30);
+        defender.fire_resist = 150;
+        assert_eq!(element(&attacker, &defender), 0);
+
+        // Neutral weapons ignore resistances
+        attacker.weapon_element = Element::None;
+        defender.fire_resist = 50;
+        assert_eq!(element(&attacker, &defender), 0);
+    }
+
+    #[test]
+    fn test_absorbed_flag_on_heavy_armor() {
+        let attacker = AttackerStats {
+            level: 30, str_stat: 10, dex_stat: 10, hit_modifier: 100, weapon_max_damage: 6,
+            weapon_type: WeaponType::Sword,
+            ..Default::default()
+        };
+        let mut defender = DefenderStats {
+            level: 30, ac: -30, dex_stat: 10, damage_reduction: 50, cur_hp: 100, max_hp: 100,
+            ..Default::default()
+        };
+
+        let result = calculate_attack(&attacker, &defender, AttackType::PcVsNpc);
+        assert!(result.hit && result.was_absorbed);
+        assert_eq!((result.damage, result.effective_damage), (1, 1));
+
+        defender.damage_reduction = 0;
+        defender.ac = 0;
+        let result = calculate_attack(&attacker, &defender, AttackType::PcVsNpc);
+        assert!(!result.was_absorbed);
+    }
+
+    #[test]
+    fn test_effective_damage_caps_overkill() {
+        let attacker = AttackerStats {
+            level: 30, str_stat: 10, dex_stat: 10, hit_modifier: 100, dmg_modifier: 50,
+            weapon_max_damage: 6, weapon_type: WeaponType::Sword,
+            ..Default::default()
+        };
+        let defender = DefenderStats {
+            level: 1, dex_stat: 10, cur_hp: 12, max_hp: 100,
+            ..Default::default()
+        };
+
+        let result = calculate_attack(&attacker, &defender, AttackType::PcVsNpc);
+        assert!(result.damage >= 51);
+        assert_eq!(result.effective_damage, 12);
+        assert_eq!(result.overkill(), result.damage - 12);
+
+        let miss = AttackResult::miss();
+        assert_eq!((miss.effective_damage, miss.overkill()), (0, 0));
+    }
+
+    #[test]
+    fn test_leech_heals_half_of_effective_damage() {
+        assert_eq!(leech_gain(40, 50, 100, 500), 20);
+        assert_eq!(leech_gain(41, 50, 100, 500), 20);
+        // Capped at the room left in the pool
+        assert_eq!(leech_gain(40, 50, 490, 500), 10);
+        assert_eq!(leech_gain(40, 50, 500, 500), 0);
+        assert_eq!(leech_gain(0, 50, 100, 500), 0);
+
+        let mut attacker = AttackerStats {
+            level: 30, str_stat: 10, dex_stat: 10, hit_modifier: 100, dmg_modifier: 40,
+            weapon_max_damage: 1, weapon_type: WeaponType::Sword,
+            leech: Some(Leech { pool: LeechPool::Hp, pct: 50, cur: 100, max: 500 }),
+            ..Default::default()
+        };
+        let mut defender = DefenderStats {
+            level: 1, dex_stat: 10, cur_hp: 500, max_hp: 500,
+            ..Default::default()
+        };
+
+        let r = calculate_attack(&attacker, &defender, AttackType::PcVsNpc);
+        assert_eq!(r.leeched, r.effective_damage / 2);
+
+        // Overkill isn't drained: only the 10 HP the target had left
+        defender.cur_hp = 10;
+        let r = calculate_attack(&attacker, &defender, AttackType::PcVsNpc);
+        assert_eq!((r.effective_damage, r.leeched), (10, 5));
+
+        // Attacker nearly full
+        defender.cur_hp = 500;
+        attacker.leech = Some(Leech { pool: LeechPool::Mp, pct: 50, cur: 97, max: 100 });
+        assert_eq!(calculate_attack(&attacker, &defender, AttackType::PcVsNpc).leeched, 3);
+    }
+
+    #[test]
+    fn test_reflect_only_with_buff_and_melee() {
+        use crate::ecs::components::skill::{skill_ids, SkillEffects};
+        use crate::ecs::skill_executor::calc_reflect_pct;
+
+        let mut attacker = AttackerStats {
+            level: 30, str_stat: 10, dex_stat: 10, hit_modifier: 100, dmg_modifier: 39,
+            weapon_max_damage: 1, weapon_type: WeaponType::Sword,
+            ..Default::default()
+        };
+        let mut effects = SkillEffects::new();
+        let mut defender = DefenderStats {
+            level: 30, dex_stat: 10, cur_hp: 500, max_hp: 500,
+            reflect_pct: calc_reflect_pct(&effects),
+            ..Default::default()
+        };
+
+        // No buff: nothing comes back
+        let r = calculate_attack(&attacker, &defender, AttackType::PcVsPc);
+        assert_eq!(r.reflected_damage, 0);
+
+        effects.add_effect(skill_ids::COUNTER_BARRIER, 100, 0);
+        defender.reflect_pct = calc_reflect_pct(&effects);
+        let r = calculate_attack(&attacker, &defender, AttackType::PcVsPc);
+        assert!(r.hit && r.damage >= 40);
+        assert_eq!(r.reflected_damage, r.effective_damage * defender.reflect_pct / 100);
+        assert!(r.reflected_damage > 0);
+
+        // Ranged attacks are never reflected
+        attacker.is_ranged = true;
+        assert_eq!(calculate_attack(&attacker, &defender, AttackType::PcVsPc).reflected_damage, 0);
+    }
+
+    #[test]
+    fn test_npc_reflect_uses_effective_damage() {
+        // AC 10: every NPC swing hits
+        let mut defender = DefenderStats {
+            level: 10, ac: 10, dex_stat: 10, cur_hp: 500, max_hp: 500, reflect_pct: 100,
+            ..Default::default()
+        };
+        let r = calculate_npc_attack(1, 40, NpcSpecialAttack::NONE, &defender);
+        assert!(r.hit && r.damage == 21);
+        assert_eq!(r.reflected_damage, 21);
+
+        // Only the 5 HP the target had left come back, not the full swing
+        defender.cur_hp = 5;
+        let r = calculate_npc_attack(1, 40, NpcSpecialAttack::NONE, &defender);
+        assert_eq!((r.damage, r.effective_damage, r.reflected_damage), (21, 5, 5));
+    }
 }
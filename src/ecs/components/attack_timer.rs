@@ -0,0 +1,138 @@
+//! Server-side attack rate limit.
+//!
+//! A hacked client can send C_ATTACK with every packet. An attack is only
+//! accepted once the weapon's attack interval has passed since the last
+//! accepted one; rejected attempts don't move the timer, so spamming
+//! doesn't push the next allowed attack back either.
+
+use std::time::{Duration, Instant};
+
+/// Attack interval (ms) used when the wielded weapon's speed is unknown.
+pub const DEFAULT_ATTACK_INTERVAL_MS: u64 = 640;
+
+/// Rejections in a row before the spam is worth a warning.
+pub const SPAM_WARN_STREAK: u32 = 10;
+
+/// Base swing interval (ms) for a wielded weapon, by the weapon table's
+/// `type` (None = bare hands). Two-handers and bows swing slower,
+/// daggers and claws faster.
+pub fn weapon_interval_ms(weapon_type: Option<i32>) -> u64 {
+    match weapon_type {
+        None => DEFAULT_ATTACK_INTERVAL_MS,
+        Some(2 | 8 | 11 | 12) => 560,            // dagger, throwing knife, claw, edoryu
+        Some(3 | 15) => 840,                     // two-handed sword, two-handed blunt
+        Some(4 | 13 | 16) => 760,                // bow, single bow, two-handed staff
+        Some(5 | 7 | 14 | 17) => 720,            // spear, staff, single spear, kiringku
+        Some(_) => DEFAULT_ATTACK_INTERVAL_MS,   // sword, blunt, gauntlet, chainsword
+    }
+}
+
+/// Haste and brave each cut the interval to 3/4 (Java AcceleratorChecker).
+const SPEED_BUFF_NUM: u64 = 3;
+const SPEED_BUFF_DEN: u64 = 4;
+
+/// Interval between attacks for a weapon swinging every `base_ms`.
+pub fn attack_interval(base_ms: u64, haste: bool, brave: bool) -> Duration {
+    let mut ms = base_ms;
+    for buffed in [haste, brave] {
+        if buffed {
+            ms = ms * SPEED_BUFF_NUM / SPEED_BUFF_DEN;
+        }
+    }
+    Duration::from_millis(ms)
+}
+
+/// When the last accepted attack happened.
+#[derive(Debug, Clone, Default)]
+pub struct AttackTimer {
+    last_attack: Option<Instant>,
+    rejected_streak: u32,
+}
+
+impl AttackTimer {
+    pub fn new() -> Self {
+        AttackTimer { last_attack: None, rejected_streak: 0 }
+    }
+
+    /// Attacks rejected since the last accepted one.
+    pub fn rejected_streak(&self) -> u32 {
+        self.rejected_streak
+    }
+
+    /// Accept an attack at `now` if `interval` has passed since the last
+    /// accepted one (the first attack always is).
+    pub fn try_attack(&mut self, now: Instant, interval: Duration) -> bool {
+        if let Some(last) = self.last_attack {
+            if now.saturating_duration_since(last) < interval {
+                self.rejected_streak += 1;
+                return false;
+            }
+        }
+        self.last_attack = Some(now);
+        self.rejected_streak = 0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attacks_within_cooldown_rejected() {
+        let t0 = Instant::now();
+        let interval = attack_interval(DEFAULT_ATTACK_INTERVAL_MS, false, false);
+        let mut timer = AttackTimer::new();
+
+        assert!(timer.try_attack(t0, interval));
+        assert!(!timer.try_attack(t0 + Duration::from_millis(100), interval));
+        assert!(!timer.try_attack(t0 + Duration::from_millis(639), interval));
+        // Spam doesn't delay the next allowed attack
+        assert!(timer.try_attack(t0 + Duration::from_millis(640), interval));
+        assert!(!timer.try_attack(t0 + Duration::from_millis(1000), interval));
+        assert!(timer.try_attack(t0 + Duration::from_millis(1280), interval));
+    }
+
+    #[test]
+    fn test_interval_follows_weapon() {
+        assert_eq!(weapon_interval_ms(None), DEFAULT_ATTACK_INTERVAL_MS);
+        assert!(weapon_interval_ms(Some(2)) < weapon_interval_ms(Some(1)));
+        assert!(weapon_interval_ms(Some(3)) > weapon_interval_ms(Some(1)));
+
+        // A dagger's next swing is allowed where a two-hander's isn't
+        let t0 = Instant::now();
+        let at = t0 + Duration::from_millis(600);
+        let mut dagger = AttackTimer::new();
+        let mut two_hander = AttackTimer::new();
+        assert!(dagger.try_attack(t0, attack_interval(weapon_interval_ms(Some(2)), false, false)));
+        assert!(two_hander.try_attack(t0, attack_interval(weapon_interval_ms(Some(3)), false, false)));
+        assert!(dagger.try_attack(at, attack_interval(weapon_interval_ms(Some(2)), false, false)));
+        assert!(!two_hander.try_attack(at, attack_interval(weapon_interval_ms(Some(3)), false, false)));
+    }
+
+    #[test]
+    fn test_rejected_streak_resets_on_accept() {
+        let t0 = Instant::now();
+        let interval = Duration::from_millis(640);
+        let mut timer = AttackTimer::new();
+        assert!(timer.try_attack(t0, interval));
+        for ms in [10, 20, 30] {
+            assert!(!timer.try_attack(t0 + Duration::from_millis(ms), interval));
+        }
+        assert_eq!(timer.rejected_streak(), 3);
+        assert!(timer.try_attack(t0 + interval, interval));
+        assert_eq!(timer.rejected_streak(), 0);
+    }
+
+    #[test]
+    fn test_speed_buffs_shorten_interval() {
+        assert_eq!(attack_interval(640, true, false), Duration::from_millis(480));
+        assert_eq!(attack_interval(640, true, true), Duration::from_millis(360));
+
+        let t0 = Instant::now();
+        let mut timer = AttackTimer::new();
+        let hasted = attack_interval(640, true, false);
+        assert!(timer.try_attack(t0, hasted));
+        assert!(timer.try_attack(t0 + Duration::from_millis(480), hasted));
+    }
+}
@@ -99,6 +99,76 @@ impl Default for ItemTemplate {
     }
 }
 
+impl ItemTemplate {
+    /// Check the class restriction flags for a class_type (see CharClass).
+    pub fn usable_by(&self, class_type: i32) -> bool {
+        match class_type {
+            0 => self.use_royal,
+            1 => self.use_knight,
+            2 => self.use_elf,
+            3 => self.use_mage,
+            4 => self.use_darkelf,
+            5 => self.use_dragonknight,
+            6 => self.use_illusionist,
+            _ => false,
+        }
+    }
+}
+
+/// Why an equip attempt was rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EquipError {
+    /// No such item in the inventory.
+    NotFound,
+    /// Etc items can't be equipped.
+    NotEquippable,
+    /// The wearer's class can't use this item.
+    WrongClass,
+    /// A cursed item stays on until the curse is removed.
+    Cursed,
+}
+
+impl EquipError {
+    /// Reply shown to the player.
+    pub fn message(self) -> &'static str {
+        match self {
+            EquipError::NotFound => "找不到該物品。",
+            EquipError::NotEquippable => "這個物品無法裝備。",
+            EquipError::WrongClass => "你的職業無法使用此裝備。",
+            EquipError::Cursed => "你無法放開它，似乎受到了詛咒。",
+        }
+    }
+}
+
+/// Bless state stored in `ItemInstance::bless` (L1J: 0 / 1 / 2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlessState {
+    Blessed,
+    Normal,
+    Cursed,
+}
+
+impl BlessState {
+    /// Hit and damage adjustment for a weapon in this state.
+    pub fn combat_modifier(self) -> i32 {
+        match self {
+            BlessState::Blessed => 1,
+            BlessState::Normal => 0,
+            BlessState::Cursed => -1,
+        }
+    }
+}
+
+/// Result of reading an enchant scroll on an item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnchantOutcome {
+    Success { level: i32 },
+    /// Failed; a blessed item survives at its current level.
+    Kept,
+    /// Failed and the item was destroyed.
+    Broken,
+}
+
 /// A single item instance owned by a character or on the ground.
 #[derive(Debug, Clone)]
 pub struct ItemInstance {
@@ -114,9 +184,12 @@ pub struct ItemInstance {
     pub bless: i32,
     pub attr_enchant_kind: i32,  // 1=earth,2=fire,4=water,8=wind
     pub attr_enchant_level: i32,
+    /// Unix time the item disappears (event gear, rentals); `None` = never.
+    pub expires_at: Option<i64>,
 }
 
 impl ItemInstance {
+    /// An identified item (shop stock, crafted, created by the server).
     pub fn new(object_id: u32, item_id: i32) -> Self {
         ItemInstance {
             object_id,
@@ -124,26 +197,62 @@ impl ItemInstance {
             count: 1,
             is_equipped: false,
             enchant_level: 0,
-            is_identified: false,
+            is_identified: true,
             durability: 0,
             charge_count: 0,
             remaining_time: 0,
             bless: 1,
             attr_enchant_kind: 0,
             attr_enchant_level: 0,
+            expires_at: None,
         }
     }
 
+    /// An item that hides its enchant and bless until identified
+    /// (monster drops, treasure boxes).
+    pub fn unidentified(object_id: u32, item_id: i32) -> Self {
+        ItemInstance { is_identified: false, ..Self::new(object_id, item_id) }
+    }
+
+    pub fn bless_state(&self) -> BlessState {
+        match self.bless & 0x7f {
+            0 => BlessState::Blessed,
+            2 => BlessState::Cursed,
+            _ => BlessState::Normal,
+        }
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|t| now >= t)
+    }
+
     /// Get the display name including enchant prefix.
+    ///
+    /// Unidentified items show only the generic template name.
     pub fn get_view_name(&self, template: &ItemTemplate) -> String {
         let base = &template.name;
+        if !self.is_identified {
+            return base.clone();
+        }
         if self.enchant_level > 0 {
             format!("+{} {}", self.enchant_level, base)
+        } else if self.enchant_level < 0 {
+            format!("{} {}", self.enchant_level, base)
         } else {
             base.clone()
         }
     }
 
+    /// Bless byte for packets: hidden (shown as normal) until identified.
+    pub fn view_bless(&self) -> i32 {
+        if self.is_identified { self.bless } else { 1 }
+    }
+
+    /// Enchant level for packets: hidden until identified.
+    pub fn view_enchant(&self) -> i32 {
+        if self.is_identified { self.enchant_level } else { 0 }
+    }
+
     /// Get effective weight (count * unit weight / 1000, min 1).
     pub fn get_weight(&self, template: &ItemTemplate) -> i32 {
         let w = self.count as i64 * template.weight as i64 / 1000;
@@ -151,6 +260,29 @@ impl ItemInstance {
     }
 }
 
+/// Success chance (%) of enchanting an item at `level` past +1.
+pub fn enchant_chance(level: i32, safe_enchant: i32) -> i32 {
+    if level < safe_enchant {
+        100
+    } else {
+        (100 / (level + 1)).max(1)
+    }
+}
+
+/// 鑑定卷軸 (Scroll of Identify).
+pub const IDENTIFY_SCROLL_ID: i32 = 40126;
+
+/// Why an identify attempt failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdentifyError {
+    /// The scroll or target isn't in the inventory.
+    NotFound,
+    /// The used item isn't an identify scroll.
+    NotIdentifyScroll,
+    /// The target is already identified (scroll is kept).
+    AlreadyIdentified,
+}
+
 /// Equipment slot indices matching the L1J client.
 pub mod equip_slot {
     pub const WEAPON: usize = 8;
@@ -168,6 +300,24 @@ pub mod equip_slot {
     pub const RING_RIGHT: usize = 19;
 }
 
+/// Stat bonuses from everything equipped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EquipmentStats {
+    pub ac: i32,
+    pub str_stat: i32,
+    pub dex_stat: i32,
+    pub con_stat: i32,
+    pub int_stat: i32,
+    pub wis_stat: i32,
+    pub cha_stat: i32,
+    pub hp: i32,
+    pub mp: i32,
+    pub sp: i32,
+    pub mr: i32,
+    pub hit: i32,
+    pub dmg: i32,
+}
+
 /// Player inventory.
 #[derive(Debug, Clone)]
 pub struct Inventory {
@@ -214,6 +364,25 @@ impl Inventory {
         }
     }
 
+    /// Remove `count` of `item_id`, drawing from as many stacks as needed.
+    /// Removes nothing and returns false if there isn't enough.
+    pub fn consume_item_id(&mut self, item_id: i32, count: i32) -> bool {
+        if !self.check_item(item_id, count) {
+            return false;
+        }
+        let mut left = count;
+        self.items.retain_mut(|i| {
+            if left == 0 || i.item_id != item_id {
+                return true;
+            }
+            let take = left.min(i.count);
+            i.count -= take;
+            left -= take;
+            i.count > 0
+        });
+        true
+    }
+
     /// Check if inventory contains item_id with at least `count`.
     pub fn check_item(&self, item_id: i32, count: i32) -> bool {
         let total: i32 = self.items.iter()
@@ -242,10 +411,194 @@ impl Inventory {
             .sum()
     }
 
+    /// Equip an item after checking its category and class restrictions.
+    pub fn equip(
+        &mut self,
+        object_id: u32,
+        template: &ItemTemplate,
+        class_type: i32,
+    ) -> Result<(), EquipError> {
+        let item = self.items.iter_mut()
+            .find(|i| i.object_id == object_id)
+            .ok_or(EquipError::NotFound)?;
+        if template.type2 == ItemType2::EtcItem {
+            return Err(EquipError::NotEquippable);
+        }
+        if !template.usable_by(class_type) {
+            return Err(EquipError::WrongClass);
+        }
+        item.is_equipped = true;
+        Ok(())
+    }
+
+    /// Take off an equipped item. Cursed items refuse.
+    pub fn unequip(&mut self, object_id: u32) -> Result<(), EquipError> {
+        let item = self.items.iter_mut()
+            .find(|i| i.object_id == object_id)
+            .ok_or(EquipError::NotFound)?;
+        if item.is_equipped && item.bless_state() == BlessState::Cursed {
+            return Err(EquipError::Cursed);
+        }
+        item.is_equipped = false;
+        Ok(())
+    }
+
+    /// Lift the curse from every cursed item (remove-curse spell or
+    /// scroll). Returns how many changed.
+    pub fn remove_curse(&mut self) -> usize {
+        let mut lifted = 0;
+        for item in self.items.iter_mut().filter(|i| i.bless_state() == BlessState::Cursed) {
+            item.bless = 1;
+            lifted += 1;
+        }
+        lifted
+    }
+
+    /// Read an enchant scroll on `object_id`, with `roll` drawn from 1..=100.
+    ///
+    /// Up to the template's `safe_enchant` always succeeds; beyond it the
+    /// chance falls with each level (`enchant_chance`). On failure a
+    /// blessed item keeps its level, anything else is destroyed.
+    pub fn enchant(&mut self, object_id: u32, template: &ItemTemplate, roll: i32) -> Option<EnchantOutcome> {
+        let pos = self.items.iter().position(|i| i.object_id == object_id)?;
+        let item = &mut self.items[pos];
+        if roll <= enchant_chance(item.enchant_level, template.safe_enchant) {
+            item.enchant_level += 1;
+            return Some(EnchantOutcome::Success { level: item.enchant_level });
+        }
+        if item.bless_state() == BlessState::Blessed {
+            return Some(EnchantOutcome::Kept);
+        }
+        self.items.remove(pos);
+        Some(EnchantOutcome::Broken)
+    }
+
+    /// Mark an item identified. Returns true if it was unidentified.
+    pub fn identify(&mut self, object_id: u32) -> bool {
+        match self.items.iter_mut().find(|i| i.object_id == object_id) {
+            Some(item) if !item.is_identified => {
+                item.is_identified = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Identify every item (NPC appraisal service). Returns how many changed.
+    pub fn identify_all(&mut self) -> usize {
+        let mut changed = 0;
+        for item in self.items.iter_mut().filter(|i| !i.is_identified) {
+            item.is_identified = true;
+            changed += 1;
+        }
+        changed
+    }
+
+    /// Read an identify scroll on a target item, consuming one scroll.
+    pub fn identify_with_scroll(&mut self, scroll_id: u32, target_id: u32) -> Result<(), IdentifyError> {
+        let scroll = self.get_item(scroll_id).ok_or(IdentifyError::NotFound)?;
+        if scroll.item_id != IDENTIFY_SCROLL_ID {
+            return Err(IdentifyError::NotIdentifyScroll);
+        }
+        let target = self.get_item(target_id).ok_or(IdentifyError::NotFound)?;
+        if target.is_identified {
+            return Err(IdentifyError::AlreadyIdentified);
+        }
+        self.identify(target_id);
+        self.remove_item(scroll_id, 1);
+        Ok(())
+    }
+
+    /// Remove every item whose time is up at `now` and return them, so
+    /// the caller can notify the owner. If any was equipped, its bonuses
+    /// are gone: recompute with `equipment_stats`.
+    pub fn sweep_expired(&mut self, now: i64) -> Vec<ItemInstance> {
+        let (expired, kept) = std::mem::take(&mut self.items)
+            .into_iter()
+            .partition(|i| i.is_expired(now));
+        self.items = kept;
+        expired
+    }
+
+    /// Sum the bonuses of equipped items (armor AC plus stat adds).
+    pub fn equipment_stats(&self, templates: &std::collections::HashMap<i32, ItemTemplate>) -> EquipmentStats {
+        let mut total = EquipmentStats::default();
+        for (item, t) in self.items.iter()
+            .filter(|i| i.is_equipped)
+            .filter_map(|i| templates.get(&i.item_id).map(|t| (i, t)))
+        {
+            if t.type2 == ItemType2::Weapon {
+                let bless = item.bless_state().combat_modifier();
+                total.hit += t.hit_modifier + bless;
+                total.dmg += t.dmg_modifier + bless;
+            }
+            total.ac += t.ac;
+            total.str_stat += t.add_str;
+            total.dex_stat += t.add_dex;
+            total.con_stat += t.add_con;
+            total.int_stat += t.add_int;
+            total.wis_stat += t.add_wis;
+            total.cha_stat += t.add_cha;
+            total.hp += t.add_hp;
+            total.mp += t.add_mp;
+            total.sp += t.add_sp;
+            total.mr += t.m_def;
+        }
+        total
+    }
+
     /// Get all currently equipped items.
     pub fn get_equipped(&self) -> Vec<&ItemInstance> {
         self.items.iter().filter(|i| i.is_equipped).collect()
     }
+
+    /// What changed from `before` to this inventory, by object ID.
+    pub fn changes_since(&self, before: &Inventory) -> InventoryChanges {
+        let mut changes = InventoryChanges::default();
+        for old in &before.items {
+            match self.get_item(old.object_id) {
+                None => changes.removed.push(old.object_id),
+                Some(new)
+                    if new.count != old.count
+                        || new.enchant_level != old.enchant_level
+                        || new.bless != old.bless
+                        || new.is_identified != old.is_identified =>
+                {
+                    changes.updated.push(new.clone());
+                }
+                Some(_) => {}
+            }
+        }
+        changes.added = self
+            .items
+            .iter()
+            .filter(|i| before.get_item(i.object_id).is_none())
+            .cloned()
+            .collect();
+        changes
+    }
+}
+
+/// Item rows to write (and show the client) after an inventory change.
+#[derive(Debug, Clone, Default)]
+pub struct InventoryChanges {
+    /// Object IDs no longer held.
+    pub removed: Vec<u32>,
+    /// Items whose count, enchant, bless or identified state changed.
+    pub updated: Vec<ItemInstance>,
+    /// New items.
+    pub added: Vec<ItemInstance>,
+}
+
+impl InventoryChanges {
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.updated.is_empty() && self.added.is_empty()
+    }
+
+    /// Item IDs of the updated and added items (for template lookups).
+    pub fn item_ids(&self) -> Vec<i32> {
+        self.updated.iter().chain(&self.added).map(|i| i.item_id).collect()
+    }
 }
 
 #[cfg(test)]
@@ -290,6 +643,38 @@ mod tests {
         assert_eq!(inv.items.len(), 2);
     }
 
+    #[test]
+    fn test_changes_since() {
+        let t = test_template(40308, true);
+        let mut before = Inventory::new();
+        before.add_item(ItemInstance { count: 10, ..ItemInstance::new(1, 40308) }, &t);
+        before.add_item(ItemInstance::new(2, 20), &test_template(20, false));
+        before.add_item(ItemInstance::new(3, 21), &test_template(21, false));
+
+        let mut after = before.clone();
+        after.remove_item(1, 4);
+        after.remove_item(2, 1);
+        after.add_item(ItemInstance::new(4, 22), &test_template(22, false));
+
+        let changes = after.changes_since(&before);
+        assert_eq!(changes.removed, [2]);
+        assert_eq!(changes.updated.iter().map(|i| (i.object_id, i.count)).collect::<Vec<_>>(), [(1, 6)]);
+        assert_eq!(changes.added.iter().map(|i| i.object_id).collect::<Vec<_>>(), [4]);
+        assert_eq!(changes.item_ids(), [40308, 22]);
+        assert!(after.changes_since(&after).is_empty());
+
+        // A curse lifted is an update too
+        let mut uncursed = after.clone();
+        uncursed.items.iter_mut().find(|i| i.object_id == 3).unwrap().bless = 2;
+        let lifted = after.changes_since(&uncursed);
+        assert_eq!(lifted.updated.iter().map(|i| (i.object_id, i.bless)).collect::<Vec<_>>(), [(3, 1)]);
+
+        // So is an identify
+        let mut unknown = after.clone();
+        unknown.items.iter_mut().find(|i| i.object_id == 4).unwrap().is_identified = false;
+        assert_eq!(after.changes_since(&unknown).updated.iter().map(|i| i.object_id).collect::<Vec<_>>(), [4]);
+    }
+
     #[test]
     fn test_inventory_remove() {
         let mut inv = Inventory::new();
@@ -326,4 +711,126 @@ mod tests {
         item.enchant_level = 7;
         assert_eq!(item.get_view_name(&t), "+7 Item_20");
     }
+
+    #[test]
+    fn test_equip_class_restriction() {
+        let mut inv = Inventory::new();
+        let mut sword = test_template(48, false);
+        sword.type2 = ItemType2::Weapon;
+        sword.use_knight = true;
+        inv.add_item(ItemInstance::new(1, 48), &sword);
+
+        assert_eq!(inv.equip(1, &sword, 3), Err(EquipError::WrongClass));
+        assert!(!inv.items[0].is_equipped);
+        assert_eq!(inv.equip(1, &sword, 1), Ok(()));
+        assert!(inv.items[0].is_equipped);
+
+        let potion = test_template(40010, true);
+        inv.add_item(ItemInstance::new(2, 40010), &potion);
+        assert_eq!(inv.equip(2, &potion, 1), Err(EquipError::NotEquippable));
+        assert_eq!(inv.equip(99, &sword, 1), Err(EquipError::NotFound));
+    }
+
+    #[test]
+    fn test_identify_reveals_attributes() {
+        let mut inv = Inventory::new();
+        let sword = test_template(20, false);
+        let scroll_t = test_template(IDENTIFY_SCROLL_ID, true);
+
+        let mut item = ItemInstance::unidentified(1, 20);
+        item.enchant_level = 5;
+        item.bless = 2; // cursed
+        inv.add_item(item, &sword);
+        let scroll = ItemInstance { count: 2, ..ItemInstance::new(2, IDENTIFY_SCROLL_ID) };
+        inv.add_item(scroll, &scroll_t);
+
+        // Unidentified: generic name, no enchant/bless revealed
+        let it = inv.get_item(1).unwrap();
+        assert_eq!(it.get_view_name(&sword), "Item_20");
+        assert_eq!(it.view_enchant(), 0);
+        assert_eq!(it.view_bless(), 1);
+
+        assert_eq!(inv.identify_with_scroll(1, 2), Err(IdentifyError::NotIdentifyScroll));
+        assert_eq!(inv.identify_with_scroll(2, 1), Ok(()));
+        assert_eq!(inv.get_item(2).unwrap().count, 1);
+
+        let it = inv.get_item(1).unwrap();
+        assert_eq!(it.get_view_name(&sword), "+5 Item_20");
+        assert_eq!(it.view_enchant(), 5);
+        assert_eq!(it.view_bless(), 2);
+
+        // Second read keeps the scroll
+        assert_eq!(inv.identify_with_scroll(2, 1), Err(IdentifyError::AlreadyIdentified));
+        assert_eq!(inv.get_item(2).unwrap().count, 1);
+    }
+
+    #[test]
+    fn test_sweep_expired() {
+        let now = 1_760_000_000;
+        let mut inv = Inventory::new();
+        let mut helm = test_template(20001, false);
+        helm.type2 = ItemType2::Armor;
+        helm.ac = -3;
+        helm.add_str = 1;
+        let templates: std::collections::HashMap<i32, ItemTemplate> =
+            [(20001, helm), (20, test_template(20, false))].into_iter().collect();
+
+        // Rented helm, equipped; a plain sword that never expires
+        inv.items.push(ItemInstance { is_equipped: true, expires_at: Some(now), ..ItemInstance::new(1, 20001) });
+        inv.items.push(ItemInstance::new(2, 20));
+        assert_eq!(inv.equipment_stats(&templates).ac, -3);
+
+        assert!(inv.sweep_expired(now - 1).is_empty());
+        let gone = inv.sweep_expired(now);
+        assert_eq!(gone.len(), 1);
+        assert!(gone[0].is_equipped);
+        assert!(inv.get_item(1).is_none());
+        assert!(inv.get_item(2).is_some());
+        assert_eq!(inv.equipment_stats(&templates), EquipmentStats::default());
+
+        assert!(inv.sweep_expired(now + 1_000_000).is_empty());
+        assert_eq!(inv.items.len(), 1);
+    }
+
+    #[test]
+    fn test_blessed_item_survives_failed_enchant() {
+        let mut sword = test_template(20, false);
+        sword.type2 = ItemType2::Weapon;
+        sword.safe_enchant = 6;
+        let mut inv = Inventory::new();
+        inv.items.push(ItemInstance { enchant_level: 5, bless: 0, ..ItemInstance::new(1, 20) });
+        inv.items.push(ItemInstance { enchant_level: 7, bless: 0, ..ItemInstance::new(2, 20) });
+        inv.items.push(ItemInstance { enchant_level: 7, ..ItemInstance::new(3, 20) });
+
+        // Below the safe level any roll works
+        assert_eq!(inv.enchant(1, &sword, 100), Some(EnchantOutcome::Success { level: 6 }));
+        // +7 -> +8 succeeds on 12 or lower
+        assert_eq!(enchant_chance(7, 6), 12);
+        assert_eq!(inv.enchant(2, &sword, 13), Some(EnchantOutcome::Kept));
+        assert_eq!(inv.get_item(2).unwrap().enchant_level, 7);
+        assert_eq!(inv.enchant(3, &sword, 13), Some(EnchantOutcome::Broken));
+        assert!(inv.get_item(3).is_none());
+        assert_eq!(inv.enchant(99, &sword, 1), None);
+    }
+
+    #[test]
+    fn test_cursed_item_cannot_unequip() {
+        let mut sword = test_template(20, false);
+        sword.type2 = ItemType2::Weapon;
+        sword.use_knight = true;
+        sword.hit_modifier = 2;
+        let templates: std::collections::HashMap<i32, ItemTemplate> = [(20, sword.clone())].into_iter().collect();
+        let mut inv = Inventory::new();
+        inv.add_item(ItemInstance { bless: 2, ..ItemInstance::new(1, 20) }, &sword);
+        assert_eq!(inv.equip(1, &sword, 1), Ok(()));
+        assert_eq!(inv.equipment_stats(&templates).hit, 1);
+
+        assert_eq!(inv.unequip(1), Err(EquipError::Cursed));
+        assert!(inv.items[0].is_equipped);
+
+        assert_eq!(inv.remove_curse(), 1);
+        assert_eq!(inv.equipment_stats(&templates).hit, 2);
+        assert_eq!(inv.unequip(1), Ok(()));
+        assert!(!inv.items[0].is_equipped);
+    }
 }
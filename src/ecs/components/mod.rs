@@ -1,7 +1,10 @@
+pub mod attack_timer;
 pub mod clan;
 pub mod item;
 pub mod movement;
 pub mod npc;
+pub mod party;
+pub mod play_time;
 pub mod position;
 pub mod skill;
 pub mod stats;
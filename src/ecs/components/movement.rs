@@ -28,3 +28,62 @@ impl Movement {
         }
     }
 }
+
+/// Is `seq` later than `last`? Wrap-safe, so a long session never stalls.
+pub fn seq_is_newer(seq: u32, last: u32) -> bool {
+    (seq.wrapping_sub(last) as i32) > 0
+}
+
+/// Server-side movement sequence for one player.
+///
+/// Each accepted step gets the next number. A step must start from where
+/// the server has the player: one claiming any other origin was sent
+/// before an earlier step (or a teleport) landed, or is a replay of a
+/// step already taken, and is rejected.
+#[derive(Debug, Clone, Default)]
+pub struct MoveSequence {
+    last: u32,
+}
+
+impl MoveSequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sequence number for a step from the client-reported `origin`
+    /// while the server has the player at `current`, or `None` if the
+    /// two differ.
+    pub fn accept(&mut self, origin: (i32, i32), current: (i32, i32)) -> Option<u32> {
+        if origin != current {
+            return None;
+        }
+        self.last = self.last.wrapping_add(1);
+        Some(self.last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_out_of_sequence_step_rejected() {
+        let mut seq = MoveSequence::new();
+        assert_eq!(seq.accept((100, 100), (100, 100)), Some(1));
+        // Replaying the step just taken: the player is at (101, 100) now
+        assert_eq!(seq.accept((100, 100), (101, 100)), None);
+        // A step sent from two tiles ahead arrived before the one between
+        assert_eq!(seq.accept((102, 100), (101, 100)), None);
+        assert_eq!(seq.accept((101, 100), (101, 100)), Some(2));
+        // Walking back over the same tile later is fine
+        assert_eq!(seq.accept((100, 100), (100, 100)), Some(3));
+    }
+
+    #[test]
+    fn test_seq_ordering_wraps() {
+        assert!(seq_is_newer(2, 1));
+        assert!(!seq_is_newer(1, 2));
+        assert!(!seq_is_newer(5, 5));
+        assert!(seq_is_newer(0, u32::MAX));
+    }
+}
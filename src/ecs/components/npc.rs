@@ -1,3 +1,5 @@
+use crate::ecs::components::position::tile_distance;
+
 /// NPC template data loaded from the `npc` database table.
 ///
 /// This is the static template data, shared by all instances of the same NPC type.
@@ -52,6 +54,24 @@ pub struct NpcTemplate {
     pub cant_resurrect: bool,
 }
 
+impl NpcTemplate {
+    /// Footprint radius in tiles: a large NPC covers the 3x3 block around
+    /// its position, anything else a single tile.
+    pub fn footprint(&self) -> i32 {
+        if self.size == "large" { 1 } else { 0 }
+    }
+
+    /// Does this NPC fight back when attacked? Monsters and guards do,
+    /// whether or not they are aggressive; shopkeepers and other
+    /// service NPCs never do.
+    pub fn retaliates(&self) -> bool {
+        self.impl_type.contains("Monster") || self.impl_type.contains("Guard")
+    }
+}
+
+/// Default leash: how far (in tiles) an idle NPC may wander from home.
+pub const DEFAULT_LEASH_RANGE: i32 = 20;
+
 /// AI state for a single NPC instance.
 #[derive(Debug, Clone)]
 pub struct AiState {
@@ -71,6 +91,12 @@ pub struct AiState {
     /// Random walk state
     pub random_walk_distance: i32,
     pub random_walk_direction: i32,
+    /// Max tile distance from home before the NPC is forced back (0 = none).
+    pub leash_range: i32,
+    /// Patrol route walked in order and looped (empty = random walk).
+    pub patrol: Vec<(i32, i32)>,
+    /// Index into `patrol` of the waypoint being walked to.
+    pub patrol_index: usize,
 }
 
 impl AiState {
@@ -85,23 +111,133 @@ impl AiState {
             home_y,
             random_walk_distance: 0,
             random_walk_direction: 0,
+            leash_range: DEFAULT_LEASH_RANGE,
+            patrol: Vec::new(),
+            patrol_index: 0,
+        }
+    }
+
+    /// Put the NPC on a patrol route, starting at its first waypoint.
+    pub fn set_patrol(&mut self, waypoints: Vec<(i32, i32)>) {
+        self.patrol = waypoints;
+        self.patrol_index = 0;
+    }
+
+    /// The waypoint to walk to from `(x, y)`. Reaching the current
+    /// waypoint moves on to the next, looping at the end of the route.
+    /// None when there is no route or nowhere to go.
+    pub fn patrol_waypoint(&mut self, x: i32, y: i32) -> Option<(i32, i32)> {
+        if self.patrol.is_empty() {
+            return None;
+        }
+        if self.patrol[self.patrol_index] == (x, y) {
+            self.patrol_index = (self.patrol_index + 1) % self.patrol.len();
+        }
+        Some(self.patrol[self.patrol_index]).filter(|&wp| wp != (x, y))
+    }
+
+    /// Forget the current target (it died, left or got away).
+    pub fn drop_target(&mut self) {
+        let target = self.target_id;
+        self.hate_list.retain(|(id, _)| *id != target);
+        self.target_id = 0;
+    }
+
+    /// Add `amount` hate toward `attacker`. An NPC with no target turns
+    /// on the attacker.
+    pub fn add_hate(&mut self, attacker: u32, amount: i32) {
+        match self.hate_list.iter_mut().find(|(id, _)| *id == attacker) {
+            Some((_, hate)) => *hate += amount,
+            None => self.hate_list.push((attacker, amount)),
+        }
+        if self.target_id == 0 {
+            self.target_id = attacker;
         }
     }
+
+    /// Is `(x, y)` farther from home than the leash allows?
+    pub fn beyond_leash(&self, x: i32, y: i32) -> bool {
+        if self.leash_range <= 0 || self.home_x == 0 || self.home_y == 0 {
+            return false;
+        }
+        tile_distance(self.home_x, self.home_y, x, y) > self.leash_range
+    }
 }
 
-/// Spawn info for an NPC instance (from spawnlist table).
-#[derive(Debug, Clone)]
+/// Per-NPC pseudo-random generator for AI decisions (SplitMix64).
+///
+/// Each NPC owns its own state, seeded from the world seed and its object
+/// ID, so an NPC's choices depend only on its own history and not on how
+/// many other NPCs rolled before it in the tick.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AiRng {
+    state: u64,
+}
+
+impl AiRng {
+    pub fn new(seed: u64) -> Self {
+        AiRng { state: seed }
+    }
+
+    /// Seed for one NPC, derived from the world seed and its object ID.
+    pub fn for_npc(world_seed: u64, object_id: u32) -> Self {
+        let mut mix = AiRng::new(world_seed ^ (((object_id as u64) << 32) | object_id as u64));
+        AiRng::new(mix.next_u64())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `lo..=hi`.
+    pub fn range(&mut self, lo: i32, hi: i32) -> i32 {
+        let span = (hi - lo) as u64 + 1;
+        lo + (((self.next_u64() >> 32) * span) >> 32) as i32
+    }
+}
+
+/// Spawn info for an NPC instance (from spawnlist table, or the
+/// reloadable game data file).
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
 pub struct SpawnInfo {
     pub spawn_id: i32,
     pub npc_template_id: i32,
     pub loc_x: i32,
     pub loc_y: i32,
     pub map_id: i32,
+    #[serde(default)]
     pub heading: i32,
+    #[serde(default)]
     pub randomx: i32,
+    #[serde(default)]
     pub randomy: i32,
     pub min_respawn_delay: i32,
     pub max_respawn_delay: i32,
     pub count: i32,
+    #[serde(default)]
     pub movement_distance: i32,
+    /// Boss spawns stay up even when their region sits empty.
+    #[serde(default)]
+    pub is_boss: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ai_rng_range_and_determinism() {
+        let mut a = AiRng::for_npc(42, 0x10000000);
+        let mut b = AiRng::for_npc(42, 0x10000000);
+        let mut other = AiRng::for_npc(42, 0x10000001);
+        let rolls: Vec<i32> = (0..1000).map(|_| a.range(0, 7)).collect();
+        assert!(rolls.iter().all(|r| (0..=7).contains(r)));
+        assert!((0..=7).all(|v| rolls.contains(&v)));
+        assert_eq!(rolls, (0..1000).map(|_| b.range(0, 7)).collect::<Vec<_>>());
+        assert_ne!(rolls, (0..1000).map(|_| other.range(0, 7)).collect::<Vec<_>>());
+    }
 }
@@ -0,0 +1,81 @@
+//! Party component.
+//!
+//! Ported from Java L1Party.java. A party has one leader and up to
+//! eight members in total; membership is tracked by object ID.
+
+/// Maximum party size, leader included.
+pub const MAX_PARTY_MEMBERS: usize = 8;
+
+/// A player party.
+#[derive(Debug, Clone)]
+pub struct Party {
+    pub leader_id: u32,
+    /// All members, leader first.
+    pub members: Vec<u32>,
+}
+
+impl Party {
+    pub fn new(leader_id: u32) -> Self {
+        Party {
+            leader_id,
+            members: vec![leader_id],
+        }
+    }
+
+    /// Add a member. Returns false if already present or the party is full.
+    pub fn add_member(&mut self, object_id: u32) -> bool {
+        if self.is_member(object_id) || self.is_full() {
+            return false;
+        }
+        self.members.push(object_id);
+        true
+    }
+
+    /// Remove a member. If the leader leaves, the next member leads.
+    pub fn remove_member(&mut self, object_id: u32) {
+        self.members.retain(|&id| id != object_id);
+        if self.leader_id == object_id {
+            if let Some(&next) = self.members.first() {
+                self.leader_id = next;
+            }
+        }
+    }
+
+    pub fn is_member(&self, object_id: u32) -> bool {
+        self.members.contains(&object_id)
+    }
+
+    pub fn is_leader(&self, object_id: u32) -> bool {
+        self.leader_id == object_id
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.members.len() >= MAX_PARTY_MEMBERS
+    }
+
+    /// A party with fewer than two members is dissolved.
+    pub fn is_vacant(&self) -> bool {
+        self.members.len() < 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_party_membership() {
+        let mut party = Party::new(1);
+        assert!(party.add_member(2));
+        assert!(!party.add_member(2));
+        for id in 3..=8 {
+            assert!(party.add_member(id));
+        }
+        assert!(party.is_full());
+        assert!(!party.add_member(9));
+
+        party.remove_member(1);
+        assert!(party.is_leader(2));
+        assert!(!party.is_member(1));
+    }
+}
@@ -0,0 +1,67 @@
+//! Play-time tracking.
+//!
+//! The timer counts whole seconds since the last checkpoint. Each save
+//! (autosave or logout) takes the elapsed seconds and moves the
+//! checkpoint forward, so the same span is never added twice and
+//! sub-second remainders carry over to the next save.
+
+use std::time::{Duration, Instant};
+
+/// Time spent in the world since the last save.
+#[derive(Debug, Clone, Default)]
+pub struct PlayTimer {
+    checkpoint: Option<Instant>,
+}
+
+impl PlayTimer {
+    pub fn new() -> Self {
+        PlayTimer { checkpoint: None }
+    }
+
+    /// Start counting (entering the world).
+    pub fn start(&mut self, now: Instant) {
+        self.checkpoint = Some(now);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.checkpoint.is_some()
+    }
+
+    /// Whole seconds played since the last checkpoint; advances the
+    /// checkpoint by that amount. Returns 0 if not running.
+    pub fn take_elapsed(&mut self, now: Instant) -> u64 {
+        let Some(since) = self.checkpoint else { return 0 };
+        let secs = now.saturating_duration_since(since).as_secs();
+        self.checkpoint = Some(since + Duration::from_secs(secs));
+        secs
+    }
+
+    /// Take the remaining seconds and stop (leaving the world).
+    pub fn stop(&mut self, now: Instant) -> u64 {
+        let secs = self.take_elapsed(now);
+        self.checkpoint = None;
+        secs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_of_known_duration() {
+        let t0 = Instant::now();
+        let mut timer = PlayTimer::new();
+        let mut played: u64 = 3600; // stored total before this session
+        timer.start(t0);
+
+        // Autosave after 5 minutes, another at 10.5 minutes, logout at 20
+        played += timer.take_elapsed(t0 + Duration::from_secs(300));
+        played += timer.take_elapsed(t0 + Duration::from_millis(630_500));
+        played += timer.stop(t0 + Duration::from_secs(1200));
+
+        assert_eq!(played, 3600 + 1200);
+        assert!(!timer.is_running());
+        assert_eq!(timer.stop(t0 + Duration::from_secs(2000)), 0);
+    }
+}
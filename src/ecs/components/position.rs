@@ -14,26 +14,19 @@ impl Position {
 
     /// Check if another position is within screen range (18 tiles).
     pub fn is_in_screen(&self, other: &Position) -> bool {
-        if self.map_id != other.map_id {
-            return false;
-        }
-        let dx = (self.x - other.x).abs();
-        let dy = (self.y - other.y).abs();
-        dx <= 18 && dy <= 18
+        self.tile_distance(other) <= 18
     }
 
-    /// Tile-line distance (Chebyshev distance).
+    /// Tile-line distance (Chebyshev distance); `i32::MAX` across maps.
     pub fn tile_distance(&self, other: &Position) -> i32 {
         if self.map_id != other.map_id {
             return i32::MAX;
         }
-        let dx = (self.x - other.x).abs();
-        let dy = (self.y - other.y).abs();
-        dx.max(dy)
+        tile_distance(self.x, self.y, other.x, other.y)
     }
 
     /// Move one step in the given heading direction.
-    /// Heading: 0=S, 1=SW, 2=W, 3=NW, 4=N, 5=NE, 6=E, 7=SE
+    /// Heading: 0=N, 1=NE, 2=E, 3=SE, 4=S, 5=SW, 6=W, 7=NW
     pub fn step(&self, heading: i32) -> Position {
         let (dx, dy) = heading_delta(heading);
         Position {
@@ -45,22 +38,56 @@ impl Position {
     }
 }
 
+/// Tiles between two points: a diagonal step counts as one tile, the
+/// same as a straight one (Chebyshev distance). Every range check
+/// (attack reach, skill range, talk range) measures with this.
+pub fn tile_distance(x1: i32, y1: i32, x2: i32, y2: i32) -> i32 {
+    (x1 - x2).abs().max((y1 - y2).abs())
+}
+
 /// Get the (dx, dy) delta for a heading direction.
-/// L1J heading: 0=South(+y), 1=SW, 2=West(-x), 3=NW, 4=North(-y), 5=NE, 6=East(+x), 7=SE
+/// L1J heading (Java HEADING_TABLE_X/Y): 0=North(-y), 1=NE, 2=East(+x),
+/// 3=SE, 4=South(+y), 5=SW, 6=West(-x), 7=NW
 pub fn heading_delta(heading: i32) -> (i32, i32) {
     match heading & 7 {
-        0 => (0, 1),    // South
-        1 => (-1, 1),   // Southwest
-        2 => (-1, 0),   // West
-        3 => (-1, -1),  // Northwest
-        4 => (0, -1),   // North
-        5 => (1, -1),   // Northeast
-        6 => (1, 0),    // East
-        7 => (1, 1),    // Southeast
+        0 => (0, -1),   // North
+        1 => (1, -1),   // Northeast
+        2 => (1, 0),    // East
+        3 => (1, 1),    // Southeast
+        4 => (0, 1),    // South
+        5 => (-1, 1),   // Southwest
+        6 => (-1, 0),   // West
+        7 => (-1, -1),  // Northwest
         _ => (0, 0),
     }
 }
 
+/// Convert a (dx, dy) direction delta to the closest L1J heading (0-7).
+///
+/// Follows Java `targetDirection`: an axis only counts when it is at
+/// least ~0.41 of the longer one, so a target 5 east and 1 south is
+/// straight east rather than southeast. (0, 0) has no direction and
+/// gives 0.
+pub fn direction_from_delta(dx: i32, dy: i32) -> i32 {
+    let dist = dx.abs().max(dy.abs());
+    if dist == 0 {
+        return 0;
+    }
+    let counts = |d: i32| (d.abs() as f32 / dist as f32 + 0.59).floor() as i32 > 0;
+    let sx = if counts(dx) { dx.signum() } else { 0 };
+    let sy = if counts(dy) { dy.signum() } else { 0 };
+    match (sx, sy) {
+        (0, -1) => 0,  // North
+        (1, -1) => 1,  // Northeast
+        (1, 0) => 2,   // East
+        (1, 1) => 3,   // Southeast
+        (0, 1) => 4,   // South
+        (-1, 1) => 5,  // Southwest
+        (-1, 0) => 6,  // West
+        _ => 7,        // Northwest
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -86,8 +113,47 @@ mod tests {
     #[test]
     fn test_step() {
         let pos = Position::new(100, 100, 4);
-        let moved = pos.step(6); // East
+        let moved = pos.step(2); // East
         assert_eq!(moved.x, 101);
         assert_eq!(moved.y, 100);
     }
+
+    #[test]
+    fn test_all_eight_headings() {
+        let expected = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+        for (heading, &delta) in expected.iter().enumerate() {
+            let heading = heading as i32;
+            assert_eq!(heading_delta(heading), delta, "heading {heading}");
+            assert_eq!(direction_from_delta(delta.0, delta.1), heading);
+            // Longer deltas in the same direction agree
+            assert_eq!(direction_from_delta(delta.0 * 7, delta.1 * 7), heading);
+            // Headings wrap
+            assert_eq!(heading_delta(heading + 8), delta);
+            // Every step is one tile away
+            let pos = Position::new(100, 100, 4);
+            assert_eq!(pos.tile_distance(&pos.step(heading)), 1);
+        }
+    }
+
+    #[test]
+    fn test_shallow_delta_rounds_to_straight() {
+        assert_eq!(direction_from_delta(5, 1), 2); // East, not SE
+        assert_eq!(direction_from_delta(5, 2), 2);
+        assert_eq!(direction_from_delta(5, 3), 3); // Close enough to diagonal
+        assert_eq!(direction_from_delta(-1, -4), 0);
+    }
+
+    #[test]
+    fn test_diagonal_distance_is_chebyshev() {
+        let a = Position::new(100, 100, 4);
+        // A diagonal tile is as close as a straight one
+        assert_eq!(a.tile_distance(&Position::new(101, 101, 4)), 1);
+        assert_eq!(a.tile_distance(&Position::new(101, 100, 4)), 1);
+        assert_eq!(a.tile_distance(&Position::new(95, 105, 4)), 5);
+        assert_eq!(a.tile_distance(&Position::new(95, 103, 4)), 5);
+        assert_eq!(tile_distance(100, 100, 95, 105), 5);
+        // Screen range is the same square
+        assert!(a.is_in_screen(&Position::new(118, 118, 4)));
+        assert!(!a.is_in_screen(&Position::new(119, 100, 4)));
+    }
 }
@@ -34,6 +34,69 @@ pub struct SkillTemplate {
     pub sys_msg_id_happen: i32,
     pub sys_msg_id_stop: i32,
     pub sys_msg_id_fail: i32,
+    pub allowed_classes: u8,   // class_mask bits; 0 = any class
+}
+
+/// `target_to` bit flags (from Java L1Skills).
+pub mod target_to {
+    pub const ME: i32 = 0;
+    pub const PC: i32 = 1;
+    pub const NPC: i32 = 2;
+    pub const CLAN: i32 = 4;
+    pub const PARTY: i32 = 8;
+    pub const PET: i32 = 16;
+    pub const PLACE: i32 = 32;
+}
+
+impl SkillTemplate {
+    /// Check whether this skill affects every party member in range.
+    pub fn is_party_skill(&self) -> bool {
+        self.target_to & target_to::PARTY != 0
+    }
+
+    /// Check whether a caster of `class_type` may use this skill.
+    pub fn allows_class(&self, class_type: i32) -> bool {
+        self.allowed_classes == 0 || self.allowed_classes & class_mask::flag(class_type) != 0
+    }
+}
+
+/// Class bit flags (bit index = class_type, see CharClass).
+pub mod class_mask {
+    pub const ROYAL: u8 = 1 << 0;
+    pub const KNIGHT: u8 = 1 << 1;
+    pub const ELF: u8 = 1 << 2;
+    pub const MAGE: u8 = 1 << 3;
+    pub const DARKELF: u8 = 1 << 4;
+    pub const DRAGONKNIGHT: u8 = 1 << 5;
+    pub const ILLUSIONIST: u8 = 1 << 6;
+    pub const ALL: u8 = 0x7F;
+
+    /// Flag for a class_type (0..=6), or 0 for an unknown class.
+    pub fn flag(class_type: i32) -> u8 {
+        if (0..=6).contains(&class_type) { 1 << class_type } else { 0 }
+    }
+
+    /// Classes allowed to use a skill.
+    ///
+    /// Common magic (1-80) is gated by grade: royal/darkelf 1-2,
+    /// knight 1, elf 1-6, mage all. Class skills come from the
+    /// class skill tables.
+    pub fn for_skill(skill_id: i32, skill_level: i32) -> u8 {
+        if (1..=80).contains(&skill_id) {
+            let mut mask = MAGE;
+            if skill_level <= 1 { mask |= KNIGHT; }
+            if skill_level <= 2 { mask |= ROYAL | DARKELF; }
+            if skill_level <= 6 { mask |= ELF; }
+            return mask;
+        }
+        if crate::ecs::darkelf_skills::all_darkelf_skills().iter().any(|s| s.skill_id == skill_id) {
+            return DARKELF;
+        }
+        crate::ecs::class_skills::all_class_skills()
+            .iter()
+            .filter(|s| s.skill_id == skill_id)
+            .fold(0, |mask, s| mask | flag(s.class as i32))
+    }
 }
 
 /// Active buff/debuff effect on an entity.
@@ -79,12 +142,17 @@ impl SkillEffects {
 
     /// Tick all effects, removing expired ones. Returns list of expired skill IDs.
     pub fn tick(&mut self) -> Vec<i32> {
+        self.advance(1)
+    }
+
+    /// Advance all effects by several ticks at once. Returns expired skill IDs.
+    pub fn advance(&mut self, elapsed: u32) -> Vec<i32> {
         let mut expired = Vec::new();
         self.effects.retain(|&skill_id, effect| {
             if effect.remaining_ticks == 0 {
                 return true; // permanent, keep
             }
-            effect.remaining_ticks -= 1;
+            effect.remaining_ticks = effect.remaining_ticks.saturating_sub(elapsed);
             if effect.remaining_ticks == 0 {
                 expired.push(skill_id);
                 false // remove
@@ -131,13 +199,19 @@ pub mod skill_ids {
     pub const FULL_HEAL: i32 = 46;
     pub const FREEZING_BLIZZARD: i32 = 49;
     pub const METEOR_STRIKE: i32 = 54;
+    pub const RESURRECTION: i32 = 61;
+    pub const GREATER_RESURRECTION: i32 = 75;
     pub const ABSOLUTE_BARRIER: i32 = 56;
 
     // Status effects
     pub const STATUS_HASTE: i32 = 1000;
     pub const STATUS_BRAVE: i32 = 1001;
+    /// Blessing of Eva (breathe underwater).
+    pub const STATUS_UNDERWATER_BREATH: i32 = 1003;
     pub const STATUS_THIRD_SPEED: i32 = 1031;
     pub const STATUS_POISON: i32 = 1060;
+    pub const STATUS_POISON_SILENCE: i32 = 1061;
+    pub const STATUS_POISON_PARALYZED: i32 = 1062;
 
     // Item effects
     pub const COOKING_1_7_N: i32 = 3000;
@@ -167,13 +241,53 @@ impl SkillCooldowns {
     }
 
     pub fn tick(&mut self) {
+        self.advance(1);
+    }
+
+    /// Advance all cooldowns by several ticks at once.
+    pub fn advance(&mut self, elapsed: u32) {
         self.cooldowns.retain(|_, ticks| {
-            *ticks = ticks.saturating_sub(1);
+            *ticks = ticks.saturating_sub(elapsed);
             *ticks > 0
         });
     }
 }
 
+/// Milliseconds per game tick (skill timers count in ticks).
+pub const TICK_MS: u128 = 200;
+
+/// Cooldowns and buffs parked while a character sits at character select.
+///
+/// Kept on the session only, so they survive C_CHANGECHAR round-trips
+/// but not a full logout. Reuse timers are never saved; a few long buffs
+/// can be, with `game.persist_buffs` (see `db::buff`).
+#[derive(Debug, Clone)]
+pub struct ParkedSkills {
+    pub cooldowns: SkillCooldowns,
+    pub effects: SkillEffects,
+    pub parked_at: std::time::Instant,
+}
+
+impl ParkedSkills {
+    pub fn park(cooldowns: SkillCooldowns, effects: SkillEffects) -> Self {
+        ParkedSkills { cooldowns, effects, parked_at: std::time::Instant::now() }
+    }
+
+    /// Restore timers, counting down the ticks spent at character select.
+    pub fn restore(self) -> (SkillCooldowns, SkillEffects) {
+        let elapsed = (self.parked_at.elapsed().as_millis() / TICK_MS).min(u32::MAX as u128) as u32;
+        self.restore_after(elapsed)
+    }
+
+    pub fn restore_after(self, elapsed_ticks: u32) -> (SkillCooldowns, SkillEffects) {
+        let mut cooldowns = self.cooldowns;
+        let mut effects = self.effects;
+        cooldowns.advance(elapsed_ticks);
+        effects.advance(elapsed_ticks);
+        (cooldowns, effects)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -227,4 +341,28 @@ mod tests {
         cd.tick();
         assert!(cd.is_ready(skill_ids::FIREBALL));
     }
+
+    #[test]
+    fn test_cooldowns_survive_char_select() {
+        let mut cd = SkillCooldowns::new();
+        cd.set_cooldown(skill_ids::HASTE, 50);
+        cd.set_cooldown(skill_ids::LIGHT, 3);
+        let mut effects = SkillEffects::new();
+        effects.add_effect(skill_ids::SHIELD, 100, 2);
+        effects.add_effect(skill_ids::HASTE, 5, 1);
+
+        // Immediate relog: everything intact
+        let parked = ParkedSkills::park(cd.clone(), effects.clone());
+        let (cd2, fx2) = parked.restore();
+        assert!(!cd2.is_ready(skill_ids::HASTE));
+        assert!(!cd2.is_ready(skill_ids::LIGHT));
+        assert!(fx2.has_effect(skill_ids::SHIELD));
+
+        // Time spent at char select still counts down
+        let (cd3, fx3) = ParkedSkills::park(cd, effects).restore_after(10);
+        assert_eq!(cd3.cooldowns.get(&skill_ids::HASTE), Some(&40));
+        assert!(cd3.is_ready(skill_ids::LIGHT));
+        assert_eq!(fx3.effects[&skill_ids::SHIELD].remaining_ticks, 90);
+        assert!(!fx3.has_effect(skill_ids::HASTE));
+    }
 }
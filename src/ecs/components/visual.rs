@@ -4,20 +4,17 @@ pub struct Visual {
     pub gfx_id: i32,       // sprite graphics ID
     pub temp_gfx_id: i32,  // temporary GFX (polymorph etc.)
     pub light_size: i32,
-    pub name: String,
-    pub nameid: String,
     pub title: String,
     pub status: i32,        // animation status
 }
 
 impl Visual {
-    pub fn new_npc(gfx_id: i32, name: String, nameid: String) -> Self {
+    /// NPC names come from the shared template, not the visual.
+    pub fn new_npc(gfx_id: i32) -> Self {
         Visual {
             gfx_id,
             temp_gfx_id: 0,
             light_size: 0,
-            name,
-            nameid,
             title: String::new(),
             status: 0,
         }
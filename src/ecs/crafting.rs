@@ -0,0 +1,193 @@
+//! Generic material-in / item-out crafting.
+//!
+//! Every crafting NPC (vulcan smith, cooking, alchemy, ...) follows the
+//! same shape: check the materials, roll for success, use up the
+//! materials either way and hand over the output on success. Each
+//! crafter only supplies its recipes as `GenericRecipe` data.
+
+use rand::RngExt;
+
+use crate::ecs::components::item::{Inventory, ItemInstance, ItemTemplate};
+
+/// No bonus pushes a craft past this success rate (%).
+pub const MAX_SUCCESS_RATE: i32 = 95;
+
+/// `count` of `item_id`, as an input or an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Material {
+    pub item_id: i32,
+    pub count: i32,
+}
+
+/// Optional item that raises the success rate; one is used up whenever
+/// the crafter carries it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Catalyst {
+    pub item_id: i32,
+    pub bonus_rate: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenericRecipe {
+    pub name: String,
+    pub materials: Vec<Material>,
+    pub output: Material,
+    /// Whether the output merges into an existing stack.
+    pub output_stackable: bool,
+    /// Base success rate (%).
+    pub success_rate: i32,
+    pub catalyst: Option<Catalyst>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CraftError {
+    InsufficientMaterials,
+    /// No free slot for the output.
+    InventoryFull,
+}
+
+/// Result of a craft that went ahead (materials were used up).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CraftOutcome {
+    Success,
+    Failure,
+}
+
+impl GenericRecipe {
+    pub fn has_materials(&self, inv: &Inventory) -> bool {
+        self.materials.iter().all(|m| inv.check_item(m.item_id, m.count))
+    }
+
+    /// Is the catalyst in `inv`?
+    pub fn has_catalyst(&self, inv: &Inventory) -> bool {
+        self.catalyst.is_some_and(|c| inv.check_item(c.item_id, 1))
+    }
+
+    /// Effective success rate with the catalyst (if carried) and a luck
+    /// bonus, capped at `MAX_SUCCESS_RATE`. Negative luck is ignored.
+    pub fn effective_rate(&self, with_catalyst: bool, luck: i32) -> i32 {
+        let catalyst = match self.catalyst {
+            Some(c) if with_catalyst => c.bonus_rate,
+            _ => 0,
+        };
+        (self.success_rate + catalyst + luck.max(0)).min(MAX_SUCCESS_RATE)
+    }
+}
+
+/// Craft `recipe` from `inv`. The output, if any, gets `output_object_id`
+/// (unless it merges into a stack). Nothing changes on an error.
+pub fn craft_generic(
+    recipe: &GenericRecipe,
+    inv: &mut Inventory,
+    output_object_id: u32,
+    luck: i32,
+) -> Result<CraftOutcome, CraftError> {
+    let roll = rand::rng().random_range(1..=100);
+    craft_with_roll(recipe, inv, output_object_id, luck, roll)
+}
+
+fn craft_with_roll(
+    recipe: &GenericRecipe,
+    inv: &mut Inventory,
+    output_object_id: u32,
+    luck: i32,
+    roll: i32,
+) -> Result<CraftOutcome, CraftError> {
+    if !recipe.has_materials(inv) {
+        return Err(CraftError::InsufficientMaterials);
+    }
+    let merges = recipe.output_stackable && inv.find_item_id(recipe.output.item_id).is_some();
+    if !merges && inv.items.len() >= inv.max_size {
+        return Err(CraftError::InventoryFull);
+    }
+
+    let with_catalyst = recipe.has_catalyst(inv);
+    for m in &recipe.materials {
+        inv.consume_item_id(m.item_id, m.count);
+    }
+    if let Some(c) = recipe.catalyst.filter(|_| with_catalyst) {
+        inv.consume_item_id(c.item_id, 1);
+    }
+    if roll > recipe.effective_rate(with_catalyst, luck) {
+        return Ok(CraftOutcome::Failure);
+    }
+
+    let made = ItemInstance {
+        count: recipe.output.count,
+        ..ItemInstance::new(output_object_id, recipe.output.item_id)
+    };
+    let template = ItemTemplate {
+        item_id: recipe.output.item_id,
+        stackable: recipe.output_stackable,
+        ..Default::default()
+    };
+    inv.add_item(made, &template);
+    Ok(CraftOutcome::Success)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A cooking recipe: 2 meat + 1 salt + 1 herb -> 3 stew
+    fn stew() -> GenericRecipe {
+        GenericRecipe {
+            name: "stew".into(),
+            materials: vec![
+                Material { item_id: 100, count: 2 },
+                Material { item_id: 101, count: 1 },
+                Material { item_id: 102, count: 1 },
+            ],
+            output: Material { item_id: 200, count: 3 },
+            output_stackable: true,
+            success_rate: 70,
+            catalyst: Some(Catalyst { item_id: 300, bonus_rate: 20 }),
+        }
+    }
+
+    fn stocked() -> Inventory {
+        let mut inv = Inventory::new();
+        for (objid, item_id, count) in [(1, 100, 5), (2, 101, 1), (3, 102, 2), (4, 300, 1)] {
+            inv.items.push(ItemInstance { count, ..ItemInstance::new(objid, item_id) });
+        }
+        inv
+    }
+
+    #[test]
+    fn test_multi_material_success() {
+        let mut inv = stocked();
+        // 85 fails at 70% but passes with the catalyst's +20%
+        assert_eq!(craft_with_roll(&stew(), &mut inv, 50, 0, 85), Ok(CraftOutcome::Success));
+        assert_eq!(inv.find_item_id(100).unwrap().count, 3);
+        assert!(inv.find_item_id(101).is_none());
+        assert_eq!(inv.find_item_id(102).unwrap().count, 1);
+        assert!(inv.find_item_id(300).is_none());
+        assert_eq!(inv.get_item(50).unwrap().count, 3);
+    }
+
+    #[test]
+    fn test_failure_still_consumes() {
+        let mut inv = stocked();
+        assert_eq!(craft_with_roll(&stew(), &mut inv, 50, 0, 100), Ok(CraftOutcome::Failure));
+        assert_eq!(inv.find_item_id(100).unwrap().count, 3);
+        assert!(inv.find_item_id(200).is_none());
+    }
+
+    #[test]
+    fn test_insufficient_materials() {
+        let mut inv = stocked();
+        inv.consume_item_id(101, 1); // out of salt
+        let before = inv.items.len();
+        assert_eq!(craft_generic(&stew(), &mut inv, 50, 0), Err(CraftError::InsufficientMaterials));
+        assert_eq!(inv.items.len(), before);
+        assert!(inv.check_item(100, 5) && inv.check_item(300, 1));
+    }
+
+    #[test]
+    fn test_effective_rate_capped() {
+        let r = stew();
+        assert_eq!(r.effective_rate(false, 0), 70);
+        assert_eq!(r.effective_rate(true, 0), 90);
+        assert_eq!(r.effective_rate(true, 30), MAX_SUCCESS_RATE);
+    }
+}
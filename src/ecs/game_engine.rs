@@ -10,15 +10,19 @@
 ///   - Movement packets are batched and flushed once per tick
 
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use rand::RngExt;
 
+use crate::ecs::arena::{Arena, Handle};
 use crate::ecs::components::movement::Movement;
-use crate::ecs::components::npc::{AiState, NpcTemplate};
-use crate::ecs::components::position::{heading_delta, Position};
+use crate::ecs::components::npc::{AiRng, AiState, NpcTemplate, SpawnInfo};
+use crate::ecs::components::position::{direction_from_delta, heading_delta, Position};
 use crate::ecs::components::stats::Health;
 use crate::ecs::components::visual::Visual;
-use crate::world::grid::{ObjectId, WorldGrid};
+use crate::protocol::server::combat::{build_attack_packet, build_hp_meter, ACTION_ATTACK, EFFECT_NONE};
+use crate::protocol::server::movement::build_move_char;
+use crate::world::grid::{ObjectId, Placement, WorldGrid};
 
 /// A single NPC entity in the game world.
 #[derive(Debug)]
@@ -28,15 +32,79 @@ pub struct NpcEntity {
     pub health: Health,
     pub movement: Movement,
     pub ai: AiState,
+    /// Drives this NPC's random AI choices.
+    pub rng: AiRng,
     pub visual: Visual,
     pub template_id: i32,
+    /// Shared template data (name, stats); never copied per NPC.
+    pub template: Arc<NpcTemplate>,
     pub alive: bool,
 }
 
+impl NpcEntity {
+    /// The tiles this NPC stands on.
+    pub fn placement(&self) -> Placement {
+        Placement { x: self.pos.x, y: self.pos.y, size: self.template.footprint() }
+    }
+
+    /// Tiles from `(x, y)` to the nearest edge of this NPC's footprint;
+    /// what targeting and melee reach measure against.
+    pub fn distance_to(&self, x: i32, y: i32) -> i32 {
+        self.placement().distance_to(x, y)
+    }
+}
+
+/// Distances (in tiles) that wake and put to sleep an NPC's AI.
+///
+/// A sleeping NPC wakes when a player comes within `activate`; an awake
+/// NPC only sleeps once no player is within `deactivate`. Keeping
+/// `deactivate` farther out stops NPCs flipping state every tick while a
+/// player stands near the edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AiRanges {
+    pub activate: i32,
+    pub deactivate: i32,
+}
+
+impl AiRanges {
+    /// `deactivate` is raised to `activate` if given smaller.
+    pub fn new(activate: i32, deactivate: i32) -> Self {
+        AiRanges { activate, deactivate: deactivate.max(activate) }
+    }
+
+    /// Range to test an NPC against, given whether its AI is running.
+    pub fn for_state(&self, active: bool) -> i32 {
+        if active { self.deactivate } else { self.activate }
+    }
+}
+
+/// NPCs placed from one spawn entry, tracked so an idle region can be
+/// emptied and refilled.
+#[derive(Debug)]
+pub struct SpawnGroup {
+    pub info: SpawnInfo,
+    pub npcs: Vec<ObjectId>,
+    /// Last tick a player was near the spawn point.
+    pub last_seen_tick: u64,
+    /// Emptied for lack of players; refilled when one comes back.
+    pub despawned: bool,
+}
+
+/// Per-tick counts from the sleep optimization.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TickStats {
+    /// Live NPCs whose AI ran (a player was in range).
+    pub active_count: usize,
+    /// Live NPCs skipped because no player was in range.
+    pub skipped_count: usize,
+    /// Awake NPCs left for a later tick by the per-map budget.
+    pub deferred_count: usize,
+}
+
 /// The game world state - holds all entities and the spatial grid.
 pub struct GameWorld {
-    /// All NPC entities, keyed by object ID.
-    pub npcs: HashMap<ObjectId, NpcEntity>,
+    /// All NPC entities, looked up by object ID.
+    pub npcs: Arena<NpcEntity>,
 
     /// Spatial grid for fast visibility queries.
     pub grid: WorldGrid,
@@ -46,24 +114,68 @@ pub struct GameWorld {
     pub player_positions: HashMap<ObjectId, Position>,
 
     /// NPC template data (shared, immutable after load).
-    pub npc_templates: HashMap<i32, NpcTemplate>,
+    pub npc_templates: HashMap<i32, Arc<NpcTemplate>>,
 
     /// Next available object ID.
     next_object_id: ObjectId,
 
     /// Current tick count.
     pub tick_count: u64,
+
+    /// How many NPCs the last tick ran or slept (for tuning the AI ranges).
+    pub last_tick: TickStats,
+
+    /// Most awake NPCs processed per map per tick (0 = no cap).
+    pub tick_budget_per_map: usize,
+
+    /// Per map, the NPC ID a capped walk resumes from next tick.
+    tick_cursors: HashMap<i32, ObjectId>,
+
+    /// NPCs placed through `add_spawn`, by spawn entry.
+    pub spawn_groups: Vec<SpawnGroup>,
+
+    /// Ticks a spawn region may sit without players before its NPCs are
+    /// despawned (0 = never).
+    pub despawn_idle_ticks: u64,
+
+    /// Seed every NPC's `AiRng` is derived from.
+    ai_seed: u64,
+
+    /// NPC handles for the current tick; kept between ticks so its
+    /// allocation is reused instead of rebuilt every tick.
+    tick_handles: Vec<Handle>,
 }
 
 impl GameWorld {
     pub fn new(npc_templates: HashMap<i32, NpcTemplate>) -> Self {
+        Self::with_seed(npc_templates, rand::rng().random())
+    }
+
+    /// Use `cell_size`-tile grid cells, re-bucketing anything already spawned.
+    pub fn set_grid_cell_size(&mut self, cell_size: i32) {
+        self.grid.rebalance(cell_size);
+    }
+
+    /// Create a world whose NPC AI is reproducible: the same seed and the
+    /// same spawns give the same decisions, tick for tick.
+    pub fn with_seed(npc_templates: HashMap<i32, NpcTemplate>, ai_seed: u64) -> Self {
         GameWorld {
-            npcs: HashMap::new(),
+            npcs: Arena::new(),
             grid: WorldGrid::new(),
             player_positions: HashMap::new(),
-            npc_templates,
+            npc_templates: npc_templates
+                .into_iter()
+                .map(|(id, t)| (id, Arc::new(t)))
+                .collect(),
             next_object_id: 0x10000000, // Same start as Java IdFactory
             tick_count: 0,
+            last_tick: TickStats::default(),
+            tick_budget_per_map: 0,
+            tick_cursors: HashMap::new(),
+            spawn_groups: Vec::new(),
+            despawn_idle_ticks: 0,
+            ai_seed,
+            tick_handles: Vec::new(),
         }
     }
 
@@ -78,53 +190,123 @@ impl GameWorld {
     ///
     /// Returns the allocated object ID, or None if the template is not found.
     pub fn spawn_npc(&mut self, template_id: i32, x: i32, y: i32, map_id: i32) -> Option<ObjectId> {
-        // Clone template data first to avoid borrow conflict with self.next_id()
-        let template = self.npc_templates.get(&template_id)?.clone();
-
-        let id = self.next_id();
-        let pos = Position::new(x, y, map_id);
-
-        let entity = NpcEntity {
-            id,
-            pos,
-            health: Health {
-                cur_hp: template.hp,
-                max_hp: template.hp,
-                cur_mp: template.mp,
-                max_mp: template.mp,
-            },
-            movement: Movement::new(),
-            ai: AiState::new(x, y),
-            visual: Visual::new_npc(
-                template.gfxid,
-                template.name.clone(),
-                template.nameid.clone(),
-            ),
-            template_id,
-            alive: true,
-        };
+        let template = self.npc_templates.get(&template_id)?;
+
+        let id = self.next_object_id;
+        self.next_object_id += 1;
+        let entity = new_npc_entity(id, template_id, template, self.ai_seed, x, y, map_id);
 
-        self.grid.add(id, map_id, x, y);
+        self.grid.add_sized(id, map_id, x, y, template.footprint());
         self.npcs.insert(id, entity);
 
         Some(id)
     }
 
+    /// Spawn one NPC of `template_id` at each `(x, y, map_id)`.
+    ///
+    /// Same result as calling `spawn_npc` in order, but looks the template
+    /// up once, reserves space up front and fills the grid in one batch.
+    /// Returns the new IDs, or None if the template is not found.
+    pub fn spawn_many(&mut self, template_id: i32, spots: &[(i32, i32, i32)]) -> Option<Vec<ObjectId>> {
+        let template = self.npc_templates.get(&template_id)?;
+        let footprint = template.footprint();
+
+        self.npcs.reserve(spots.len());
+        let mut placed = Vec::with_capacity(spots.len());
+        for &(x, y, map_id) in spots {
+            let id = self.next_object_id;
+            self.next_object_id += 1;
+            let entity = new_npc_entity(id, template_id, template, self.ai_seed, x, y, map_id);
+            self.npcs.insert(id, entity);
+            placed.push((id, map_id, x, y));
+        }
+        if footprint == 0 {
+            self.grid.add_batch(&placed);
+        } else {
+            for &(id, map_id, x, y) in &placed {
+                self.grid.add_sized(id, map_id, x, y, footprint);
+            }
+        }
+
+        Some(placed.into_iter().map(|(id, ..)| id).collect())
+    }
+
+    /// Place `info.count` NPCs around a spawn point (within
+    /// `randomx`/`randomy` of it) and track them as one group. Returns the
+    /// group's index in `spawn_groups`, or None if the template is not found.
+    pub fn add_spawn(&mut self, info: SpawnInfo) -> Option<usize> {
+        let npcs = self.place_spawn(&info)?;
+        self.spawn_groups.push(SpawnGroup {
+            info,
+            npcs,
+            last_seen_tick: self.tick_count,
+            despawned: false,
+        });
+        Some(self.spawn_groups.len() - 1)
+    }
+
+    fn place_spawn(&mut self, info: &SpawnInfo) -> Option<Vec<ObjectId>> {
+        let mut rng = AiRng::for_npc(self.ai_seed, self.next_object_id);
+        let (rx, ry) = (info.randomx.abs(), info.randomy.abs());
+        let spots: Vec<(i32, i32, i32)> = (0..info.count.max(1))
+            .map(|_| (info.loc_x + rng.range(-rx, rx), info.loc_y + rng.range(-ry, ry), info.map_id))
+            .collect();
+        self.spawn_many(info.npc_template_id, &spots)
+    }
+
+    /// Despawn spawn groups no player has come within `range` of for
+    /// `despawn_idle_ticks`, and refill emptied groups once a player is
+    /// back. Boss spawns are never despawned.
+    fn update_idle_spawns(&mut self, range: i32) {
+        if self.despawn_idle_ticks == 0 {
+            return;
+        }
+        for i in 0..self.spawn_groups.len() {
+            let group = &self.spawn_groups[i];
+            let info = &group.info;
+            let center = Position::new(info.loc_x, info.loc_y, info.map_id);
+            let reach = range + info.randomx.abs().max(info.randomy.abs());
+            if any_player_nearby(&self.player_positions, &center, reach) {
+                if group.despawned {
+                    let info = info.clone();
+                    let npcs = self.place_spawn(&info).unwrap_or_default();
+                    self.spawn_groups[i].npcs = npcs;
+                    self.spawn_groups[i].despawned = false;
+                }
+                self.spawn_groups[i].last_seen_tick = self.tick_count;
+            } else if !group.despawned
+                && !info.is_boss
+                && self.tick_count - group.last_seen_tick >= self.despawn_idle_ticks
+            {
+                let npcs = std::mem::take(&mut self.spawn_groups[i].npcs);
+                for id in npcs {
+                    self.remove_npc(id);
+                }
+                self.spawn_groups[i].despawned = true;
+            }
+        }
+    }
+
     /// Remove an NPC from the world.
     pub fn remove_npc(&mut self, id: ObjectId) {
-        if let Some(npc) = self.npcs.remove(&id) {
+        if let Some(npc) = self.npcs.remove(id) {
             self.grid.remove(id, npc.pos.map_id, npc.pos.x, npc.pos.y);
         }
     }
 
-    /// Check if any player is near the given position (within AI sleep range).
-    fn any_player_nearby(&self, pos: &Position, range: i32) -> bool {
-        for player_pos in self.player_positions.values() {
-            if pos.tile_distance(player_pos) <= range {
-                return true;
-            }
+    /// Apply `damage` from `attacker` to an NPC (the combat path).
+    ///
+    /// Monsters retaliate even if their template is not aggressive: the
+    /// attacker goes on the hate list and becomes the target if the NPC
+    /// had none. Peaceful NPCs only lose HP. Returns the HP left, or None
+    /// if there is no such NPC.
+    pub fn damage_npc(&mut self, id: ObjectId, attacker: ObjectId, damage: i32) -> Option<i32> {
+        let npc = self.npcs.get_mut(id)?;
+        npc.health.cur_hp = (npc.health.cur_hp - damage.max(0)).max(0);
+        if npc.template.retaliates() {
+            npc.ai.add_hate(attacker, damage.max(1));
         }
-        false
+        Some(npc.health.cur_hp)
     }
 
     /// Execute one game tick.
@@ -134,46 +316,66 @@ impl GameWorld {
     ///
     /// Returns a list of (npc_id, old_pos, new_pos) for NPCs that moved,
     /// so the caller can generate movement packets.
-    pub fn tick(&mut self, ai_sleep_range: i32) -> Vec<NpcMovement> {
+    pub fn tick(&mut self, ai_ranges: AiRanges) -> Vec<NpcMovement> {
+        let started = std::time::Instant::now();
         self.tick_count += 1;
         let mut movements = Vec::new();
-        let mut rng = rand::rng();
-
-        // Collect NPC IDs to iterate (avoids borrow issues)
-        let npc_ids: Vec<ObjectId> = self.npcs.keys().copied().collect();
+        let mut stats = TickStats::default();
+        self.update_idle_spawns(ai_ranges.deactivate);
+        let budget = self.tick_budget_per_map;
+        let mut serviced: HashMap<i32, usize> = HashMap::new();
+        let mut next_cursors: HashMap<i32, ObjectId> = HashMap::new();
 
-        for npc_id in npc_ids {
-            // Check if NPC exists and is alive
-            let (_pos, template_id, should_process) = {
-                let npc = match self.npcs.get(&npc_id) {
-                    Some(n) => n,
-                    None => continue,
-                };
-                if !npc.alive {
+        // Snapshot NPC handles into the reused buffer (avoids borrow
+        // issues). Handles resolve without hashing, in slot order; an NPC
+        // removed mid-tick no longer resolves and is skipped. Under a
+        // budget each map is walked round-robin in ID order: IDs from its
+        // cursor onward first, then the ones before it.
+        let mut handles = std::mem::take(&mut self.tick_handles);
+        handles.clear();
+        handles.extend(self.npcs.handles());
+        let passes = if budget == 0 { 1 } else { 2 };
+        if budget > 0 {
+            let npcs = &self.npcs;
+            handles.sort_unstable_by_key(|&h| npcs.resolve(h).map(|n| n.id));
+        }
+        for (pass, &handle) in (0..passes).flat_map(|pass| handles.iter().map(move |h| (pass, h))) {
+            let Some(npc) = self.npcs.resolve_mut(handle) else { continue };
+            let npc_id = npc.id;
+            if !npc.alive {
+                continue;
+            }
+            let map_id = npc.pos.map_id;
+            if budget > 0 {
+                let from = self.tick_cursors.get(&map_id).copied().unwrap_or(0);
+                if (npc_id >= from) != (pass == 0) {
                     continue;
                 }
-                let nearby = self.any_player_nearby(&npc.pos, ai_sleep_range);
-                (npc.pos, npc.template_id, nearby)
-            };
+            }
 
             // Skip AI for NPCs with no players nearby (sleep optimization)
-            if !should_process {
-                if let Some(npc) = self.npcs.get_mut(&npc_id) {
-                    npc.ai.players_nearby = false;
-                }
+            let range = ai_ranges.for_state(npc.ai.active);
+            if !any_player_nearby(&self.player_positions, &npc.pos, range) {
+                npc.ai.players_nearby = false;
+                npc.ai.active = false;
+                stats.skipped_count += 1;
                 continue;
             }
 
-            // Get template for this NPC
-            let is_monster = self.npc_templates.get(&template_id)
-                .map(|t| t.impl_type.contains("Monster"))
-                .unwrap_or(false);
+            // Over this map's budget: leave the NPC untouched (cooldown
+            // included) and start here next tick
+            if budget > 0 {
+                let done = serviced.entry(map_id).or_insert(0);
+                if *done >= budget {
+                    stats.deferred_count += 1;
+                    next_cursors.entry(map_id).or_insert(npc_id);
+                    continue;
+                }
+                *done += 1;
+            }
+            stats.active_count += 1;
 
-            // Process AI for this NPC
-            let npc = match self.npcs.get_mut(&npc_id) {
-                Some(n) => n,
-                None => continue,
-            };
+            let is_monster = npc.template.impl_type.contains("Monster");
 
             npc.ai.players_nearby = true;
             npc.ai.active = true;
@@ -186,15 +388,28 @@ impl GameWorld {
                 continue;
             }
 
-            // AI Decision: random walk if no target (monsters and guards)
-            if npc.ai.target_id == 0 && is_monster {
-                // Random walk behavior
-                if npc.ai.random_walk_distance == 0 {
-                    npc.ai.random_walk_distance = rng.random_range(1..=5);
-                    npc.ai.random_walk_direction = rng.random_range(0..8);
+            // AI Decision: patrol route (chasing whatever it spots), or
+            // random walk if no target (monsters and guards)
+            let heading = if !npc.ai.patrol.is_empty() {
+                let agro = npc.template.agro;
+                match patrol_heading(&mut npc.ai, &npc.pos, agro, &self.player_positions) {
+                    Some(heading) => heading,
+                    None => continue,
+                }
+            } else if npc.ai.target_id == 0 && is_monster {
+                if npc.ai.beyond_leash(npc.pos.x, npc.pos.y) {
+                    // Leashed: head straight home, then start a fresh walk
+                    let dx = npc.ai.home_x - npc.pos.x;
+                    let dy = npc.ai.home_y - npc.pos.y;
+                    npc.ai.random_walk_direction = direction_from_delta(dx, dy);
+                    npc.ai.random_walk_distance = 0;
+                } else if npc.ai.random_walk_distance == 0 {
+                    // Random walk behavior
+                    npc.ai.random_walk_distance = npc.rng.range(1, 5);
+                    npc.ai.random_walk_direction = npc.rng.range(0, 7);
 
                     // Occasionally walk toward home point
-                    if npc.ai.home_x != 0 && npc.ai.home_y != 0 && rng.random_range(0..3) == 0 {
+                    if npc.ai.home_x != 0 && npc.ai.home_y != 0 && npc.rng.range(0, 2) == 0 {
                         let dx = npc.ai.home_x - npc.pos.x;
                         let dy = npc.ai.home_y - npc.pos.y;
                         if dx != 0 || dy != 0 {
@@ -204,40 +419,255 @@ impl GameWorld {
                 } else {
                     npc.ai.random_walk_distance -= 1;
                 }
+                npc.ai.random_walk_direction
+            } else {
+                continue;
+            };
+
+            // Execute the move
+            let (dx, dy) = heading_delta(heading);
+            let new_x = npc.pos.x + dx;
+            let new_y = npc.pos.y + dy;
 
-                // Execute the move
-                let heading = npc.ai.random_walk_direction;
-                let (dx, dy) = heading_delta(heading);
-                let new_x = npc.pos.x + dx;
-                let new_y = npc.pos.y + dy;
-
-                // Record the movement
-                let old_pos = npc.pos;
-                npc.pos.x = new_x;
-                npc.pos.y = new_y;
-                npc.pos.heading = heading;
-                npc.movement.cooldown_ticks = npc.movement.move_delay_ticks;
-
-                // Update grid
-                self.grid.move_object(
-                    npc_id,
-                    old_pos.map_id,
-                    old_pos.x,
-                    old_pos.y,
-                    new_x,
-                    new_y,
-                );
-
-                movements.push(NpcMovement {
-                    npc_id,
-                    old_pos,
-                    new_pos: npc.pos,
-                });
+            // Large footprints can't walk into each other
+            let footprint = npc.template.footprint();
+            if self.grid.is_blocked(npc_id, npc.pos.map_id, new_x, new_y, footprint) {
+                npc.ai.random_walk_distance = 0;
+                continue;
             }
+
+            // Record the movement
+            let old_pos = npc.pos;
+            npc.pos.x = new_x;
+            npc.pos.y = new_y;
+            npc.pos.heading = heading;
+            npc.movement.cooldown_ticks = npc.movement.move_delay_ticks;
+
+            // Update grid
+            self.grid.move_object(
+                npc_id,
+                old_pos.map_id,
+                old_pos.x,
+                old_pos.y,
+                new_x,
+                new_y,
+            );
+
+            movements.push(NpcMovement {
+                npc_id,
+                old_pos,
+                new_pos: npc.pos,
+            });
         }
 
+        self.tick_handles = handles;
+        self.tick_cursors = next_cursors;
+        self.last_tick = stats;
+        crate::network::metrics::global().record_tick(started.elapsed(), self.npcs.len(), stats.active_count);
         movements
     }
+
+    /// Panic if the grid and the NPCs' positions have drifted apart:
+    /// every NPC must be in the grid exactly where its `pos` says, and the
+    /// grid must hold nothing else. Meant for tests and debug runs; it
+    /// walks every NPC and grid cell, so release builds skip it.
+    pub fn validate_consistency(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        self.grid.validate_consistency();
+        assert_eq!(
+            self.grid.total_objects(),
+            self.npcs.len(),
+            "grid tracks {} objects but the world has {} NPCs",
+            self.grid.total_objects(),
+            self.npcs.len(),
+        );
+        let tracked: HashMap<ObjectId, (i32, i32, i32)> =
+            self.grid.entries().map(|(id, map, x, y)| (id, (map, x, y))).collect();
+        for (id, npc) in self.npcs.iter() {
+            assert_eq!(npc.id, id, "NPC stored under the wrong id");
+            let pos = (npc.pos.map_id, npc.pos.x, npc.pos.y);
+            assert_eq!(tracked.get(&id), Some(&pos), "NPC {id} grid entry does not match its position");
+        }
+    }
+
+    /// Group a tick's NPC moves into one batch of S_MOVECHARPACKETs per
+    /// player, so each session gets a single flush per tick.
+    ///
+    /// A player only gets moves for NPCs whose new position is within
+    /// `view_range` tiles; players with nothing in view get no batch.
+    pub fn batch_movements(&self, movements: &[NpcMovement], view_range: i32) -> Vec<MoveBatch> {
+        if movements.is_empty() {
+            return Vec::new();
+        }
+        let moved: HashMap<ObjectId, &NpcMovement> =
+            movements.iter().map(|m| (m.npc_id, m)).collect();
+
+        let mut batches: Vec<MoveBatch> = self.player_positions.iter()
+            .filter_map(|(&player_id, p)| {
+                let mut seen: Vec<&NpcMovement> = self.grid
+                    .objects_in_range(p.map_id, p.x, p.y, view_range)
+                    .into_iter()
+                    .filter_map(|id| moved.get(&id).copied())
+                    .collect();
+                if seen.is_empty() {
+                    return None;
+                }
+                seen.sort_unstable_by_key(|m| m.npc_id);
+                let packets = seen.iter()
+                    .map(|m| build_move_char(m.npc_id as i32, m.new_pos.x, m.new_pos.y, m.new_pos.heading))
+                    .collect();
+                Some(MoveBatch { player_id, packets })
+            })
+            .collect();
+        batches.sort_unstable_by_key(|b| b.player_id);
+        batches
+    }
+
+    /// Group a tick's NPC attacks and HP-bar changes into one batch per
+    /// player, like `batch_movements`.
+    ///
+    /// Works from the positions carried by each event rather than the
+    /// grid, so units kept outside `npcs` (siege guards) use it too.
+    pub fn batch_attacks(&self, attacks: &[NpcAttack], hp_changes: &[NpcHpChange], view_range: i32) -> Vec<MoveBatch> {
+        if attacks.is_empty() && hp_changes.is_empty() {
+            return Vec::new();
+        }
+        let mut batches: Vec<MoveBatch> = self.player_positions.iter()
+            .filter_map(|(&player_id, p)| {
+                let in_view = |pos: &Position| pos.tile_distance(p) <= view_range;
+                let packets: Vec<Vec<u8>> = attacks.iter()
+                    .filter(|a| in_view(&a.pos))
+                    .map(NpcAttack::packet)
+                    .chain(hp_changes.iter().filter(|h| in_view(&h.pos)).map(NpcHpChange::packet))
+                    .collect();
+                if packets.is_empty() {
+                    return None;
+                }
+                Some(MoveBatch { player_id, packets })
+            })
+            .collect();
+        batches.sort_unstable_by_key(|b| b.player_id);
+        batches
+    }
+}
+
+/// How far (in tiles) an aggressive patrolling NPC spots players.
+pub const PATROL_SIGHT_RANGE: i32 = 8;
+
+/// Next step for an NPC on a patrol route: toward its target while the
+/// target is in reach, otherwise back along the route. An aggressive
+/// patroller with no target picks up the nearest player in sight.
+/// None = stay put this tick.
+fn patrol_heading(
+    ai: &mut AiState,
+    pos: &Position,
+    agro: bool,
+    players: &HashMap<ObjectId, Position>,
+) -> Option<i32> {
+    if ai.target_id == 0 && agro {
+        let spotted = players
+            .iter()
+            .filter(|(_, p)| p.map_id == pos.map_id && pos.tile_distance(p) <= PATROL_SIGHT_RANGE)
+            .min_by_key(|(&id, p)| (pos.tile_distance(p), id));
+        if let Some((&id, _)) = spotted {
+            ai.add_hate(id, 0);
+        }
+    }
+    if ai.target_id != 0 {
+        match players.get(&ai.target_id) {
+            Some(t) if t.map_id == pos.map_id
+                && (ai.leash_range <= 0 || pos.tile_distance(t) <= ai.leash_range) =>
+            {
+                if pos.tile_distance(t) <= 1 {
+                    return None;
+                }
+                return Some(direction_from_delta(t.x - pos.x, t.y - pos.y));
+            }
+            _ => ai.drop_target(),
+        }
+    }
+    let (wx, wy) = ai.patrol_waypoint(pos.x, pos.y)?;
+    Some(direction_from_delta(wx - pos.x, wy - pos.y))
+}
+
+/// Check if any player is near the given position (within AI sleep range).
+fn any_player_nearby(players: &HashMap<ObjectId, Position>, pos: &Position, range: i32) -> bool {
+    players.values().any(|player_pos| pos.tile_distance(player_pos) <= range)
+}
+
+/// Build a fresh NPC instance from its template.
+fn new_npc_entity(
+    id: ObjectId,
+    template_id: i32,
+    template: &Arc<NpcTemplate>,
+    ai_seed: u64,
+    x: i32,
+    y: i32,
+    map_id: i32,
+) -> NpcEntity {
+    NpcEntity {
+        id,
+        pos: Position::new(x, y, map_id),
+        health: Health {
+            cur_hp: template.hp,
+            max_hp: template.hp,
+            cur_mp: template.mp,
+            max_mp: template.mp,
+        },
+        movement: Movement::new(),
+        ai: AiState::new(x, y),
+        rng: AiRng::for_npc(ai_seed, id),
+        visual: Visual::new_npc(template.gfxid),
+        template_id,
+        template: Arc::clone(template),
+        alive: true,
+    }
+}
+
+/// NPC packets (moves, attacks, HP bars) for one player, sent together
+/// at the end of a tick.
+#[derive(Debug)]
+pub struct MoveBatch {
+    pub player_id: ObjectId,
+    pub packets: Vec<Vec<u8>>,
+}
+
+/// A single NPC attack during a tick.
+#[derive(Debug, Clone)]
+pub struct NpcAttack {
+    pub npc_id: ObjectId,
+    pub target_id: ObjectId,
+    /// Attacker's position, heading toward the target.
+    pub pos: Position,
+    pub damage: i32,
+}
+
+impl NpcAttack {
+    /// S_ATTACKPACKET for everyone in view.
+    pub fn packet(&self) -> Vec<u8> {
+        build_attack_packet(
+            self.npc_id as i32, self.target_id as i32, ACTION_ATTACK,
+            self.damage, self.pos.heading, EFFECT_NONE,
+        )
+    }
+}
+
+/// An NPC's HP bar changed (it took damage or picked a fight).
+#[derive(Debug, Clone)]
+pub struct NpcHpChange {
+    pub npc_id: ObjectId,
+    pub pos: Position,
+    pub cur_hp: i32,
+    pub max_hp: i32,
+}
+
+impl NpcHpChange {
+    /// S_HPMETER for everyone in view.
+    pub fn packet(&self) -> Vec<u8> {
+        build_hp_meter(self.npc_id as i32, self.cur_hp, self.max_hp)
+    }
 }
 
 /// Represents a single NPC movement during a tick.
@@ -248,22 +678,12 @@ pub struct NpcMovement {
     pub new_pos: Position,
 }
 
-/// Convert a (dx, dy) direction delta to the closest L1J heading (0-7).
-fn direction_from_delta(dx: i32, dy: i32) -> i32 {
-    if dx == 0 && dy > 0 { return 0; }  // South
-    if dx < 0 && dy > 0 { return 1; }   // Southwest
-    if dx < 0 && dy == 0 { return 2; }  // West
-    if dx < 0 && dy < 0 { return 3; }   // Northwest
-    if dx == 0 && dy < 0 { return 4; }  // North
-    if dx > 0 && dy < 0 { return 5; }   // Northeast
-    if dx > 0 && dy == 0 { return 6; }  // East
-    if dx > 0 && dy > 0 { return 7; }   // Southeast
-    0
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ecs::components::npc::DEFAULT_LEASH_RANGE;
+
+    const RANGES: AiRanges = AiRanges { activate: 30, deactivate: 35 };
 
     fn make_test_template(npc_id: i32, name: &str, impl_type: &str) -> NpcTemplate {
         NpcTemplate {
@@ -332,7 +752,7 @@ mod tests {
         world.spawn_npc(45000, 32800, 32800, 4);
 
         // No players registered - NPCs should not move
-        let movements = world.tick(30);
+        let movements = world.tick(RANGES);
         assert!(movements.is_empty());
     }
 
@@ -348,7 +768,7 @@ mod tests {
         world.player_positions.insert(99999, Position::new(32810, 32810, 4));
 
         // Tick - NPC should attempt to move
-        let movements = world.tick(30);
+        let movements = world.tick(RANGES);
 
         // NPC should have moved (random walk)
         assert_eq!(movements.len(), 1);
@@ -375,7 +795,7 @@ mod tests {
         // Run 10 ticks
         let mut total_movements = 0;
         for _ in 0..10 {
-            let movements = world.tick(30);
+            let movements = world.tick(RANGES);
             total_movements += movements.len();
         }
 
@@ -397,4 +817,486 @@ mod tests {
         assert_eq!(world.npcs.len(), 0);
         assert_eq!(world.grid.total_objects(), 0);
     }
+
+    #[test]
+    fn test_ai_hysteresis_at_boundary() {
+        let mut templates = HashMap::new();
+        templates.insert(70000, make_test_template(70000, "Guard", "L1Guard"));
+
+        let mut world = GameWorld::new(templates);
+        let id = world.spawn_npc(70000, 32800, 32800, 4).unwrap();
+        let active = |w: &GameWorld| w.npcs[&id].ai.active;
+
+        // Between the two ranges: a sleeping NPC stays asleep
+        world.player_positions.insert(99999, Position::new(32832, 32800, 4));
+        world.tick(RANGES);
+        assert!(!active(&world));
+
+        // Wakes inside the activate range
+        world.player_positions.insert(99999, Position::new(32830, 32800, 4));
+        world.tick(RANGES);
+        assert!(active(&world));
+
+        // Hovering across the activate edge never puts it back to sleep
+        for step in 0..20 {
+            let x = 32830 + step % 6; // 30..=35 tiles away
+            world.player_positions.insert(99999, Position::new(x, 32800, 4));
+            world.tick(RANGES);
+            assert!(active(&world), "flapped on tick {step}");
+        }
+
+        // Sleeps only past the deactivate range
+        world.player_positions.insert(99999, Position::new(32836, 32800, 4));
+        world.tick(RANGES);
+        assert!(!active(&world));
+    }
+
+    #[test]
+    fn test_same_seed_same_movements() {
+        let run = |seed: u64| {
+            let mut templates = HashMap::new();
+            templates.insert(45000, make_test_template(45000, "TestMob", "L1Monster"));
+            let mut world = GameWorld::with_seed(templates, seed);
+            for i in 0..50 {
+                world.spawn_npc(45000, 32800 + i % 10, 32800 + i / 10, 4);
+            }
+            world.player_positions.insert(99999, Position::new(32805, 32802, 4));
+
+            let mut trace = Vec::new();
+            for _ in 0..200 {
+                let mut moves: Vec<_> = world
+                    .tick(RANGES)
+                    .into_iter()
+                    .map(|m| (m.npc_id, m.new_pos.x, m.new_pos.y, m.new_pos.heading))
+                    .collect();
+                // HashMap iteration order differs between worlds
+                moves.sort_unstable();
+                trace.push(moves);
+            }
+            trace
+        };
+
+        let a = run(0xC0FFEE);
+        assert!(a.iter().map(Vec::len).sum::<usize>() > 0);
+        assert_eq!(a, run(0xC0FFEE));
+        assert_ne!(a, run(0xBEEF));
+    }
+
+    #[test]
+    fn test_leashed_npc_walks_home() {
+        let mut templates = HashMap::new();
+        templates.insert(45000, make_test_template(45000, "TestMob", "L1Monster"));
+
+        let mut world = GameWorld::with_seed(templates, 7);
+        let id = world.spawn_npc(45000, 32800, 32800, 4).unwrap();
+        world.player_positions.insert(99999, Position::new(32830, 32790, 4));
+
+        // Knock the NPC 50 tiles east of home
+        world.grid.move_object(id, 4, 32800, 32800, 32850, 32770);
+        let npc = world.npcs.get_mut(id).unwrap();
+        npc.pos.x = 32850;
+        npc.pos.y = 32770;
+
+        let dist = |w: &GameWorld| {
+            let p = w.npcs[&id].pos;
+            (p.x - 32800).abs().max((p.y - 32800).abs())
+        };
+        let mut last = dist(&world);
+        let mut ticks = 0;
+        while world.npcs[&id].ai.beyond_leash(world.npcs[&id].pos.x, world.npcs[&id].pos.y) {
+            world.tick(RANGES);
+            let now = dist(&world);
+            assert!(now <= last, "moved away from home while leashed");
+            last = now;
+            ticks += 1;
+            assert!(ticks <= 2 * (50 - DEFAULT_LEASH_RANGE), "not home after {ticks} ticks");
+        }
+        assert_eq!(dist(&world), DEFAULT_LEASH_RANGE);
+    }
+
+    #[test]
+    fn test_spawn_many_matches_spawn_npc() {
+        let make = || {
+            let mut templates = HashMap::new();
+            templates.insert(45000, make_test_template(45000, "TestMob", "L1Monster"));
+            GameWorld::with_seed(templates, 99)
+        };
+        let spots: Vec<(i32, i32, i32)> = (0..500)
+            .map(|i| (32000 + i % 50, 32000 + i / 50, 4 + i % 2))
+            .collect();
+
+        let mut one_by_one = make();
+        let ids_a: Vec<ObjectId> = spots.iter()
+            .map(|&(x, y, map)| one_by_one.spawn_npc(45000, x, y, map).unwrap())
+            .collect();
+        let mut bulk = make();
+        let ids_b = bulk.spawn_many(45000, &spots).unwrap();
+
+        assert_eq!(ids_a, ids_b);
+        assert_eq!(one_by_one.next_id(), bulk.next_id());
+        assert_eq!(bulk.grid.total_objects(), 500);
+        for id in ids_a {
+            let (a, b) = (&one_by_one.npcs[&id], &bulk.npcs[&id]);
+            assert_eq!((a.pos, a.template_id, a.health.max_hp), (b.pos, b.template_id, b.health.max_hp));
+            assert_eq!((a.ai.home_x, a.ai.home_y), (b.ai.home_x, b.ai.home_y));
+            assert_eq!(a.rng, b.rng);
+            assert_eq!(a.template.name, b.template.name);
+            let (x, y, map) = (a.pos.x, a.pos.y, a.pos.map_id);
+            let mut near_a = one_by_one.grid.get_nearby(map, x, y);
+            let mut near_b = bulk.grid.get_nearby(map, x, y);
+            near_a.sort_unstable();
+            near_b.sort_unstable();
+            assert_eq!(near_a, near_b);
+        }
+
+        assert!(bulk.spawn_many(12345, &spots).is_none());
+    }
+
+    #[test]
+    fn test_removal_between_ticks_with_reused_ids() {
+        let mut templates = HashMap::new();
+        templates.insert(45000, make_test_template(45000, "TestMob", "L1Monster"));
+
+        let mut world = GameWorld::with_seed(templates, 3);
+        let spots: Vec<_> = (0..100).map(|i| (32800 + i % 10, 32800 + i / 10, 4)).collect();
+        let ids = world.spawn_many(45000, &spots).unwrap();
+        world.player_positions.insert(99999, Position::new(32805, 32805, 4));
+        world.tick(RANGES);
+
+        // Remove half; the reused handle buffer must not bring them back
+        for id in ids.iter().step_by(2) {
+            world.remove_npc(*id);
+        }
+        for _ in 0..5 {
+            for m in world.tick(RANGES) {
+                assert!(world.npcs.contains_key(m.npc_id));
+            }
+        }
+        assert_eq!(world.npcs.len(), 50);
+        assert_eq!(world.grid.total_objects(), 50);
+        assert!(world.tick_handles.capacity() >= 100);
+    }
+
+    #[test]
+    fn test_removed_npc_id_does_not_resolve() {
+        let mut templates = HashMap::new();
+        templates.insert(45000, make_test_template(45000, "TestMob", "L1Monster"));
+
+        let mut world = GameWorld::new(templates);
+        let old = world.spawn_npc(45000, 32800, 32800, 4).unwrap();
+        let old_handle = world.npcs.handle_of(old).unwrap();
+        world.remove_npc(old);
+
+        // The new NPC reuses the slot but gets a fresh ObjectId
+        let new = world.spawn_npc(45000, 32900, 32900, 4).unwrap();
+        assert_ne!(old, new);
+        assert_eq!(world.npcs.slot_count(), 1);
+        assert!(world.npcs.get(old).is_none());
+        assert!(world.npcs.resolve(old_handle).is_none());
+        assert_eq!(world.npcs[&new].pos.x, 32900);
+
+        // Removing a dangling ID is a no-op
+        world.remove_npc(old);
+        assert_eq!(world.npcs.len(), 1);
+        assert_eq!(world.grid.total_objects(), 1);
+    }
+
+    #[test]
+    fn test_spawned_npcs_share_template() {
+        let mut templates = HashMap::new();
+        templates.insert(45000, make_test_template(45000, "TestMob", "L1Monster"));
+
+        let mut world = GameWorld::new(templates);
+        let a = world.spawn_npc(45000, 32800, 32800, 4).unwrap();
+        let many = world.spawn_many(45000, &[(32801, 32800, 4), (32802, 32800, 4)]).unwrap();
+
+        let shared = &world.npc_templates[&45000];
+        for id in [a, many[0], many[1]] {
+            let npc = &world.npcs[&id];
+            assert!(Arc::ptr_eq(&npc.template, shared));
+            assert_eq!((npc.template_id, npc.template.name.as_str()), (45000, "TestMob"));
+            assert_eq!(npc.health.max_hp, 100);
+        }
+        // The world's copy plus one per NPC
+        assert_eq!(Arc::strong_count(shared), 4);
+
+        world.remove_npc(a);
+        assert_eq!(Arc::strong_count(&world.npc_templates[&45000]), 3);
+    }
+
+    #[test]
+    fn test_move_batches_only_in_view() {
+        let mut templates = HashMap::new();
+        templates.insert(45000, make_test_template(45000, "TestMob", "L1Monster"));
+
+        let mut world = GameWorld::new(templates);
+        let near = world.spawn_npc(45000, 32805, 32800, 4).unwrap();
+        let near2 = world.spawn_npc(45000, 32790, 32790, 4).unwrap();
+        let far = world.spawn_npc(45000, 32900, 32900, 4).unwrap();
+        let other_map = world.spawn_npc(45000, 32800, 32800, 5).unwrap();
+        world.player_positions.insert(1, Position::new(32800, 32800, 4));
+        world.player_positions.insert(2, Position::new(33500, 33500, 4)); // sees nothing
+
+        let movements = world.tick(AiRanges::new(200, 200));
+        let moved: Vec<ObjectId> = movements.iter().map(|m| m.npc_id).collect();
+        assert!(moved.contains(&near) && moved.contains(&near2) && moved.contains(&far));
+        assert!(!moved.contains(&other_map));
+
+        let batches = world.batch_movements(&movements, 18);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].player_id, 1);
+        let expected: Vec<Vec<u8>> = {
+            let mut ids = [near, near2];
+            ids.sort_unstable();
+            ids.iter().map(|id| {
+                let p = world.npcs[id].pos;
+                build_move_char(*id as i32, p.x, p.y, p.heading)
+            }).collect()
+        };
+        assert_eq!(batches[0].packets, expected);
+        assert!(world.batch_movements(&[], 18).is_empty());
+    }
+
+    #[test]
+    fn test_grid_stays_consistent_over_many_ticks() {
+        let mut templates = HashMap::new();
+        templates.insert(45000, make_test_template(45000, "TestMob", "L1Monster"));
+
+        let mut world = GameWorld::with_seed(templates, 11);
+        let spots: Vec<_> = (0..400).map(|i| (32780 + i % 20 * 2, 32780 + i / 20 * 2, 4)).collect();
+        let mut ids = world.spawn_many(45000, &spots).unwrap();
+        world.player_positions.insert(99999, Position::new(32800, 32800, 4));
+        world.validate_consistency();
+
+        for t in 0..300 {
+            world.tick(RANGES);
+            // Churn: despawn and respawn some NPCs along the way
+            if t % 25 == 0 {
+                for id in ids.drain(..10) {
+                    world.remove_npc(id);
+                }
+                ids.extend(world.spawn_many(45000, &spots[..10]).unwrap());
+            }
+            world.validate_consistency();
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "does not match its position")]
+    fn test_validate_catches_missed_grid_move() {
+        let mut templates = HashMap::new();
+        templates.insert(45000, make_test_template(45000, "TestMob", "L1Monster"));
+
+        let mut world = GameWorld::new(templates);
+        let id = world.spawn_npc(45000, 32800, 32800, 4).unwrap();
+        world.npcs.get_mut(id).unwrap().pos.x += 1; // moved without move_object
+        world.validate_consistency();
+    }
+
+    #[test]
+    fn test_large_npcs_keep_their_footprints_apart() {
+        let mut giant = make_test_template(45001, "Giant", "L1Monster");
+        giant.size = "large".to_string();
+        let mut templates = HashMap::new();
+        templates.insert(45001, giant);
+
+        let mut world = GameWorld::with_seed(templates, 7);
+        let ids = world.spawn_many(45001, &[(32800, 32800, 4), (32803, 32800, 4), (32800, 32803, 4)]).unwrap();
+        world.player_positions.insert(1, Position::new(32801, 32801, 4));
+
+        // Reach is measured to the footprint edge, not the center tile
+        let npc = world.npcs.get(ids[0]).unwrap();
+        assert_eq!(npc.distance_to(32802, 32800), 1);
+        assert_eq!(npc.distance_to(32800, 32800), 0);
+
+        for _ in 0..500 {
+            world.tick(RANGES);
+            world.validate_consistency();
+            let placed: Vec<_> = ids.iter().map(|&id| world.npcs[&id].placement()).collect();
+            for (i, a) in placed.iter().enumerate() {
+                for b in &placed[i + 1..] {
+                    assert!(!a.overlaps(b), "{a:?} walked into {b:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hit_monster_retaliates_shopkeeper_does_not() {
+        let mut passive = make_test_template(45002, "Rabbit", "L1Monster");
+        passive.agro = false;
+        let mut templates = HashMap::new();
+        templates.insert(45002, passive);
+        templates.insert(70001, make_test_template(70001, "Shopkeeper", "L1Merchant"));
+
+        let mut world = GameWorld::new(templates);
+        let rabbit = world.spawn_npc(45002, 32800, 32800, 4).unwrap();
+        let shop = world.spawn_npc(70001, 32805, 32800, 4).unwrap();
+
+        assert_eq!(world.damage_npc(rabbit, 90001, 10), Some(90));
+        assert_eq!(world.npcs[&rabbit].ai.target_id, 90001);
+        assert_eq!(world.npcs[&rabbit].ai.hate_list, vec![(90001, 10)]);
+
+        // A second attacker adds hate but doesn't steal the target
+        world.damage_npc(rabbit, 90002, 5);
+        assert_eq!(world.npcs[&rabbit].ai.target_id, 90001);
+
+        assert_eq!(world.damage_npc(shop, 90001, 10), Some(90));
+        assert_eq!(world.npcs[&shop].ai.target_id, 0);
+        assert!(world.npcs[&shop].ai.hate_list.is_empty());
+        assert_eq!(world.damage_npc(12345, 90001, 10), None);
+    }
+
+    #[test]
+    fn test_patrol_visits_waypoints_in_order() {
+        let mut templates = HashMap::new();
+        templates.insert(70000, NpcTemplate { agro: false, ..make_test_template(70000, "Guard", "L1Guard") });
+
+        let mut world = GameWorld::with_seed(templates, 1);
+        let id = world.spawn_npc(70000, 32800, 32800, 4).unwrap();
+        world.player_positions.insert(99999, Position::new(32790, 32790, 4));
+        let route = vec![(32804, 32800), (32804, 32804), (32800, 32804), (32800, 32800)];
+        world.npcs.get_mut(id).unwrap().ai.set_patrol(route.clone());
+
+        let mut visited = Vec::new();
+        for _ in 0..500 {
+            world.tick(RANGES);
+            let p = world.npcs[&id].pos;
+            if route.contains(&(p.x, p.y)) && visited.last() != Some(&(p.x, p.y)) {
+                visited.push((p.x, p.y));
+            }
+        }
+        // Two full laps, in route order
+        assert!(visited.len() >= 8, "only reached {:?}", visited);
+        for (i, wp) in visited.iter().enumerate() {
+            assert_eq!(*wp, route[i % route.len()]);
+        }
+    }
+
+    #[test]
+    fn test_patrol_breaks_to_chase_then_returns() {
+        let mut templates = HashMap::new();
+        templates.insert(70000, make_test_template(70000, "Guard", "L1Guard"));
+
+        let mut world = GameWorld::with_seed(templates, 1);
+        let id = world.spawn_npc(70000, 32800, 32800, 4).unwrap();
+        world.npcs.get_mut(id).unwrap().ai.set_patrol(vec![(32810, 32800), (32800, 32800)]);
+
+        // An enemy in sight just off the route
+        world.player_positions.insert(99999, Position::new(32800, 32806, 4));
+        for _ in 0..200 {
+            world.tick(RANGES);
+        }
+        let npc = &world.npcs[&id];
+        assert_eq!(npc.ai.target_id, 99999);
+        assert!(npc.pos.tile_distance(&Position::new(32800, 32806, 4)) <= 1);
+
+        // The enemy runs off (far enough to escape but still keeping the AI awake)
+        world.player_positions.insert(99999, Position::new(32800, 32830, 4));
+        for _ in 0..500 {
+            world.tick(RANGES);
+        }
+        let npc = &world.npcs[&id];
+        assert_eq!(npc.ai.target_id, 0);
+        assert_eq!(npc.pos.y, 32800, "back on the route");
+    }
+
+    #[test]
+    fn test_tick_stats_with_clustered_player() {
+        let mut templates = HashMap::new();
+        templates.insert(45000, make_test_template(45000, "TestMob", "L1Monster"));
+
+        let mut world = GameWorld::new(templates);
+        for i in 0..10_000 {
+            world.spawn_npc(45000, 32000 + (i % 200), 32000 + (i / 200), 4);
+        }
+        // One player in a corner of the 200x50 spawn field
+        world.player_positions.insert(99999, Position::new(32000, 32000, 4));
+
+        world.tick(RANGES);
+        let stats = world.last_tick;
+        assert_eq!(stats.active_count + stats.skipped_count, 10_000);
+        // Only the 31x31 corner within the activate range wakes up
+        assert_eq!(stats.active_count, 31 * 31);
+        assert!(stats.active_count * 10 < world.npcs.len());
+    }
+
+    #[test]
+    fn test_tick_budget_round_robin() {
+        let mut templates = HashMap::new();
+        templates.insert(45000, make_test_template(45000, "TestMob", "L1Monster"));
+
+        let mut world = GameWorld::with_seed(templates, 3);
+        let ids: Vec<ObjectId> = (0..10)
+            .map(|i| world.spawn_npc(45000, 32800 + i * 3, 32800, 4).unwrap())
+            .collect();
+        // A quiet map alongside gets its own budget
+        let other = world.spawn_npc(45000, 32800, 32800, 5).unwrap();
+        world.player_positions.insert(99999, Position::new(32810, 32805, 4));
+        world.player_positions.insert(99998, Position::new(32800, 32805, 5));
+        world.tick_budget_per_map = 3;
+
+        let mut moved = std::collections::HashSet::new();
+        for _ in 0..4 {
+            let movements = world.tick(RANGES);
+            let on_busy_map = movements.iter().filter(|m| m.npc_id != other).count();
+            assert!(on_busy_map <= 3);
+            assert!(movements.iter().any(|m| m.npc_id == other));
+            assert_eq!(world.last_tick.active_count + world.last_tick.deferred_count, 11);
+            moved.extend(movements.iter().map(|m| m.npc_id));
+        }
+        for id in &ids {
+            assert!(moved.contains(id), "NPC {id:#x} never serviced");
+        }
+    }
+
+    fn spawn_info(spawn_id: i32, loc_x: i32, is_boss: bool) -> SpawnInfo {
+        SpawnInfo {
+            spawn_id, npc_template_id: 45000,
+            loc_x, loc_y: 32800, map_id: 4, heading: 0,
+            randomx: 5, randomy: 5,
+            min_respawn_delay: 60, max_respawn_delay: 60,
+            count: 4, movement_distance: 0, is_boss,
+        }
+    }
+
+    #[test]
+    fn test_idle_region_despawns_and_respawns() {
+        let mut templates = HashMap::new();
+        templates.insert(45000, make_test_template(45000, "TestMob", "L1Monster"));
+
+        let mut world = GameWorld::with_seed(templates, 9);
+        world.despawn_idle_ticks = 10;
+        let group = world.add_spawn(spawn_info(1, 32800, false)).unwrap();
+        let boss = world.add_spawn(spawn_info(2, 32900, true)).unwrap();
+        assert_eq!(world.npcs.len(), 8);
+
+        // No one around: still up until the window runs out
+        for _ in 0..9 {
+            world.tick(RANGES);
+        }
+        assert_eq!(world.npcs.len(), 8);
+        world.tick(RANGES);
+        assert!(world.spawn_groups[group].despawned);
+        assert!(world.spawn_groups[group].npcs.is_empty());
+        // Only the boss is left
+        assert_eq!(world.npcs.len(), 4);
+        assert_eq!(world.grid.total_objects(), 4);
+        assert!(!world.spawn_groups[boss].despawned);
+
+        // A player walks back into the region
+        world.player_positions.insert(99999, Position::new(32780, 32800, 4));
+        world.tick(RANGES);
+        assert!(!world.spawn_groups[group].despawned);
+        assert_eq!(world.spawn_groups[group].npcs.len(), 4);
+        assert_eq!(world.npcs.len(), 8);
+        world.validate_consistency();
+
+        // While the player stays, the region stays populated
+        for _ in 0..20 {
+            world.tick(RANGES);
+        }
+        assert_eq!(world.npcs.len(), 8);
+    }
 }
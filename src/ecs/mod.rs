@@ -1,9 +1,15 @@
+pub mod arena;
 pub mod class_skills;
 pub mod components;
 pub mod combat;
+pub mod crafting;
 pub mod darkelf_skills;
 pub mod game_engine;
+pub mod party;
+pub mod recall;
 pub mod siege;
 pub mod siege_units;
 pub mod skill_executor;
+pub mod skill_learning;
+pub mod trade;
 pub mod vulcan;
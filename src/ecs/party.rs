@@ -0,0 +1,227 @@
+//! Party invitations and rosters.
+//!
+//! Ported from Java C_CreateParty / C_Attr (953) / C_LeaveParty:
+//!   - A player invites another; the target answers a yes/no dialog.
+//!   - Accepting founds a party led by the inviter, or joins the
+//!     inviter's party if they already lead one.
+//!   - A party left with a single member is dissolved.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::ecs::components::party::Party;
+
+/// S_YES_NO message: "%0 邀請您加入隊伍。(Y/N)"
+pub const MSG_PARTY_INVITE: i32 = 953;
+
+/// How long an invitation waits for the target's answer.
+pub const INVITE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Identifies a party for its whole life; it does not change when
+/// leadership passes on.
+pub type PartyId = u32;
+
+/// Why an invitation was refused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PartyError {
+    SelfTarget,
+    AlreadyInParty,
+    NotLeader,
+    PartyFull,
+    AlreadyPending,
+}
+
+/// An invitation waiting for the target's yes/no answer.
+#[derive(Debug, Clone)]
+pub struct PartyInvite {
+    pub inviter_id: i32,
+    pub yes_no_id: i32,
+    pub created_at: Instant,
+}
+
+/// An accepted invitation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PartyJoin {
+    pub party: PartyId,
+    pub inviter_id: i32,
+    pub member_id: i32,
+    /// The inviter was not in a party before; they are now its leader.
+    pub founded: bool,
+}
+
+/// All parties and outstanding invitations, keyed by object ID.
+#[derive(Debug, Default)]
+pub struct Parties {
+    parties: HashMap<PartyId, Party>,
+    member_of: HashMap<i32, PartyId>,
+    /// Keyed by target object ID.
+    invites: HashMap<i32, PartyInvite>,
+    next_party_id: PartyId,
+    next_yes_no_id: i32,
+}
+
+impl Parties {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn party_of(&self, object_id: i32) -> Option<PartyId> {
+        self.member_of.get(&object_id).copied()
+    }
+
+    pub fn get(&self, party: PartyId) -> Option<&Party> {
+        self.parties.get(&party)
+    }
+
+    /// Invite `target_id` into `inviter_id`'s party. Returns the
+    /// `yes_no_id` to send the target with S_YES_NO.
+    pub fn invite(&mut self, inviter_id: i32, target_id: i32) -> Result<i32, PartyError> {
+        if inviter_id == target_id {
+            return Err(PartyError::SelfTarget);
+        }
+        if self.member_of.contains_key(&target_id) {
+            return Err(PartyError::AlreadyInParty);
+        }
+        if let Some(party) = self.party_of(inviter_id).and_then(|id| self.parties.get(&id)) {
+            if !party.is_leader(inviter_id as u32) {
+                return Err(PartyError::NotLeader);
+            }
+            if party.is_full() {
+                return Err(PartyError::PartyFull);
+            }
+        }
+        if self.invites.get(&target_id).is_some_and(|i| i.created_at.elapsed() < INVITE_TIMEOUT) {
+            return Err(PartyError::AlreadyPending);
+        }
+        self.next_yes_no_id += 1;
+        self.invites.insert(target_id, PartyInvite {
+            inviter_id,
+            yes_no_id: self.next_yes_no_id,
+            created_at: Instant::now(),
+        });
+        Ok(self.next_yes_no_id)
+    }
+
+    /// Resolve the target's answer to dialog `yes_no_id`. Declined or
+    /// expired invitations are dropped; an answer to any other dialog
+    /// leaves the invitation alone. Also returns None if the party
+    /// filled up or either side joined another party in the meantime.
+    pub fn respond(&mut self, target_id: i32, yes_no_id: i32, accept: bool) -> Option<PartyJoin> {
+        if self.invites.get(&target_id)?.yes_no_id != yes_no_id {
+            return None;
+        }
+        let invite = self.invites.remove(&target_id)?;
+        if !accept || invite.created_at.elapsed() >= INVITE_TIMEOUT || self.member_of.contains_key(&target_id) {
+            return None;
+        }
+        let inviter_id = invite.inviter_id;
+        let (party, founded) = match self.party_of(inviter_id) {
+            Some(id) => {
+                let party = self.parties.get_mut(&id)?;
+                if !party.is_leader(inviter_id as u32) || !party.add_member(target_id as u32) {
+                    return None;
+                }
+                (id, false)
+            }
+            None => {
+                self.next_party_id += 1;
+                let id = self.next_party_id;
+                let mut party = Party::new(inviter_id as u32);
+                party.add_member(target_id as u32);
+                self.parties.insert(id, party);
+                self.member_of.insert(inviter_id, id);
+                (id, true)
+            }
+        };
+        self.member_of.insert(target_id, party);
+        Some(PartyJoin { party, inviter_id, member_id: target_id, founded })
+    }
+
+    /// Take `object_id` out of their party. Returns everyone no longer
+    /// in a party because of it: the leaver, plus the last member if the
+    /// party dissolved.
+    pub fn leave(&mut self, object_id: i32) -> Vec<i32> {
+        let Some(id) = self.member_of.remove(&object_id) else { return Vec::new() };
+        let mut left = vec![object_id];
+        if let Some(party) = self.parties.get_mut(&id) {
+            party.remove_member(object_id as u32);
+            if party.is_vacant() {
+                for member in &party.members {
+                    self.member_of.remove(&(*member as i32));
+                    left.push(*member as i32);
+                }
+                self.parties.remove(&id);
+            }
+        }
+        left
+    }
+
+    /// Drop a player's invitations and party membership when they leave
+    /// the world. Returns the same as `leave`.
+    pub fn remove_player(&mut self, object_id: i32) -> Vec<i32> {
+        self.invites.retain(|&target, i| target != object_id && i.inviter_id != object_id);
+        self.leave(object_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn join(parties: &mut Parties, inviter: i32, target: i32) -> Option<PartyJoin> {
+        let id = parties.invite(inviter, target).ok()?;
+        parties.respond(target, id, true)
+    }
+
+    #[test]
+    fn test_invite_consent_flow() {
+        let mut parties = Parties::new();
+        let id = parties.invite(1, 2).unwrap();
+        assert_eq!(parties.invite(1, 2), Err(PartyError::AlreadyPending));
+        assert_eq!(parties.invite(1, 1), Err(PartyError::SelfTarget));
+
+        // Answers to another dialog don't resolve it
+        assert_eq!(parties.respond(2, id + 1, true), None);
+        let joined = parties.respond(2, id, true).unwrap();
+        assert!(joined.founded);
+        assert_eq!((parties.party_of(1), parties.party_of(2)), (Some(joined.party), Some(joined.party)));
+        assert!(parties.get(joined.party).unwrap().is_leader(1));
+
+        // Declining joins nobody
+        let id = parties.invite(1, 3).unwrap();
+        assert_eq!(parties.respond(3, id, false), None);
+        assert!(parties.party_of(3).is_none());
+
+        // Only the leader invites, and members can't be invited elsewhere
+        assert_eq!(parties.invite(2, 3), Err(PartyError::NotLeader));
+        assert_eq!(parties.invite(4, 2), Err(PartyError::AlreadyInParty));
+        let more = join(&mut parties, 1, 3).unwrap();
+        assert_eq!((more.party, more.founded), (joined.party, false));
+    }
+
+    #[test]
+    fn test_party_full() {
+        let mut parties = Parties::new();
+        for target in 2..=8 {
+            join(&mut parties, 1, target).unwrap();
+        }
+        assert_eq!(parties.invite(1, 9), Err(PartyError::PartyFull));
+    }
+
+    #[test]
+    fn test_last_member_leaving_dissolves() {
+        let mut parties = Parties::new();
+        let party = join(&mut parties, 1, 2).unwrap().party;
+        join(&mut parties, 1, 3).unwrap();
+
+        // The leader leaving passes the lead on
+        assert_eq!(parties.leave(1), vec![1]);
+        assert!(parties.get(party).unwrap().is_leader(2));
+
+        let mut left = parties.remove_player(3);
+        left.sort();
+        assert_eq!(left, vec![2, 3]);
+        assert!(parties.get(party).is_none() && parties.party_of(2).is_none());
+        assert!(parties.leave(2).is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+//! Recall and escape teleports.
+//!
+//! Ported from Java L1SkillUse CALL_CLAN / RUN_CLAN handling and the
+//! GetBack town table:
+//!   - Recall skills summon an ally to the caster (target must accept a
+//!     yes/no dialog) or move the caster to an ally.
+//!   - Escape returns a character to the town that owns their area.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::ecs::components::position::tile_distance;
+use crate::protocol::client::char_create::{START_MAP, START_X, START_Y};
+use crate::protocol::client::skill::{SKILL_CALL_ALLY, SKILL_RUN_ALLY, SKILL_TELEPORT};
+
+/// S_YES_NO message: "盟主正在呼喚你，你要接受他的呼喚嗎？(Y/N)"
+pub const MSG_CALL_CLAN_CONFIRM: i32 = 729;
+
+/// How long a recall request waits for the target's answer.
+pub const RECALL_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// A town escape destination.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TownLocation {
+    pub name: &'static str,
+    pub x: i32,
+    pub y: i32,
+    pub map_id: i32,
+}
+
+/// Towns on the mainland (map 4) and Talking Island (map 0).
+pub const TOWNS: &[TownLocation] = &[
+    TownLocation { name: "說話之島", x: 32583, y: 32929, map_id: 0 },
+    TownLocation { name: "古魯丁村", x: 32608, y: 32735, map_id: 4 },
+    TownLocation { name: "肯特村", x: 33050, y: 32780, map_id: 4 },
+    TownLocation { name: "風木村", x: 32612, y: 33188, map_id: 4 },
+    TownLocation { name: "銀騎士村", x: 33080, y: 33392, map_id: 4 },
+    TownLocation { name: "奇岩村", x: 33430, y: 32815, map_id: 4 },
+    TownLocation { name: "海音村", x: 33611, y: 33253, map_id: 4 },
+    TownLocation { name: "歐瑞村", x: 34060, y: 32281, map_id: 4 },
+];
+
+/// Town used when a map has no town of its own (dungeons, special maps).
+pub const DEFAULT_TOWN: TownLocation = TownLocation { name: "奇岩村", x: 33430, y: 32815, map_id: 4 };
+
+/// Whether a C_USESKILL is an escape: Teleport cast without picking a
+/// bookmark sends the caster back to town.
+pub fn is_escape(skill_id: i32, bookmark_id: i32) -> bool {
+    skill_id == SKILL_TELEPORT && bookmark_id == 0
+}
+
+/// Where an escape from (map_id, x, y) lands.
+///
+/// Maps with towns return to the nearest one; the beginner map returns
+/// to its own start point; everything else falls back to Giran.
+pub fn escape_destination(map_id: i32, x: i32, y: i32) -> TownLocation {
+    if map_id == START_MAP {
+        return TownLocation { name: "新手村", x: START_X, y: START_Y, map_id: START_MAP };
+    }
+    TOWNS
+        .iter()
+        .filter(|t| t.map_id == map_id)
+        .min_by_key(|t| tile_distance(t.x, t.y, x, y))
+        .copied()
+        .unwrap_or(DEFAULT_TOWN)
+}
+
+/// Which way a recall skill moves people.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecallKind {
+    /// Pull the target to the caster (needs the target's consent).
+    SummonAlly,
+    /// Move the caster to the target.
+    JoinAlly,
+}
+
+/// Per-skill recall rules.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecallRule {
+    pub skill_id: i32,
+    pub kind: RecallKind,
+    pub cross_map: bool,
+}
+
+/// Recall skills: 呼喚盟友 works across maps, 援護盟友 only on the same map.
+pub const RECALL_RULES: &[RecallRule] = &[
+    RecallRule { skill_id: SKILL_CALL_ALLY, kind: RecallKind::SummonAlly, cross_map: true },
+    RecallRule { skill_id: SKILL_RUN_ALLY, kind: RecallKind::JoinAlly, cross_map: false },
+];
+
+pub fn recall_rule(skill_id: i32) -> Option<RecallRule> {
+    RECALL_RULES.iter().find(|r| r.skill_id == skill_id).copied()
+}
+
+/// Why a recall was refused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecallError {
+    NotRecallSkill,
+    DifferentMap,
+    SelfTarget,
+    AlreadyPending,
+}
+
+/// A summon waiting for the target's yes/no answer.
+#[derive(Debug, Clone)]
+pub struct PendingRecall {
+    pub caster_id: i32,
+    pub target_id: i32,
+    pub skill_id: i32,
+    pub dest_x: i32,
+    pub dest_y: i32,
+    pub dest_map: i32,
+    pub yes_no_id: i32,
+    pub created_at: Instant,
+}
+
+/// Where to send someone once a recall resolves.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecallMove {
+    pub object_id: i32,
+    pub x: i32,
+    pub y: i32,
+    pub map_id: i32,
+}
+
+/// Outstanding recall requests, keyed by target object ID.
+#[derive(Debug, Default)]
+pub struct RecallRequests {
+    pending: HashMap<i32, PendingRecall>,
+    next_yes_no_id: i32,
+}
+
+/// Position of one side of a recall: (object_id, x, y, map_id).
+pub type RecallPos = (i32, i32, i32, i32);
+
+impl RecallRequests {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a recall.
+    ///
+    /// `JoinAlly` resolves immediately and returns the caster's move;
+    /// `SummonAlly` queues a request and returns `Ok(None)` — send the
+    /// target S_YES_NO with the pending request's `yes_no_id`.
+    pub fn request(
+        &mut self,
+        skill_id: i32,
+        caster: RecallPos,
+        target: RecallPos,
+    ) -> Result<Option<RecallMove>, RecallError> {
+        let rule = recall_rule(skill_id).ok_or(RecallError::NotRecallSkill)?;
+        let (caster_id, cx, cy, cmap) = caster;
+        let (target_id, tx, ty, tmap) = target;
+        if caster_id == target_id {
+            return Err(RecallError::SelfTarget);
+        }
+        if !rule.cross_map && cmap != tmap {
+            return Err(RecallError::DifferentMap);
+        }
+
+        match rule.kind {
+            RecallKind::JoinAlly => Ok(Some(RecallMove { object_id: caster_id, x: tx, y: ty, map_id: tmap })),
+            RecallKind::SummonAlly => {
+                if self.pending.get(&target_id).is_some_and(|p| p.created_at.elapsed() < RECALL_TIMEOUT) {
+                    return Err(RecallError::AlreadyPending);
+                }
+                self.next_yes_no_id += 1;
+                self.pending.insert(target_id, PendingRecall {
+                    caster_id,
+                    target_id,
+                    skill_id,
+                    dest_x: cx,
+                    dest_y: cy,
+                    dest_map: cmap,
+                    yes_no_id: self.next_yes_no_id,
+                    created_at: Instant::now(),
+                });
+                Ok(None)
+            }
+        }
+    }
+
+    /// The pending request for a target, if any.
+    pub fn pending_for(&self, target_id: i32) -> Option<&PendingRecall> {
+        self.pending.get(&target_id)
+    }
+
+    /// Resolve the target's answer to dialog `yes_no_id`. Returns the
+    /// target's move if they accepted a live request; declined or expired
+    /// requests are dropped. An answer to any other dialog (a stale or
+    /// forged id) leaves the pending request alone.
+    pub fn respond(&mut self, target_id: i32, yes_no_id: i32, accept: bool) -> Option<RecallMove> {
+        if self.pending.get(&target_id)?.yes_no_id != yes_no_id {
+            return None;
+        }
+        let req = self.pending.remove(&target_id)?;
+        if !accept || req.created_at.elapsed() >= RECALL_TIMEOUT {
+            return None;
+        }
+        Some(RecallMove { object_id: target_id, x: req.dest_x, y: req.dest_y, map_id: req.dest_map })
+    }
+
+    /// Drop a player's requests when they leave the world.
+    pub fn remove_player(&mut self, object_id: i32) {
+        self.pending.retain(|&target, p| target != object_id && p.caster_id != object_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recall_consent_flow() {
+        let mut recalls = RecallRequests::new();
+        let caster = (1, 33430, 32815, 4);
+        let target = (2, 32700, 32800, 0);
+
+        // Summon is queued until the target answers
+        assert_eq!(recalls.request(116, caster, target), Ok(None));
+        assert!(recalls.pending_for(2).is_some());
+        assert_eq!(recalls.request(116, caster, target), Err(RecallError::AlreadyPending));
+
+        // Answers to another dialog don't resolve it
+        let id = recalls.pending_for(2).unwrap().yes_no_id;
+        assert_eq!(recalls.respond(2, id + 1, true), None);
+        assert!(recalls.pending_for(2).is_some());
+
+        // Accepting moves the target to the caster
+        assert_eq!(
+            recalls.respond(2, id, true),
+            Some(RecallMove { object_id: 2, x: 33430, y: 32815, map_id: 4 })
+        );
+        assert!(recalls.pending_for(2).is_none());
+
+        // Declining moves nobody
+        recalls.request(116, caster, target).unwrap();
+        let id = recalls.pending_for(2).unwrap().yes_no_id;
+        assert_eq!(recalls.respond(2, id, false), None);
+        assert_eq!(recalls.respond(2, id, true), None);
+    }
+
+    #[test]
+    fn test_recall_map_rules() {
+        let mut recalls = RecallRequests::new();
+        // 援護盟友 is same-map only and needs no consent
+        assert_eq!(
+            recalls.request(118, (1, 0, 0, 4), (2, 10, 10, 0)),
+            Err(RecallError::DifferentMap)
+        );
+        assert_eq!(
+            recalls.request(118, (1, 0, 0, 4), (2, 10, 10, 4)),
+            Ok(Some(RecallMove { object_id: 1, x: 10, y: 10, map_id: 4 }))
+        );
+        assert_eq!(recalls.request(116, (1, 0, 0, 4), (1, 0, 0, 4)), Err(RecallError::SelfTarget));
+        assert_eq!(recalls.request(9, (1, 0, 0, 4), (2, 0, 0, 4)), Err(RecallError::NotRecallSkill));
+    }
+
+    #[test]
+    fn test_escape_returns_to_correct_town() {
+        // Near Kent → Kent
+        assert_eq!(escape_destination(4, 33060, 32790).name, "肯特村");
+        // Near Giran → Giran
+        assert_eq!(escape_destination(4, 33400, 32800).name, "奇岩村");
+        // Talking Island
+        assert_eq!(escape_destination(0, 32600, 32900).map_id, 0);
+        // Beginner map → its own start point
+        let dest = escape_destination(START_MAP, 32700, 32850);
+        assert_eq!((dest.x, dest.y, dest.map_id), (START_X, START_Y, START_MAP));
+        // Dungeon → default town
+        assert_eq!(escape_destination(7, 32700, 32800), DEFAULT_TOWN);
+    }
+}
@@ -7,6 +7,11 @@
 
 use std::collections::HashMap;
 
+use crate::ecs::components::clan::{ClanData, ADENA_ITEM_ID};
+use crate::ecs::components::item::Inventory;
+use crate::ecs::siege_units::SiegeUnitManager;
+use crate::world::persist::PersistentState;
+
 // ---------------------------------------------------------------------------
 // Castle definitions (from L1CastleLocation.java)
 // ---------------------------------------------------------------------------
@@ -142,6 +147,121 @@ impl ActiveWar {
     }
 }
 
+// ---------------------------------------------------------------------------
+// Siege registration
+// ---------------------------------------------------------------------------
+
+/// Adena a clan leader pays to register for a castle's siege.
+pub const SIEGE_REGISTRATION_FEE: i32 = 100_000;
+
+/// Registration closes this many seconds before the war starts.
+pub const SIEGE_REGISTRATION_CLOSE_SECS: i64 = 60 * 60;
+
+/// Why a siege registration was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationError {
+    UnknownCastle,
+    /// Only the clan leader may register.
+    NotLeader,
+    /// The clan already owns this castle; it defends.
+    OwnCastle,
+    AlreadyRegistered,
+    /// Past the registration deadline (or no war scheduled).
+    Closed,
+    /// The leader doesn't carry the fee.
+    InsufficientFee { needed: i32 },
+}
+
+/// The character paying for a registration.
+pub struct SiegePayer<'a> {
+    pub char_id: i32,
+    pub inventory: &'a mut Inventory,
+}
+
+// ---------------------------------------------------------------------------
+// Weekly siege schedule (from WarTimeController.java)
+// ---------------------------------------------------------------------------
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+const WEEK_SECS: i64 = 7 * DAY_SECS;
+
+/// Schedules are written in server local time (UTC+8).
+pub const SIEGE_UTC_OFFSET_SECS: i64 = 8 * 60 * 60;
+
+/// When a castle's siege recurs each week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SiegeSchedule {
+    /// Day of the week, 0 = Sunday .. 6 = Saturday.
+    pub weekday: i64,
+    pub start_hour: i64,
+    pub duration_mins: i64,
+}
+
+impl SiegeSchedule {
+    /// The siege window (start, end as unix seconds) in progress at
+    /// `now`, or else the next one to begin.
+    pub fn next_window(&self, now: i64) -> (i64, i64) {
+        let local = now + SIEGE_UTC_OFFSET_SECS;
+        let day = local.div_euclid(DAY_SECS);
+        // 1970-01-01 was a Thursday
+        let today = (day + 4).rem_euclid(7);
+        let ahead = (self.weekday - today).rem_euclid(7);
+        let duration = self.duration_mins * 60;
+        // Start a week early so a window running past midnight is found
+        let mut start = (day + ahead) * DAY_SECS + self.start_hour * 3600 - WEEK_SECS;
+        while start + duration <= local {
+            start += WEEK_SECS;
+        }
+        (start - SIEGE_UTC_OFFSET_SECS, start - SIEGE_UTC_OFFSET_SECS + duration)
+    }
+}
+
+/// Where a scheduled castle is in its weekly cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SiegePhase {
+    /// Clans may register for the coming siege.
+    Registration,
+    /// Registration has closed; the siege starts soon.
+    Closed,
+    Active,
+}
+
+/// A scheduled castle entered a new phase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhaseChange {
+    pub castle_id: i32,
+    pub phase: SiegePhase,
+}
+
+// ---------------------------------------------------------------------------
+// Castle tax and treasury
+// ---------------------------------------------------------------------------
+
+/// Lowest and highest tax rate (percent) an owner may set.
+pub const MIN_TAX_RATE: i32 = 10;
+pub const MAX_TAX_RATE: i32 = 50;
+
+/// Why a tax or treasury request was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreasuryError {
+    UnknownCastle,
+    /// The clan does not own this castle.
+    NotOwner,
+    /// Only the owning clan's leader may manage the treasury.
+    NotLeader,
+    /// Rate outside `MIN_TAX_RATE..=MAX_TAX_RATE`.
+    InvalidRate,
+    /// The treasury holds less than requested.
+    InsufficientFunds { available: i32 },
+}
+
+/// Why a taxed shop purchase was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PurchaseError {
+    /// The buyer carries less than the tax-inclusive total.
+    InsufficientAdena { needed: i32 },
+}
+
 // ---------------------------------------------------------------------------
 // Door mechanics (from L1DoorInstance.java)
 // ---------------------------------------------------------------------------
@@ -262,6 +382,10 @@ impl TowerState {
 // Crown (王冠) mechanics - spawns when tower is destroyed
 // ---------------------------------------------------------------------------
 
+/// Ticks an attacker must hold the crown without interruption to take
+/// the castle (10 seconds).
+pub const CROWN_CAPTURE_TICKS: u32 = 50;
+
 /// Crown state - only exists after tower destruction during active war.
 #[derive(Debug, Clone)]
 pub struct CrownState {
@@ -270,6 +394,51 @@ pub struct CrownState {
     pub x: i32,
     pub y: i32,
     pub map_id: i32,
+    /// Character currently standing on the crown (0 = nobody).
+    pub capturer_id: u32,
+    /// Consecutive ticks `capturer_id` has held it.
+    pub capture_ticks: u32,
+}
+
+impl CrownState {
+    pub fn new(castle_id: i32, x: i32, y: i32, map_id: i32) -> Self {
+        CrownState { object_id: 0, castle_id, x, y, map_id, capturer_id: 0, capture_ticks: 0 }
+    }
+
+    /// Advance the capture by one tick with `holder` (if any) standing on
+    /// the crown. A different holder, or nobody, restarts the count.
+    /// Returns true once the holder has stayed `CROWN_CAPTURE_TICKS`.
+    pub fn tick_capture(&mut self, holder: Option<u32>) -> bool {
+        match holder {
+            Some(id) if id == self.capturer_id => self.capture_ticks += 1,
+            Some(id) => {
+                self.capturer_id = id;
+                self.capture_ticks = 1;
+            }
+            None => {
+                self.capturer_id = 0;
+                self.capture_ticks = 0;
+            }
+        }
+        self.capturer_id != 0 && self.capture_ticks >= CROWN_CAPTURE_TICKS
+    }
+}
+
+/// The character standing on a crown this tick.
+#[derive(Debug, Clone)]
+pub struct CrownOccupant {
+    pub char_id: u32,
+    pub clan_id: i32,
+    pub clan_name: String,
+}
+
+/// A castle changed hands. Its guards are already back at their posts
+/// for the new owner; the caller announces the result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CastleCaptured {
+    pub castle_id: i32,
+    pub clan_id: i32,
+    pub clan_name: String,
 }
 
 // ---------------------------------------------------------------------------
@@ -286,6 +455,12 @@ pub struct SiegeManager {
     pub castle_info: Vec<CastleInfo>,
     /// Aden sub-tower destruction count (need 3+ to attack main tower).
     pub aden_sub_towers_destroyed: i32,
+    /// Clans registered to attack, keyed by castle ID.
+    pub registrations: HashMap<i32, Vec<String>>,
+    /// Weekly siege schedule, keyed by castle ID.
+    pub schedules: HashMap<i32, SiegeSchedule>,
+    /// Last phase `tick_schedule` saw for each scheduled castle.
+    phases: HashMap<i32, SiegePhase>,
 }
 
 impl SiegeManager {
@@ -298,7 +473,162 @@ impl SiegeManager {
             crowns: Vec::new(),
             castle_info: get_castle_info(),
             aden_sub_towers_destroyed: 0,
+            registrations: HashMap::new(),
+            schedules: HashMap::new(),
+            phases: HashMap::new(),
+        }
+    }
+
+    /// Give `castle_id` a weekly siege schedule.
+    pub fn set_schedule(&mut self, castle_id: i32, schedule: SiegeSchedule) {
+        self.schedules.insert(castle_id, schedule);
+        self.phases.remove(&castle_id);
+    }
+
+    /// Phase of a scheduled castle at `now`.
+    pub fn phase_at(&self, castle_id: i32, now: i64) -> Option<SiegePhase> {
+        let (start, _) = self.schedules.get(&castle_id)?.next_window(now);
+        Some(if now >= start {
+            SiegePhase::Active
+        } else if now >= start - SIEGE_REGISTRATION_CLOSE_SECS {
+            SiegePhase::Closed
+        } else {
+            SiegePhase::Registration
+        })
+    }
+
+    /// Drive scheduled castles through their weekly cycle.
+    ///
+    /// Keeps each castle's `war_time` on its next window so registration
+    /// checks the right deadline, and when a window opens starts the
+    /// castle war with the registered clans as attackers (`defender`
+    /// names the owning clan). Returns the castles that changed phase.
+    pub fn tick_schedule(&mut self, now: i64, defender: impl Fn(i32) -> String) -> Vec<PhaseChange> {
+        let mut changes = Vec::new();
+        let mut scheduled: Vec<i32> = self.schedules.keys().copied().collect();
+        scheduled.sort_unstable();
+        for castle_id in scheduled {
+            let Some(castle) = self.castles.get_mut(&castle_id) else { continue };
+            let (start, end) = self.schedules[&castle_id].next_window(now);
+            castle.war_time = start;
+            let owner = castle.owner_clan_id;
+
+            let phase = self.phase_at(castle_id, now).expect("castle is scheduled");
+            if self.phases.insert(castle_id, phase) == Some(phase) {
+                continue;
+            }
+            if phase == SiegePhase::Active && !self.is_now_war(castle_id) {
+                let attackers = self.registrations.remove(&castle_id).unwrap_or_default();
+                self.active_wars.push(ActiveWar {
+                    attack_clans: attackers,
+                    ..ActiveWar::new_castle_war(String::new(), defender(owner), castle_id, end)
+                });
+            }
+            changes.push(PhaseChange { castle_id, phase });
         }
+        changes
+    }
+
+    /// Register `clan` to attack `castle_id` at its next war.
+    ///
+    /// `payer` must be the clan leader and carry `SIEGE_REGISTRATION_FEE`
+    /// adena, and `now` must be before the deadline (one hour before the
+    /// castle's war time). The fee is only taken once every check passes.
+    pub fn register_clan(
+        &mut self,
+        castle_id: i32,
+        clan: &ClanData,
+        payer: SiegePayer<'_>,
+        now: i64,
+    ) -> Result<(), RegistrationError> {
+        let castle = self.castles.get(&castle_id).ok_or(RegistrationError::UnknownCastle)?;
+        if payer.char_id != clan.leader_id {
+            return Err(RegistrationError::NotLeader);
+        }
+        if clan.castle_id == castle_id || castle.owner_clan_id == clan.clan_id {
+            return Err(RegistrationError::OwnCastle);
+        }
+        let registered = self.registrations.entry(castle_id).or_default();
+        if registered.contains(&clan.clan_name) {
+            return Err(RegistrationError::AlreadyRegistered);
+        }
+        if castle.war_time <= 0 || now >= castle.war_time - SIEGE_REGISTRATION_CLOSE_SECS {
+            return Err(RegistrationError::Closed);
+        }
+        if !payer.inventory.consume_item_id(ADENA_ITEM_ID, SIEGE_REGISTRATION_FEE) {
+            return Err(RegistrationError::InsufficientFee { needed: SIEGE_REGISTRATION_FEE });
+        }
+        registered.push(clan.clan_name.clone());
+        Ok(())
+    }
+
+    /// Check that `char_id` leads the clan that owns `castle_id`.
+    fn check_lord(&self, castle_id: i32, clan: &ClanData, char_id: i32) -> Result<&CastleData, TreasuryError> {
+        let castle = self.castles.get(&castle_id).ok_or(TreasuryError::UnknownCastle)?;
+        if castle.owner_clan_id == 0 || castle.owner_clan_id != clan.clan_id {
+            return Err(TreasuryError::NotOwner);
+        }
+        if char_id != clan.leader_id {
+            return Err(TreasuryError::NotLeader);
+        }
+        Ok(castle)
+    }
+
+    /// Set the tax rate of the castle's town. Leader of the owning clan only.
+    pub fn set_tax_rate(&mut self, castle_id: i32, clan: &ClanData, char_id: i32, rate: i32) -> Result<(), TreasuryError> {
+        self.check_lord(castle_id, clan, char_id)?;
+        if !(MIN_TAX_RATE..=MAX_TAX_RATE).contains(&rate) {
+            return Err(TreasuryError::InvalidRate);
+        }
+        if let Some(castle) = self.castles.get_mut(&castle_id) {
+            castle.tax_rate = rate;
+        }
+        Ok(())
+    }
+
+    /// Charge `buyer` for a shop purchase of `price` adena in a town
+    /// under `castle_id`.
+    ///
+    /// The tax-inclusive total is taken from the buyer first; only once
+    /// that succeeded does the tax go into the castle treasury. Unowned
+    /// castles don't tax. Returns the total paid.
+    pub fn apply_purchase_tax(
+        &mut self,
+        castle_id: i32,
+        price: i32,
+        buyer: &mut Inventory,
+    ) -> Result<i32, PurchaseError> {
+        let tax = match self.castles.get(&castle_id) {
+            Some(castle) if castle.owner_clan_id != 0 => (price as i64 * castle.tax_rate as i64 / 100) as i32,
+            _ => 0,
+        };
+        let total = price.saturating_add(tax);
+        if !buyer.consume_item_id(ADENA_ITEM_ID, total) {
+            return Err(PurchaseError::InsufficientAdena { needed: total });
+        }
+        if let Some(castle) = self.castles.get_mut(&castle_id) {
+            castle.public_money = castle.public_money.saturating_add(tax);
+        }
+        Ok(total)
+    }
+
+    /// Take `amount` adena out of the castle treasury. Leader of the
+    /// owning clan only; the caller hands the returned adena to them.
+    pub fn withdraw_treasury(
+        &mut self,
+        castle_id: i32,
+        clan: &ClanData,
+        char_id: i32,
+        amount: i32,
+    ) -> Result<i32, TreasuryError> {
+        let available = self.check_lord(castle_id, clan, char_id)?.public_money;
+        if amount <= 0 || amount > available {
+            return Err(TreasuryError::InsufficientFunds { available });
+        }
+        if let Some(castle) = self.castles.get_mut(&castle_id) {
+            castle.public_money -= amount;
+        }
+        Ok(amount)
     }
 
     /// Check if a castle is currently at war.
@@ -332,6 +662,37 @@ impl SiegeManager {
         self.active_wars.iter().find(|w| w.involves_clan(clan_name))
     }
 
+    /// Spawn a castle door with the state it was saved in, if any.
+    pub fn spawn_door(&mut self, mut door: DoorState, saved: &PersistentState) {
+        saved.restore_door(&mut door);
+        self.doors.push(door);
+    }
+
+    /// Damage a door and record its new HP. Returns whether it was
+    /// destroyed, or None if there is no such door.
+    pub fn damage_door(&mut self, object_id: u32, damage: i32, saved: &mut PersistentState) -> Option<bool> {
+        let door = self.doors.iter_mut().find(|d| d.object_id == object_id)?;
+        let destroyed = door.receive_damage(damage);
+        saved.record_door(door);
+        Some(destroyed)
+    }
+
+    /// Open or close a door and record it. False if there is no such door.
+    pub fn set_door_open(&mut self, object_id: u32, open: bool, saved: &mut PersistentState) -> bool {
+        let Some(door) = self.doors.iter_mut().find(|d| d.object_id == object_id) else { return false };
+        door.is_open = open;
+        saved.record_door(door);
+        true
+    }
+
+    /// Repair and close every door of a castle (after its war).
+    pub fn repair_doors(&mut self, castle_id: i32, saved: &mut PersistentState) {
+        for door in self.doors.iter_mut().filter(|d| d.castle_id == castle_id) {
+            door.repair();
+            saved.record_door(door);
+        }
+    }
+
     /// Handle tower destruction - spawn crown if appropriate.
     pub fn on_tower_destroyed(&mut self, tower: &TowerState) -> bool {
         // Check Aden sub-tower logic
@@ -345,19 +706,56 @@ impl SiegeManager {
         }
 
         // Spawn crown at tower location
-        self.crowns.push(CrownState {
-            object_id: 0, // caller assigns
-            castle_id: tower.castle_id,
-            x: tower.x,
-            y: tower.y,
-            map_id: tower.map_id,
-        });
+        // object_id: caller assigns
+        self.crowns.push(CrownState::new(tower.castle_id, tower.x, tower.y, tower.map_id));
 
         true // crown spawned
     }
 
-    /// Check war timers - expire wars that have timed out.
-    pub fn tick_war_timers(&mut self) -> Vec<i32> {
+    /// Is `clan_name` signed up to attack `castle_id`?
+    pub fn is_attacker(&self, castle_id: i32, clan_name: &str) -> bool {
+        self.registrations.get(&castle_id).is_some_and(|r| r.iter().any(|c| c == clan_name))
+            || self.active_wars.iter().any(|w| {
+                w.castle_id == castle_id && w.is_active && w.attack_clans.iter().any(|c| c == clan_name)
+            })
+    }
+
+    /// Advance every crown's capture timer.
+    ///
+    /// `occupant` reports who stands on a crown this tick; only members
+    /// of an attacking clan count, anyone else (or a death, or stepping
+    /// off) resets the timer. A completed capture gives the castle to
+    /// the capturer's clan, ends its war, removes the crown and
+    /// respawns the castle's guards.
+    pub fn tick_crowns(
+        &mut self,
+        units: &mut SiegeUnitManager,
+        occupant: impl Fn(&CrownState) -> Option<CrownOccupant>,
+    ) -> Vec<CastleCaptured> {
+        let mut captured = Vec::new();
+        for i in 0..self.crowns.len() {
+            let castle_id = self.crowns[i].castle_id;
+            let holder = occupant(&self.crowns[i]).filter(|o| self.is_attacker(castle_id, &o.clan_name));
+            if self.crowns[i].tick_capture(holder.as_ref().map(|o| o.char_id)) {
+                let o = holder.expect("capture completes only with a holder");
+                captured.push(CastleCaptured { castle_id, clan_id: o.clan_id, clan_name: o.clan_name });
+            }
+        }
+        for c in &captured {
+            if let Some(castle) = self.castles.get_mut(&c.castle_id) {
+                castle.owner_clan_id = c.clan_id;
+            }
+            self.crowns.retain(|crown| crown.castle_id != c.castle_id);
+            self.active_wars.retain(|w| w.castle_id != c.castle_id);
+            self.registrations.remove(&c.castle_id);
+            units.respawn_guards(c.castle_id);
+        }
+        captured
+    }
+
+    /// Check war timers - expire wars that have timed out. Each ended
+    /// castle gets its guards back at their posts.
+    pub fn tick_war_timers(&mut self, units: &mut SiegeUnitManager) -> Vec<i32> {
         let mut ended_castle_ids = Vec::new();
         for war in &mut self.active_wars {
             if war.is_active && war.is_expired() {
@@ -366,6 +764,9 @@ impl SiegeManager {
             }
         }
         self.active_wars.retain(|w| w.is_active);
+        for &castle_id in &ended_castle_ids {
+            units.respawn_guards(castle_id);
+        }
         ended_castle_ids
     }
 }
@@ -417,6 +818,30 @@ mod tests {
         assert_eq!(door.cur_hp, 600);
     }
 
+    #[test]
+    fn test_door_changes_survive_respawn() {
+        let door = DoorState {
+            object_id: 1, castle_id: 1, max_hp: 600, cur_hp: 600,
+            is_open: false, direction: 0, x: 100, y: 200, map_id: 4,
+        };
+        let mut saved = PersistentState::new();
+        let mut siege = SiegeManager::new();
+        siege.spawn_door(door.clone(), &saved);
+        assert_eq!(siege.damage_door(1, 200, &mut saved), Some(false));
+        assert!(siege.set_door_open(1, true, &mut saved));
+        assert_eq!(siege.damage_door(2, 200, &mut saved), None);
+
+        // After a restart the door comes back as it was left
+        let mut restarted = SiegeManager::new();
+        restarted.spawn_door(door, &saved);
+        assert_eq!((restarted.doors[0].cur_hp, restarted.doors[0].is_open), (400, true));
+
+        restarted.repair_doors(1, &mut saved);
+        let mut again = SiegeManager::new();
+        again.spawn_door(restarted.doors[0].clone(), &saved);
+        assert_eq!((again.doors[0].cur_hp, again.doors[0].is_open), (600, false));
+    }
+
     #[test]
     fn test_tower_crack() {
         let mut tower = TowerState {
@@ -476,6 +901,8 @@ mod tests {
 
     #[test]
     fn test_war_timer_expiry() {
+        use crate::ecs::siege_units::{GuardPost, GuardType};
+
         let mut mgr = SiegeManager::new();
         mgr.active_wars.push(ActiveWar {
             war_type: WarType::CastleWar,
@@ -486,8 +913,279 @@ mod tests {
             is_active: true,
         });
 
-        let ended = mgr.tick_war_timers();
+        let mut units = SiegeUnitManager::new();
+        units.station_guard(1, GuardPost {
+            object_id: 1, guard_type: GuardType::RoyalGuard, x: 100, y: 190, map_id: 15, heading: 0,
+        });
+        units.guards.get_mut(&1).unwrap().receive_damage(99_999);
+
+        let ended = mgr.tick_war_timers(&mut units);
         assert_eq!(ended, vec![1]);
         assert!(mgr.active_wars.is_empty());
+        assert_eq!(units.alive_guard_count(1), 1);
+    }
+
+    fn registration_setup() -> (SiegeManager, ClanData, Inventory) {
+        use crate::ecs::components::item::{ItemInstance, ItemTemplate};
+
+        let mut mgr = SiegeManager::new();
+        mgr.castles.insert(KENT_CASTLE_ID, CastleData {
+            castle_id: KENT_CASTLE_ID, name: "Kent".into(), war_time: 1_000_000,
+            tax_rate: 10, public_money: 0, owner_clan_id: 50,
+        });
+        let clan = ClanData::new(60, "Attackers".into(), 1001, "Leader".into());
+        let mut inv = Inventory::new();
+        let adena = ItemTemplate { item_id: ADENA_ITEM_ID, stackable: true, ..Default::default() };
+        inv.add_item(ItemInstance { count: SIEGE_REGISTRATION_FEE, ..ItemInstance::new(1, ADENA_ITEM_ID) }, &adena);
+        (mgr, clan, inv)
+    }
+
+    #[test]
+    fn test_registration_requires_leader() {
+        let (mut mgr, clan, mut inv) = registration_setup();
+        let payer = SiegePayer { char_id: 1002, inventory: &mut inv };
+        assert_eq!(mgr.register_clan(KENT_CASTLE_ID, &clan, payer, 0), Err(RegistrationError::NotLeader));
+        assert!(inv.check_item(ADENA_ITEM_ID, SIEGE_REGISTRATION_FEE));
+    }
+
+    #[test]
+    fn test_registration_requires_fee() {
+        let (mut mgr, clan, mut inv) = registration_setup();
+        inv.consume_item_id(ADENA_ITEM_ID, 1);
+        let payer = SiegePayer { char_id: 1001, inventory: &mut inv };
+        assert_eq!(
+            mgr.register_clan(KENT_CASTLE_ID, &clan, payer, 0),
+            Err(RegistrationError::InsufficientFee { needed: SIEGE_REGISTRATION_FEE })
+        );
+        assert!(inv.check_item(ADENA_ITEM_ID, SIEGE_REGISTRATION_FEE - 1));
+        assert!(mgr.registrations.get(&KENT_CASTLE_ID).is_none_or(|r| r.is_empty()));
+    }
+
+    #[test]
+    fn test_registration_success_and_deadline() {
+        let (mut mgr, clan, mut inv) = registration_setup();
+        let deadline = 1_000_000 - SIEGE_REGISTRATION_CLOSE_SECS;
+
+        let payer = SiegePayer { char_id: 1001, inventory: &mut inv };
+        assert_eq!(mgr.register_clan(KENT_CASTLE_ID, &clan, payer, deadline), Err(RegistrationError::Closed));
+
+        let payer = SiegePayer { char_id: 1001, inventory: &mut inv };
+        assert_eq!(mgr.register_clan(KENT_CASTLE_ID, &clan, payer, deadline - 1), Ok(()));
+        assert_eq!(mgr.registrations[&KENT_CASTLE_ID], vec!["Attackers".to_string()]);
+        assert!(!inv.check_item(ADENA_ITEM_ID, 1));
+
+        let payer = SiegePayer { char_id: 1001, inventory: &mut inv };
+        assert_eq!(mgr.register_clan(KENT_CASTLE_ID, &clan, payer, 0), Err(RegistrationError::AlreadyRegistered));
+
+        // The owner defends instead
+        let owner = ClanData::new(50, "Owners".into(), 2001, "Lord".into());
+        let payer = SiegePayer { char_id: 2001, inventory: &mut inv };
+        assert_eq!(mgr.register_clan(KENT_CASTLE_ID, &owner, payer, 0), Err(RegistrationError::OwnCastle));
+    }
+
+    fn capture_setup() -> SiegeManager {
+        let mut mgr = SiegeManager::new();
+        mgr.castles.insert(KENT_CASTLE_ID, CastleData {
+            castle_id: KENT_CASTLE_ID, name: "Kent".into(), war_time: 0,
+            tax_rate: 10, public_money: 0, owner_clan_id: 50,
+        });
+        mgr.active_wars.push(ActiveWar::new_castle_war("Attackers".into(), "Owners".into(), KENT_CASTLE_ID, i64::MAX));
+        mgr.crowns.push(CrownState::new(KENT_CASTLE_ID, 100, 200, 15));
+        mgr
+    }
+
+    fn attacker(char_id: u32) -> Option<CrownOccupant> {
+        Some(CrownOccupant { char_id, clan_id: 60, clan_name: "Attackers".into() })
+    }
+
+    #[test]
+    fn test_crown_capture_completes() {
+        use crate::ecs::siege_units::{GuardPost, GuardType};
+
+        let mut mgr = capture_setup();
+        let mut units = SiegeUnitManager::new();
+        units.station_guard(KENT_CASTLE_ID, GuardPost {
+            object_id: 1, guard_type: GuardType::RoyalGuard, x: 100, y: 190, map_id: 15, heading: 0,
+        });
+        units.guards.get_mut(&1).unwrap().receive_damage(99_999);
+
+        for _ in 1..CROWN_CAPTURE_TICKS {
+            assert!(mgr.tick_crowns(&mut units, |_| attacker(1001)).is_empty());
+        }
+        assert_eq!(units.alive_guard_count(KENT_CASTLE_ID), 0);
+        let captured = mgr.tick_crowns(&mut units, |_| attacker(1001));
+        assert_eq!(captured, vec![CastleCaptured { castle_id: KENT_CASTLE_ID, clan_id: 60, clan_name: "Attackers".into() }]);
+        assert_eq!(mgr.castles[&KENT_CASTLE_ID].owner_clan_id, 60);
+        assert!(mgr.crowns.is_empty());
+        assert!(!mgr.is_now_war(KENT_CASTLE_ID));
+
+        // The capture itself puts the guards back at their posts
+        assert_eq!(units.alive_guard_count(KENT_CASTLE_ID), 1);
+    }
+
+    #[test]
+    fn test_crown_capture_resets_on_interruption() {
+        let mut mgr = capture_setup();
+        let mut units = SiegeUnitManager::new();
+        let almost = CROWN_CAPTURE_TICKS - 1;
+
+        // Stepping off (or dying) resets the timer
+        for _ in 0..almost {
+            mgr.tick_crowns(&mut units, |_| attacker(1001));
+        }
+        assert!(mgr.tick_crowns(&mut units, |_| None).is_empty());
+        assert_eq!(mgr.crowns[0].capture_ticks, 0);
+
+        // Another attacker stepping on starts over
+        for _ in 0..almost {
+            mgr.tick_crowns(&mut units, |_| attacker(1001));
+        }
+        assert!(mgr.tick_crowns(&mut units, |_| attacker(1002)).is_empty());
+        assert_eq!((mgr.crowns[0].capturer_id, mgr.crowns[0].capture_ticks), (1002, 1));
+
+        // Non-attackers never capture
+        let outsider = || Some(CrownOccupant { char_id: 3000, clan_id: 70, clan_name: "Bystanders".into() });
+        for _ in 0..CROWN_CAPTURE_TICKS * 2 {
+            assert!(mgr.tick_crowns(&mut units, |_| outsider()).is_empty());
+        }
+        assert_eq!(mgr.castles[&KENT_CASTLE_ID].owner_clan_id, 50);
+    }
+
+    fn treasury_setup() -> (SiegeManager, ClanData) {
+        let mut mgr = SiegeManager::new();
+        mgr.castles.insert(GIRAN_CASTLE_ID, CastleData {
+            castle_id: GIRAN_CASTLE_ID, name: "Giran".into(), war_time: 0,
+            tax_rate: 10, public_money: 0, owner_clan_id: 50,
+        });
+        (mgr, ClanData::new(50, "Owners".into(), 2001, "Lord".into()))
+    }
+
+    fn adena(count: i32) -> Inventory {
+        use crate::ecs::components::item::ItemInstance;
+        let mut inv = Inventory::new();
+        inv.items.push(ItemInstance { count, ..ItemInstance::new(1, ADENA_ITEM_ID) });
+        inv
+    }
+
+    #[test]
+    fn test_purchase_tax_credits_treasury() {
+        let (mut mgr, lord) = treasury_setup();
+        assert_eq!(mgr.set_tax_rate(GIRAN_CASTLE_ID, &lord, 2001, 20), Ok(()));
+        assert_eq!(mgr.set_tax_rate(GIRAN_CASTLE_ID, &lord, 2001, 60), Err(TreasuryError::InvalidRate));
+
+        let mut buyer = adena(2000);
+        assert_eq!(mgr.apply_purchase_tax(GIRAN_CASTLE_ID, 1000, &mut buyer), Ok(1200));
+        assert_eq!(mgr.apply_purchase_tax(GIRAN_CASTLE_ID, 50, &mut buyer), Ok(60));
+        assert_eq!(mgr.castles[&GIRAN_CASTLE_ID].public_money, 210);
+        assert!(buyer.check_item(ADENA_ITEM_ID, 740) && !buyer.check_item(ADENA_ITEM_ID, 741));
+
+        // An unowned castle's town sells at list price
+        mgr.castles.get_mut(&GIRAN_CASTLE_ID).unwrap().owner_clan_id = 0;
+        assert_eq!(mgr.apply_purchase_tax(GIRAN_CASTLE_ID, 100, &mut buyer), Ok(100));
+        assert_eq!(mgr.castles[&GIRAN_CASTLE_ID].public_money, 210);
+    }
+
+    #[test]
+    fn test_purchase_tax_needs_payment_first() {
+        let (mut mgr, _) = treasury_setup();
+        // 1000 + 10% tax = 1100; 1050 adena isn't enough
+        let mut buyer = adena(1050);
+        assert_eq!(
+            mgr.apply_purchase_tax(GIRAN_CASTLE_ID, 1000, &mut buyer),
+            Err(PurchaseError::InsufficientAdena { needed: 1100 }),
+        );
+        assert_eq!(mgr.castles[&GIRAN_CASTLE_ID].public_money, 0);
+        assert!(buyer.check_item(ADENA_ITEM_ID, 1050));
+    }
+
+    #[test]
+    fn test_treasury_withdraw_leader_only() {
+        let (mut mgr, lord) = treasury_setup();
+        mgr.apply_purchase_tax(GIRAN_CASTLE_ID, 10_000, &mut adena(11_000)).unwrap();
+
+        assert_eq!(mgr.withdraw_treasury(GIRAN_CASTLE_ID, &lord, 2002, 500), Err(TreasuryError::NotLeader));
+        assert_eq!(mgr.set_tax_rate(GIRAN_CASTLE_ID, &lord, 2002, 30), Err(TreasuryError::NotLeader));
+        let other = ClanData::new(60, "Others".into(), 3001, "Rival".into());
+        assert_eq!(mgr.withdraw_treasury(GIRAN_CASTLE_ID, &other, 3001, 500), Err(TreasuryError::NotOwner));
+        assert_eq!(mgr.castles[&GIRAN_CASTLE_ID].public_money, 1000);
+
+        assert_eq!(
+            mgr.withdraw_treasury(GIRAN_CASTLE_ID, &lord, 2001, 1001),
+            Err(TreasuryError::InsufficientFunds { available: 1000 })
+        );
+        assert_eq!(mgr.withdraw_treasury(GIRAN_CASTLE_ID, &lord, 2001, 600), Ok(600));
+        assert_eq!(mgr.castles[&GIRAN_CASTLE_ID].public_money, 400);
+    }
+
+    // Thu 2025-10-16 00:00 UTC+8
+    const THURSDAY: i64 = 1_760_544_000;
+    const HOUR: i64 = 3600;
+
+    #[test]
+    fn test_next_window_across_week_boundary() {
+        // Sunday 20:00 for two hours
+        let sunday = SiegeSchedule { weekday: 0, start_hour: 20, duration_mins: 120 };
+        let this_sunday = THURSDAY + 3 * DAY_SECS + 20 * HOUR;
+        assert_eq!(sunday.next_window(THURSDAY), (this_sunday, this_sunday + 2 * HOUR));
+        // Mid-siege returns the running window
+        assert_eq!(sunday.next_window(this_sunday + HOUR).0, this_sunday);
+        // Once it ends, the next is a week later
+        assert_eq!(sunday.next_window(this_sunday + 2 * HOUR).0, this_sunday + WEEK_SECS);
+
+        // Saturday 23:00 for three hours runs into Sunday
+        let late = SiegeSchedule { weekday: 6, start_hour: 23, duration_mins: 180 };
+        let saturday = THURSDAY + 2 * DAY_SECS + 23 * HOUR;
+        let sunday_1am = saturday + 2 * HOUR;
+        assert_eq!(late.next_window(sunday_1am), (saturday, saturday + 3 * HOUR));
+        assert_eq!(late.next_window(saturday + 3 * HOUR).0, saturday + WEEK_SECS);
+
+        // Earlier weekday than today wraps into next week
+        let monday = SiegeSchedule { weekday: 1, start_hour: 21, duration_mins: 60 };
+        assert_eq!(monday.next_window(THURSDAY).0, THURSDAY + 4 * DAY_SECS + 21 * HOUR);
+    }
+
+    #[test]
+    fn test_schedule_drives_registration_then_war() {
+        let mut mgr = SiegeManager::new();
+        for (id, name) in [(KENT_CASTLE_ID, "Kent"), (GIRAN_CASTLE_ID, "Giran")] {
+            mgr.castles.insert(id, CastleData {
+                castle_id: id, name: name.into(), war_time: 0,
+                tax_rate: 10, public_money: 0, owner_clan_id: 50,
+            });
+        }
+        // Kent on Saturday, Giran on Sunday
+        mgr.set_schedule(KENT_CASTLE_ID, SiegeSchedule { weekday: 6, start_hour: 20, duration_mins: 120 });
+        mgr.set_schedule(GIRAN_CASTLE_ID, SiegeSchedule { weekday: 0, start_hour: 20, duration_mins: 120 });
+        let kent_start = THURSDAY + 2 * DAY_SECS + 20 * HOUR;
+        let owner = |_| "Owners".to_string();
+
+        let changes = mgr.tick_schedule(THURSDAY, owner);
+        assert_eq!(changes.len(), 2);
+        assert!(changes.iter().all(|c| c.phase == SiegePhase::Registration));
+        assert_eq!(mgr.castles[&KENT_CASTLE_ID].war_time, kent_start);
+        assert!(mgr.tick_schedule(THURSDAY + HOUR, owner).is_empty());
+        mgr.registrations.insert(KENT_CASTLE_ID, vec!["Attackers".into()]);
+
+        assert_eq!(
+            mgr.tick_schedule(kent_start - 1, owner),
+            vec![PhaseChange { castle_id: KENT_CASTLE_ID, phase: SiegePhase::Closed }]
+        );
+        assert_eq!(
+            mgr.tick_schedule(kent_start, owner),
+            vec![PhaseChange { castle_id: KENT_CASTLE_ID, phase: SiegePhase::Active }]
+        );
+        assert!(mgr.is_now_war(KENT_CASTLE_ID));
+        assert!(!mgr.is_now_war(GIRAN_CASTLE_ID));
+        let war = &mgr.active_wars[0];
+        assert_eq!((war.attack_clans.clone(), war.defence_clan.as_str()), (vec!["Attackers".to_string()], "Owners"));
+        assert_eq!(war.war_end_time, kent_start + 2 * HOUR);
+
+        // After the window Kent reopens registration for next week
+        mgr.active_wars.clear();
+        assert_eq!(
+            mgr.tick_schedule(kent_start + 2 * HOUR, owner),
+            vec![PhaseChange { castle_id: KENT_CASTLE_ID, phase: SiegePhase::Registration }]
+        );
+        assert_eq!(mgr.castles[&KENT_CASTLE_ID].war_time, kent_start + WEEK_SECS);
     }
 }
@@ -20,6 +20,11 @@
 use std::collections::HashMap;
 use rand::RngExt;
 
+use crate::ecs::components::position::{direction_from_delta, Position};
+use crate::ecs::siege::{get_castle_info, ADEN_CASTLE_ID};
+use crate::protocol::server::combat::{build_do_action_gfx, ACTION_DIE};
+use crate::ecs::game_engine::{NpcAttack, NpcHpChange};
+
 // ===========================================================================
 // 投石器 (Catapult) - 官方機制
 // ===========================================================================
@@ -39,6 +44,9 @@ pub const BOMB_ITEM_ID: i32 = 41900;
 /// 投石器冷卻時間（ticks，10 秒 = 50 ticks @ 200ms/tick）。
 pub const CATAPULT_RELOAD_TICKS: u32 = 50;
 
+/// 射擊扇形半角（度）：目標方向與允許方向的夾角不得超過此值。
+pub const CATAPULT_ARC_HALF_ANGLE: f64 = 60.0;
+
 /// 投石器狀態。
 #[derive(Debug, Clone)]
 pub struct CatapultState {
@@ -56,6 +64,8 @@ pub struct CatapultState {
     pub reload_remaining: u32,
     /// 是否已被摧毀。
     pub destroyed: bool,
+    /// 從投石器指向城堡守護塔的向量（城內方向）；(0, 0) = 不限方向。
+    pub toward_castle: (i32, i32),
 }
 
 /// 投石器操作結果。
@@ -73,6 +83,32 @@ pub enum CatapultAction {
     Destroyed,
     /// 缺少炸彈。
     NoBombs,
+    /// 目標不在該方投石器的射擊扇形內。
+    OutOfArc,
+}
+
+/// 投石器被摧毀：殘骸廣播與被彈出的操作者。
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatapultWreck {
+    pub catapult_id: u32,
+    pub pos: Position,
+    /// 摧毀時正在操作的王族；session 需清除其操作狀態。
+    /// 3.80c 沒有獨立的下車封包，操作者與周圍玩家一樣收到殘骸動作。
+    pub ejected_operator: Option<u32>,
+}
+
+impl CatapultWreck {
+    /// S_DOACTIONGFX 死亡動作，廣播給周圍玩家（含被彈出的操作者）。
+    pub fn packet(&self) -> Vec<u8> {
+        build_do_action_gfx(self.catapult_id as i32, ACTION_DIE)
+    }
+}
+
+/// 投石器受擊結果：血條更新，摧毀時附帶殘骸事件。
+#[derive(Debug, Clone)]
+pub struct CatapultHit {
+    pub hp: NpcHpChange,
+    pub wreck: Option<CatapultWreck>,
 }
 
 impl CatapultState {
@@ -85,9 +121,33 @@ impl CatapultState {
             operator_id: 0,
             reload_remaining: 0,
             destroyed: false,
+            toward_castle: get_castle_info()
+                .iter()
+                .find(|c| c.castle_id == castle_id)
+                .map_or((0, 0), |c| (c.tower_loc.0 - x, c.tower_loc.1 - y)),
         }
     }
 
+    /// 目標是否在射擊扇形內。
+    /// 攻擊方朝城內（守護塔方向），防守方朝城外（反方向），各 ±60°。
+    pub fn in_arc(&self, target_x: i32, target_y: i32) -> bool {
+        let (ix, iy) = self.toward_castle;
+        if (ix, iy) == (0, 0) {
+            return true;
+        }
+        let (ax, ay) = match self.side {
+            CatapultSide::Attacker => (ix, iy),
+            CatapultSide::Defender => (-ix, -iy),
+        };
+        let (tx, ty) = (target_x - self.x, target_y - self.y);
+        if (tx, ty) == (0, 0) {
+            return false;
+        }
+        let dot = (ax as f64) * (tx as f64) + (ay as f64) * (ty as f64);
+        let len = (ax as f64).hypot(ay as f64) * (tx as f64).hypot(ty as f64);
+        dot >= len * CATAPULT_ARC_HALF_ANGLE.to_radians().cos()
+    }
+
     /// 玩家上車操作（必須是王族）。
     pub fn mount(&mut self, player_id: u32, is_royal: bool) -> bool {
         if self.destroyed || self.operator_id != 0 || !is_royal {
@@ -115,6 +175,9 @@ impl CatapultState {
         if self.reload_remaining > 0 {
             return CatapultAction::Reloading { ticks_left: self.reload_remaining };
         }
+        if !self.in_arc(target_x, target_y) {
+            return CatapultAction::OutOfArc;
+        }
         if !has_bomb {
             return CatapultAction::NoBombs;
         }
@@ -149,6 +212,28 @@ impl CatapultState {
         }
     }
 
+    fn position(&self) -> Position {
+        Position::new(self.x, self.y, self.map_id)
+    }
+
+    /// 受到傷害並產生廣播事件；已摧毀的投石器不再受擊（None）。
+    pub fn take_hit(&mut self, damage: i32) -> Option<CatapultHit> {
+        if self.destroyed {
+            return None;
+        }
+        let operator = self.operator_id;
+        let destroyed = self.receive_damage(damage);
+        let pos = self.position();
+        Some(CatapultHit {
+            hp: NpcHpChange { npc_id: self.object_id, pos, cur_hp: self.cur_hp, max_hp: self.max_hp },
+            wreck: destroyed.then(|| CatapultWreck {
+                catapult_id: self.object_id,
+                pos,
+                ejected_operator: (operator != 0).then_some(operator),
+            }),
+        })
+    }
+
     /// 自動修復（攻城開始/城主交替時）。
     pub fn repair(&mut self) {
         self.cur_hp = self.max_hp;
@@ -270,6 +355,26 @@ pub fn official_guard_templates() -> Vec<GuardTemplate> {
     ]
 }
 
+/// 城堡 `castle_id` 使用的 `guard_type` 模板：亞丁城用亞丁限定數據，
+/// 其他城堡用非亞丁數據；該城堡沒有此類守衛時為 None。
+pub fn guard_template(castle_id: i32, guard_type: GuardType) -> Option<GuardTemplate> {
+    let aden = castle_id == ADEN_CASTLE_ID;
+    official_guard_templates()
+        .into_iter()
+        .find(|t| t.guard_type == guard_type && if aden { t.aden_only } else { t.non_aden })
+}
+
+/// 守衛駐守點（重生位置）。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GuardPost {
+    pub object_id: u32,
+    pub guard_type: GuardType,
+    pub x: i32,
+    pub y: i32,
+    pub map_id: i32,
+    pub heading: i32,
+}
+
 /// 守衛實體。
 #[derive(Debug, Clone)]
 pub struct GuardState {
@@ -319,6 +424,53 @@ impl GuardState {
         if self.cur_hp <= 0 { self.is_alive = false; self.target_id = 0; true }
         else { false }
     }
+
+    fn position(&self) -> Position {
+        Position { x: self.x, y: self.y, map_id: self.map_id, heading: self.heading }
+    }
+
+    /// 守衛血條（與一般 NPC 相同的廣播流程）。
+    pub fn hp_change(&self) -> NpcHpChange {
+        NpcHpChange {
+            npc_id: self.object_id,
+            pos: self.position(),
+            cur_hp: self.cur_hp,
+            max_hp: self.max_hp,
+        }
+    }
+
+    /// 鎖定新目標；目標改變時回傳血條更新供廣播。
+    pub fn acquire_target(&mut self, target_id: u32) -> Option<NpcHpChange> {
+        if !self.is_alive || target_id == 0 || self.target_id == target_id {
+            return None;
+        }
+        self.target_id = target_id;
+        Some(self.hp_change())
+    }
+
+    /// 受到攻擊：扣血並回傳血條更新。
+    pub fn take_hit(&mut self, damage: i32) -> NpcHpChange {
+        self.receive_damage(damage);
+        self.hp_change()
+    }
+
+    /// 對位於 (target_x, target_y) 的目標攻擊；冷卻中或無目標時為 None。
+    /// 守衛會轉向目標。
+    pub fn attack(&mut self, target_x: i32, target_y: i32) -> Option<NpcAttack> {
+        let damage = self.try_attack();
+        if damage == 0 {
+            return None;
+        }
+        if (target_x, target_y) != (self.x, self.y) {
+            self.heading = direction_from_delta(target_x - self.x, target_y - self.y);
+        }
+        Some(NpcAttack {
+            npc_id: self.object_id,
+            target_id: self.target_id,
+            pos: self.position(),
+            damage,
+        })
+    }
 }
 
 // ===========================================================================
@@ -359,6 +511,8 @@ pub mod siege_buff {
 pub struct SiegeUnitManager {
     pub catapults: HashMap<u32, CatapultState>,
     pub guards: HashMap<u32, GuardState>,
+    /// 各城堡的守衛駐守點 (castle_id -> posts)。
+    pub guard_posts: HashMap<i32, Vec<GuardPost>>,
 }
 
 impl SiegeUnitManager {
@@ -366,9 +520,45 @@ impl SiegeUnitManager {
         SiegeUnitManager {
             catapults: HashMap::new(),
             guards: HashMap::new(),
+            guard_posts: HashMap::new(),
         }
     }
 
+    /// 投石器受擊；不存在或已摧毀時為 None。
+    pub fn damage_catapult(&mut self, catapult_id: u32, damage: i32) -> Option<CatapultHit> {
+        self.catapults.get_mut(&catapult_id)?.take_hit(damage)
+    }
+
+    /// 登記駐守點並派出守衛；該城堡沒有此類守衛時回傳 false。
+    pub fn station_guard(&mut self, castle_id: i32, post: GuardPost) -> bool {
+        let Some(t) = guard_template(castle_id, post.guard_type) else { return false };
+        self.guards.insert(post.object_id, Self::guard_at_post(&t, castle_id, &post));
+        self.guard_posts.entry(castle_id).or_default().push(post);
+        true
+    }
+
+    fn guard_at_post(t: &GuardTemplate, castle_id: i32, post: &GuardPost) -> GuardState {
+        let mut guard = GuardState::from_template(post.object_id, t, castle_id, post.x, post.y, post.map_id);
+        guard.heading = post.heading;
+        guard
+    }
+
+    /// 攻城結束或城堡易主時（`SiegeManager::tick_war_timers` / `tick_crowns`）呼叫，
+    /// 在駐守點以官方模板重建該城堡所有守衛（滿血、無目標）。
+    /// 回傳重建數量。
+    pub fn respawn_guards(&mut self, castle_id: i32) -> usize {
+        self.guards.retain(|_, g| g.castle_id != castle_id);
+        let Some(posts) = self.guard_posts.get(&castle_id) else { return 0 };
+        let mut count = 0;
+        for post in posts {
+            if let Some(t) = guard_template(castle_id, post.guard_type) {
+                self.guards.insert(post.object_id, Self::guard_at_post(&t, castle_id, post));
+                count += 1;
+            }
+        }
+        count
+    }
+
     /// 攻城開始時修復所有投石器。
     pub fn repair_all_catapults(&mut self, castle_id: i32) {
         for cat in self.catapults.values_mut() {
@@ -384,6 +574,19 @@ impl SiegeUnitManager {
         for guard in self.guards.values_mut() { guard.tick(); }
     }
 
+    /// 所有有目標的守衛出手；`target_pos` 查目標座標（離線/不存在回傳 None）。
+    pub fn guard_attacks(&mut self, target_pos: impl Fn(u32) -> Option<(i32, i32)>) -> Vec<NpcAttack> {
+        let mut attacks: Vec<NpcAttack> = self.guards.values_mut()
+            .filter(|g| g.is_alive && g.target_id != 0)
+            .filter_map(|g| {
+                let (x, y) = target_pos(g.target_id)?;
+                g.attack(x, y)
+            })
+            .collect();
+        attacks.sort_unstable_by_key(|a| a.npc_id);
+        attacks
+    }
+
     /// 取得城堡存活守衛數。
     pub fn alive_guard_count(&self, castle_id: i32) -> usize {
         self.guards.values().filter(|g| g.castle_id == castle_id && g.is_alive).count()
@@ -422,6 +625,32 @@ mod tests {
         assert!(matches!(cat.try_fire(110, 210, false), CatapultAction::NoBombs));
     }
 
+    #[test]
+    fn test_catapult_arc_by_side() {
+        use crate::ecs::siege::KENT_CASTLE_ID;
+
+        // 肯特城守護塔 (33139, 32768)；投石器架在塔西方 20 格
+        let (x, y) = (33119, 32768);
+        let mut attacker = CatapultState::new(1, KENT_CASTLE_ID, CatapultSide::Attacker, x, y, 4);
+        let mut defender = CatapultState::new(2, KENT_CASTLE_ID, CatapultSide::Defender, x, y, 4);
+        attacker.mount(10, true);
+        defender.mount(11, true);
+
+        // 攻擊方：往東（城內）可以，往西不行
+        assert!(matches!(attacker.try_fire(x + 10, y + 3, true), CatapultAction::Fire { .. }));
+        attacker.reload_remaining = 0;
+        assert!(matches!(attacker.try_fire(x - 10, y, true), CatapultAction::OutOfArc));
+        // 側面超出 ±60°
+        assert!(matches!(attacker.try_fire(x + 2, y + 10, true), CatapultAction::OutOfArc));
+
+        // 防守方：往西（城外）可以，往東不行
+        assert!(matches!(defender.try_fire(x - 10, y - 3, true), CatapultAction::Fire { .. }));
+        defender.reload_remaining = 0;
+        assert!(matches!(defender.try_fire(x + 10, y, true), CatapultAction::OutOfArc));
+        // 被拒絕的射擊不進入冷卻
+        assert_eq!(defender.reload_remaining, 0);
+    }
+
     #[test]
     fn test_catapult_repair() {
         let mut cat = CatapultState::new(1, 1, CatapultSide::Defender, 100, 200, 4);
@@ -433,6 +662,33 @@ mod tests {
         assert_eq!(cat.cur_hp, cat.max_hp);
     }
 
+    #[test]
+    fn test_destroying_occupied_catapult() {
+        let mut units = SiegeUnitManager::new();
+        units.catapults.insert(1, CatapultState::new(1, 1, CatapultSide::Attacker, 100, 200, 4));
+        assert!(units.catapults.get_mut(&1).unwrap().mount(77, true));
+
+        // 受損：只有血條
+        let hit = units.damage_catapult(1, 200).unwrap();
+        assert_eq!((hit.hp.npc_id, hit.hp.cur_hp, hit.hp.max_hp), (1, 300, 500));
+        assert!(hit.wreck.is_none());
+        assert_eq!(units.catapults[&1].operator_id, 77);
+
+        // 摧毀：操作者被彈出，產生殘骸事件
+        let hit = units.damage_catapult(1, 400).unwrap();
+        assert_eq!(hit.hp.cur_hp, 0);
+        let wreck = hit.wreck.unwrap();
+        assert_eq!(wreck.ejected_operator, Some(77));
+        assert_eq!((wreck.pos.x, wreck.pos.y), (100, 200));
+        assert_eq!(wreck.packet(), build_do_action_gfx(1, ACTION_DIE));
+        assert_eq!(units.catapults[&1].operator_id, 0);
+        assert!(units.catapults[&1].destroyed);
+
+        // 殘骸不再受擊
+        assert!(units.damage_catapult(1, 100).is_none());
+        assert!(units.damage_catapult(9, 100).is_none());
+    }
+
     #[test]
     fn test_official_guard_hp() {
         let templates = official_guard_templates();
@@ -484,6 +740,78 @@ mod tests {
         assert_eq!(guard.try_attack(), 0);
     }
 
+    #[test]
+    fn test_guard_attack_is_broadcast() {
+        use crate::ecs::game_engine::GameWorld;
+        use crate::protocol::server::combat::build_attack_packet;
+
+        let templates = official_guard_templates();
+        let knight_t = templates.iter().find(|t| t.guard_type == GuardType::RoyalKnight && t.aden_only).unwrap();
+        let mut units = SiegeUnitManager::new();
+        units.guards.insert(1, GuardState::from_template(1, knight_t, 7, 100, 200, 4));
+
+        // 鎖定目標時送出血條
+        let hp = units.guards.get_mut(&1).unwrap().acquire_target(999).unwrap();
+        assert_eq!((hp.npc_id, hp.cur_hp, hp.max_hp), (1, 11_513, 11_513));
+        assert!(units.guards.get_mut(&1).unwrap().acquire_target(999).is_none());
+
+        let attacks = units.guard_attacks(|id| (id == 999).then_some((101, 200)));
+        assert_eq!(attacks.len(), 1);
+        let atk = &attacks[0];
+        assert_eq!((atk.npc_id, atk.target_id, atk.pos.heading), (1, 999, 2));
+        assert!((30..=60).contains(&atk.damage));
+        assert_eq!(atk.packet(), build_attack_packet(1, 999, 1, atk.damage, 2, 0));
+        // 冷卻中不再出手
+        assert!(units.guard_attacks(|_| Some((101, 200))).is_empty());
+
+        let hit = units.guards.get_mut(&1).unwrap().take_hit(513);
+        assert_eq!(hit.cur_hp, 11_000);
+
+        let mut world = GameWorld::new(HashMap::new());
+        world.player_positions.insert(999, Position::new(101, 200, 4));
+        world.player_positions.insert(500, Position::new(300, 300, 4));
+        let batches = world.batch_attacks(&attacks, &[hit.clone()], 18);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].player_id, 999);
+        assert_eq!(batches[0].packets, vec![atk.packet(), hit.packet()]);
+    }
+
+    #[test]
+    fn test_respawn_guards_after_siege() {
+        use crate::ecs::siege::KENT_CASTLE_ID;
+
+        let post = |object_id, guard_type| GuardPost { object_id, guard_type, x: 100, y: 200, map_id: 4, heading: 2 };
+        let mut units = SiegeUnitManager::new();
+        assert!(units.station_guard(ADEN_CASTLE_ID, post(1, GuardType::King)));
+        assert!(units.station_guard(ADEN_CASTLE_ID, post(2, GuardType::RoyalGuard)));
+        assert!(units.station_guard(KENT_CASTLE_ID, post(3, GuardType::RoyalGuard)));
+        assert!(units.station_guard(KENT_CASTLE_ID, post(4, GuardType::RoyalPriest)));
+        // 肯特城沒有國王
+        assert!(!units.station_guard(KENT_CASTLE_ID, post(5, GuardType::King)));
+
+        // 攻城中：守衛全滅
+        units.guards.get_mut(&3).unwrap().receive_damage(99_999);
+        units.guards.clear();
+        assert_eq!(units.alive_guard_count(ADEN_CASTLE_ID), 0);
+
+        assert_eq!(units.respawn_guards(ADEN_CASTLE_ID), 2);
+        assert_eq!(units.alive_guard_count(KENT_CASTLE_ID), 0);
+        assert_eq!(units.respawn_guards(KENT_CASTLE_ID), 2);
+
+        // 亞丁城親衛隊 Lv68/11049，其他城堡 Lv75/8403
+        let g = &units.guards;
+        assert_eq!((g[&1].level, g[&1].max_hp), (75, 8_403));
+        assert_eq!((g[&2].level, g[&2].max_hp, g[&2].castle_id), (68, 11_049, ADEN_CASTLE_ID));
+        assert_eq!((g[&3].level, g[&3].max_hp, g[&3].castle_id), (75, 8_403, KENT_CASTLE_ID));
+        assert_eq!(g[&4].guard_type, GuardType::RoyalPriest);
+        assert!(g.values().all(|g| g.is_alive && g.cur_hp == g.max_hp && g.target_id == 0));
+        assert_eq!((g[&2].x, g[&2].y, g[&2].heading), (100, 200, 2));
+
+        // 重生不會重複
+        assert_eq!(units.respawn_guards(ADEN_CASTLE_ID), 2);
+        assert_eq!(units.guards.len(), 4);
+    }
+
     #[test]
     fn test_siege_buff_constants() {
         assert_eq!(siege_buff::KINGS_GUARD_ATK_BONUS, 30);
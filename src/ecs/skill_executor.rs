@@ -12,7 +12,10 @@
 
 use rand::RngExt;
 
-use crate::ecs::components::skill::{SkillEffects, SkillCooldowns, SkillTemplate};
+use crate::ecs::combat::{is_heavy_hit, leech_gain, StatusRider};
+use crate::ecs::components::party::Party;
+use crate::ecs::components::position::tile_distance;
+use crate::ecs::components::skill::{skill_ids, SkillEffects, SkillCooldowns, SkillTemplate};
 
 // ===========================================================================
 // Skill execution context
@@ -28,6 +31,7 @@ pub struct CasterInfo {
     pub heading: i32,
     pub level: i32,
     pub cur_hp: i32,
+    pub max_hp: i32,
     pub cur_mp: i32,
     pub int_stat: i32,      // affects damage and MP reduction
     pub sp_bonus: i32,      // spell power from equipment
@@ -47,6 +51,7 @@ pub struct TargetInfo {
     pub cur_mp: i32,
     pub mr: i32,            // magic resistance
     pub is_undead: bool,
+    pub is_dead: bool,
 }
 
 /// Result of a skill execution attempt.
@@ -62,6 +67,8 @@ pub enum SkillResult {
     OnCooldown { ticks_left: u32 },
     /// Caster level too low.
     LevelTooLow,
+    /// Caster's class cannot use this skill.
+    WrongClass,
     /// Target out of range.
     OutOfRange,
     /// Target resisted (MR check failed).
@@ -70,6 +77,8 @@ pub enum SkillResult {
     CounterMagic,
     /// No valid target.
     NoTarget,
+    /// Resurrection cast on a living target.
+    TargetAlive,
 }
 
 /// What effects to apply after successful skill use.
@@ -91,6 +100,41 @@ pub struct SkillOutcome {
     pub skill_id: i32,
     /// Cooldown ticks to set.
     pub cooldown_ticks: u32,
+    /// Dead targets to revive.
+    pub resurrections: Vec<Resurrection>,
+    /// Percent of damage dealt that returns to the caster as HP.
+    pub leech_pct: i32,
+    /// HP to restore to the caster from draining (already capped).
+    pub hp_leeched: i32,
+    /// Targets hit hard enough to be knocked back (see `combat::Knockback`).
+    pub knockbacks: Vec<u32>,
+}
+
+/// HP drain of a damage skill, in percent of the damage dealt.
+pub fn skill_leech_pct(skill_id: i32) -> i32 {
+    match skill_id {
+        skill_ids::CHILL_TOUCH => 100, // 寒冷戰慄: caster absorbs the damage
+        _ => 0,
+    }
+}
+
+/// A revive to apply: clear the death state, then restore HP and lost exp.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resurrection {
+    pub target_id: u32,
+    /// HP the target comes back with.
+    pub hp: i32,
+    /// Percent of the exp lost on death to give back.
+    pub exp_restore_pct: i32,
+}
+
+/// Resurrection strength per skill: (HP % of max, lost exp % restored).
+pub fn resurrection_params(skill_id: i32) -> Option<(i32, i32)> {
+    match skill_id {
+        skill_ids::RESURRECTION => Some((50, 50)),          // 返生術
+        skill_ids::GREATER_RESURRECTION => Some((100, 100)), // 終極返生術
+        _ => None,
+    }
 }
 
 // ===========================================================================
@@ -108,6 +152,11 @@ pub fn execute_skill(
     cooldowns: &SkillCooldowns,
     _caster_effects: &SkillEffects,
 ) -> SkillResult {
+    // 0. Class check
+    if !skill.allows_class(caster.class_type) {
+        return SkillResult::WrongClass;
+    }
+
     // 1. Cooldown check
     if !cooldowns.is_ready(skill.skill_id) {
         let ticks = cooldowns.cooldowns.get(&skill.skill_id).copied().unwrap_or(0);
@@ -136,14 +185,58 @@ pub fn execute_skill(
         return SkillResult::NoTarget;
     }
 
-    // 6. Calculate effects per target
+    // 6. Resurrection: only dead targets, nothing else applies
+    if let Some((hp_pct, exp_pct)) = resurrection_params(skill.skill_id) {
+        if targets.iter().any(|t| !t.is_dead) {
+            return SkillResult::TargetAlive;
+        }
+        let resurrections = targets
+            .iter()
+            .filter(|t| {
+                let dist = tile_distance(caster.x, caster.y, t.x, t.y);
+                skill.range <= 0 || dist <= skill.range
+            })
+            .map(|t| Resurrection {
+                target_id: t.object_id,
+                hp: (t.max_hp * hp_pct / 100).max(1),
+                exp_restore_pct: exp_pct,
+            })
+            .collect::<Vec<_>>();
+        if resurrections.is_empty() {
+            return SkillResult::OutOfRange;
+        }
+        return SkillResult::Success(SkillOutcome {
+            damage: Vec::new(),
+            buffs: Vec::new(),
+            mp_consumed: mp_cost,
+            hp_consumed: hp_cost,
+            gfx_id: skill.cast_gfx,
+            is_aoe: false,
+            skill_id: skill.skill_id,
+            cooldown_ticks: reuse_ticks(skill),
+            resurrections,
+            leech_pct: 0,
+            hp_leeched: 0,
+            knockbacks: Vec::new(),
+        });
+    }
+
+    // 7. Calculate effects per target
     let mut damage_list = Vec::new();
     let mut buff_list = Vec::new();
     let mut any_hit = false;
+    let leech_pct = skill_leech_pct(skill.skill_id);
+    let mut drained = 0;
+    let mut knockbacks = Vec::new();
 
     for target in targets {
+        // Dead targets only accept resurrection
+        if target.is_dead {
+            continue;
+        }
+
         // Range check
-        let dist = ((caster.x - target.x).abs()).max((caster.y - target.y).abs());
+        let dist = tile_distance(caster.x, caster.y, target.x, target.y);
         if skill.range > 0 && dist > skill.range {
             continue;
         }
@@ -151,7 +244,19 @@ pub fn execute_skill(
         // Counter Magic check
         // (simplified: check if target has counter magic buff active)
 
-        if skill.damage_value > 0 || skill.damage_dice > 0 {
+        if skill.damage_value < 0 && skill.buff_duration == 0 {
+            // Healing spell
+            let heal = calc_healing(skill, caster);
+            damage_list.push((target.object_id, -heal)); // negative damage = healing
+            any_hit = true;
+
+        } else if skill.is_party_skill() && skill.buff_duration > 0 {
+            // Party buff - friendly, no MR check
+            let duration_ticks = (skill.buff_duration as u32) * 5;
+            buff_list.push((target.object_id, skill.skill_id, duration_ticks, skill.damage_value));
+            any_hit = true;
+
+        } else if skill.damage_value > 0 || skill.damage_dice > 0 {
             // Attack spell - calculate magic damage
             let mr_result = check_magic_resist(caster.level, target.level, target.mr);
             if !mr_result {
@@ -168,6 +273,11 @@ pub fn execute_skill(
             };
 
             damage_list.push((target.object_id, final_damage));
+            let effective = final_damage.clamp(0, target.cur_hp.max(0));
+            drained += effective;
+            if is_heavy_hit(effective, target.max_hp) {
+                knockbacks.push(target.object_id);
+            }
             any_hit = true;
 
         } else if skill.buff_duration > 0 {
@@ -184,11 +294,6 @@ pub fn execute_skill(
             buff_list.push((target.object_id, skill.skill_id, duration_ticks, skill.damage_value));
             any_hit = true;
 
-        } else if skill.damage_value < 0 {
-            // Healing spell
-            let heal = calc_healing(skill, caster);
-            damage_list.push((target.object_id, -heal)); // negative damage = healing
-            any_hit = true;
         }
     }
 
@@ -203,13 +308,6 @@ pub fn execute_skill(
         return SkillResult::Resisted;
     }
 
-    // Calculate cooldown
-    let cooldown_ticks = if skill.reuse_delay > 0 {
-        (skill.reuse_delay as u32) / 200 // ms → ticks
-    } else {
-        0
-    };
-
     SkillResult::Success(SkillOutcome {
         damage: damage_list,
         buffs: buff_list,
@@ -218,10 +316,46 @@ pub fn execute_skill(
         gfx_id: skill.cast_gfx,
         is_aoe: skill.area > 0,
         skill_id: skill.skill_id,
-        cooldown_ticks,
+        cooldown_ticks: reuse_ticks(skill),
+        resurrections: Vec::new(),
+        leech_pct,
+        hp_leeched: leech_gain(drained, leech_pct, caster.cur_hp, caster.max_hp),
+        knockbacks,
     })
 }
 
+/// Cooldown ticks for a skill's reuse delay.
+fn reuse_ticks(skill: &SkillTemplate) -> u32 {
+    if skill.reuse_delay > 0 {
+        (skill.reuse_delay as u32) / 200 // ms → ticks
+    } else {
+        0
+    }
+}
+
+/// Gather the targets of a party heal/buff.
+///
+/// Returns every party member among `candidates` on the caster's map
+/// within the skill's area (or range when it has no area). Include the
+/// caster in `candidates` for skills that also affect the caster.
+pub fn gather_party_targets(
+    skill: &SkillTemplate,
+    caster: &CasterInfo,
+    party: &Party,
+    candidates: &[TargetInfo],
+) -> Vec<TargetInfo> {
+    let radius = if skill.area > 0 { skill.area } else { skill.range };
+    candidates
+        .iter()
+        .filter(|t| party.is_member(t.object_id) && t.map_id == caster.map_id)
+        .filter(|t| {
+            let dist = tile_distance(caster.x, caster.y, t.x, t.y);
+            radius <= 0 || dist <= radius
+        })
+        .cloned()
+        .collect()
+}
+
 // ===========================================================================
 // Calculation helpers
 // ===========================================================================
@@ -386,10 +520,49 @@ pub fn calc_armor_break_multiplier(target_effects: &SkillEffects) -> f32 {
     }
 }
 
+/// Default share of melee damage reflected by Counter Barrier.
+pub const COUNTER_BARRIER_REFLECT_PCT: i32 = 50;
+
+/// Percent of incoming melee damage reflected to the attacker.
+///
+/// 反擊屏障: the effect's value overrides the default when set.
+pub fn calc_reflect_pct(target_effects: &SkillEffects) -> i32 {
+    match target_effects.effects.get(&skill_ids::COUNTER_BARRIER) {
+        Some(e) if e.value > 0 => e.value,
+        Some(_) => COUNTER_BARRIER_REFLECT_PCT,
+        None => 0,
+    }
+}
+
+/// Poison from an NPC hit: damage per tick and duration (30s).
+pub const NPC_POISON_DAMAGE: i32 = 5;
+pub const NPC_POISON_TICKS: u32 = 150;
+/// Silence poison from an NPC hit (30s).
+pub const NPC_SILENCE_TICKS: u32 = 150;
+/// Paralysis from an NPC hit (5s).
+pub const NPC_PARALYSIS_TICKS: u32 = 25;
+
+/// Put an NPC hit's status rider on the player it hit.
+pub fn apply_status_rider(target_effects: &mut SkillEffects, rider: StatusRider) {
+    match rider {
+        StatusRider::Poison => {
+            target_effects.add_effect(skill_ids::STATUS_POISON, NPC_POISON_TICKS, NPC_POISON_DAMAGE)
+        }
+        StatusRider::Silence => {
+            target_effects.add_effect(skill_ids::STATUS_POISON_SILENCE, NPC_SILENCE_TICKS, 0)
+        }
+        StatusRider::Paralysis => {
+            target_effects.add_effect(skill_ids::STATUS_POISON_PARALYZED, NPC_PARALYSIS_TICKS, 0)
+        }
+    }
+}
+
 /// Check if target is stunned/sleeping/paralyzed (cannot act).
 pub fn is_incapacitated(effects: &SkillEffects) -> bool {
+    // 麻痺毒
+    effects.has_effect(skill_ids::STATUS_POISON_PARALYZED)
     // 衝擊之暈 (120)
-    effects.has_effect(120)
+    || effects.has_effect(120)
     // 暗黑盲咒 (103) - sleep
     || effects.has_effect(103)
     // 幻想 (153) - petrify
@@ -402,6 +575,8 @@ pub fn is_silenced(effects: &SkillEffects) -> bool {
     effects.has_effect(132)
     // 混亂 (154)
     || effects.has_effect(154)
+    // 沉默毒
+    || effects.has_effect(skill_ids::STATUS_POISON_SILENCE)
 }
 
 #[cfg(test)]
@@ -421,13 +596,14 @@ mod tests {
             is_through: false, range: 10, area: 0,
             action_id: 19, cast_gfx: 1505, cast_gfx2: 0,
             sys_msg_id_happen: 0, sys_msg_id_stop: 0, sys_msg_id_fail: 0,
+            allowed_classes: 0,
         }
     }
 
     fn make_caster() -> CasterInfo {
         CasterInfo {
             object_id: 100, x: 32800, y: 32800, map_id: 4,
-            heading: 0, level: 52, cur_hp: 300, cur_mp: 200,
+            heading: 0, level: 52, cur_hp: 300, max_hp: 400, cur_mp: 200,
             int_stat: 18, sp_bonus: 3, class_type: 3,
         }
     }
@@ -436,7 +612,7 @@ mod tests {
         TargetInfo {
             object_id: 200, x: 32805, y: 32800, map_id: 4,
             level: 50, cur_hp: 500, max_hp: 500, cur_mp: 100,
-            mr: 30, is_undead: false,
+            mr: 30, is_undead: false, is_dead: false,
         }
     }
 
@@ -505,6 +681,159 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_class_restricted_skill() {
+        use crate::ecs::components::skill::class_mask;
+
+        let mut skill = make_test_skill();
+        skill.allowed_classes = class_mask::for_skill(skill.skill_id, skill.skill_level);
+        let mut caster = make_caster();
+        let target = make_target();
+        let cd = SkillCooldowns::new();
+        let effects = SkillEffects::new();
+
+        // Fireball (grade 4): knights and royals can't cast it
+        caster.class_type = 1;
+        assert!(matches!(
+            execute_skill(&skill, &caster, &[target.clone()], &cd, &effects),
+            SkillResult::WrongClass
+        ));
+        caster.class_type = 0;
+        assert!(matches!(
+            execute_skill(&skill, &caster, &[target.clone()], &cd, &effects),
+            SkillResult::WrongClass
+        ));
+
+        // Mages and elves can
+        caster.class_type = 2;
+        assert!(!matches!(
+            execute_skill(&skill, &caster, &[target], &cd, &effects),
+            SkillResult::WrongClass
+        ));
+        assert!(skill.allows_class(3));
+    }
+
+    #[test]
+    fn test_class_mask_ranges() {
+        use crate::ecs::components::skill::class_mask;
+
+        assert_eq!(class_mask::for_skill(120, 2), class_mask::KNIGHT); // 衝擊之暈
+        assert_eq!(class_mask::for_skill(107, 3), class_mask::DARKELF); // 暗影之牙
+        assert_eq!(class_mask::for_skill(113, 1), class_mask::ROYAL); // 精準目標
+        assert_ne!(class_mask::for_skill(1, 1) & class_mask::KNIGHT, 0);
+        assert_eq!(class_mask::for_skill(46, 10), class_mask::MAGE);
+        assert_eq!(class_mask::flag(7), 0);
+    }
+
+    fn make_party_skill(damage_value: i32, buff_duration: i32) -> SkillTemplate {
+        let mut skill = make_test_skill();
+        skill.skill_id = 57;
+        skill.target = "buff".into();
+        skill.target_to = crate::ecs::components::skill::target_to::PARTY;
+        skill.damage_value = damage_value;
+        skill.damage_dice = 0;
+        skill.damage_dice_count = 0;
+        skill.buff_duration = buff_duration;
+        skill.area = 8;
+        skill
+    }
+
+    fn party_scene() -> (Party, Vec<TargetInfo>) {
+        let mut party = Party::new(100);
+        party.add_member(201);
+        party.add_member(202);
+
+        let mut candidates = Vec::new();
+        for (id, dx) in [(201, 2), (202, 5), (300, 3), (203, 1)] {
+            let mut t = make_target();
+            t.object_id = id;
+            t.x = 32800 + dx;
+            t.cur_hp = 100;
+            candidates.push(t);
+        }
+        (party, candidates)
+    }
+
+    #[test]
+    fn test_party_heal_hits_members_only() {
+        let skill = make_party_skill(-30, 0);
+        assert!(skill.is_party_skill());
+        let caster = make_caster();
+        let (party, candidates) = party_scene();
+
+        let targets = gather_party_targets(&skill, &caster, &party, &candidates);
+        let ids: Vec<u32> = targets.iter().map(|t| t.object_id).collect();
+        assert_eq!(ids, vec![201, 202]); // 300 is in range but not in the party
+
+        match execute_skill(&skill, &caster, &targets, &SkillCooldowns::new(), &SkillEffects::new()) {
+            SkillResult::Success(outcome) => {
+                assert_eq!(outcome.damage.len(), 2);
+                assert!(outcome.damage.iter().all(|&(_, d)| d < 0), "Heals are negative damage");
+            }
+            other => panic!("Expected Success, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_party_buff_applies_to_each_member() {
+        let skill = make_party_skill(2, 60);
+        let caster = make_caster();
+        let (party, mut candidates) = party_scene();
+        candidates[1].x = 32800 + 20; // 202 out of area
+
+        let targets = gather_party_targets(&skill, &caster, &party, &candidates);
+        match execute_skill(&skill, &caster, &targets, &SkillCooldowns::new(), &SkillEffects::new()) {
+            SkillResult::Success(outcome) => {
+                let buffed: Vec<u32> = outcome.buffs.iter().map(|b| b.0).collect();
+                assert_eq!(buffed, vec![201]);
+            }
+            other => panic!("Expected Success, got {:?}", other),
+        }
+    }
+
+    fn make_resurrection_skill() -> SkillTemplate {
+        let mut skill = make_test_skill();
+        skill.skill_id = skill_ids::RESURRECTION;
+        skill.damage_value = 0;
+        skill.damage_dice = 0;
+        skill.range = 3;
+        skill.skill_level = 8;
+        skill
+    }
+
+    #[test]
+    fn test_resurrect_dead_target() {
+        let skill = make_resurrection_skill();
+        let caster = make_caster();
+        let mut target = make_target();
+        target.x = caster.x + 1;
+        target.is_dead = true;
+        target.cur_hp = 0;
+
+        match execute_skill(&skill, &caster, &[target], &SkillCooldowns::new(), &SkillEffects::new()) {
+            SkillResult::Success(outcome) => {
+                assert_eq!(outcome.resurrections, vec![Resurrection {
+                    target_id: 200, hp: 250, exp_restore_pct: 50,
+                }]);
+                assert!(outcome.damage.is_empty());
+            }
+            other => panic!("Expected Success, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resurrect_living_target_rejected() {
+        let skill = make_resurrection_skill();
+        let caster = make_caster();
+        let mut target = make_target();
+        target.x = caster.x + 1;
+
+        assert!(matches!(
+            execute_skill(&skill, &caster, &[target], &SkillCooldowns::new(), &SkillEffects::new()),
+            SkillResult::TargetAlive
+        ));
+    }
+
     #[test]
     fn test_buff_damage_modifiers() {
         let mut effects = SkillEffects::new();
@@ -549,6 +878,22 @@ mod tests {
         assert!(is_silenced(&effects));
     }
 
+    #[test]
+    fn test_apply_status_rider() {
+        let mut effects = SkillEffects::new();
+        apply_status_rider(&mut effects, StatusRider::Poison);
+        assert_eq!(effects.effects[&skill_ids::STATUS_POISON].value, NPC_POISON_DAMAGE);
+        assert!(!is_incapacitated(&effects) && !is_silenced(&effects));
+
+        apply_status_rider(&mut effects, StatusRider::Silence);
+        assert!(is_silenced(&effects));
+        apply_status_rider(&mut effects, StatusRider::Paralysis);
+        assert!(is_incapacitated(&effects));
+
+        effects.advance(NPC_PARALYSIS_TICKS);
+        assert!(!is_incapacitated(&effects));
+    }
+
     #[test]
     fn test_self_buff_no_target() {
         // Shield spell (skill 3) - self buff, no target needed
@@ -563,6 +908,7 @@ mod tests {
             is_through: false, range: 0, area: 0,
             action_id: 0, cast_gfx: 768, cast_gfx2: 0,
             sys_msg_id_happen: 0, sys_msg_id_stop: 0, sys_msg_id_fail: 0,
+            allowed_classes: 0,
         };
         let caster = make_caster();
         let cd = SkillCooldowns::new();
@@ -578,4 +924,59 @@ mod tests {
             other => panic!("Expected Success, got {:?}", other),
         }
     }
+
+    #[test]
+    fn test_chill_touch_drains_effective_damage() {
+        let mut skill = make_test_skill();
+        skill.skill_id = skill_ids::CHILL_TOUCH;
+        let mut caster = make_caster();
+        let mut target = make_target();
+        target.cur_hp = 5; // less than any hit
+
+        let cast = |caster: &CasterInfo, target: &TargetInfo, skill: &SkillTemplate| {
+            // MR can resist; retry until the spell lands
+            (0..200)
+                .find_map(|_| match execute_skill(skill, caster, &[target.clone()], &SkillCooldowns::new(), &SkillEffects::new()) {
+                    SkillResult::Success(o) => Some(o),
+                    _ => None,
+                })
+                .expect("spell never landed")
+        };
+
+        let outcome = cast(&caster, &target, &skill);
+        assert_eq!(outcome.leech_pct, 100);
+        assert!(outcome.damage[0].1 > 5);
+        assert_eq!(outcome.hp_leeched, 5);
+
+        // Capped at the caster's max HP
+        caster.cur_hp = 398;
+        assert_eq!(cast(&caster, &target, &skill).hp_leeched, 2);
+
+        // Ordinary attack spells don't drain
+        skill.skill_id = skill_ids::FIREBALL;
+        let outcome = cast(&caster, &target, &skill);
+        assert_eq!((outcome.leech_pct, outcome.hp_leeched), (0, 0));
+    }
+
+    #[test]
+    fn test_heavy_spell_knocks_back() {
+        let skill = make_test_skill();
+        let caster = make_caster();
+        let mut target = make_target();
+        target.mr = 0;
+
+        let cast = |target: &TargetInfo| {
+            (0..200)
+                .find_map(|_| match execute_skill(&skill, &caster, &[target.clone()], &SkillCooldowns::new(), &SkillEffects::new()) {
+                    SkillResult::Success(o) => Some(o),
+                    _ => None,
+                })
+                .expect("spell never landed")
+        };
+
+        assert!(cast(&target).knockbacks.is_empty());
+        target.max_hp = 20;
+        target.cur_hp = 20;
+        assert_eq!(cast(&target).knockbacks, vec![200]);
+    }
 }
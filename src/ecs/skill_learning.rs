@@ -0,0 +1,193 @@
+//! Skill learning rules.
+//!
+//! Ported from Java C_SkillBuyOK / L1SkillUse learn checks:
+//!   - Common magic (1-80) is bought from the magic NPC for adena.
+//!     Spell grade = row of the 8-column skill grid, and each class
+//!     unlocks one grade every N levels (royal 10, knight 50, elf 8,
+//!     mage 4, dark elf 12).
+//!   - Class skills are learned from a spellbook/crystal at the level
+//!     listed in the class skill tables.
+
+use crate::ecs::class_skills::all_class_skills;
+use crate::ecs::components::skill::{class_mask, skill_ids};
+use crate::ecs::darkelf_skills::all_darkelf_skills;
+
+/// Skills that require another skill to be learned first. (skill, prerequisite)
+pub const PREREQUISITES: &[(i32, i32)] = &[
+    (skill_ids::GREATER_HEAL, skill_ids::HEAL),
+    (skill_ids::FULL_HEAL, skill_ids::GREATER_HEAL),
+    (skill_ids::GREATER_HASTE, skill_ids::HASTE),
+];
+
+/// Why a learn attempt was rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LearnError {
+    /// No learn data for this skill.
+    UnknownSkill,
+    /// Caster's class can't learn this skill.
+    WrongClass,
+    /// Character level below the requirement.
+    LevelTooLow { required: i32 },
+    /// Skill is already in the learned set.
+    AlreadyLearned,
+    /// A prerequisite skill hasn't been learned.
+    MissingPrereq(i32),
+    /// Not enough adena to pay the NPC.
+    NotEnoughAdena { required: i32 },
+    /// Class skills need the matching spellbook.
+    MissingSpellbook,
+}
+
+/// What a successful learn consumes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LearnCost {
+    Adena(i32),
+    Spellbook,
+}
+
+/// Everything needed to validate a learn attempt.
+#[derive(Debug, Clone)]
+pub struct LearnRequest<'a> {
+    pub skill_id: i32,
+    pub class_type: i32,
+    pub level: i32,
+    pub learned: &'a [i32],
+    pub adena: i32,
+    pub has_spellbook: bool,
+}
+
+/// Check whether a skill ID is common magic (bought from NPCs).
+pub fn is_common_magic(skill_id: i32) -> bool {
+    (1..=80).contains(&skill_id)
+}
+
+/// Spell grade of common magic: one grade per row of 8.
+pub fn magic_grade(skill_id: i32) -> i32 {
+    (skill_id - 1) / 8 + 1
+}
+
+/// Levels per common-magic grade for a class (None = can't learn magic).
+fn magic_level_step(class_type: i32) -> Option<i32> {
+    match class_type {
+        0 => Some(10),
+        1 => Some(50),
+        2 => Some(8),
+        3 => Some(4),
+        4 => Some(12),
+        _ => None,
+    }
+}
+
+/// Adena price of a common magic grade.
+pub fn magic_price(grade: i32) -> i32 {
+    grade * grade * 100
+}
+
+/// Level needed to learn a skill as the given class.
+///
+/// The class skill tables take precedence; other common magic falls
+/// back to the per-grade level step.
+pub fn required_level(skill_id: i32, class_type: i32) -> Option<i32> {
+    if let Some(s) = all_class_skills()
+        .iter()
+        .find(|s| s.skill_id == skill_id && s.class as i32 == class_type)
+    {
+        return Some(s.learn_level);
+    }
+    if is_common_magic(skill_id) {
+        return magic_level_step(class_type).map(|step| magic_grade(skill_id) * step);
+    }
+    all_darkelf_skills()
+        .iter()
+        .find(|s| s.skill_id == skill_id)
+        .map(|s| s.learn_level)
+}
+
+/// Validate a learn attempt and return what it costs.
+pub fn check_learn(req: &LearnRequest) -> Result<LearnCost, LearnError> {
+    let required = required_level(req.skill_id, req.class_type);
+    let mask = class_mask::for_skill(req.skill_id, magic_grade(req.skill_id));
+    if mask == 0 && required.is_none() {
+        return Err(LearnError::UnknownSkill);
+    }
+    if mask & class_mask::flag(req.class_type) == 0 {
+        return Err(LearnError::WrongClass);
+    }
+    let required = required.ok_or(LearnError::UnknownSkill)?;
+    if req.learned.contains(&req.skill_id) {
+        return Err(LearnError::AlreadyLearned);
+    }
+    if req.level < required {
+        return Err(LearnError::LevelTooLow { required });
+    }
+    if let Some(&(_, pre)) = PREREQUISITES.iter().find(|(s, _)| *s == req.skill_id) {
+        if !req.learned.contains(&pre) {
+            return Err(LearnError::MissingPrereq(pre));
+        }
+    }
+
+    if is_common_magic(req.skill_id) {
+        let price = magic_price(magic_grade(req.skill_id));
+        if req.adena < price {
+            return Err(LearnError::NotEnoughAdena { required: price });
+        }
+        Ok(LearnCost::Adena(price))
+    } else if req.has_spellbook {
+        Ok(LearnCost::Spellbook)
+    } else {
+        Err(LearnError::MissingSpellbook)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(skill_id: i32, class_type: i32, level: i32, learned: &[i32]) -> LearnRequest<'_> {
+        LearnRequest { skill_id, class_type, level, learned, adena: 10_000, has_spellbook: false }
+    }
+
+    #[test]
+    fn test_learn_success() {
+        // Mage Lv16 buying Fireball (grade 3: Lv12, 900 adena)
+        let req = request(skill_ids::FIREBALL, 3, 16, &[]);
+        assert_eq!(check_learn(&req), Ok(LearnCost::Adena(900)));
+
+        // Knight learning 衝擊之暈 from a spellbook
+        let mut req = request(120, 1, 60, &[]);
+        req.has_spellbook = true;
+        assert_eq!(check_learn(&req), Ok(LearnCost::Spellbook));
+    }
+
+    #[test]
+    fn test_learn_level_too_low() {
+        let req = request(skill_ids::FIREBALL, 3, 8, &[]);
+        assert_eq!(check_learn(&req), Err(LearnError::LevelTooLow { required: 12 }));
+
+        // Royal grade 2 needs Lv20
+        let req = request(skill_ids::CHILL_TOUCH, 0, 19, &[]);
+        assert_eq!(check_learn(&req), Err(LearnError::LevelTooLow { required: 20 }));
+    }
+
+    #[test]
+    fn test_learn_rejections() {
+        // Knights can't buy grade 3 magic
+        assert_eq!(check_learn(&request(skill_ids::FIREBALL, 1, 99, &[])), Err(LearnError::WrongClass));
+        // Prerequisite
+        assert_eq!(
+            check_learn(&request(skill_ids::GREATER_HEAL, 3, 99, &[])),
+            Err(LearnError::MissingPrereq(skill_ids::HEAL))
+        );
+        // Already learned
+        assert_eq!(
+            check_learn(&request(skill_ids::LIGHT, 3, 99, &[skill_ids::LIGHT])),
+            Err(LearnError::AlreadyLearned)
+        );
+        // Adena
+        let mut req = request(skill_ids::FIREBALL, 3, 99, &[]);
+        req.adena = 100;
+        assert_eq!(check_learn(&req), Err(LearnError::NotEnoughAdena { required: 900 }));
+        // Class skill without a book
+        assert_eq!(check_learn(&request(120, 1, 60, &[])), Err(LearnError::MissingSpellbook));
+    }
+}
@@ -0,0 +1,422 @@
+//! Player-to-player trade window.
+//!
+//! Ported from Java L1Trade.java. Each side offers items (by object ID
+//! and count) and both must confirm. Offers are only promises: the swap
+//! re-validates ownership and counts against the live inventories at the
+//! moment of completion, so dropping or using an offered item after
+//! offering it aborts the trade instead of duplicating it.
+//!
+//! A trade opens once the asked player accepts a yes/no dialog
+//! (Java C_Trade / C_Attr 252).
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::ecs::components::item::{Inventory, ItemInstance, ItemTemplate};
+
+/// Maximum distinct item stacks one side may offer.
+pub const MAX_TRADE_ITEMS: usize = 16;
+
+/// S_YES_NO message: "%0 要與你交易。願不願意交易？ (Y/N)"
+pub const MSG_TRADE_REQUEST: i32 = 252;
+
+/// How long a trade request waits for the target's answer.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// A single offered item.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TradeOffer {
+    pub object_id: u32,
+    pub item_id: i32,
+    pub count: i32,
+}
+
+/// One side of a trade.
+#[derive(Debug, Clone, Default)]
+pub struct TradeSide {
+    pub char_id: i32,
+    pub offers: Vec<TradeOffer>,
+    pub confirmed: bool,
+}
+
+/// Why a trade action was refused or the swap aborted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TradeError {
+    /// Character isn't part of this trade.
+    NotParticipant,
+    /// Item isn't in the offering character's inventory.
+    ItemMissing(u32),
+    /// Offered count exceeds what the character holds.
+    CountMismatch(u32),
+    /// Equipped or untradable items can't be offered.
+    NotTradable(u32),
+    /// Too many stacks offered.
+    TooManyItems,
+    /// Both sides haven't confirmed yet.
+    NotConfirmed,
+    /// Receiver's inventory can't take the items.
+    InventoryFull,
+    /// Asked to trade with themselves.
+    SelfTarget,
+    /// Either side is already trading or has a request pending.
+    Busy,
+}
+
+/// An open trade between two characters.
+#[derive(Debug, Clone)]
+pub struct Trade {
+    pub a: TradeSide,
+    pub b: TradeSide,
+}
+
+impl Trade {
+    pub fn new(char_a: i32, char_b: i32) -> Self {
+        Trade {
+            a: TradeSide { char_id: char_a, ..Default::default() },
+            b: TradeSide { char_id: char_b, ..Default::default() },
+        }
+    }
+
+    /// The other participant.
+    pub fn partner_of(&self, char_id: i32) -> Option<i32> {
+        if self.a.char_id == char_id {
+            Some(self.b.char_id)
+        } else if self.b.char_id == char_id {
+            Some(self.a.char_id)
+        } else {
+            None
+        }
+    }
+
+    fn side_mut(&mut self, char_id: i32) -> Result<&mut TradeSide, TradeError> {
+        if self.a.char_id == char_id {
+            Ok(&mut self.a)
+        } else if self.b.char_id == char_id {
+            Ok(&mut self.b)
+        } else {
+            Err(TradeError::NotParticipant)
+        }
+    }
+
+    /// Offer an item. Checked against the inventory now, and again at swap time.
+    /// Any change to the offers clears both confirmations.
+    pub fn offer(
+        &mut self,
+        char_id: i32,
+        inv: &Inventory,
+        templates: &HashMap<i32, ItemTemplate>,
+        object_id: u32,
+        count: i32,
+    ) -> Result<(), TradeError> {
+        let item = inv.get_item(object_id).ok_or(TradeError::ItemMissing(object_id))?;
+        check_tradable(item, templates)?;
+        let side = self.side_mut(char_id)?;
+        let already: i32 = side.offers.iter().filter(|o| o.object_id == object_id).map(|o| o.count).sum();
+        if count <= 0 || already + count > item.count {
+            return Err(TradeError::CountMismatch(object_id));
+        }
+        match side.offers.iter_mut().find(|o| o.object_id == object_id) {
+            Some(o) => o.count += count,
+            None => {
+                if side.offers.len() >= MAX_TRADE_ITEMS {
+                    return Err(TradeError::TooManyItems);
+                }
+                side.offers.push(TradeOffer { object_id, item_id: item.item_id, count });
+            }
+        }
+        self.a.confirmed = false;
+        self.b.confirmed = false;
+        Ok(())
+    }
+
+    /// Confirm one side. Returns true once both sides have confirmed.
+    pub fn confirm(&mut self, char_id: i32) -> Result<bool, TradeError> {
+        self.side_mut(char_id)?.confirmed = true;
+        Ok(self.a.confirmed && self.b.confirmed)
+    }
+
+    /// Perform the swap atomically.
+    ///
+    /// Every offer on both sides is re-validated against the live
+    /// inventories first; if anything changed since it was offered the
+    /// trade aborts and neither inventory is touched.
+    pub fn complete(
+        &self,
+        inv_a: &mut Inventory,
+        inv_b: &mut Inventory,
+        templates: &HashMap<i32, ItemTemplate>,
+    ) -> Result<(), TradeError> {
+        if !(self.a.confirmed && self.b.confirmed) {
+            return Err(TradeError::NotConfirmed);
+        }
+        validate_offers(&self.a.offers, inv_a, templates)?;
+        validate_offers(&self.b.offers, inv_b, templates)?;
+        if !has_room(inv_b, &self.a.offers, templates) || !has_room(inv_a, &self.b.offers, templates) {
+            return Err(TradeError::InventoryFull);
+        }
+
+        let from_a = take_offers(&self.a.offers, inv_a);
+        let from_b = take_offers(&self.b.offers, inv_b);
+        give(from_a, inv_b, templates);
+        give(from_b, inv_a, templates);
+        Ok(())
+    }
+}
+
+/// A trade request waiting for the target's yes/no answer.
+#[derive(Debug, Clone)]
+pub struct TradeRequest {
+    pub requester_id: i32,
+    pub yes_no_id: i32,
+    pub created_at: Instant,
+}
+
+/// All open trades and outstanding requests, keyed by object ID.
+#[derive(Debug, Default)]
+pub struct Trades {
+    /// Keyed by the requester's object ID.
+    open: HashMap<i32, Trade>,
+    /// Each participant's key into `open`.
+    trading: HashMap<i32, i32>,
+    /// Keyed by target object ID.
+    requests: HashMap<i32, TradeRequest>,
+    next_yes_no_id: i32,
+}
+
+impl Trades {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask `target_id` to trade with `requester_id`. Returns the
+    /// `yes_no_id` to send the target with S_YES_NO.
+    pub fn request(&mut self, requester_id: i32, target_id: i32) -> Result<i32, TradeError> {
+        if requester_id == target_id {
+            return Err(TradeError::SelfTarget);
+        }
+        let pending = |id: i32| self.requests.get(&id).is_some_and(|r| r.created_at.elapsed() < REQUEST_TIMEOUT);
+        if self.trading.contains_key(&requester_id) || self.trading.contains_key(&target_id) || pending(target_id) {
+            return Err(TradeError::Busy);
+        }
+        self.next_yes_no_id += 1;
+        self.requests.insert(target_id, TradeRequest {
+            requester_id,
+            yes_no_id: self.next_yes_no_id,
+            created_at: Instant::now(),
+        });
+        Ok(self.next_yes_no_id)
+    }
+
+    /// Resolve the target's answer to dialog `yes_no_id`, opening the
+    /// trade on acceptance. Returns the requester's object ID. Declined
+    /// or expired requests are dropped; an answer to any other dialog
+    /// leaves the request alone.
+    pub fn respond(&mut self, target_id: i32, yes_no_id: i32, accept: bool) -> Option<i32> {
+        if self.requests.get(&target_id)?.yes_no_id != yes_no_id {
+            return None;
+        }
+        let request = self.requests.remove(&target_id)?;
+        let requester_id = request.requester_id;
+        if !accept
+            || request.created_at.elapsed() >= REQUEST_TIMEOUT
+            || self.trading.contains_key(&requester_id)
+            || self.trading.contains_key(&target_id)
+        {
+            return None;
+        }
+        self.open.insert(requester_id, Trade::new(requester_id, target_id));
+        self.trading.insert(requester_id, requester_id);
+        self.trading.insert(target_id, requester_id);
+        Some(requester_id)
+    }
+
+    /// The open trade `char_id` is part of.
+    pub fn get_mut(&mut self, char_id: i32) -> Option<&mut Trade> {
+        let key = self.trading.get(&char_id)?;
+        self.open.get_mut(key)
+    }
+
+    /// End `char_id`'s trade (completed or cancelled) and hand it back.
+    pub fn close(&mut self, char_id: i32) -> Option<Trade> {
+        let key = *self.trading.get(&char_id)?;
+        let trade = self.open.remove(&key)?;
+        self.trading.remove(&trade.a.char_id);
+        self.trading.remove(&trade.b.char_id);
+        Some(trade)
+    }
+
+    /// Drop a player's requests and cancel their trade when they leave
+    /// the world. Returns the cancelled trade, if any.
+    pub fn remove_player(&mut self, object_id: i32) -> Option<Trade> {
+        self.requests.retain(|&target, r| target != object_id && r.requester_id != object_id);
+        self.close(object_id)
+    }
+}
+
+fn check_tradable(item: &ItemInstance, templates: &HashMap<i32, ItemTemplate>) -> Result<(), TradeError> {
+    let tradable = templates.get(&item.item_id).is_some_and(|t| t.tradable);
+    if item.is_equipped || !tradable {
+        return Err(TradeError::NotTradable(item.object_id));
+    }
+    Ok(())
+}
+
+/// Check each offer still matches what the inventory holds right now.
+fn validate_offers(
+    offers: &[TradeOffer],
+    inv: &Inventory,
+    templates: &HashMap<i32, ItemTemplate>,
+) -> Result<(), TradeError> {
+    for o in offers {
+        let item = inv.get_item(o.object_id).ok_or(TradeError::ItemMissing(o.object_id))?;
+        if item.item_id != o.item_id || item.count < o.count {
+            return Err(TradeError::CountMismatch(o.object_id));
+        }
+        check_tradable(item, templates)?;
+    }
+    Ok(())
+}
+
+/// Check the receiver has slots for every non-merging stack.
+fn has_room(receiver: &Inventory, offers: &[TradeOffer], templates: &HashMap<i32, ItemTemplate>) -> bool {
+    let new_slots = offers
+        .iter()
+        .filter(|o| {
+            let stackable = templates.get(&o.item_id).is_some_and(|t| t.stackable);
+            !(stackable && receiver.find_item_id(o.item_id).is_some())
+        })
+        .count();
+    receiver.items.len() + new_slots <= receiver.max_size
+}
+
+/// Remove offered items from the giver, returning the instances to hand over.
+fn take_offers(offers: &[TradeOffer], inv: &mut Inventory) -> Vec<ItemInstance> {
+    let mut taken = Vec::with_capacity(offers.len());
+    for o in offers {
+        if let Some(item) = inv.get_item(o.object_id) {
+            let mut moved = item.clone();
+            moved.count = o.count;
+            moved.is_equipped = false;
+            inv.remove_item(o.object_id, o.count);
+            taken.push(moved);
+        }
+    }
+    taken
+}
+
+fn give(items: Vec<ItemInstance>, inv: &mut Inventory, templates: &HashMap<i32, ItemTemplate>) {
+    for item in items {
+        if let Some(t) = templates.get(&item.item_id) {
+            inv.add_item(item, t);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::components::clan::ADENA_ITEM_ID;
+
+    fn templates() -> HashMap<i32, ItemTemplate> {
+        let mut map = HashMap::new();
+        let mut adena = ItemTemplate::default();
+        adena.item_id = ADENA_ITEM_ID;
+        adena.stackable = true;
+        map.insert(ADENA_ITEM_ID, adena);
+        let mut sword = ItemTemplate::default();
+        sword.item_id = 48;
+        map.insert(48, sword);
+        map
+    }
+
+    fn setup() -> (Trade, Inventory, Inventory, HashMap<i32, ItemTemplate>) {
+        let t = templates();
+        let mut inv_a = Inventory::new();
+        inv_a.add_item(ItemInstance::new(10, 48), &t[&48]);
+        let mut inv_b = Inventory::new();
+        inv_b.add_item(ItemInstance { count: 1000, ..ItemInstance::new(20, ADENA_ITEM_ID) }, &t[&ADENA_ITEM_ID]);
+        (Trade::new(1, 2), inv_a, inv_b, t)
+    }
+
+    #[test]
+    fn test_trade_swaps_items() {
+        let (mut trade, mut inv_a, mut inv_b, t) = setup();
+        trade.offer(1, &inv_a, &t, 10, 1).unwrap();
+        trade.offer(2, &inv_b, &t, 20, 600).unwrap();
+        assert!(!trade.confirm(1).unwrap());
+        assert!(trade.confirm(2).unwrap());
+
+        trade.complete(&mut inv_a, &mut inv_b, &t).unwrap();
+        assert!(!inv_a.check_item(48, 1));
+        assert!(inv_a.check_item(ADENA_ITEM_ID, 600));
+        assert!(inv_b.check_item(48, 1));
+        assert!(inv_b.check_item(ADENA_ITEM_ID, 400));
+        assert!(!inv_b.check_item(ADENA_ITEM_ID, 401));
+    }
+
+    #[test]
+    fn test_item_dropped_after_offer_aborts_swap() {
+        let (mut trade, mut inv_a, mut inv_b, t) = setup();
+        trade.offer(1, &inv_a, &t, 10, 1).unwrap();
+        trade.offer(2, &inv_b, &t, 20, 600).unwrap();
+        trade.confirm(1).unwrap();
+        trade.confirm(2).unwrap();
+
+        // Side A drops the sword before the swap
+        inv_a.remove_item(10, 1);
+
+        assert_eq!(trade.complete(&mut inv_a, &mut inv_b, &t), Err(TradeError::ItemMissing(10)));
+        // Nothing moved: B keeps all adena and gets no sword
+        assert!(inv_b.check_item(ADENA_ITEM_ID, 1000));
+        assert!(!inv_b.check_item(48, 1));
+        assert!(!inv_a.check_item(ADENA_ITEM_ID, 1));
+    }
+
+    #[test]
+    fn test_adena_spent_after_offer_aborts_swap() {
+        let (mut trade, mut inv_a, mut inv_b, t) = setup();
+        trade.offer(1, &inv_a, &t, 10, 1).unwrap();
+        trade.offer(2, &inv_b, &t, 20, 600).unwrap();
+        trade.confirm(1).unwrap();
+        trade.confirm(2).unwrap();
+
+        inv_b.remove_item(20, 500); // only 500 left
+
+        assert_eq!(trade.complete(&mut inv_a, &mut inv_b, &t), Err(TradeError::CountMismatch(20)));
+        assert!(inv_a.check_item(48, 1));
+        assert!(inv_b.check_item(ADENA_ITEM_ID, 500));
+    }
+
+    #[test]
+    fn test_offer_change_clears_confirmation() {
+        let (mut trade, inv_a, inv_b, t) = setup();
+        trade.offer(1, &inv_a, &t, 10, 1).unwrap();
+        trade.confirm(1).unwrap();
+        trade.offer(2, &inv_b, &t, 20, 100).unwrap();
+        assert!(!trade.a.confirmed);
+        assert_eq!(trade.offer(2, &inv_b, &t, 20, 901), Err(TradeError::CountMismatch(20)));
+        assert_eq!(trade.offer(3, &inv_b, &t, 20, 1), Err(TradeError::NotParticipant));
+    }
+
+    #[test]
+    fn test_request_consent_flow() {
+        let mut trades = Trades::new();
+        assert_eq!(trades.request(1, 1), Err(TradeError::SelfTarget));
+        let id = trades.request(1, 2).unwrap();
+        assert_eq!(trades.request(3, 2), Err(TradeError::Busy));
+
+        // Answers to another dialog don't resolve it; declining opens nothing
+        assert_eq!(trades.respond(2, id + 1, true), None);
+        let id2 = trades.request(4, 5).unwrap();
+        assert_eq!(trades.respond(5, id2, false), None);
+        assert!(trades.get_mut(5).is_none());
+
+        assert_eq!(trades.respond(2, id, true), Some(1));
+        assert_eq!(trades.get_mut(2).unwrap().partner_of(2), Some(1));
+        assert_eq!(trades.request(3, 1), Err(TradeError::Busy));
+
+        let closed = trades.remove_player(2).unwrap();
+        assert_eq!((closed.a.char_id, closed.b.char_id), (1, 2));
+        assert!(trades.get_mut(1).is_none() && trades.close(1).is_none());
+    }
+}
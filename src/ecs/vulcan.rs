@@ -4,7 +4,11 @@
 /// 功能：熔煉裝備 → 火神結晶體、製作武器/防具
 ///
 /// 資料來源：天堂官方活動頁面、17173 天堂攻略
-use rand::RngExt;
+use std::collections::HashMap;
+
+use crate::ecs::components::item::{Inventory, ItemInstance, ItemTemplate};
+use crate::ecs::components::position::tile_distance;
+use crate::ecs::crafting::{self, Catalyst, CraftError, CraftOutcome, GenericRecipe, Material};
 
 /// 火神結晶體 item ID。
 pub const VULCAN_CRYSTAL_ID: i32 = 41246;
@@ -17,6 +21,13 @@ pub const VULCAN_HAMMER_ID: i32 = 41248;
 pub const VULCAN_NPC_X: i32 = 33456;
 pub const VULCAN_NPC_Y: i32 = 32776;
 pub const VULCAN_NPC_MAP: i32 = 4;
+/// 可與工匠對話的距離（格）。
+pub const VULCAN_TALK_RANGE: i32 = 5;
+
+/// 玩家是否站在工匠旁。
+pub fn is_near_vulcan(map_id: i32, x: i32, y: i32) -> bool {
+    map_id == VULCAN_NPC_MAP && tile_distance(x, y, VULCAN_NPC_X, VULCAN_NPC_Y) <= VULCAN_TALK_RANGE
+}
 
 // ===========================================================================
 // 熔煉系統：裝備 → 火神結晶體
@@ -70,6 +81,11 @@ pub fn weapon_smelt_table() -> Vec<SmeltEntry> {
     ]
 }
 
+/// 查詢物品的熔煉資料。
+pub fn smelt_entry(item_id: i32) -> Option<SmeltEntry> {
+    armor_smelt_table().into_iter().chain(weapon_smelt_table()).find(|e| e.item_id == item_id)
+}
+
 /// 計算熔煉獲得的火神結晶體數量。
 /// 返回 None 表示該裝備/強化等級不可熔煉。
 pub fn calc_smelt_crystals(item_id: i32, enchant_level: i32) -> Option<i32> {
@@ -94,14 +110,50 @@ pub fn calc_smelt_crystals(item_id: i32, enchant_level: i32) -> Option<i32> {
     None // 不在熔煉表中
 }
 
+/// 批次熔煉遇到不可熔煉物品時的處理方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchMode {
+    /// 整批取消。
+    Abort,
+    /// 跳過該物品，其餘照常熔煉。
+    Skip,
+}
+
+/// 批次熔煉結果。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchSmelt {
+    pub total_crystals: i32,
+    /// 實際熔煉的物品 (item_id, 強化等級)。
+    pub consumed: Vec<(i32, i32)>,
+    /// 跳過的物品（僅 `BatchMode::Skip`）。
+    pub skipped: Vec<(i32, i32)>,
+}
+
+/// 批次熔煉 (item_id, 強化等級) 清單，逐件以 `calc_smelt_crystals` 計算。
+/// `BatchMode::Abort` 時遇到不可熔煉物品返回其索引。
+pub fn smelt_batch(items: &[(i32, i32)], mode: BatchMode) -> Result<BatchSmelt, usize> {
+    let mut batch = BatchSmelt::default();
+    for (index, &(item_id, enchant)) in items.iter().enumerate() {
+        match calc_smelt_crystals(item_id, enchant) {
+            Some(crystals) => {
+                batch.total_crystals += crystals;
+                batch.consumed.push((item_id, enchant));
+            }
+            None if mode == BatchMode::Abort => return Err(index),
+            None => batch.skipped.push((item_id, enchant)),
+        }
+    }
+    Ok(batch)
+}
+
 // ===========================================================================
 // 製作系統：火神契約 + 火神結晶體 → 武器/防具
 // ===========================================================================
 
 /// 製作配方。
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
 pub struct CraftRecipe {
-    pub result_item_name: &'static str,
+    pub result_item_name: String,
     pub result_item_id: i32,
     pub contract_cost: i32,      // 火神契約數量
     pub crystal_cost: i32,       // 火神結晶體數量
@@ -111,43 +163,60 @@ pub struct CraftRecipe {
     pub hammer_bonus_rate: i32,
 }
 
+impl CraftRecipe {
+    /// 轉為通用製作配方：契約 + 結晶體 → 成品，火神之槌為催化劑。
+    pub fn to_generic(&self) -> GenericRecipe {
+        GenericRecipe {
+            name: self.result_item_name.clone(),
+            materials: vec![
+                Material { item_id: VULCAN_CONTRACT_ID, count: self.contract_cost },
+                Material { item_id: VULCAN_CRYSTAL_ID, count: self.crystal_cost },
+            ],
+            output: Material { item_id: self.result_item_id, count: 1 },
+            output_stackable: false,
+            success_rate: self.base_success_rate,
+            catalyst: Some(Catalyst { item_id: VULCAN_HAMMER_ID, bonus_rate: self.hammer_bonus_rate }),
+        }
+    }
+}
+
 /// 官方製作配方表。
 pub fn craft_recipes() -> Vec<CraftRecipe> {
     vec![
         // 武器
         CraftRecipe {
-            result_item_name: "武官之刃", result_item_id: 80,
+            result_item_name: "武官之刃".into(), result_item_id: 80,
             contract_cost: 8, crystal_cost: 40,
             base_success_rate: 80, hammer_bonus_rate: 10,
         },
         CraftRecipe {
-            result_item_name: "黑暗雙刀", result_item_id: 81,
+            result_item_name: "黑暗雙刀".into(), result_item_id: 81,
             contract_cost: 10, crystal_cost: 40,
             base_success_rate: 75, hammer_bonus_rate: 10,
         },
         CraftRecipe {
-            result_item_name: "克特之劍", result_item_id: 82,
+            result_item_name: "克特之劍".into(), result_item_id: 82,
             contract_cost: 9, crystal_cost: 75,
             base_success_rate: 70, hammer_bonus_rate: 10,
         },
         CraftRecipe {
-            result_item_name: "宙斯巨劍", result_item_id: 83,
+            result_item_name: "宙斯巨劍".into(), result_item_id: 83,
             contract_cost: 20, crystal_cost: 80,
             base_success_rate: 60, hammer_bonus_rate: 15,
         },
         CraftRecipe {
-            result_item_name: "瑪那魔杖", result_item_id: 84,
+            result_item_name: "瑪那魔杖".into(), result_item_id: 84,
             contract_cost: 40, crystal_cost: 60,
             base_success_rate: 55, hammer_bonus_rate: 15,
         },
         // 防具
         CraftRecipe {
-            result_item_name: "蚩尤鎧甲", result_item_id: 20200,
+            result_item_name: "蚩尤鎧甲".into(), result_item_id: 20200,
             contract_cost: 5, crystal_cost: 100,
             base_success_rate: 65, hammer_bonus_rate: 10,
         },
         CraftRecipe {
-            result_item_name: "黑長者長袍", result_item_id: 20201,
+            result_item_name: "黑長者長袍".into(), result_item_id: 20201,
             contract_cost: 5, crystal_cost: 100,
             base_success_rate: 65, hammer_bonus_rate: 10,
         },
@@ -161,44 +230,247 @@ pub enum CraftResult {
     Success(i32),
     /// 製作失敗，材料消耗。
     Failure,
-    /// 材料不足。
+}
+
+/// 實際成功率：基礎 + 火神之槌 + 幸運/祝福加成，上限 `crafting::MAX_SUCCESS_RATE`。
+pub fn success_rate(recipe: &CraftRecipe, has_hammer: bool, luck: i32) -> i32 {
+    recipe.to_generic().effective_rate(has_hammer, luck)
+}
+
+// ===========================================================================
+// 工匠對話：熔煉／製作流程
+// ===========================================================================
+
+/// 工匠對話選項（C_NPCACTION 的 action 字串）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VulcanAction {
+    /// "smelt <物品 objid> <強化等級>"
+    Smelt { object_id: u32, enchant: i32 },
+    /// "craft <產出物品 ID>"
+    Craft(i32),
+}
+
+/// 解析對話選項，無法辨識時返回 None。
+pub fn parse_action(action: &str) -> Option<VulcanAction> {
+    let mut it = action.split_whitespace();
+    let kind = it.next()?;
+    let arg = it.next()?;
+    match kind {
+        "smelt" => {
+            let object_id = arg.parse().ok()?;
+            let enchant = it.next()?.parse().ok()?;
+            Some(VulcanAction::Smelt { object_id, enchant })
+        }
+        "craft" => arg.parse().ok().map(VulcanAction::Craft),
+        _ => None,
+    }
+}
+
+/// 工匠主選單 HTML（列出背包中可熔煉的裝備與 `recipes` 中的配方）。
+pub fn menu_html(items: &[ItemInstance], recipes: &[CraftRecipe]) -> String {
+    let mut buttons: Vec<(String, String)> = items
+        .iter()
+        .filter(|i| check_smelt(i, i.enchant_level).is_ok())
+        .filter_map(|i| {
+            let entry = smelt_entry(i.item_id)?;
+            Some((format!("熔煉 +{} {}", i.enchant_level, entry.item_name), format!("smelt {} {}", i.object_id, i.enchant_level)))
+        })
+        .collect();
+    for r in recipes {
+        buttons.push((format!("製作 {}", r.result_item_name), format!("craft {}", r.result_item_id)));
+    }
+    let refs: Vec<(&str, &str)> = buttons.iter().map(|(l, a)| (l.as_str(), a.as_str())).collect();
+    crate::protocol::server::npc_dialog::build_simple_dialog("火神工匠", "要熔煉還是製作？", &refs)
+}
+
+/// 熔煉／製作被拒絕的原因。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VulcanError {
+    /// 背包中沒有該物品。
+    ItemNotFound,
+    /// 裝備中的物品不可熔煉。
+    Equipped,
+    /// 客戶端所稱的強化等級與物品實際不符。
+    EnchantMismatch,
+    /// 不在熔煉表中或強化等級不符。
+    NotSmeltable,
     InsufficientMaterials,
-    /// 配方不存在。
     RecipeNotFound,
+    /// 背包已滿，放不下成品。
+    InventoryFull,
+    /// 距離上次製作未滿冷卻時間。
+    Cooldown { remaining_secs: i64 },
+    /// 今日製作次數已達上限。
+    DailyLimit,
 }
 
-/// 執行製作判定。
-pub fn try_craft(
-    recipe_item_id: i32,
-    has_contracts: i32,
-    has_crystals: i32,
-    has_hammer: bool,
-) -> CraftResult {
-    let recipes = craft_recipes();
-    let recipe = match recipes.iter().find(|r| r.result_item_id == recipe_item_id) {
-        Some(r) => r,
-        None => return CraftResult::RecipeNotFound,
-    };
+impl VulcanError {
+    /// 回覆玩家的訊息。
+    pub fn message(self) -> &'static str {
+        match self {
+            VulcanError::ItemNotFound => "找不到該物品。",
+            VulcanError::Equipped => "請先卸下裝備。",
+            VulcanError::EnchantMismatch => "裝備狀態已變更，請重新選擇。",
+            VulcanError::NotSmeltable => "這件裝備無法熔煉。",
+            VulcanError::InsufficientMaterials => "材料不足。",
+            VulcanError::RecipeNotFound => "沒有這個配方。",
+            VulcanError::InventoryFull => "背包已滿。",
+            VulcanError::Cooldown { .. } => "火神工匠正在休息，請稍後再來。",
+            VulcanError::DailyLimit => "今天已經製作太多次了，明天再來吧。",
+        }
+    }
+}
 
-    if has_contracts < recipe.contract_cost || has_crystals < recipe.crystal_cost {
-        return CraftResult::InsufficientMaterials;
+/// 檢查物品能否熔煉，返回可獲得的結晶體數量。
+///
+/// `claimed_enchant` 是客戶端送來的強化等級，只用來核對；
+/// 結晶體數量一律依物品實際的強化等級計算。
+pub fn check_smelt(item: &ItemInstance, claimed_enchant: i32) -> Result<i32, VulcanError> {
+    if item.is_equipped {
+        return Err(VulcanError::Equipped);
+    }
+    if item.enchant_level != claimed_enchant {
+        return Err(VulcanError::EnchantMismatch);
     }
+    calc_smelt_crystals(item.item_id, item.enchant_level).ok_or(VulcanError::NotSmeltable)
+}
 
-    let success_rate = if has_hammer {
-        recipe.base_success_rate + recipe.hammer_bonus_rate
-    } else {
-        recipe.base_success_rate
-    };
+/// 在 `recipes`（目前載入的遊戲資料）中查詢配方。
+pub fn find_recipe(recipes: &[CraftRecipe], result_item_id: i32) -> Option<&CraftRecipe> {
+    recipes.iter().find(|r| r.result_item_id == result_item_id)
+}
+
+fn crystal_template() -> ItemTemplate {
+    ItemTemplate { item_id: VULCAN_CRYSTAL_ID, name: "火神結晶體".into(), stackable: true, ..Default::default() }
+}
+
+/// 熔煉背包中的 `object_id`：移除該裝備並放入結晶體。
+/// 結晶體若需新建一疊，使用 `crystal_object_id`。返回獲得數量。
+pub fn smelt(
+    inv: &mut Inventory,
+    object_id: u32,
+    claimed_enchant: i32,
+    crystal_object_id: u32,
+) -> Result<i32, VulcanError> {
+    let item = inv.get_item(object_id).ok_or(VulcanError::ItemNotFound)?;
+    let crystals = check_smelt(item, claimed_enchant)?;
+    inv.remove_item(object_id, item.count);
+    let stack = ItemInstance { count: crystals, ..ItemInstance::new(crystal_object_id, VULCAN_CRYSTAL_ID) };
+    inv.add_item(stack, &crystal_template());
+    Ok(crystals)
+}
+
+/// 以背包中的材料依 `recipe` 製作（經由 `crafting::craft_generic`）。
+///
+/// 成功或失敗都會消耗契約與結晶體（帶著火神之槌時一併消耗一把），
+/// 成功時以 `result_object_id` 放入成品。
+pub fn craft(
+    inv: &mut Inventory,
+    recipe: &CraftRecipe,
+    result_object_id: u32,
+    luck: i32,
+) -> Result<CraftResult, VulcanError> {
+    match crafting::craft_generic(&recipe.to_generic(), inv, result_object_id, luck) {
+        Ok(CraftOutcome::Success) => Ok(CraftResult::Success(recipe.result_item_id)),
+        Ok(CraftOutcome::Failure) => Ok(CraftResult::Failure),
+        Err(CraftError::InsufficientMaterials) => Err(VulcanError::InsufficientMaterials),
+        Err(CraftError::InventoryFull) => Err(VulcanError::InventoryFull),
+    }
+}
 
-    let roll = rand::rng().random_range(1..=100);
+/// 新物品的暫用物件編號：呼叫端以此執行 `run_action`，
+/// 只有變更中真的新增了物品才分配正式編號。
+pub const PENDING_OBJECT_ID: u32 = 0;
 
-    if roll <= success_rate {
-        CraftResult::Success(recipe.result_item_id)
-    } else {
-        CraftResult::Failure
+/// 對背包執行工匠選項，返回回覆玩家的訊息。
+///
+/// 配方取自 `recipes`（目前載入的遊戲資料）；熔煉新開的結晶體疊或
+/// 製作的成品使用 `new_object_id`。失敗時背包不變。
+pub fn run_action(
+    inv: &mut Inventory,
+    action: VulcanAction,
+    recipes: &[CraftRecipe],
+    new_object_id: u32,
+    luck: i32,
+) -> Result<String, VulcanError> {
+    match action {
+        VulcanAction::Smelt { object_id, enchant } => {
+            let crystals = smelt(inv, object_id, enchant, new_object_id)?;
+            Ok(format!("獲得火神結晶體 {} 個。", crystals))
+        }
+        VulcanAction::Craft(result_id) => {
+            let recipe = find_recipe(recipes, result_id).ok_or(VulcanError::RecipeNotFound)?;
+            Ok(match craft(inv, recipe, new_object_id, luck)? {
+                CraftResult::Success(_) => format!("製作成功：{}。", recipe.result_item_name),
+                CraftResult::Failure => "製作失敗，材料已消耗。".to_string(),
+            })
+        }
     }
 }
 
+// ===========================================================================
+// 製作節奏限制
+// ===========================================================================
+
+/// 每日次數於台灣時間 (UTC+8) 午夜重置。
+const DAILY_RESET_OFFSET_SECS: i64 = 8 * 60 * 60;
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// 製作冷卻與每日上限，0 表示不限制。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CraftLimits {
+    pub cooldown_secs: i64,
+    pub daily_limit: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CraftRecord {
+    last_craft: i64,
+    day: i64,
+    count: u32,
+}
+
+/// 每個角色最近的製作紀錄（以角色 objid 為鍵）。
+#[derive(Debug, Default)]
+pub struct CraftLog {
+    records: HashMap<i32, CraftRecord>,
+}
+
+impl CraftLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `now`（unix 秒）時角色能否製作。
+    pub fn check(&self, char_id: i32, now: i64, limits: CraftLimits) -> Result<(), VulcanError> {
+        let Some(rec) = self.records.get(&char_id) else { return Ok(()) };
+        let ready_at = rec.last_craft + limits.cooldown_secs;
+        if limits.cooldown_secs > 0 && now < ready_at {
+            return Err(VulcanError::Cooldown { remaining_secs: ready_at - now });
+        }
+        if limits.daily_limit > 0 && rec.day == craft_day(now) && rec.count >= limits.daily_limit {
+            return Err(VulcanError::DailyLimit);
+        }
+        Ok(())
+    }
+
+    /// 記錄一次製作（無論成敗）。
+    pub fn record(&mut self, char_id: i32, now: i64) {
+        let day = craft_day(now);
+        let rec = self.records.entry(char_id).or_insert(CraftRecord { last_craft: now, day, count: 0 });
+        if rec.day != day {
+            rec.day = day;
+            rec.count = 0;
+        }
+        rec.last_craft = now;
+        rec.count += 1;
+    }
+}
+
+fn craft_day(now: i64) -> i64 {
+    (now + DAILY_RESET_OFFSET_SECS).div_euclid(DAY_SECS)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -236,27 +508,44 @@ mod tests {
 
     #[test]
     fn test_craft_insufficient_materials() {
-        let result = try_craft(80, 3, 20, false); // 武官之刃需要 8 契約 40 結晶
-        assert_eq!(result, CraftResult::InsufficientMaterials);
+        let recipes = craft_recipes();
+        let mut inv = Inventory::new();
+        inv.items.push(stack(1, VULCAN_CONTRACT_ID, 3)); // 武官之刃需要 8 契約 40 結晶
+        inv.items.push(stack(2, VULCAN_CRYSTAL_ID, 20));
+        let recipe = find_recipe(&recipes, 80).unwrap();
+        assert_eq!(craft(&mut inv, recipe, 50, 0), Err(VulcanError::InsufficientMaterials));
     }
 
     #[test]
     fn test_craft_recipe_not_found() {
-        let result = try_craft(99999, 100, 100, false);
-        assert_eq!(result, CraftResult::RecipeNotFound);
+        let mut inv = Inventory::new();
+        let result = run_action(&mut inv, VulcanAction::Craft(99999), &craft_recipes(), 50, 0);
+        assert_eq!(result, Err(VulcanError::RecipeNotFound));
     }
 
-    #[test]
-    fn test_craft_with_materials() {
-        // 用大量嘗試確認成功率合理
+    /// 以充足材料製作 1000 次，返回成功次數。
+    fn craft_successes(result_item_id: i32, has_hammer: bool, luck: i32) -> i32 {
+        let recipes = craft_recipes();
+        let recipe = find_recipe(&recipes, result_item_id).unwrap();
         let mut successes = 0;
         for _ in 0..1000 {
-            match try_craft(80, 100, 100, false) {
-                CraftResult::Success(_) => successes += 1,
-                CraftResult::Failure => {}
-                _ => panic!("Unexpected result"),
+            let mut inv = Inventory::new();
+            inv.items.push(stack(1, VULCAN_CONTRACT_ID, 100));
+            inv.items.push(stack(2, VULCAN_CRYSTAL_ID, 100));
+            if has_hammer {
+                inv.items.push(stack(3, VULCAN_HAMMER_ID, 1));
+            }
+            if craft(&mut inv, recipe, 50, luck) == Ok(CraftResult::Success(result_item_id)) {
+                successes += 1;
             }
         }
+        successes
+    }
+
+    #[test]
+    fn test_craft_with_materials() {
+        // 用大量嘗試確認成功率合理
+        let successes = craft_successes(80, false, 0);
         // 武官之刃基礎成功率 80%，1000 次中約 750-850 次成功
         assert!(successes > 700 && successes < 900,
             "Success count {} outside expected range for 80% rate", successes);
@@ -264,15 +553,191 @@ mod tests {
 
     #[test]
     fn test_craft_hammer_bonus() {
-        let mut with_hammer = 0;
-        let mut without_hammer = 0;
-        for _ in 0..1000 {
-            if let CraftResult::Success(_) = try_craft(83, 100, 100, true) { with_hammer += 1; }
-            if let CraftResult::Success(_) = try_craft(83, 100, 100, false) { without_hammer += 1; }
-        }
         // 宙斯巨劍：基礎 60%，火神之槌 +15% = 75%
         // 有槌應該比沒槌高
+        let with_hammer = craft_successes(83, true, 0);
+        let without_hammer = craft_successes(83, false, 0);
         assert!(with_hammer > without_hammer,
             "Hammer bonus not working: with={}, without={}", with_hammer, without_hammer);
     }
+
+    fn stack(object_id: u32, item_id: i32, count: i32) -> ItemInstance {
+        ItemInstance { count, ..ItemInstance::new(object_id, item_id) }
+    }
+
+    #[test]
+    fn test_smelt_flow() {
+        let mut inv = Inventory::new();
+        // 金屬盔甲 +7 = 13 結晶，原有 5 結晶
+        inv.items.push(ItemInstance { enchant_level: 7, ..ItemInstance::new(10, 20011) });
+        inv.items.push(stack(11, VULCAN_CRYSTAL_ID, 5));
+
+        assert_eq!(smelt(&mut inv, 10, 7, 99), Ok(13));
+        assert!(inv.get_item(10).is_none());
+        assert_eq!(inv.get_item(11).unwrap().count, 18);
+        assert!(inv.get_item(99).is_none());
+
+        // 裝備中與不可熔煉的物品保留在背包
+        inv.items.push(ItemInstance { enchant_level: 7, is_equipped: true, ..ItemInstance::new(12, 20011) });
+        inv.items.push(ItemInstance { enchant_level: 3, ..ItemInstance::new(13, 20011) });
+        assert_eq!(smelt(&mut inv, 12, 7, 99), Err(VulcanError::Equipped));
+        assert_eq!(smelt(&mut inv, 13, 3, 99), Err(VulcanError::NotSmeltable));
+        assert_eq!(smelt(&mut inv, 14, 7, 99), Err(VulcanError::ItemNotFound));
+        assert_eq!(inv.items.len(), 3);
+    }
+
+    #[test]
+    fn test_craft_flow_deducts_materials() {
+        let recipes = craft_recipes();
+        let mut inv = Inventory::new();
+        // 武官之刃需要 8 契約 40 結晶
+        inv.items.push(stack(1, VULCAN_CONTRACT_ID, 10));
+        inv.items.push(stack(2, VULCAN_CRYSTAL_ID, 45));
+        inv.items.push(stack(3, VULCAN_HAMMER_ID, 1));
+
+        let recipe = find_recipe(&recipes, 80).unwrap();
+        let result = craft(&mut inv, recipe, 50, 0).unwrap();
+        assert!(inv.check_item(VULCAN_CONTRACT_ID, 2) && !inv.check_item(VULCAN_CONTRACT_ID, 3));
+        assert!(inv.check_item(VULCAN_CRYSTAL_ID, 5) && !inv.check_item(VULCAN_CRYSTAL_ID, 6));
+        assert!(!inv.check_item(VULCAN_HAMMER_ID, 1));
+        assert_eq!(inv.check_item(80, 1), result == CraftResult::Success(80));
+
+        // 剩餘材料不足，背包不變
+        let before = inv.items.len();
+        assert_eq!(craft(&mut inv, recipe, 51, 0), Err(VulcanError::InsufficientMaterials));
+        assert_eq!(inv.items.len(), before);
+        assert!(inv.check_item(VULCAN_CONTRACT_ID, 2));
+    }
+
+    #[test]
+    fn test_run_action_changes() {
+        let recipes = craft_recipes();
+        let mut before = Inventory::new();
+        before.items.push(ItemInstance { enchant_level: 7, ..ItemInstance::new(10, 20011) });
+
+        // 熔煉：裝備消失，新開一疊結晶體
+        let mut after = before.clone();
+        let reply = run_action(&mut after, VulcanAction::Smelt { object_id: 10, enchant: 7 }, &recipes, 99, 0);
+        assert_eq!(reply.unwrap(), "獲得火神結晶體 13 個。");
+        let changes = after.changes_since(&before);
+        assert_eq!(changes.removed, [10]);
+        assert_eq!(changes.added.iter().map(|i| (i.object_id, i.count)).collect::<Vec<_>>(), [(99, 13)]);
+
+        // 製作：材料減少，成功時多出成品
+        let mut before = Inventory::new();
+        before.items.push(stack(1, VULCAN_CONTRACT_ID, 10));
+        before.items.push(stack(2, VULCAN_CRYSTAL_ID, 45));
+        let mut after = before.clone();
+        let reply = run_action(&mut after, VulcanAction::Craft(80), &recipes, 50, 0).unwrap();
+        let changes = after.changes_since(&before);
+        assert_eq!(changes.updated.iter().map(|i| (i.object_id, i.count)).collect::<Vec<_>>(), [(1, 2), (2, 5)]);
+        assert_eq!(changes.added.len(), usize::from(reply.starts_with("製作成功")));
+
+        // 熔煉進既有的結晶體疊：不新增物品，不需要新編號
+        let mut before = Inventory::new();
+        before.items.push(ItemInstance { enchant_level: 7, ..ItemInstance::new(10, 20011) });
+        before.items.push(stack(2, VULCAN_CRYSTAL_ID, 5));
+        let mut after = before.clone();
+        run_action(&mut after, VulcanAction::Smelt { object_id: 10, enchant: 7 }, &recipes, PENDING_OBJECT_ID, 0).unwrap();
+        let changes = after.changes_since(&before);
+        assert!(changes.added.is_empty());
+        assert_eq!(changes.updated.iter().map(|i| (i.object_id, i.count)).collect::<Vec<_>>(), [(2, 18)]);
+
+        // 配方不存在：背包不變
+        let mut same = before.clone();
+        assert_eq!(run_action(&mut same, VulcanAction::Craft(99999), &recipes, 50, 0), Err(VulcanError::RecipeNotFound));
+        assert!(same.changes_since(&before).is_empty());
+    }
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(parse_action("smelt 12345 7"), Some(VulcanAction::Smelt { object_id: 12345, enchant: 7 }));
+        assert_eq!(parse_action("smelt 12345"), None);
+        assert_eq!(parse_action("craft 80"), Some(VulcanAction::Craft(80)));
+        assert_eq!(parse_action("craft"), None);
+        assert_eq!(parse_action("buy 1"), None);
+    }
+
+    #[test]
+    fn test_smelt_rejects_claimed_enchant_mismatch() {
+        let mut inv = Inventory::new();
+        // 實際為 +6 的大馬士革雙刀，客戶端謊稱 +12
+        inv.items.push(ItemInstance { enchant_level: 6, ..ItemInstance::new(10, 64) });
+
+        assert_eq!(smelt(&mut inv, 10, 12, 99), Err(VulcanError::EnchantMismatch));
+        assert!(inv.get_item(10).is_some());
+        assert!(!inv.check_item(VULCAN_CRYSTAL_ID, 1));
+
+        assert_eq!(smelt(&mut inv, 10, 6, 99), Ok(13));
+        assert!(inv.get_item(10).is_none());
+        assert!(inv.check_item(VULCAN_CRYSTAL_ID, 13));
+    }
+
+    // 2025-10-16 12:00 UTC+8
+    const NOON: i64 = 1_760_587_200;
+
+    #[test]
+    fn test_craft_cooldown() {
+        let limits = CraftLimits { cooldown_secs: 60, daily_limit: 0 };
+        let mut log = CraftLog::new();
+        assert_eq!(log.check(1, NOON, limits), Ok(()));
+        log.record(1, NOON);
+
+        assert_eq!(log.check(1, NOON + 1, limits), Err(VulcanError::Cooldown { remaining_secs: 59 }));
+        // 其他角色不受影響
+        assert_eq!(log.check(2, NOON + 1, limits), Ok(()));
+        assert_eq!(log.check(1, NOON + 60, limits), Ok(()));
+        // 未設定限制時不擋
+        assert_eq!(log.check(1, NOON + 1, CraftLimits::default()), Ok(()));
+    }
+
+    #[test]
+    fn test_craft_daily_limit_resets_at_midnight() {
+        let limits = CraftLimits { cooldown_secs: 0, daily_limit: 2 };
+        let mut log = CraftLog::new();
+        log.record(1, NOON);
+        log.record(1, NOON + 10);
+        assert_eq!(log.check(1, NOON + 20, limits), Err(VulcanError::DailyLimit));
+        assert_eq!(log.check(1, NOON + 12 * 3600, limits), Ok(()));
+    }
+
+    #[test]
+    fn test_craft_luck_bonus() {
+        // 瑪那魔杖：基礎 55%，幸運 +30% = 85%
+        let lucky = craft_successes(84, false, 30);
+        let plain = craft_successes(84, false, 0);
+        assert!(lucky > plain, "Luck bonus not working: lucky={}, plain={}", lucky, plain);
+    }
+
+    #[test]
+    fn test_craft_rate_clamped() {
+        let recipes = craft_recipes();
+        let recipe = find_recipe(&recipes, 80).unwrap(); // 武官之刃 80% + 槌 10%
+        assert_eq!(success_rate(recipe, true, 0), 90);
+        assert_eq!(success_rate(recipe, false, 0), 80);
+        assert_eq!(success_rate(recipe, true, 50), crafting::MAX_SUCCESS_RATE);
+        assert_eq!(success_rate(recipe, false, -20), 80);
+    }
+
+    #[test]
+    fn test_smelt_batch_sums() {
+        // 金屬盔甲 +5 (3) + 力量手套 +4 (9) + 長劍 +8 (9)
+        let items = [(20011, 5), (20164, 4), (4, 8)];
+        let batch = smelt_batch(&items, BatchMode::Abort).unwrap();
+        assert_eq!(batch.total_crystals, 21);
+        assert_eq!(batch.consumed, items.to_vec());
+        assert!(batch.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_smelt_batch_with_unsmeltable() {
+        // 長劍 +5 未達最低強化 +6
+        let items = [(20011, 5), (4, 5), (64, 6)];
+        assert_eq!(smelt_batch(&items, BatchMode::Abort), Err(1));
+
+        let batch = smelt_batch(&items, BatchMode::Skip).unwrap();
+        assert_eq!(batch.total_crystals, 3 + 13);
+        assert_eq!(batch.consumed, vec![(20011, 5), (64, 6)]);
+        assert_eq!(batch.skipped, vec![(4, 5)]);
+    }
 }
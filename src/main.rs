@@ -22,20 +22,134 @@ async fn main() -> Result<()> {
     let db_pool = match db::pool::create_pool(&config.database).await {
         Ok(pool) => {
             info!("Database connected");
+            if let Err(e) = db::account::migrate_schema(&pool).await {
+                warn!("Account schema migration failed: {}", e);
+            }
+            if let Err(e) = db::character::migrate_schema(&pool).await {
+                warn!("Character schema migration failed: {}", e);
+            }
+            if let Err(e) = db::inventory::migrate_schema(&pool).await {
+                warn!("Inventory schema migration failed: {}", e);
+            }
+            if let Err(e) = db::buff::migrate_schema(&pool).await {
+                warn!("Buff schema migration failed: {}", e);
+            }
+            if let Err(e) = db::inventory::seed_item_ids(&pool).await {
+                warn!("Item ID allocator not seeded: {}", e);
+            }
+            match db::account::clear_all_online(&pool).await {
+                Ok(0) => {}
+                Ok(n) => info!("Cleared online flag on {} account(s) left from the last run", n),
+                Err(e) => warn!("Failed to clear online flags: {}", e),
+            }
             Some(pool)
         }
+        Err(e) if config.database.required => {
+            return Err(e.context("database is required (database.required = true)"));
+        }
         Err(e) => {
-            warn!("Database failed: {}. Running without DB.", e);
+            warn!("Database failed: {:#}. Running without DB.", e);
             None
         }
     };
 
     // Create shared world state (lets players see each other)
-    let world = network::shared_state::create_shared_world();
+    let world = std::sync::Arc::new(network::shared_state::World::with_grid_cell_size(
+        config.game.grid_cell_size.max(1),
+    ));
     info!("Shared world initialized");
 
+    if let Some(pool) = &db_pool {
+        if let Err(e) = l1j_rust::data::dungeon_table::DungeonTable::migrate_schema(pool).await {
+            warn!("Dungeon schema migration failed: {}", e);
+        }
+        if let Err(e) = l1j_rust::data::spawn_table::migrate_schema(pool).await {
+            warn!("Spawn schema migration failed: {}", e);
+        }
+        match l1j_rust::data::dungeon_table::DungeonTable::load(pool).await {
+            Ok(table) => *world.dungeons.write().unwrap() = table,
+            Err(e) => warn!("Dungeon portals unavailable: {}", e),
+        }
+    }
+
+    match &db_pool {
+        Some(pool) => {
+            let maps = l1j_rust::data::map_table::load_maps(
+                pool,
+                &config.paths.maps_dir,
+                config.start_locations.map_ids(),
+            )
+            .await;
+            let bad = config.start_locations.validate(|id| maps.get(&id));
+            if !bad.is_empty() {
+                anyhow::bail!("start_locations on unwalkable tiles: {}", bad.join(", "));
+            }
+        }
+        None => warn!("No database: start_locations not checked against map data"),
+    }
+
+    if std::path::Path::new(&config.paths.game_data).exists() {
+        match world.game_data.reload_file(&config.paths.game_data).await {
+            Ok(()) => info!("Game data: {}", config.paths.game_data),
+            Err(e) => warn!("Game data {} rejected, using built-in data: {:#}", config.paths.game_data, e),
+        }
+    }
+
+    match network::audit::AuditLog::open(&config.paths.audit_log).await {
+        Ok((log, _writer)) => {
+            network::audit::init(log);
+            info!("Audit log: {}", config.paths.audit_log);
+        }
+        Err(e) => warn!("Audit log {} unavailable: {}", config.paths.audit_log, e),
+    }
+
+    if config.game.stats_log_interval_secs > 0 {
+        network::metrics::spawn_reporter(std::time::Duration::from_secs(config.game.stats_log_interval_secs));
+    }
+
+    if let Some(pool) = &db_pool {
+        let interval = std::time::Duration::from_secs(config.game.autosave_interval_secs.max(1));
+        network::autosave::spawn_flusher(world.clone(), pool.clone(), interval);
+    }
+
+    let world_state_secs = config.game.world_state_save_secs;
+    let world_state_path = config.paths.world_state.clone();
+    if world_state_secs > 0 {
+        let path = world_state_path.clone();
+        match tokio::task::spawn_blocking(move || l1j_rust::world::persist::PersistentState::load(&path)).await? {
+            Ok(state) => *world.persistent.lock().unwrap() = state,
+            Err(e) => warn!("World state {} not restored: {:#}", world_state_path, e),
+        }
+        let (w, path) = (world.clone(), world_state_path.clone());
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(std::time::Duration::from_secs(world_state_secs));
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                save_world_state(&w, &path).await;
+            }
+        });
+    }
+
     info!("=== Server ready ===");
-    network::listener::start(config, db_pool, world).await?;
+    tokio::select! {
+        result = network::listener::start(config, db_pool, world.clone()) => result?,
+        _ = tokio::signal::ctrl_c() => info!("Shutting down"),
+    }
+    if world_state_secs > 0 {
+        save_world_state(&world, &world_state_path).await;
+    }
 
     Ok(())
 }
+
+/// Write the door states on the blocking pool so file I/O never stalls
+/// a runtime worker.
+async fn save_world_state(world: &network::shared_state::World, path: &str) {
+    let state = world.persistent.lock().unwrap().clone();
+    let target = path.to_string();
+    let result = tokio::task::spawn_blocking(move || state.save(&target)).await;
+    if let Err(e) = result.map_err(anyhow::Error::from).and_then(|r| r) {
+        warn!("Failed to save world state to {}: {:#}", path, e);
+    }
+}
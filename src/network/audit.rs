@@ -0,0 +1,165 @@
+//! Audit trail for security-sensitive actions.
+//!
+//! Handlers call `record()` which only queues the entry on a channel;
+//! a background task appends it to the audit file, so logging never
+//! blocks a session. One line per record, tab-separated:
+//!
+//!   unix_timestamp  action  account  details
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// What happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    AccountCreated,
+    Trade,
+    GmCommand,
+    ItemGrant,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::AccountCreated => "account_created",
+            AuditAction::Trade => "trade",
+            AuditAction::GmCommand => "gm_command",
+            AuditAction::ItemGrant => "item_grant",
+        }
+    }
+}
+
+/// One audit entry.
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    /// Unix timestamp (seconds).
+    pub timestamp: u64,
+    pub account: String,
+    pub action: AuditAction,
+    pub details: String,
+}
+
+impl AuditRecord {
+    pub fn new(account: &str, action: AuditAction, details: &str) -> Self {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        AuditRecord {
+            timestamp,
+            account: account.to_string(),
+            action,
+            details: details.to_string(),
+        }
+    }
+
+    /// Format as one log line. Tabs and newlines in free text are escaped
+    /// so a crafted name can't forge extra fields or records.
+    pub fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\n",
+            self.timestamp,
+            self.action.as_str(),
+            escape(&self.account),
+            escape(&self.details),
+        )
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+/// Handle for queueing audit records.
+#[derive(Clone)]
+pub struct AuditLog {
+    tx: mpsc::UnboundedSender<AuditRecord>,
+}
+
+impl AuditLog {
+    /// Open (or create) the audit file in append mode and start the writer task.
+    ///
+    /// The task exits once every `AuditLog` handle is dropped and the
+    /// queue is drained.
+    pub async fn open(path: impl AsRef<Path>) -> Result<(Self, tokio::task::JoinHandle<()>)> {
+        let path: PathBuf = path.as_ref().to_path_buf();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<AuditRecord>();
+        let handle = tokio::spawn(async move {
+            while let Some(rec) = rx.recv().await {
+                if let Err(e) = file.write_all(rec.to_line().as_bytes()).await {
+                    warn!("Audit write to {} failed: {}", path.display(), e);
+                }
+                // Flush once the burst is written
+                if rx.is_empty() {
+                    let _ = file.flush().await;
+                }
+            }
+            let _ = file.flush().await;
+        });
+
+        Ok((AuditLog { tx }, handle))
+    }
+
+    /// Queue a record without waiting for the write.
+    pub fn record(&self, account: &str, action: AuditAction, details: &str) {
+        let _ = self.tx.send(AuditRecord::new(account, action, details));
+    }
+}
+
+static AUDIT: OnceLock<AuditLog> = OnceLock::new();
+
+/// Install the process-wide audit log (once, at startup).
+pub fn init(log: AuditLog) {
+    let _ = AUDIT.set(log);
+}
+
+/// Record to the process-wide audit log. A no-op until `init` is called.
+pub fn record(account: &str, action: AuditAction, details: &str) {
+    if let Some(log) = AUDIT.get() {
+        log.record(account, action, details);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_login_and_gm_command_are_audited() {
+        let path = std::env::temp_dir().join(format!("l1j_audit_{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let (log, writer) = AuditLog::open(&path).await.unwrap();
+        log.record("alice", AuditAction::Login, "ip=10.0.0.5");
+        log.record("gm01", AuditAction::GmCommand, ".summon 40308 1000\tx");
+        drop(log);
+        writer.await.unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let lines: Vec<Vec<&str>> = content.lines().map(|l| l.split('\t').collect()).collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(&lines[0][1..], ["login", "alice", "ip=10.0.0.5"]);
+        assert_eq!(&lines[1][1..], ["gm_command", "gm01", ".summon 40308 1000\\tx"]);
+        assert!(lines[0][0].parse::<u64>().unwrap() > 0);
+    }
+}
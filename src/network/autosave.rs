@@ -0,0 +1,217 @@
+//! Batched autosave.
+//!
+//! On their autosave tick, sessions whose character moved queue it here
+//! instead of writing it; a single task flushes the queue with one
+//! position UPDATE and one play-time UPDATE for all of them.
+//!
+//! A flush and a direct save (logout, character change) never overlap:
+//! both write while holding `Autosaves::writes`, and a direct save takes
+//! the character's queued entry under it. A batch has therefore either
+//! landed before the direct save, or no longer holds that character, so
+//! it can't move them back. A failed batch is re-queued before the lock
+//! is released, which means a logout after it finds and takes the entry
+//! instead of the entry outliving the session.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use sqlx::MySqlPool;
+use tokio::sync::{Mutex, MutexGuard};
+use tracing::{debug, warn};
+
+use crate::db::character::{self, CharSave};
+use crate::db::retry::is_transient;
+use crate::network::shared_state::SharedWorld;
+
+/// Characters waiting for the next flush, by objid.
+#[derive(Debug, Default)]
+pub struct DirtySaves {
+    pending: HashMap<i32, CharSave>,
+}
+
+impl DirtySaves {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue `save`. A character queued again before the flush keeps its
+    /// latest position and the sum of the play time.
+    pub fn mark(&mut self, save: CharSave) {
+        let played = self.pending.get(&save.objid).map_or(0, |old| old.played_secs);
+        self.pending.insert(save.objid, CharSave { played_secs: save.played_secs + played, ..save });
+    }
+
+    /// Drop a character's queued save (it is being saved directly).
+    pub fn remove(&mut self, objid: i32) -> Option<CharSave> {
+        self.pending.remove(&objid)
+    }
+
+    /// Everything queued, in objid order, leaving the queue empty.
+    pub fn take(&mut self) -> Vec<CharSave> {
+        let mut saves: Vec<CharSave> = self.pending.drain().map(|(_, s)| s).collect();
+        saves.sort_by_key(|s| s.objid);
+        saves
+    }
+
+    /// Put back the parts of a failed batch worth another try. Play time
+    /// is only carried over if its write failed, so it's never added
+    /// twice; a character queued again meanwhile keeps the newer
+    /// position.
+    pub fn requeue(&mut self, saves: Vec<CharSave>, retry_positions: bool, retry_played: bool) {
+        for s in saves {
+            let played_secs = if retry_played { s.played_secs } else { 0 };
+            if !retry_positions && played_secs == 0 {
+                continue;
+            }
+            match self.pending.get_mut(&s.objid) {
+                Some(newer) => newer.played_secs += played_secs,
+                None => {
+                    self.pending.insert(s.objid, CharSave { played_secs, ..s });
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// The autosave queue and the lock that orders batch and direct writes.
+#[derive(Debug, Default)]
+pub struct Autosaves {
+    queue: Mutex<DirtySaves>,
+    writes: Mutex<()>,
+}
+
+impl Autosaves {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn mark(&self, save: CharSave) {
+        self.queue.lock().await.mark(save);
+    }
+
+    /// Start a direct save of `objid`: waits out any batch in flight and
+    /// takes the character's queued save. Hold the guard until the
+    /// direct write is done.
+    pub async fn begin_direct_save(&self, objid: i32) -> (MutexGuard<'_, ()>, Option<CharSave>) {
+        let guard = self.writes.lock().await;
+        let queued = self.queue.lock().await.remove(objid);
+        (guard, queued)
+    }
+
+    /// Characters waiting for the next flush.
+    pub async fn queued(&self) -> usize {
+        self.queue.lock().await.len()
+    }
+}
+
+/// Is a failed write worth retrying on the next flush? Errors like a
+/// missing column would fail forever, so they are only logged.
+fn retryable(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<sqlx::Error>().is_some_and(is_transient)
+}
+
+/// Write all queued saves.
+pub async fn flush(world: &SharedWorld, pool: &MySqlPool) {
+    let autosaves = &world.autosaves;
+    let _writing = autosaves.writes.lock().await;
+    let saves = autosaves.queue.lock().await.take();
+    if saves.is_empty() {
+        return;
+    }
+    let positions = character::save_batch(pool, &saves).await;
+    let played = character::add_played_batch(pool, &saves).await;
+    if let Err(e) = &positions {
+        warn!("Batched autosave of {} character(s) failed: {}", saves.len(), e);
+    }
+    if let Err(e) = &played {
+        warn!("Batched play time update failed: {}", e);
+    }
+    let retry_positions = positions.as_ref().is_err_and(retryable);
+    let retry_played = played.as_ref().is_err_and(retryable);
+    if positions.is_ok() && played.is_ok() {
+        debug!("Autosaved {} character(s)", saves.len());
+    } else if retry_positions || retry_played {
+        autosaves.queue.lock().await.requeue(saves, retry_positions, retry_played);
+    }
+}
+
+/// Flush the queue every `interval`.
+pub fn spawn_flusher(world: SharedWorld, pool: MySqlPool, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            flush(&world, &pool).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save(objid: i32, x: i32, played_secs: u64) -> CharSave {
+        CharSave { objid, x, y: 32800, map_id: 4, heading: 0, played_secs }
+    }
+
+    #[test]
+    fn test_mark_merges_play_time() {
+        let mut dirty = DirtySaves::new();
+        dirty.mark(save(1, 100, 300));
+        dirty.mark(save(2, 200, 300));
+        dirty.mark(save(1, 110, 60));
+        assert_eq!(dirty.len(), 2);
+
+        let saves = dirty.take();
+        assert_eq!(saves, vec![save(1, 110, 360), save(2, 200, 300)]);
+        assert!(dirty.is_empty());
+    }
+
+    #[test]
+    fn test_remove_for_direct_save() {
+        let mut dirty = DirtySaves::new();
+        dirty.mark(save(1, 100, 300));
+        assert_eq!(dirty.remove(1).unwrap().played_secs, 300);
+        assert!(dirty.take().is_empty());
+    }
+
+    #[test]
+    fn test_requeue_after_failed_batch() {
+        let mut dirty = DirtySaves::new();
+        let batch = vec![save(1, 100, 300), save(2, 200, 300)];
+        // Character 2 moved again while the batch was being written
+        dirty.mark(save(2, 210, 60));
+
+        dirty.requeue(batch.clone(), true, false);
+        // Positions retried, play time (already written) not added again
+        assert_eq!(dirty.take(), vec![save(1, 100, 0), save(2, 210, 60)]);
+
+        dirty.requeue(batch.clone(), false, true);
+        assert_eq!(dirty.take(), vec![save(1, 100, 300), save(2, 200, 300)]);
+
+        // A permanent failure is not retried at all
+        dirty.requeue(batch, false, false);
+        assert!(dirty.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_direct_save_takes_queued_entry() {
+        let autosaves = Autosaves::new();
+        autosaves.mark(save(1, 100, 300)).await;
+        let (guard, queued) = autosaves.begin_direct_save(1).await;
+        assert_eq!(queued, Some(save(1, 100, 300)));
+        // A flush can't start writing while the direct save holds the lock
+        assert!(autosaves.writes.try_lock().is_err());
+        drop(guard);
+        assert_eq!(autosaves.queued().await, 0);
+    }
+}
@@ -3,36 +3,113 @@
 /// Frame format: [2-byte LE length][payload]
 /// The length field includes itself (length = payload.len() + 2).
 ///
-/// This module is provided for future use with tokio_util::codec::Framed.
-/// Currently the session handler does direct reads/writes.
+/// The session handler does direct reads/writes; `seal_frame` and
+/// `write_burst` build and send outgoing frames.
+
+use anyhow::Result;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::network::cipher::Cipher;
 
 /// Encode a packet payload into a framed byte vector.
 ///
 /// Returns: [length_lo, length_hi, ...payload]
 pub fn encode_frame(payload: &[u8]) -> Vec<u8> {
-    let length = (payload.len() + 2) as u16;
     let mut frame = Vec::with_capacity(2 + payload.len());
-    frame.push((length & 0xFF) as u8);
-    frame.push((length >> 8) as u8);
-    frame.extend_from_slice(payload);
+    encode_frame_into(payload, &mut frame);
     frame
 }
 
-/// Calculate data length from the 2-byte LE length header.
+/// `encode_frame`, appending to `out` instead of allocating.
+pub fn encode_frame_into(payload: &[u8], out: &mut Vec<u8>) {
+    let length = (payload.len() + 2) as u16;
+    out.extend_from_slice(&length.to_le_bytes());
+    out.extend_from_slice(payload);
+}
+
+/// Pad `payload` to 4-byte alignment, encrypt it (once the cipher is
+/// set up) and frame it.
+pub fn seal_frame(payload: &[u8], cipher: Option<&mut Cipher>) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(2 + payload.len() + 3);
+    seal_frame_into(payload, cipher, &mut frame);
+    frame
+}
+
+/// `seal_frame`, appending to `out`. Padding and encryption happen in
+/// place, so a reused `out` makes sending allocation-free.
+pub fn seal_frame_into(payload: &[u8], cipher: Option<&mut Cipher>, out: &mut Vec<u8>) {
+    let padded_len = (payload.len() + 3) & !3;
+    let start = out.len() + 2;
+    encode_frame_into(payload, out);
+    out.resize(start + padded_len, 0);
+    out[start - 2..start].copy_from_slice(&((padded_len + 2) as u16).to_le_bytes());
+
+    if let Some(cipher) = cipher {
+        cipher.encrypt(&mut out[start..]);
+    }
+}
+
+/// Send several packets in order, framing them in `buf` (cleared first).
 ///
-/// Returns None if the length is invalid (< 2 or > 65535).
-pub fn decode_length(lo: u8, hi: u8) -> Option<usize> {
-    let length = (hi as u16) << 8 | (lo as u16);
-    if length < 2 {
-        return None;
+/// Coalesced, the frames go out in one buffer with a single write and
+/// flush, so a burst (the login init packets) leaves in as few TCP
+/// segments as possible instead of one small segment per packet.
+/// Otherwise each packet is written and flushed on its own.
+pub async fn write_burst<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    mut cipher: Option<&mut Cipher>,
+    payloads: &[Vec<u8>],
+    coalesce: bool,
+    buf: &mut Vec<u8>,
+) -> Result<()> {
+    buf.clear();
+    if coalesce {
+        for payload in payloads {
+            seal_frame_into(payload, cipher.as_deref_mut(), buf);
+        }
+        writer.write_all(buf).await?;
+        writer.flush().await?;
+    } else {
+        for payload in payloads {
+            buf.clear();
+            seal_frame_into(payload, cipher.as_deref_mut(), buf);
+            writer.write_all(buf).await?;
+            writer.flush().await?;
+        }
     }
-    let data_len = (length - 2) as usize;
-    if data_len > 65533 {
+    Ok(())
+}
+
+/// Largest packet body (bytes after the length header) accepted from a
+/// client. Real client packets stay well under this; anything longer is
+/// treated as a bad frame rather than buffered.
+pub const MAX_PACKET_LEN: usize = 8192;
+
+/// Calculate data length from the 2-byte length header.
+///
+/// The header is `[lo, hi]`, a little-endian u16 counting the whole
+/// frame including the header itself, so the body is `length - 2` bytes.
+/// Returns None if the length is invalid (< 2) or the body would exceed
+/// `MAX_PACKET_LEN`.
+pub fn decode_length(lo: u8, hi: u8) -> Option<usize> {
+    let length = u16::from_le_bytes([lo, hi]);
+    let data_len = (length as usize).checked_sub(2)?;
+    if data_len > MAX_PACKET_LEN {
         return None;
     }
     Some(data_len)
 }
 
+/// Is an encrypted body of `len` bytes long enough to decrypt?
+///
+/// The cipher mixes the first 4 bytes as a block and chains the rest byte
+/// by byte, so any body of at least 4 bytes decrypts; shorter ones would
+/// panic it. Real clients do send lengths that aren't multiples of 4, so
+/// only the minimum is enforced.
+pub fn is_cipher_sized(len: usize) -> bool {
+    len >= 4
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -46,6 +123,38 @@ mod tests {
         assert_eq!(&frame[2..], &payload);
     }
 
+    #[test]
+    fn test_encode_frame_into_matches() {
+        let payload = vec![0x96, 0x01, 0x02, 0x03, 0x04];
+        let mut out = Vec::new();
+        encode_frame_into(&payload, &mut out);
+        assert_eq!(out, encode_frame(&payload));
+
+        // Appends after whatever is already there
+        encode_frame_into(&[], &mut out);
+        assert_eq!(&out[..7], &encode_frame(&payload)[..]);
+        assert_eq!(&out[7..], &[2, 0]);
+    }
+
+    #[test]
+    fn test_seal_frame_into_reused_buffer() {
+        let payload = vec![0x96, 0x01, 0x02, 0x03, 0x04];
+
+        // Reference: pad to 4 bytes, encrypt, then frame
+        let mut padded = payload.clone();
+        padded.resize(8, 0);
+        Cipher::new(42).encrypt(&mut padded);
+        let expected = encode_frame(&padded);
+
+        let mut buf = vec![0xAA; 64];
+        buf.clear();
+        seal_frame_into(&payload, Some(&mut Cipher::new(42)), &mut buf);
+        assert_eq!(buf, expected);
+        assert_eq!(seal_frame(&payload, Some(&mut Cipher::new(42))), expected);
+        // Already aligned payloads get no padding
+        assert_eq!(seal_frame(&[1, 2, 3, 4], None), encode_frame(&[1, 2, 3, 4]));
+    }
+
     #[test]
     fn test_decode_length_valid() {
         assert_eq!(decode_length(6, 0), Some(4));
@@ -53,9 +162,92 @@ mod tests {
         assert_eq!(decode_length(0x00, 0x01), Some(254)); // 256 - 2
     }
 
+    /// Records each write call separately.
+    #[derive(Default)]
+    struct MockWriter {
+        writes: Vec<Vec<u8>>,
+        flushes: usize,
+    }
+
+    impl AsyncWrite for MockWriter {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            self.writes.push(buf.to_vec());
+            std::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(
+            mut self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            self.flushes += 1;
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    fn burst() -> Vec<Vec<u8>> {
+        (0..17u8).map(|i| vec![i; 3 + i as usize]).collect()
+    }
+
+    #[tokio::test]
+    async fn test_burst_coalesced() {
+        let mut w = MockWriter::default();
+        let mut cipher = Cipher::new(0x1234_5678);
+        write_burst(&mut w, Some(&mut cipher), &burst(), true, &mut Vec::new()).await.unwrap();
+        assert_eq!((w.writes.len(), w.flushes), (1, 1));
+
+        // Same bytes as sealing each packet in turn
+        let mut expected_cipher = Cipher::new(0x1234_5678);
+        let expected: Vec<u8> = burst()
+            .iter()
+            .flat_map(|p| seal_frame(p, Some(&mut expected_cipher)))
+            .collect();
+        assert_eq!(w.writes[0], expected);
+    }
+
+    #[tokio::test]
+    async fn test_burst_uncoalesced() {
+        let mut w = MockWriter::default();
+        write_burst(&mut w, None, &burst(), false, &mut Vec::new()).await.unwrap();
+        assert_eq!((w.writes.len(), w.flushes), (17, 17));
+        assert_eq!(w.writes[2], seal_frame(&[2; 5], None));
+    }
+
     #[test]
     fn test_decode_length_invalid() {
         assert_eq!(decode_length(0, 0), None); // length 0
         assert_eq!(decode_length(1, 0), None); // length 1 (< 2)
     }
+
+    #[test]
+    fn test_decode_length_max_clamp() {
+        let header = |len: usize| ((len + 2) as u16).to_le_bytes();
+        let [lo, hi] = header(MAX_PACKET_LEN);
+        assert_eq!(decode_length(lo, hi), Some(MAX_PACKET_LEN));
+        let [lo, hi] = header(MAX_PACKET_LEN + 1);
+        assert_eq!(decode_length(lo, hi), None);
+        assert_eq!(decode_length(0xFF, 0xFF), None);
+        // An empty body (header only) is a valid length
+        assert_eq!(decode_length(2, 0), Some(0));
+    }
+
+    #[test]
+    fn test_cipher_minimum_length() {
+        for len in [4, 5, 6, 7, 9, MAX_PACKET_LEN] {
+            assert!(is_cipher_sized(len), "len {}", len);
+        }
+        for len in [0, 1, 3] {
+            assert!(!is_cipher_sized(len), "len {}", len);
+        }
+    }
 }
@@ -0,0 +1,99 @@
+//! Opcode dispatch tables.
+//!
+//! Each session state owns a table mapping client opcodes to handler
+//! functions. Handlers are registered once and looked up by opcode, so
+//! adding an opcode is one `on(...)` line and a handler can be called
+//! (and tested) on its own.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use anyhow::Result;
+
+/// Future returned by a packet handler.
+pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// Handles one client packet (`data[0]` is the opcode) for context `S`.
+pub type Handler<S> = for<'a> fn(&'a mut S, &'a [u8]) -> HandlerFuture<'a>;
+
+/// Opcode -> handler for one session state.
+pub struct DispatchTable<S> {
+    handlers: [Option<Handler<S>>; 256],
+}
+
+impl<S> Default for DispatchTable<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> DispatchTable<S> {
+    pub fn new() -> Self {
+        DispatchTable { handlers: [None; 256] }
+    }
+
+    /// Register `handler` for `opcode`. An opcode has one handler;
+    /// registering it twice is a bug.
+    pub fn on(mut self, opcode: u8, handler: Handler<S>) -> Self {
+        debug_assert!(self.handlers[opcode as usize].is_none(), "opcode {} registered twice", opcode);
+        self.handlers[opcode as usize] = Some(handler);
+        self
+    }
+
+    pub fn get(&self, opcode: u8) -> Option<Handler<S>> {
+        self.handlers[opcode as usize]
+    }
+
+    /// Run the handler for `data`'s opcode. None if nothing is registered
+    /// for it (or `data` is empty).
+    pub async fn dispatch(&self, ctx: &mut S, data: &[u8]) -> Option<Result<()>> {
+        let handler = self.get(*data.first()?)?;
+        Some(handler(ctx, data).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Calls {
+        log: Vec<(&'static str, usize)>,
+    }
+
+    async fn on_move(calls: &mut Calls, data: &[u8]) -> Result<()> {
+        calls.log.push(("move", data.len()));
+        Ok(())
+    }
+
+    async fn on_chat(calls: &mut Calls, data: &[u8]) -> Result<()> {
+        calls.log.push(("chat", data.len()));
+        anyhow::bail!("chat failed")
+    }
+
+    fn table() -> DispatchTable<Calls> {
+        DispatchTable::new()
+            .on(10, |c, d| Box::pin(on_move(c, d)))
+            .on(40, |c, d| Box::pin(on_chat(c, d)))
+    }
+
+    #[tokio::test]
+    async fn test_routes_opcode_to_handler() {
+        let table = table();
+        let mut calls = Calls::default();
+
+        assert!(table.dispatch(&mut calls, &[10, 1, 2]).await.unwrap().is_ok());
+        assert!(table.dispatch(&mut calls, &[40]).await.unwrap().is_err());
+        assert_eq!(calls.log, vec![("move", 3), ("chat", 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_unregistered_opcode() {
+        let table = table();
+        let mut calls = Calls::default();
+
+        assert!(table.dispatch(&mut calls, &[11]).await.is_none());
+        assert!(table.dispatch(&mut calls, &[]).await.is_none());
+        assert!(calls.log.is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+//! GM chat commands (`.command args`).
+//!
+//! Every command names the minimum access tier it needs; the session's
+//! `access_level` (loaded from the account at login) is the only thing
+//! checked against it.
+
+use crate::db::account::AccessLevel;
+
+/// A parsed `.command` line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GmCommand<'a> {
+    pub name: &'a str,
+    pub args: &'a str,
+}
+
+/// Known commands and the tier each requires.
+pub const GM_COMMANDS: &[(&str, AccessLevel)] = &[
+    ("announce", AccessLevel::Gm),
+    ("access", AccessLevel::Admin),
+    ("confirmip", AccessLevel::Gm),
+    ("reload", AccessLevel::Admin),
+    ("who", AccessLevel::Gm),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GmError {
+    UnknownCommand,
+    NotPermitted,
+}
+
+/// Split a chat line into a command, if it starts with `.`.
+pub fn parse(text: &str) -> Option<GmCommand<'_>> {
+    let line = text.strip_prefix('.')?.trim();
+    if line.is_empty() {
+        return None;
+    }
+    let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+    Some(GmCommand { name, args: args.trim() })
+}
+
+/// The tier a command needs, if it exists.
+pub fn required_access(name: &str) -> Option<AccessLevel> {
+    GM_COMMANDS.iter().find(|(n, _)| *n == name).map(|(_, lvl)| *lvl)
+}
+
+/// Check that `access` may run `name`.
+pub fn authorize(access: AccessLevel, name: &str) -> Result<(), GmError> {
+    let required = required_access(name).ok_or(GmError::UnknownCommand)?;
+    if access.allows(required) {
+        Ok(())
+    } else {
+        Err(GmError::NotPermitted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            parse(".announce  Boss spawned "),
+            Some(GmCommand { name: "announce", args: "Boss spawned" })
+        );
+        assert_eq!(parse(".access"), Some(GmCommand { name: "access", args: "" }));
+        assert_eq!(parse("hello"), None);
+        assert_eq!(parse("."), None);
+    }
+
+    #[test]
+    fn test_gate_respects_tiers() {
+        assert_eq!(authorize(AccessLevel::Player, "announce"), Err(GmError::NotPermitted));
+        assert_eq!(authorize(AccessLevel::Gm, "announce"), Ok(()));
+        assert_eq!(authorize(AccessLevel::Gm, "access"), Err(GmError::NotPermitted));
+        assert_eq!(authorize(AccessLevel::Admin, "access"), Ok(()));
+        assert_eq!(authorize(AccessLevel::Admin, "nope"), Err(GmError::UnknownCommand));
+    }
+}
@@ -0,0 +1,146 @@
+//! Clan and party membership of online players.
+//!
+//! Party HP bars and clan chat go to members on any map. Rather than
+//! scanning every shard for matching players, the world keeps each
+//! group's online members and their packet channels here. Sessions update
+//! it on join/leave and `World::remove_player` drops a player on logout.
+
+use std::collections::HashMap;
+
+use tokio::sync::mpsc::UnboundedSender;
+
+pub use crate::ecs::party::PartyId;
+
+type Members = HashMap<i32, UnboundedSender<Vec<u8>>>;
+
+/// Online members per clan and per party.
+#[derive(Debug, Default)]
+pub struct GroupIndex {
+    clans: HashMap<String, Members>,
+    parties: HashMap<PartyId, Members>,
+    /// Reverse index: the clan and party each player is listed under.
+    player_clan: HashMap<i32, String>,
+    player_party: HashMap<i32, PartyId>,
+}
+
+impl GroupIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// List `object_id` under `clan`, leaving any clan it was in. An
+    /// empty name just leaves.
+    pub fn join_clan(&mut self, object_id: i32, clan: &str, tx: UnboundedSender<Vec<u8>>) {
+        self.leave_clan(object_id);
+        if clan.is_empty() {
+            return;
+        }
+        self.clans.entry(clan.to_string()).or_default().insert(object_id, tx);
+        self.player_clan.insert(object_id, clan.to_string());
+    }
+
+    pub fn leave_clan(&mut self, object_id: i32) {
+        if let Some(clan) = self.player_clan.remove(&object_id) {
+            remove_member(&mut self.clans, &clan, object_id);
+        }
+    }
+
+    /// List `object_id` under `party`, leaving any party it was in.
+    pub fn join_party(&mut self, object_id: i32, party: PartyId, tx: UnboundedSender<Vec<u8>>) {
+        self.leave_party(object_id);
+        self.parties.entry(party).or_default().insert(object_id, tx);
+        self.player_party.insert(object_id, party);
+    }
+
+    pub fn leave_party(&mut self, object_id: i32) {
+        if let Some(party) = self.player_party.remove(&object_id) {
+            remove_member(&mut self.parties, &party, object_id);
+        }
+    }
+
+    /// Drop a player from every group (disconnect).
+    pub fn remove_player(&mut self, object_id: i32) {
+        self.leave_clan(object_id);
+        self.leave_party(object_id);
+    }
+
+    pub fn clan_of(&self, object_id: i32) -> Option<&str> {
+        self.player_clan.get(&object_id).map(String::as_str)
+    }
+
+    pub fn party_of(&self, object_id: i32) -> Option<PartyId> {
+        self.player_party.get(&object_id).copied()
+    }
+
+    /// Packet channels of `clan`'s online members, minus `exclude_id`.
+    pub fn clan_senders(&self, clan: &str, exclude_id: i32) -> Vec<UnboundedSender<Vec<u8>>> {
+        senders(self.clans.get(clan), exclude_id)
+    }
+
+    /// Packet channels of `party`'s online members, minus `exclude_id`.
+    pub fn party_senders(&self, party: PartyId, exclude_id: i32) -> Vec<UnboundedSender<Vec<u8>>> {
+        senders(self.parties.get(&party), exclude_id)
+    }
+}
+
+fn remove_member<K: std::hash::Hash + Eq>(groups: &mut HashMap<K, Members>, key: &K, object_id: i32) {
+    if let Some(members) = groups.get_mut(key) {
+        members.remove(&object_id);
+        if members.is_empty() {
+            groups.remove(key);
+        }
+    }
+}
+
+fn senders(members: Option<&Members>, exclude_id: i32) -> Vec<UnboundedSender<Vec<u8>>> {
+    members
+        .into_iter()
+        .flatten()
+        .filter(|(id, _)| **id != exclude_id)
+        .map(|(_, tx)| tx.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+    fn channel() -> (UnboundedSender<Vec<u8>>, UnboundedReceiver<Vec<u8>>) {
+        mpsc::unbounded_channel()
+    }
+
+    #[test]
+    fn test_clan_join_leave() {
+        let mut groups = GroupIndex::new();
+        let (tx, _rx) = channel();
+        groups.join_clan(1, "Knights", tx.clone());
+        groups.join_clan(2, "Knights", tx.clone());
+        assert_eq!(groups.clan_senders("Knights", 0).len(), 2);
+        assert_eq!(groups.clan_senders("Knights", 1).len(), 1);
+
+        // Switching clans leaves the old one
+        groups.join_clan(1, "Rogues", tx.clone());
+        assert_eq!(groups.clan_of(1), Some("Rogues"));
+        assert_eq!(groups.clan_senders("Knights", 0).len(), 1);
+
+        groups.join_clan(2, "", tx);
+        assert!(groups.clan_of(2).is_none());
+        assert!(groups.clans.get("Knights").is_none());
+    }
+
+    #[test]
+    fn test_disconnect_leaves_all_groups() {
+        let mut groups = GroupIndex::new();
+        let (tx, _rx) = channel();
+        groups.join_clan(1, "Knights", tx.clone());
+        groups.join_party(1, 1, tx.clone());
+        groups.join_party(2, 1, tx);
+
+        groups.remove_player(1);
+        assert!(groups.clan_of(1).is_none() && groups.party_of(1).is_none());
+        assert!(groups.clan_senders("Knights", 0).is_empty());
+        assert_eq!(groups.party_senders(1, 0).len(), 1);
+        assert_eq!(groups.party_of(2), Some(1));
+    }
+}
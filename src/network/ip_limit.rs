@@ -0,0 +1,113 @@
+//! Per-IP connection cap.
+//!
+//! Each accepted connection holds an `IpPermit`; dropping it (normal
+//! logout, error, panic unwinding the session task) gives the slot back.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{LazyLock, Mutex};
+
+/// Active connection counts per source IP.
+#[derive(Debug, Default)]
+pub struct IpConnections {
+    counts: Mutex<HashMap<IpAddr, u32>>,
+}
+
+impl IpConnections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Claim a slot for `ip` if it has fewer than `max` open connections.
+    /// `max == 0` means unlimited.
+    pub fn try_acquire(&self, ip: IpAddr, max: u32) -> Option<IpPermit<'_>> {
+        let mut counts = self.counts.lock().unwrap();
+        let n = counts.entry(ip).or_insert(0);
+        if max > 0 && *n >= max {
+            return None;
+        }
+        *n += 1;
+        Some(IpPermit { owner: self, ip })
+    }
+
+    /// Open connections from `ip`.
+    pub fn active(&self, ip: IpAddr) -> u32 {
+        self.counts.lock().unwrap().get(&ip).copied().unwrap_or(0)
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(n) = counts.get_mut(&ip) {
+            *n = n.saturating_sub(1);
+            if *n == 0 {
+                counts.remove(&ip);
+            }
+        }
+    }
+}
+
+/// One counted connection; released on drop.
+#[derive(Debug)]
+pub struct IpPermit<'a> {
+    owner: &'a IpConnections,
+    ip: IpAddr,
+}
+
+impl Drop for IpPermit<'_> {
+    fn drop(&mut self) {
+        self.owner.release(self.ip);
+    }
+}
+
+static CONNECTIONS: LazyLock<IpConnections> = LazyLock::new(IpConnections::new);
+
+/// The process-wide per-IP counter.
+pub fn global() -> &'static IpConnections {
+    &CONNECTIONS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cap_per_ip() {
+        let conns = IpConnections::new();
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let p1 = conns.try_acquire(a, 2).unwrap();
+        let _p2 = conns.try_acquire(a, 2).unwrap();
+        assert!(conns.try_acquire(a, 2).is_none());
+        // Other IPs are unaffected
+        assert!(conns.try_acquire(b, 2).is_some());
+
+        drop(p1);
+        assert_eq!(conns.active(a), 1);
+        assert!(conns.try_acquire(a, 2).is_some());
+    }
+
+    #[test]
+    fn test_released_on_abnormal_close() {
+        let conns = IpConnections::new();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _permit = conns.try_acquire(ip, 1).unwrap();
+            panic!("session task died");
+        }));
+        assert!(result.is_err());
+        assert_eq!(conns.active(ip), 0);
+        assert!(conns.try_acquire(ip, 1).is_some());
+    }
+
+    #[test]
+    fn test_zero_means_unlimited() {
+        let conns = IpConnections::new();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let permits: Vec<_> = (0..100).map(|_| conns.try_acquire(ip, 0).unwrap()).collect();
+        assert_eq!(conns.active(ip), 100);
+        drop(permits);
+        assert_eq!(conns.active(ip), 0);
+    }
+}
@@ -1,15 +1,47 @@
-use anyhow::Result;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use socket2::{SockRef, TcpKeepalive};
 use sqlx::MySqlPool;
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
 use tracing::{info, warn};
 
-use crate::config::ServerConfig;
+use crate::config::{ServerConfig, ServerSection};
 use crate::network::shared_state::SharedWorld;
 
+/// Bind the game port from `server`'s host, port and listen backlog.
+pub async fn bind_listener(server: &ServerSection) -> Result<TcpListener> {
+    let addr = tokio::net::lookup_host((server.host.as_str(), server.port))
+        .await?
+        .next()
+        .with_context(|| format!("{} does not resolve to an address", server.host))?;
+
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    // Let a restarted server rebind while old connections sit in TIME_WAIT
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr).with_context(|| format!("binding {}", addr))?;
+    Ok(socket.listen(server.listen_backlog)?)
+}
+
+/// Apply `server`'s per-connection socket options (TCP_NODELAY and
+/// keepalive) to an accepted stream.
+pub fn tune_stream(stream: &TcpStream, server: &ServerSection) -> std::io::Result<()> {
+    stream.set_nodelay(server.tcp_nodelay)?;
+
+    let sock = SockRef::from(stream);
+    if server.tcp_keepalive_idle_secs == 0 {
+        return sock.set_keepalive(false);
+    }
+    let keepalive = TcpKeepalive::new()
+        .with_time(Duration::from_secs(server.tcp_keepalive_idle_secs))
+        .with_interval(Duration::from_secs(server.tcp_keepalive_interval_secs))
+        .with_retries(server.tcp_keepalive_count);
+    sock.set_tcp_keepalive(&keepalive)
+}
+
 pub async fn start(config: ServerConfig, db_pool: Option<MySqlPool>, world: SharedWorld) -> Result<()> {
-    let addr = format!("{}:{}", config.server.host, config.server.port);
-    let listener = TcpListener::bind(&addr).await?;
-    info!("Listening on {}", addr);
+    let listener = bind_listener(&config.server).await?;
+    info!("Listening on {} (backlog {})", listener.local_addr()?, config.server.listen_backlog);
 
     loop {
         let (socket, addr) = listener.accept().await?;
@@ -27,3 +59,72 @@ pub async fn start(config: ServerConfig, db_pool: Option<MySqlPool>, world: Shar
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(host: &str, port: u16, backlog: u32) -> ServerSection {
+        section_with(&format!("host = \"{host}\"\nport = {port}\nlisten_backlog = {backlog}"))
+    }
+
+    fn section_with(extra: &str) -> ServerSection {
+        toml::from_str(&format!("max_online_users = 10\n{extra}")).unwrap()
+    }
+
+    /// A connected (server side, client side) pair on loopback.
+    async fn accepted_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (server, client)
+    }
+
+    #[tokio::test]
+    async fn test_bind_listener_uses_config() {
+        // Find a free port, then ask for it explicitly
+        let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        let listener = bind_listener(&section("127.0.0.1", port, 16)).await.unwrap();
+        assert_eq!(listener.local_addr().unwrap(), format!("127.0.0.1:{}", port).parse().unwrap());
+
+        let client = tokio::net::TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let (_accepted, peer) = listener.accept().await.unwrap();
+        assert_eq!(peer, client.local_addr().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_bind_listener_bad_host() {
+        assert!(bind_listener(&section("256.1.1.1", 0, 16)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tune_stream_applies_options() {
+        let (stream, _client) = accepted_pair().await;
+        let server = section_with(
+            "host = \"127.0.0.1\"\nport = 0\ntcp_keepalive_idle_secs = 45\n\
+             tcp_keepalive_interval_secs = 7\ntcp_keepalive_count = 3",
+        );
+        tune_stream(&stream, &server).unwrap();
+
+        let sock = SockRef::from(&stream);
+        assert!(stream.nodelay().unwrap());
+        assert!(sock.keepalive().unwrap());
+        assert_eq!(sock.tcp_keepalive_time().unwrap(), Duration::from_secs(45));
+        assert_eq!(sock.tcp_keepalive_interval().unwrap(), Duration::from_secs(7));
+        assert_eq!(sock.tcp_keepalive_retries().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_tune_stream_disabled() {
+        let (stream, _client) = accepted_pair().await;
+        let server = section_with(
+            "host = \"127.0.0.1\"\nport = 0\ntcp_nodelay = false\ntcp_keepalive_idle_secs = 0",
+        );
+        stream.set_nodelay(true).unwrap();
+        SockRef::from(&stream).set_keepalive(true).unwrap();
+        tune_stream(&stream, &server).unwrap();
+
+        assert!(!stream.nodelay().unwrap());
+        assert!(!SockRef::from(&stream).keepalive().unwrap());
+    }
+}
@@ -0,0 +1,255 @@
+//! Live server statistics.
+//!
+//! Lock-free counters updated from the session and tick hot paths.
+//! Read them with `snapshot()`; `spawn_reporter` logs a line every
+//! few seconds so operators can see load without attaching a debugger.
+
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use tracing::info;
+
+/// Atomic server counters.
+pub struct ServerMetrics {
+    /// Characters currently in the game world.
+    online_players: AtomicI64,
+    /// Open client connections (including login/char select).
+    connections: AtomicI64,
+    /// Packets received from clients since start.
+    packets_in: AtomicU64,
+    /// Packets sent to clients since start.
+    packets_out: AtomicU64,
+    /// Game ticks processed since start.
+    ticks: AtomicU64,
+    /// Sum of tick durations in microseconds.
+    tick_micros: AtomicU64,
+    /// Live NPCs after the last tick.
+    npc_count: AtomicU64,
+    /// NPCs whose AI ran (had a player in range) in the last tick.
+    active_npcs: AtomicU64,
+}
+
+/// A point-in-time copy of the counters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetricsSnapshot {
+    pub online_players: i64,
+    pub connections: i64,
+    pub packets_in: u64,
+    pub packets_out: u64,
+    pub ticks: u64,
+    pub tick_micros: u64,
+    pub npc_count: u64,
+    pub active_npcs: u64,
+}
+
+impl MetricsSnapshot {
+    /// Average tick duration in microseconds.
+    pub fn avg_tick_micros(&self) -> u64 {
+        self.tick_micros.checked_div(self.ticks).unwrap_or(0)
+    }
+
+    /// Packets per second (in + out) between an earlier snapshot and this one.
+    pub fn packets_per_sec(&self, earlier: &MetricsSnapshot, elapsed: Duration) -> f64 {
+        let secs = elapsed.as_secs_f64();
+        if secs <= 0.0 {
+            return 0.0;
+        }
+        let delta = (self.packets_in + self.packets_out)
+            .saturating_sub(earlier.packets_in + earlier.packets_out);
+        delta as f64 / secs
+    }
+
+    /// Average tick duration over the ticks between two snapshots.
+    pub fn avg_tick_micros_since(&self, earlier: &MetricsSnapshot) -> u64 {
+        let ticks = self.ticks.saturating_sub(earlier.ticks);
+        self.tick_micros.saturating_sub(earlier.tick_micros)
+            .checked_div(ticks)
+            .unwrap_or(0)
+    }
+}
+
+impl Default for ServerMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerMetrics {
+    pub const fn new() -> Self {
+        ServerMetrics {
+            online_players: AtomicI64::new(0),
+            connections: AtomicI64::new(0),
+            packets_in: AtomicU64::new(0),
+            packets_out: AtomicU64::new(0),
+            ticks: AtomicU64::new(0),
+            tick_micros: AtomicU64::new(0),
+            npc_count: AtomicU64::new(0),
+            active_npcs: AtomicU64::new(0),
+        }
+    }
+
+    pub fn connection_opened(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_closed(&self) {
+        self.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Count an open connection until the returned guard is dropped, so
+    /// every way a session can end (a failed handshake included) gives
+    /// it back.
+    pub fn track_connection(&self) -> ConnectionGuard<'_> {
+        self.connection_opened();
+        ConnectionGuard { metrics: self }
+    }
+
+    pub fn player_entered(&self) {
+        self.online_players.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn player_left(&self) {
+        self.online_players.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn packet_received(&self) {
+        self.packets_in.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn packet_sent(&self) {
+        self.packets_out.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record one finished game tick.
+    pub fn record_tick(&self, duration: Duration, npc_count: usize, active_npcs: usize) {
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+        self.tick_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.npc_count.store(npc_count as u64, Ordering::Relaxed);
+        self.active_npcs.store(active_npcs as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            online_players: self.online_players.load(Ordering::Relaxed),
+            connections: self.connections.load(Ordering::Relaxed),
+            packets_in: self.packets_in.load(Ordering::Relaxed),
+            packets_out: self.packets_out.load(Ordering::Relaxed),
+            ticks: self.ticks.load(Ordering::Relaxed),
+            tick_micros: self.tick_micros.load(Ordering::Relaxed),
+            npc_count: self.npc_count.load(Ordering::Relaxed),
+            active_npcs: self.active_npcs.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// One open connection; see `ServerMetrics::track_connection`.
+pub struct ConnectionGuard<'a> {
+    metrics: &'a ServerMetrics,
+}
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        self.metrics.connection_closed();
+    }
+}
+
+static METRICS: ServerMetrics = ServerMetrics::new();
+
+/// The process-wide metrics instance.
+pub fn global() -> &'static ServerMetrics {
+    &METRICS
+}
+
+/// Log a stats line every `interval` until the runtime shuts down.
+pub fn spawn_reporter(interval: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut timer = tokio::time::interval(interval);
+        timer.tick().await;
+        let mut last = global().snapshot();
+        let mut last_at = Instant::now();
+        loop {
+            timer.tick().await;
+            let now = global().snapshot();
+            info!(
+                "Stats: online={} conns={} pkt/s={:.1} avg_tick={}us npcs={} active={}",
+                now.online_players,
+                now.connections,
+                now.packets_per_sec(&last, last_at.elapsed()),
+                now.avg_tick_micros_since(&last),
+                now.npc_count,
+                now.active_npcs,
+            );
+            last = now;
+            last_at = Instant::now();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_counters_under_concurrent_load() {
+        let metrics = Arc::new(ServerMetrics::new());
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let m = metrics.clone();
+                std::thread::spawn(move || {
+                    m.connection_opened();
+                    m.player_entered();
+                    for _ in 0..1000 {
+                        m.packet_received();
+                        m.packet_sent();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        metrics.player_left();
+
+        let snap = metrics.snapshot();
+        assert_eq!(snap.connections, 8);
+        assert_eq!(snap.online_players, 7);
+        assert_eq!(snap.packets_in, 8000);
+        assert_eq!(snap.packets_out, 8000);
+    }
+
+    #[test]
+    fn test_connection_guard_releases_on_early_exit() {
+        let metrics = ServerMetrics::new();
+        let handshake = |fail: bool| -> Result<(), ()> {
+            let _conn = metrics.track_connection();
+            if fail {
+                return Err(());
+            }
+            assert_eq!(metrics.snapshot().connections, 1);
+            Ok(())
+        };
+        assert!(handshake(true).is_err());
+        assert!(handshake(false).is_ok());
+        assert_eq!(metrics.snapshot().connections, 0);
+    }
+
+    #[test]
+    fn test_tick_average_and_rate() {
+        let metrics = ServerMetrics::new();
+        let start = metrics.snapshot();
+        metrics.record_tick(Duration::from_micros(100), 50, 10);
+        metrics.record_tick(Duration::from_micros(300), 40, 5);
+        for _ in 0..20 {
+            metrics.packet_sent();
+        }
+
+        let snap = metrics.snapshot();
+        assert_eq!(snap.ticks, 2);
+        assert_eq!(snap.avg_tick_micros(), 200);
+        assert_eq!(snap.avg_tick_micros_since(&start), 200);
+        assert_eq!(snap.npc_count, 40);
+        assert_eq!(snap.active_npcs, 5);
+        assert_eq!(snap.packets_per_sec(&start, Duration::from_secs(2)), 10.0);
+    }
+}
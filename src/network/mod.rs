@@ -1,5 +1,14 @@
+pub mod audit;
+pub mod autosave;
 pub mod cipher;
 pub mod codec;
+pub mod dispatch;
+pub mod gm;
+pub mod groups;
+pub mod ip_limit;
 pub mod listener;
+pub mod metrics;
+pub mod reconnect;
 pub mod session;
 pub mod shared_state;
+pub mod transport;
@@ -0,0 +1,139 @@
+//! Reconnect grace window.
+//!
+//! When an in-game connection drops without a clean quit, the character
+//! stays in the world for a short time. A new connection for the same
+//! account that picks the same character within the window resumes it
+//! in place; otherwise a timer finishes the normal logout cleanup.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::ecs::components::skill::ParkedSkills;
+
+/// In-game state kept alive for a dropped connection.
+#[derive(Debug)]
+pub struct ResumeState {
+    pub char_name: String,
+    pub objid: i32,
+    pub map_id: i32,
+    pub x: i32,
+    pub y: i32,
+    pub heading: i32,
+    pub skills: ParkedSkills,
+}
+
+#[derive(Debug)]
+struct Parked {
+    state: ResumeState,
+    token: u64,
+    deadline: Instant,
+}
+
+/// Dropped sessions waiting to be resumed, keyed by account name.
+#[derive(Debug, Default)]
+pub struct ReconnectGrace {
+    parked: HashMap<String, Parked>,
+    next_token: u64,
+}
+
+impl ReconnectGrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep a dropped session's state for `grace`. Returns the token the
+    /// expiry timer must present to `expire`.
+    pub fn park(&mut self, account: &str, state: ResumeState, now: Instant, grace: Duration) -> u64 {
+        self.next_token += 1;
+        let token = self.next_token;
+        self.parked.insert(
+            account.to_string(),
+            Parked { state, token, deadline: now + grace },
+        );
+        token
+    }
+
+    /// Is a session for `account` waiting inside its window?
+    pub fn is_parked(&self, account: &str, now: Instant) -> bool {
+        self.parked.get(account).is_some_and(|p| now < p.deadline)
+    }
+
+    /// Take the parked state for `account` if the window is still open.
+    ///
+    /// A late caller gets `None` and the entry is left for `expire`,
+    /// so the cleanup runs exactly once.
+    pub fn resume(&mut self, account: &str, now: Instant) -> Option<ResumeState> {
+        if !self.is_parked(account, now) {
+            return None;
+        }
+        self.parked.remove(account).map(|p| p.state)
+    }
+
+    /// Called by the expiry timer: removes and returns the state for
+    /// cleanup, unless it was resumed (or re-parked) in the meantime.
+    pub fn expire(&mut self, account: &str, token: u64) -> Option<ResumeState> {
+        match self.parked.get(account) {
+            Some(p) if p.token == token => self.parked.remove(account).map(|p| p.state),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::components::skill::{SkillCooldowns, SkillEffects};
+
+    const GRACE: Duration = Duration::from_secs(10);
+
+    fn state(objid: i32) -> ResumeState {
+        ResumeState {
+            char_name: "Alice".to_string(),
+            objid,
+            map_id: 4,
+            x: 32800,
+            y: 32800,
+            heading: 2,
+            skills: ParkedSkills::park(SkillCooldowns::new(), SkillEffects::new()),
+        }
+    }
+
+    #[test]
+    fn test_resume_within_window() {
+        let t0 = Instant::now();
+        let mut grace = ReconnectGrace::new();
+        let token = grace.park("alice", state(100), t0, GRACE);
+
+        assert!(grace.is_parked("alice", t0 + Duration::from_secs(5)));
+        let resumed = grace.resume("alice", t0 + Duration::from_secs(5)).unwrap();
+        assert_eq!((resumed.objid, resumed.x, resumed.y, resumed.heading), (100, 32800, 32800, 2));
+
+        // The expiry timer finds nothing left to clean up
+        assert!(grace.expire("alice", token).is_none());
+    }
+
+    #[test]
+    fn test_full_cleanup_after_window() {
+        let t0 = Instant::now();
+        let mut grace = ReconnectGrace::new();
+        let token = grace.park("alice", state(100), t0, GRACE);
+
+        assert!(!grace.is_parked("alice", t0 + GRACE));
+        assert!(grace.resume("alice", t0 + GRACE).is_none());
+        assert_eq!(grace.expire("alice", token).unwrap().objid, 100);
+        assert!(grace.expire("alice", token).is_none());
+    }
+
+    #[test]
+    fn test_stale_timer_does_not_expire_new_park() {
+        let t0 = Instant::now();
+        let mut grace = ReconnectGrace::new();
+        let old = grace.park("alice", state(100), t0, GRACE);
+        grace.resume("alice", t0).unwrap();
+        let new = grace.park("alice", state(100), t0 + Duration::from_secs(3), GRACE);
+
+        assert!(grace.expire("alice", old).is_none());
+        assert!(grace.is_parked("alice", t0 + Duration::from_secs(4)));
+        assert!(grace.expire("alice", new).is_some());
+    }
+}
@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
 use anyhow::{bail, Result};
 use rand::RngExt;
 use sqlx::MySqlPool;
@@ -6,16 +9,20 @@ use tokio::net::TcpStream;
 use tracing::{debug, info, warn};
 
 use crate::config::ServerConfig;
+use crate::db::account::AccessLevel;
+use crate::ecs::components::attack_timer::{self, AttackTimer};
+use crate::ecs::components::movement::MoveSequence;
+use crate::ecs::components::play_time::PlayTimer;
+use crate::ecs::components::skill::{skill_ids, ParkedSkills, SkillCooldowns, SkillEffects};
 use crate::network::cipher::Cipher;
+use crate::network::audit::{self, AuditAction};
 use crate::network::codec;
-use crate::network::shared_state::{SharedWorld, OnlinePlayer};
+use crate::network::dispatch::DispatchTable;
+use crate::network::metrics;
+use crate::network::shared_state::{broadcast_range, SharedWorld, OnlinePlayer};
+use crate::network::transport::Transport;
 use crate::protocol::opcodes;
 
-/// 3.80c Taiwan Server first packet payload (after opcode + key).
-const FIRST_PACKET: [u8; 11] = [
-    0x9d, 0xd1, 0xd6, 0x7a, 0xf4, 0x62, 0xe7, 0xa0, 0x66, 0x02, 0xfa,
-];
-
 /// Session state machine.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SessionState {
@@ -27,13 +34,15 @@ pub enum SessionState {
 
 /// Represents a single client connection.
 pub struct Session {
-    stream: TcpStream,
+    stream: Box<dyn Transport>,
     cipher: Option<Cipher>,
     pub state: SessionState,
     pub config: ServerConfig,
     pub db: Option<MySqlPool>,
     /// Authenticated account name (set after successful login)
     pub account_name: Option<String>,
+    /// Privilege tier of the logged-in account (the one GM check source)
+    pub access_level: AccessLevel,
     /// Selected character name (set after character selection)
     pub char_name: Option<String>,
     /// Server start time as unix timestamp
@@ -46,6 +55,35 @@ pub struct Session {
     pub char_map: i32,
     pub char_heading: i32,
     pub char_objid: i32,
+    pub char_level: i32,
+    pub char_type: i32,
+    pub char_clan_id: i32,
+    /// Sequence numbers for this session's moves
+    pub move_seq: MoveSequence,
+    /// Set by C_QUITGAME: a clean quit skips the reconnect window
+    pub quitting: bool,
+    /// Saved columns as last written, so a save only touches what changed
+    last_saved: Option<crate::db::character::CharSnapshot>,
+    /// Position as last handed to the batched autosave
+    last_queued: Option<crate::db::character::CharSnapshot>,
+    /// The account is flagged online in the DB and this session must
+    /// clear it (see `Drop`)
+    marked_online: bool,
+    /// Time in the world not yet added to `played_seconds`
+    pub play_timer: PlayTimer,
+    /// Last accepted C_ATTACK, for the attack-speed check
+    pub attack_timer: AttackTimer,
+    /// Weapon table `type` of the wielded weapon (None = bare hands)
+    pub weapon_type: Option<i32>,
+    /// Skill IDs the character has learned
+    pub learned_skills: Vec<i32>,
+    /// Active skill reuse timers and buffs
+    pub skill_cooldowns: SkillCooldowns,
+    pub skill_effects: SkillEffects,
+    /// Timers parked at character select, keyed by char objid
+    parked_skills: HashMap<i32, ParkedSkills>,
+    /// Reused for framing outgoing packets
+    send_buf: Vec<u8>,
     /// Shared world state (for seeing other players)
     pub world: SharedWorld,
     /// Channel to receive packets from other sessions (broadcasts)
@@ -55,7 +93,7 @@ pub struct Session {
 
 impl Session {
     pub fn new(
-        stream: TcpStream,
+        stream: impl Transport + 'static,
         config: ServerConfig,
         db: Option<MySqlPool>,
         client_ip: String,
@@ -69,12 +107,13 @@ impl Session {
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
 
         Session {
-            stream,
+            stream: Box::new(stream),
             cipher: None,
             state: SessionState::Connected,
             config,
             db,
             account_name: None,
+            access_level: AccessLevel::Player,
             char_name: None,
             server_start_time: start_time,
             client_ip,
@@ -83,6 +122,22 @@ impl Session {
             char_map: 0,
             char_heading: 0,
             char_objid: 0,
+            char_level: 0,
+            char_type: 0,
+            char_clan_id: 0,
+            play_timer: PlayTimer::new(),
+            move_seq: MoveSequence::new(),
+            quitting: false,
+            last_saved: None,
+            last_queued: None,
+            marked_online: false,
+            attack_timer: AttackTimer::new(),
+            weapon_type: None,
+            learned_skills: Vec::new(),
+            skill_cooldowns: SkillCooldowns::new(),
+            skill_effects: SkillEffects::new(),
+            parked_skills: HashMap::new(),
+            send_buf: Vec::with_capacity(1024),
             world,
             packet_rx: rx,
             packet_tx: tx,
@@ -93,13 +148,11 @@ impl Session {
     async fn send_handshake(&mut self) -> Result<u32> {
         let key: u32 = rand::rng().random_range(1..=0x7FFFFFFFu32);
 
-        let mut payload = Vec::with_capacity(1 + 4 + FIRST_PACKET.len());
-        payload.push(opcodes::server::S_OPCODE_INITPACKET);
-        payload.push((key & 0xFF) as u8);
-        payload.push((key >> 8 & 0xFF) as u8);
-        payload.push((key >> 16 & 0xFF) as u8);
-        payload.push((key >> 24 & 0xFF) as u8);
-        payload.extend_from_slice(&FIRST_PACKET);
+        let version = &self.config.server.protocol_version;
+        let Some(first_packet) = crate::protocol::server::init::first_packet(version) else {
+            bail!("unknown protocol version {}", version);
+        };
+        let payload = crate::protocol::server::init::build_init_packet(key, first_packet);
 
         let frame = codec::encode_frame(&payload);
         self.stream.write_all(&frame).await?;
@@ -116,37 +169,74 @@ impl Session {
 
         let data_length = match codec::decode_length(lo, hi) {
             Some(len) => len,
-            None => bail!("Invalid packet length header: [{}, {}]", lo, hi),
+            None => bail!("Invalid or oversized packet length header: [{}, {}]", lo, hi),
         };
 
         let mut data = vec![0u8; data_length];
         self.stream.read_exact(&mut data).await?;
 
         if let Some(ref mut cipher) = self.cipher {
+            if !codec::is_cipher_sized(data_length) {
+                bail!("Encrypted packet body of {} bytes is too short to decrypt", data_length);
+            }
             cipher.decrypt(&mut data);
         }
 
         Ok(data)
     }
 
-    /// Send one packet to the client (encrypts + pads to 4-byte alignment).
-    pub async fn send_packet(&mut self, payload: &[u8]) -> Result<()> {
-        let padded_len = (payload.len() + 3) & !3;
-        let mut data = vec![0u8; padded_len];
-        data[..payload.len()].copy_from_slice(payload);
-
-        if let Some(ref mut cipher) = self.cipher {
-            cipher.encrypt(&mut data);
+    /// The character's saved columns as they stand now.
+    fn snapshot(&self) -> crate::db::character::CharSnapshot {
+        crate::db::character::CharSnapshot {
+            x: self.char_x,
+            y: self.char_y,
+            map_id: self.char_map,
+            heading: self.char_heading,
         }
+    }
 
-        let frame = codec::encode_frame(&data);
-        self.stream.write_all(&frame).await?;
+    /// Send one packet to the client (encrypts + pads to 4-byte alignment).
+    pub async fn send_packet(&mut self, payload: &[u8]) -> Result<()> {
+        self.send_buf.clear();
+        codec::seal_frame_into(payload, self.cipher.as_mut(), &mut self.send_buf);
+        self.stream.write_all(&self.send_buf).await?;
         self.stream.flush().await?;
+        metrics::global().packet_sent();
+
+        Ok(())
+    }
 
+    /// Send a burst of packets in order, coalesced into one write when
+    /// `coalesce_packet_bursts` is on.
+    pub async fn send_packets(&mut self, payloads: &[Vec<u8>]) -> Result<()> {
+        let coalesce = self.config.server.coalesce_packet_bursts;
+        codec::write_burst(&mut self.stream, self.cipher.as_mut(), payloads, coalesce, &mut self.send_buf).await?;
+        for _ in payloads {
+            metrics::global().packet_sent();
+        }
         Ok(())
     }
 }
 
+impl Drop for Session {
+    /// Backstop for `cleanup_session`: if the session task panicked (or
+    /// was cancelled) while the account was flagged online, clear the
+    /// flag in the background so the account can log in again.
+    fn drop(&mut self) {
+        if !self.marked_online {
+            return;
+        }
+        let (Some(pool), Some(account)) = (self.db.clone(), self.account_name.clone()) else { return };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else { return };
+        warn!("Session for {} ended without cleanup, setting offline", account);
+        runtime.spawn(async move {
+            if let Err(e) = crate::db::account::set_offline(&pool, &account).await {
+                warn!("Failed to set {} offline: {}", account, e);
+            }
+        });
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Session lifecycle
 // ---------------------------------------------------------------------------
@@ -157,12 +247,30 @@ pub async fn handle_session(
     db: Option<MySqlPool>,
     world: SharedWorld,
 ) -> Result<()> {
-    let client_ip = stream
-        .peer_addr()
-        .map(|a| a.ip().to_string())
-        .unwrap_or_default();
+    let peer_ip = stream.peer_addr().map(|a| a.ip()).ok();
+    let client_ip = peer_ip.map(|ip| ip.to_string()).unwrap_or_default();
+
+    // Per-IP cap, checked before any per-session allocation. The permit
+    // is held until this function returns, however the session ends.
+    let _ip_permit = match peer_ip {
+        Some(ip) => match crate::network::ip_limit::global()
+            .try_acquire(ip, config.server.max_connections_per_ip)
+        {
+            Some(permit) => Some(permit),
+            None => {
+                warn!("Too many connections from {}, rejecting", ip);
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
+    if let Err(e) = crate::network::listener::tune_stream(&stream, &config.server) {
+        warn!("Failed to set socket options for {}: {}", client_ip, e);
+    }
 
     let mut session = Session::new(stream, config, db, client_ip, world);
+    let _connection = metrics::global().track_connection();
 
     // Step 1: Send handshake
     let key = session.send_handshake().await?;
@@ -176,7 +284,13 @@ pub async fn handle_session(
         tokio::sync::mpsc::unbounded_channel().1, // dummy rx
     );
 
-    loop {
+    let autosave_secs = session.config.game.autosave_interval_secs.max(1);
+    let mut autosave = tokio::time::interval(std::time::Duration::from_secs(autosave_secs));
+    autosave.tick().await;
+    let mut expiry_sweep = tokio::time::interval(std::time::Duration::from_secs(ITEM_EXPIRY_SWEEP_SECS));
+    expiry_sweep.tick().await;
+
+    let result = loop {
         tokio::select! {
             // Client sent us a packet
             result = session.read_packet() => {
@@ -184,11 +298,12 @@ pub async fn handle_session(
                     Ok(d) => d,
                     Err(e) => {
                         debug!("Connection closed: {}", e);
-                        break;
+                        break Ok(());
                     }
                 };
 
                 if data.is_empty() { continue; }
+                metrics::global().packet_received();
 
                 let opcode = data[0];
                 debug!(
@@ -196,168 +311,309 @@ pub async fn handle_session(
                     opcode, opcode, data.len(), session.state
                 );
 
-                match session.state {
-                    SessionState::Connected => handle_connected(&mut session, opcode, &data).await?,
-                    SessionState::VersionVerified => handle_version_verified(&mut session, opcode, &data).await?,
-                    SessionState::Authenticated => handle_authenticated(&mut session, opcode, &data).await?,
-                    SessionState::InGame => handle_in_game(&mut session, opcode, &data).await?,
+                let state = session.state;
+                let handled = match handlers(state).dispatch(&mut session, &data).await {
+                    Some(result) => result,
+                    None => {
+                        log_unhandled(state, opcode);
+                        Ok(())
+                    }
+                };
+                if let Err(e) = handled {
+                    break Err(e);
                 }
             }
             // Another session sent us a broadcast packet (e.g., movement, chat)
             Some(broadcast_pkt) = packet_rx.recv() => {
                 if let Err(e) = session.send_packet(&broadcast_pkt).await {
                     debug!("Failed to send broadcast: {}", e);
-                    break;
+                    break Ok(());
+                }
+            }
+            _ = autosave.tick() => {
+                if session.state == SessionState::InGame {
+                    queue_autosave(&mut session).await;
+                }
+            }
+            _ = expiry_sweep.tick() => {
+                if session.state == SessionState::InGame {
+                    if let Err(e) = sweep_expired_items(&mut session).await {
+                        warn!("Expired item sweep failed for {:?}: {}", session.char_name, e);
+                    }
                 }
             }
         }
-    }
+    };
 
-    // Cleanup: save character + set account offline
-    cleanup_session(&session).await;
+    // Cleanup: save character + set account offline (also after handler errors)
+    cleanup_session(&mut session).await;
 
     info!("Session ended");
-    Ok(())
+    result
 }
 
 // ---------------------------------------------------------------------------
 // State handlers
 // ---------------------------------------------------------------------------
 
-async fn handle_connected(session: &mut Session, opcode: u8, data: &[u8]) -> Result<()> {
-    if opcode == opcodes::client::C_CLIENTVERSION {
-        let cv = crate::protocol::client::login::parse_client_version(data);
-        info!(
-            "Client version: lang={}, ver=0x{:08X}",
-            cv.client_language, cv.client_version
-        );
+/// Wrap an `async fn(&mut Session, &[u8]) -> Result<()>` as a `Handler`.
+macro_rules! handler {
+    ($f:ident) => {
+        |session, data| Box::pin($f(session, data))
+    };
+}
+
+/// The opcode table for `state`.
+fn handlers(state: SessionState) -> &'static DispatchTable<Session> {
+    use opcodes::client::*;
+
+    static CONNECTED: OnceLock<DispatchTable<Session>> = OnceLock::new();
+    static VERSION_VERIFIED: OnceLock<DispatchTable<Session>> = OnceLock::new();
+    static AUTHENTICATED: OnceLock<DispatchTable<Session>> = OnceLock::new();
+    static IN_GAME: OnceLock<DispatchTable<Session>> = OnceLock::new();
+
+    match state {
+        SessionState::Connected => CONNECTED.get_or_init(|| {
+            DispatchTable::new().on(C_CLIENTVERSION, handler!(handle_client_version))
+        }),
+        SessionState::VersionVerified => VERSION_VERIFIED.get_or_init(|| {
+            DispatchTable::new()
+                .on(C_BEANFUNLOGIN, handler!(handle_login))
+                .on(C_LOGINPACKET, handler!(handle_login))
+        }),
+        SessionState::Authenticated => AUTHENTICATED.get_or_init(|| {
+            DispatchTable::new()
+                .on(C_LOGINTOSERVER, handler!(handle_login_to_server))
+                .on(C_NEWCHAR, handler!(handle_create_char))
+                .on(C_DELETECHAR, handler!(handle_delete_char_request))
+        }),
+        SessionState::InGame => IN_GAME.get_or_init(|| {
+            DispatchTable::new()
+                .on(C_MOVECHAR, handler!(handle_move_char))
+                .on(C_ENTERPORTAL, handler!(handle_enter_portal))
+                .on(C_CHANGEHEADING, handler!(handle_change_heading))
+                .on(C_CHAT, handler!(handle_chat))
+                .on(C_ATTACK, handler!(handle_attack))
+                .on(C_USESKILL, handler!(handle_use_skill))
+                .on(C_ATTR, handler!(handle_attr))
+                .on(C_CREATEPARTY, handler!(handle_create_party))
+                .on(C_LEAVEPARTY, handler!(handle_leave_party))
+                .on(C_TRADE, handler!(handle_trade))
+                .on(C_TRADEADDITEM, handler!(handle_trade_add_item))
+                .on(C_TRADEADDOK, handler!(handle_trade_ok))
+                .on(C_TRADEADDCANCEL, handler!(handle_trade_cancel))
+                .on(C_LEAVECLAN, handler!(handle_leave_clan))
+                .on(C_SKILLBUY, handler!(handle_learn_skill))
+                .on(C_NPCTALK, handler!(handle_npc_talk))
+                .on(C_NPCACTION, handler!(handle_npc_action))
+                .on(C_USEITEM, handler!(handle_use_item))
+                .on(C_KEEPALIVE, handler!(handle_keepalive))
+                .on(C_QUITGAME, handler!(handle_quit_game))
+                .on(C_CHANGECHAR, handler!(handle_change_char))
+                .on(C_RESTARTMENU, handler!(handle_restart_menu_request))
+                .on(C_RESTART, handler!(handle_restart))
+        }),
+    }
+}
+
+fn log_unhandled(state: SessionState, opcode: u8) {
+    match state {
+        SessionState::Connected => warn!("Unexpected opcode {} in Connected state", opcode),
+        SessionState::VersionVerified => debug!("Opcode {} in VersionVerified (not handled)", opcode),
+        SessionState::Authenticated => debug!("Opcode {} in Authenticated (not handled)", opcode),
+        // Silently ignore unhandled opcodes to reduce log spam
+        SessionState::InGame => {}
+    }
+}
 
-        // Send S_SERVERVERSION
-        let pkt = crate::protocol::server::login::build_server_version(session.server_start_time);
+/// C_CLIENTVERSION: check the client and answer with the server version.
+async fn handle_client_version(session: &mut Session, data: &[u8]) -> Result<()> {
+    let cv = crate::protocol::client::login::parse_client_version(data);
+    info!(
+        "Client version: lang={}, ver=0x{:08X}",
+        cv.client_language, cv.client_version
+    );
+
+    // Reject unsupported clients before any DB work
+    let server_cfg = &session.config.server;
+    if !cv.is_supported(&server_cfg.allowed_client_versions, &server_cfg.allowed_client_languages) {
+        warn!(
+            "Unsupported client from {}: lang={}, ver=0x{:08X}",
+            session.client_ip, cv.client_language, cv.client_version
+        );
+        let pkt = crate::protocol::server::login::build_server_version_rejected();
         session.send_packet(&pkt).await?;
-        session.state = SessionState::VersionVerified;
-        info!("State -> VersionVerified");
-    } else {
-        warn!("Unexpected opcode {} in Connected state", opcode);
+        bail!("unsupported client version 0x{:08X}", cv.client_version);
     }
+
+    // Send S_SERVERVERSION
+    let pkt = crate::protocol::server::login::build_server_version(session.server_start_time);
+    session.send_packet(&pkt).await?;
+    session.state = SessionState::VersionVerified;
+    info!("State -> VersionVerified");
     Ok(())
 }
 
-async fn handle_version_verified(session: &mut Session, opcode: u8, data: &[u8]) -> Result<()> {
-    // Handle both login packet types:
-    //   opcode 210 (C_BEANFUNLOGIN) - has action byte prefix
-    //   opcode 119 (C_LOGINPACKET)  - direct account+password
-    if opcode == opcodes::client::C_BEANFUNLOGIN || opcode == 119 {
-        let auth = if opcode == 119 {
-            crate::protocol::client::login::parse_login_packet(data)
-        } else {
-            crate::protocol::client::login::parse_auth_login(data)
-        };
+/// Login, from either packet type:
+///   C_BEANFUNLOGIN (210) - has action byte prefix
+///   C_LOGINPACKET (119)  - direct account+password
+async fn handle_login(session: &mut Session, data: &[u8]) -> Result<()> {
+    let auth = if data[0] == opcodes::client::C_LOGINPACKET {
+        crate::protocol::client::login::parse_login_packet(data)
+    } else {
+        crate::protocol::client::login::parse_auth_login(data)
+    };
+
+    if auth.action != crate::protocol::client::login::LOGIN_ACTION_LOGIN {
+        debug!("Auth action {} (not login)", auth.action);
+        return Ok(());
+    }
 
-        if auth.action != crate::protocol::client::login::LOGIN_ACTION_LOGIN {
-            debug!("Auth action {} (not login)", auth.action);
+    info!("Login attempt: account={}", auth.account);
+
+    // Validate against DB
+    let pool = match &session.db {
+        Some(p) => p,
+        None => {
+            warn!("No database - cannot authenticate");
+            let pkt = crate::protocol::server::login::build_login_result(
+                crate::protocol::server::login::REASON_ACCESS_FAILED,
+            );
+            session.send_packet(&pkt).await?;
             return Ok(());
         }
+    };
 
-        info!("Login attempt: account={}", auth.account);
-
-        // Validate against DB
-        let pool = match &session.db {
-            Some(p) => p,
-            None => {
-                warn!("No database - cannot authenticate");
+    let account_data = crate::db::account::load_account(pool, &auth.account).await?;
+
+    let account = match account_data {
+        Some(a) => a,
+        None => {
+            if let Some(reason) = crate::protocol::server::login::unknown_account_result(
+                session.config.server.auto_create_account,
+            ) {
+                info!("Account not found: {}", auth.account);
+                audit::record(&auth.account, AuditAction::LoginFailed, &format!("unknown account ip={}", session.client_ip));
+                let pkt = crate::protocol::server::login::build_login_result(reason);
+                session.send_packet(&pkt).await?;
+                return Ok(());
+            }
+            // Auto-create account (common in L1J private servers)
+            info!("Account not found, auto-creating: {}", auth.account);
+            if let Err(e) = crate::db::account::create_account(
+                pool, &auth.account, &auth.password,
+            ).await {
+                warn!("Failed to create account: {}", e);
                 let pkt = crate::protocol::server::login::build_login_result(
                     crate::protocol::server::login::REASON_ACCESS_FAILED,
                 );
                 session.send_packet(&pkt).await?;
                 return Ok(());
             }
-        };
-
-        let account_data = crate::db::account::load_account(pool, &auth.account).await?;
-
-        let account = match account_data {
-            Some(a) => a,
-            None => {
-                // Auto-create account (common in L1J private servers)
-                info!("Account not found, auto-creating: {}", auth.account);
-                if let Err(e) = crate::db::account::create_account(
-                    pool, &auth.account, &auth.password,
-                ).await {
-                    warn!("Failed to create account: {}", e);
-                    let pkt = crate::protocol::server::login::build_login_result(
-                        crate::protocol::server::login::REASON_ACCESS_FAILED,
-                    );
-                    session.send_packet(&pkt).await?;
-                    return Ok(());
-                }
-                info!("Account created: {}", auth.account);
-                // Re-load the newly created account
-                match crate::db::account::load_account(pool, &auth.account).await? {
-                    Some(a) => a,
-                    None => return Ok(()),
-                }
+            info!("Account created: {}", auth.account);
+            audit::record(&auth.account, AuditAction::AccountCreated, &format!("ip={}", session.client_ip));
+            // Re-load the newly created account
+            match crate::db::account::load_account(pool, &auth.account).await? {
+                Some(a) => a,
+                None => return Ok(()),
             }
-        };
-
-        // Check banned
-        if account.banned != 0 {
-            info!("Account banned: {}", auth.account);
-            let pkt = crate::protocol::server::login::build_login_result(
-                crate::protocol::server::login::REASON_ACCESS_FAILED,
-            );
-            session.send_packet(&pkt).await?;
-            return Ok(());
         }
+    };
 
-        // Check already online
-        if account.online != 0 {
-            info!("Account already in use: {}", auth.account);
-            let pkt = crate::protocol::server::login::build_login_result(
-                crate::protocol::server::login::REASON_ACCOUNT_IN_USE,
-            );
-            session.send_packet(&pkt).await?;
-            return Ok(());
-        }
+    // Check banned
+    if account.banned != 0 {
+        info!("Account banned: {}", auth.account);
+        let pkt = crate::protocol::server::login::build_login_result(
+            crate::protocol::server::login::REASON_ACCESS_FAILED,
+        );
+        session.send_packet(&pkt).await?;
+        return Ok(());
+    }
+
+    // Check already online (unless resuming a dropped session)
+    let resumable = session.world.reconnects.lock().await
+        .is_parked(&auth.account, std::time::Instant::now());
+    if account.online != 0 && !resumable {
+        info!("Account already in use: {}", auth.account);
+        let pkt = crate::protocol::server::login::build_login_result(
+            crate::protocol::server::login::REASON_ACCOUNT_IN_USE,
+        );
+        session.send_packet(&pkt).await?;
+        return Ok(());
+    }
+
+    // Validate password
+    let check = crate::db::account::check_password(&auth.password, &account.password).await?;
+    if check == crate::db::account::PasswordCheck::Invalid {
+        info!("Wrong password for: {}", auth.account);
+        audit::record(&auth.account, AuditAction::LoginFailed, &format!("ip={}", session.client_ip));
+        let pkt = crate::protocol::server::login::build_login_result(
+            crate::protocol::server::login::REASON_USER_OR_PASS_WRONG,
+        );
+        session.send_packet(&pkt).await?;
+        return Ok(());
+    }
 
-        // Validate password
-        if !crate::db::account::validate_password(&auth.password, &account.password) {
-            info!("Wrong password for: {}", auth.account);
+    // Account protection: hold back logins from unseen IPs
+    if session.config.server.reject_new_ip {
+        let history = crate::db::account::recent_logins(
+            pool, &auth.account, crate::db::account::LOGIN_HISTORY_WINDOW,
+        ).await?;
+        if crate::db::account::is_new_ip_rejected(true, &history, &session.client_ip) {
+            info!("Login from new IP held back: {} ({})", auth.account, session.client_ip);
+            if let Err(e) = crate::db::account::record_login(pool, &auth.account, &session.client_ip, false).await {
+                warn!("Login history not recorded for {}: {}", auth.account, e);
+            }
+            audit::record(&auth.account, AuditAction::LoginFailed, &format!("new ip={}", session.client_ip));
             let pkt = crate::protocol::server::login::build_login_result(
                 crate::protocol::server::login::REASON_ACCESS_FAILED,
             );
             session.send_packet(&pkt).await?;
             return Ok(());
         }
+    }
 
-        // Login success!
-        info!("Login OK: {}", auth.account);
-        crate::db::account::set_online(pool, &auth.account, &session.client_ip).await?;
-        session.account_name = Some(auth.account.clone());
-
-        // Send login result
-        let pkt = crate::protocol::server::login::build_login_result(
-            crate::protocol::server::login::REASON_LOGIN_OK,
-        );
-        session.send_packet(&pkt).await?;
+    // Login success! Upgrade legacy hashes while we have the raw password
+    if check == crate::db::account::PasswordCheck::ValidLegacy {
+        match crate::db::account::update_password(pool, &auth.account, &auth.password).await {
+            Ok(()) => info!("Password hash upgraded: {}", auth.account),
+            Err(e) => warn!("Failed to upgrade password hash for {}: {}", auth.account, e),
+        }
+    }
+    info!("Login OK: {}", auth.account);
+    audit::record(&auth.account, AuditAction::Login, &format!("ip={}", session.client_ip));
+    crate::db::account::set_online(pool, &auth.account, &session.client_ip).await?;
+    session.marked_online = true;
+    session.account_name = Some(auth.account.clone());
+    session.access_level = account.access();
+
+    // Send login result
+    let pkt = crate::protocol::server::login::build_login_result(
+        crate::protocol::server::login::REASON_LOGIN_OK,
+    );
+    session.send_packet(&pkt).await?;
 
-        // Send character list
-        send_char_list(session).await?;
+    // Send character list
+    send_char_list(session).await?;
 
-        session.state = SessionState::Authenticated;
-        info!("State -> Authenticated");
-    } else {
-        debug!("Opcode {} in VersionVerified (not handled)", opcode);
-    }
+    session.state = SessionState::Authenticated;
+    info!("State -> Authenticated");
     Ok(())
 }
 
 async fn send_char_list(session: &mut Session) -> Result<()> {
-    let pool = session.db.as_ref().unwrap();
-    let account = session.account_name.as_ref().unwrap();
+    let (Some(pool), Some(account)) = (session.db.as_ref(), session.account_name.as_ref()) else {
+        return Ok(());
+    };
+
+    let purged = crate::db::char_delete::purge_expired(pool, account, unix_now()).await?;
+    if purged > 0 {
+        info!("Purged {} characters past their deletion date for {}", purged, account);
+    }
 
-    let chars = crate::db::character::load_char_list(pool, account).await?;
-    let max_slots = crate::DEFAULT_CHARACTER_SLOT;
+    let crate::db::character::CharList { chars, extra_slots } =
+        crate::db::character::load_char_list(pool, account).await?;
+    let max_slots = crate::protocol::client::char_create::max_character_slots(extra_slots);
 
     // S_CHARAMOUNT
     let pkt = crate::protocol::server::char_list::build_char_amount(chars.len() as i32, max_slots);
@@ -381,259 +637,1165 @@ async fn send_char_list(session: &mut Session) -> Result<()> {
     Ok(())
 }
 
-async fn handle_authenticated(session: &mut Session, opcode: u8, data: &[u8]) -> Result<()> {
-    match opcode {
-        opcodes::client::C_LOGINTOSERVER => {
-            let req = crate::protocol::client::char_select::parse_login_to_server(data);
-            info!("Character selected: {}", req.char_name);
+/// C_LOGINTOSERVER: enter the world with the selected character.
+async fn handle_login_to_server(session: &mut Session, data: &[u8]) -> Result<()> {
+    let req = crate::protocol::client::char_select::parse_login_to_server(data);
+    info!("Character selected: {}", req.char_name);
 
-            let pool = match &session.db {
-                Some(p) => p,
-                None => return Ok(()),
-            };
-            let account = session.account_name.as_ref().unwrap();
+    let pool = match &session.db {
+        Some(p) => p,
+        None => return Ok(()),
+    };
+    let account = session.account_name.as_ref().unwrap();
 
-            let ch = crate::db::character::load_character(pool, &req.char_name, account).await?;
+    let ch = crate::db::character::load_character(pool, &req.char_name, account).await?;
 
-            let ch = match ch {
-                Some(c) => c,
-                None => {
-                    warn!("Character not found: {}", req.char_name);
-                    return Ok(());
-                }
-            };
+    let mut ch = match ch {
+        Some(c) => c,
+        None => {
+            warn!("Character not found: {}", req.char_name);
+            return Ok(());
+        }
+    };
 
-            session.char_name = Some(req.char_name);
-            session.char_x = ch.loc_x;
-            session.char_y = ch.loc_y;
-            session.char_map = ch.map_id;
-            session.char_heading = ch.heading;
-            session.char_objid = ch.objid;
-
-            // Send ALL game init packets (17+ packets in correct order)
-            let init_packets = crate::protocol::server::game_init::build_all_game_init_packets(&ch, 4);
-            for pkt in &init_packets {
-                session.send_packet(pkt).await?;
-            }
+    // Pick up a session dropped inside its reconnect window
+    let resumed = session.world.reconnects.lock().await
+        .resume(account, std::time::Instant::now());
+    let mut resumed_skills = None;
+    if let Some(r) = resumed {
+        leave_world(&session.world, r.map_id, r.x, r.y, r.objid).await;
+        if r.objid == ch.objid {
+            info!("Resuming {} after reconnect", r.char_name);
+            ch.loc_x = r.x;
+            ch.loc_y = r.y;
+            ch.map_id = r.map_id;
+            ch.heading = r.heading;
+            resumed_skills = Some(r.skills);
+        }
+    }
 
-            // Register in shared world so other players can see us
-            let gfxid = crate::protocol::client::char_create::get_gfx_id(ch.char_type, ch.sex);
-            let nearby_packets = {
-                let mut world = session.world.lock().await;
-
-                // Collect nearby player packets (can't send while holding lock)
-                let nearby = world.get_nearby_players(ch.map_id, ch.loc_x, ch.loc_y, ch.objid);
-                let packets: Vec<Vec<u8>> = nearby.iter().map(|p| build_player_charpack(p)).collect();
-
-                // Register ourselves
-                let me = OnlinePlayer {
-                    object_id: ch.objid,
-                    name: ch.char_name.clone(),
-                    x: ch.loc_x,
-                    y: ch.loc_y,
-                    map_id: ch.map_id,
-                    heading: ch.heading,
-                    gfx_id: gfxid,
-                    level: ch.level,
-                    lawful: ch.lawful,
-                    char_type: ch.char_type,
-                    sex: ch.sex,
-                    clan_name: ch.clanname.clone(),
-                    title: String::new(),
-                    packet_tx: session.packet_tx.clone(),
-                };
+    session.char_name = Some(req.char_name);
+    session.char_x = ch.loc_x;
+    session.char_y = ch.loc_y;
+    session.char_map = ch.map_id;
+    session.char_heading = ch.heading;
+    session.char_objid = ch.objid;
+    session.last_saved = Some(session.snapshot());
+    session.char_level = ch.level;
+    session.char_type = ch.char_type;
+    session.char_clan_id = ch.clanid;
+    session.learned_skills = crate::db::skill::load_skills(pool, ch.objid).await?;
+    session.weapon_type = load_weapon_type(pool, ch.objid).await?;
+    let (cooldowns, effects) = match resumed_skills.or_else(|| session.parked_skills.remove(&ch.objid)) {
+        Some(parked) => parked.restore(),
+        None => (SkillCooldowns::new(), load_saved_buffs(session, ch.objid).await),
+    };
+    session.skill_cooldowns = cooldowns;
+    session.skill_effects = effects;
+
+    // ALL game init packets (17+ packets in correct order), sent
+    // together with the nearby players below
+    let mut init_packets = crate::protocol::server::game_init::build_all_game_init_packets(&ch, 4);
+    let init_count = init_packets.len();
+
+    // Register in shared world so other players can see us
+    let gfxid = crate::protocol::client::char_create::get_gfx_id(ch.char_type, ch.sex);
+    let nearby_packets = {
+        let mut world = session.world.lock_map(ch.map_id).await;
+
+        // Collect nearby player packets (can't send while holding lock)
+        let nearby = world.get_nearby_players(ch.map_id, ch.loc_x, ch.loc_y, ch.objid);
+        let packets: Vec<Vec<u8>> = nearby.iter().map(|p| build_player_charpack(p)).collect();
+
+        // Register ourselves
+        let me = OnlinePlayer {
+            object_id: ch.objid,
+            name: ch.char_name.clone(),
+            x: ch.loc_x,
+            y: ch.loc_y,
+            map_id: ch.map_id,
+            heading: ch.heading,
+            gfx_id: gfxid,
+            level: ch.level,
+            lawful: ch.lawful,
+            char_type: ch.char_type,
+            sex: ch.sex,
+            clan_name: ch.clanname.clone(),
+            title: String::new(),
+            cur_hp: ch.cur_hp,
+            max_hp: ch.max_hp,
+            is_dead: false,
+            move_seq: 0,
+            packet_tx: session.packet_tx.clone(),
+        };
 
-                // Broadcast our appearance to nearby players
-                let my_pack = build_player_charpack(&me);
-                world.broadcast_to_nearby(ch.map_id, ch.loc_x, ch.loc_y, ch.objid, &my_pack);
+        // Broadcast our appearance to nearby players
+        let my_pack = build_player_charpack(&me);
+        world.broadcast_to_nearby(
+            ch.map_id, ch.loc_x, ch.loc_y, ch.objid,
+            broadcast_range::SCREEN, &my_pack,
+        );
 
-                world.add_player(me);
-                packets
-            };
-            // Now send collected packets (lock released)
-            for pkt in &nearby_packets {
-                session.send_packet(pkt).await?;
-            }
+        world.add_player(me);
+        packets
+    };
+    session
+        .world
+        .groups
+        .lock()
+        .await
+        .join_clan(ch.objid, &ch.clanname, session.packet_tx.clone());
+    // Now send everything as one burst (lock released)
+    init_packets.extend(nearby_packets);
+    init_packets.extend(speed_buff_packets(ch.objid, &session.skill_effects));
+    session.send_packets(&init_packets).await?;
+
+    session.state = SessionState::InGame;
+    session.play_timer.start(std::time::Instant::now());
+    metrics::global().player_entered();
+    info!(
+        "State -> InGame (char={}, map={}, pos={},{}) - sent {} init packets",
+        ch.char_name, ch.map_id, ch.loc_x, ch.loc_y, init_count
+    );
+    Ok(())
+}
 
-            session.state = SessionState::InGame;
-            info!(
-                "State -> InGame (char={}, map={}, pos={},{}) - sent {} init packets",
-                ch.char_name, ch.map_id, ch.loc_x, ch.loc_y, init_packets.len()
-            );
-        }
-        opcodes::client::C_NEWCHAR => {
-            info!("Character creation requested");
-            handle_create_char(session, data).await?;
-        }
-        opcodes::client::C_DELETECHAR => {
-            info!("Character deletion requested");
-            // TODO: Parse char name, mark for deletion in DB
-        }
-        _ => {
-            debug!("Opcode {} in Authenticated (not handled)", opcode);
-        }
+/// C_DELETECHAR packet; see `handle_delete_char`.
+async fn handle_delete_char_request(session: &mut Session, data: &[u8]) -> Result<()> {
+    let name = crate::protocol::client::char_select::parse_delete_char(data);
+    info!("Character deletion requested: {}", name);
+    handle_delete_char(session, &name).await
+}
+
+/// C_MOVECHAR: one step in the sent heading.
+async fn handle_move_char(session: &mut Session, data: &[u8]) -> Result<()> {
+    let mv = crate::protocol::client::movement::parse_move_char(data);
+    let Some(seq) = session.move_seq.accept((mv.x, mv.y), (session.char_x, session.char_y)) else {
+        debug!(
+            "Move from ({},{}) dropped, player is at ({},{})",
+            mv.x, mv.y, session.char_x, session.char_y,
+        );
+        return Ok(());
+    };
+    let (dx, dy) = crate::ecs::components::position::heading_delta(mv.heading);
+    let (new_x, new_y) = (session.char_x + dx, session.char_y + dy);
+
+    let mut world = session.world.lock_map(session.char_map).await;
+    if !world.update_position(session.char_objid, new_x, new_y, mv.heading, seq) {
+        debug!("Out-of-order move {} dropped", seq);
+        return Ok(());
+    }
+    session.char_x = new_x;
+    session.char_y = new_y;
+    session.char_heading = mv.heading;
+
+    // Broadcast the authoritative position to nearby players
+    let move_pkt = crate::protocol::server::movement::build_move_char(
+        session.char_objid, session.char_x, session.char_y, mv.heading,
+    );
+    world.broadcast_to_nearby(
+        session.char_map, session.char_x, session.char_y,
+        session.char_objid, broadcast_range::SCREEN, &move_pkt,
+    );
+    drop(world);
+    enter_portal(session, new_x, new_y).await
+}
+
+/// C_ENTERPORTAL: walk into the portal the player stands on.
+async fn handle_enter_portal(session: &mut Session, data: &[u8]) -> Result<()> {
+    let req = crate::protocol::client::teleport::parse_enter_portal(data);
+    // Only the tile the player is standing on (or next to)
+    if crate::ecs::components::position::tile_distance(req.x, req.y, session.char_x, session.char_y) <= 1 {
+        enter_portal(session, req.x, req.y).await?;
     }
     Ok(())
 }
 
-async fn handle_in_game(session: &mut Session, opcode: u8, data: &[u8]) -> Result<()> {
-    match opcode {
-        opcodes::client::C_MOVECHAR => {
-            let mv = crate::protocol::client::movement::parse_move_char(data);
-            let (dx, dy) = crate::ecs::components::position::heading_delta(mv.heading);
-            session.char_x += dx;
-            session.char_y += dy;
-            session.char_heading = mv.heading;
+/// C_CHANGEHEADING: turn without moving.
+async fn handle_change_heading(session: &mut Session, data: &[u8]) -> Result<()> {
+    let ch = crate::protocol::client::movement::parse_change_heading(data);
+    session.char_heading = ch.heading;
+    Ok(())
+}
 
-            // Broadcast movement to nearby players
-            let move_pkt = crate::protocol::server::movement::build_move_char(
-                session.char_objid, session.char_x, session.char_y, mv.heading,
-            );
-            let mut world = session.world.lock().await;
-            world.update_position(session.char_objid, session.char_x, session.char_y, mv.heading);
-            world.broadcast_to_nearby(
-                session.char_map, session.char_x, session.char_y,
-                session.char_objid, &move_pkt,
-            );
+/// C_CHAT: normal chat, or a GM command from a GM.
+async fn handle_chat(session: &mut Session, data: &[u8]) -> Result<()> {
+    let msg = crate::protocol::client::chat::parse_chat(data);
+    if session.access_level.allows(AccessLevel::Gm) {
+        if let Some(cmd) = crate::network::gm::parse(&msg.text) {
+            handle_gm_command(session, cmd.name, cmd.args).await?;
+            return Ok(());
         }
-        opcodes::client::C_CHANGEHEADING => {
-            let ch = crate::protocol::client::movement::parse_change_heading(data);
-            session.char_heading = ch.heading;
+    }
+    let name = session.char_name.as_deref().unwrap_or("Unknown");
+    info!("[CHAT] {}: {}", name, msg.text);
+
+    // Clan and party chat reach members on any map
+    use crate::protocol::client::chat::{CHAT_CLAN, CHAT_PARTY};
+    if msg.chat_type == CHAT_CLAN || msg.chat_type == CHAT_PARTY {
+        let pkt = crate::protocol::server::chat::build_global_chat(msg.chat_type as i32, name, &msg.text);
+        let (clan, party) = {
+            let groups = session.world.groups.lock().await;
+            (groups.clan_of(session.char_objid).map(str::to_string), groups.party_of(session.char_objid))
+        };
+        match (msg.chat_type, clan, party) {
+            (CHAT_CLAN, Some(clan), _) => {
+                session.world.broadcast_to_clan(&clan, session.char_objid, &pkt).await;
+            }
+            (CHAT_PARTY, _, Some(party)) => {
+                session.world.broadcast_to_party(party, session.char_objid, &pkt).await;
+            }
+            _ => return Ok(()),
         }
-        opcodes::client::C_CHAT => {
-            let msg = crate::protocol::client::chat::parse_chat(data);
-            let name = session.char_name.as_deref().unwrap_or("Unknown");
-            info!("[CHAT] {}: {}", name, msg.text);
+        return session.send_packet(&pkt).await;
+    }
 
-            // Build chat packet and send to self + broadcast to nearby
-            let pkt = crate::protocol::server::chat::build_normal_chat(
-                session.char_objid, msg.chat_type as i32, name, &msg.text,
-            );
-            session.send_packet(&pkt).await?;
+    // Build chat packet and send to self + broadcast to nearby
+    let pkt = crate::protocol::server::chat::build_normal_chat(
+        session.char_objid, msg.chat_type as i32, name, &msg.text,
+    );
+    session.send_packet(&pkt).await?;
 
-            let world = session.world.lock().await;
-            world.broadcast_to_nearby(
-                session.char_map, session.char_x, session.char_y,
-                session.char_objid, &pkt,
-            );
-        }
-        opcodes::client::C_ATTACK => {
-            debug!("Attack received (not fully handled yet)");
-        }
-        opcodes::client::C_USESKILL => {
-            debug!("Skill use received (not fully handled yet)");
-        }
-        opcodes::client::C_USEITEM => {
-            debug!("Item use received (not fully handled yet)");
+    let world = session.world.lock_map(session.char_map).await;
+    world.broadcast_to_nearby(
+        session.char_map, session.char_x, session.char_y,
+        session.char_objid, broadcast_range::SCREEN, &pkt,
+    );
+    Ok(())
+}
+
+/// C_ATTACK: melee swing (only the attack-speed check so far).
+async fn handle_attack(session: &mut Session, _data: &[u8]) -> Result<()> {
+    let effects = &session.skill_effects;
+    let haste = effects.has_effect(skill_ids::STATUS_HASTE)
+        || effects.has_effect(skill_ids::HASTE)
+        || effects.has_effect(skill_ids::GREATER_HASTE);
+    let brave = effects.has_effect(skill_ids::STATUS_BRAVE);
+    let interval = attack_timer::attack_interval(
+        attack_timer::weapon_interval_ms(session.weapon_type), haste, brave,
+    );
+    if !session.attack_timer.try_attack(std::time::Instant::now(), interval) {
+        // Lag can bunch a legit swing or two; only sustained spam is worth a warning
+        if session.attack_timer.rejected_streak() == attack_timer::SPAM_WARN_STREAK {
+            warn!("Attack spam from {:?}: {} too-fast attacks in a row", session.char_name, attack_timer::SPAM_WARN_STREAK);
+        } else {
+            debug!("Attack too fast from {:?}, ignored", session.char_name);
         }
-        opcodes::client::C_KEEPALIVE => {
-            // Heartbeat - no response needed
+        return Ok(());
+    }
+    debug!("Attack received (not fully handled yet)");
+    Ok(())
+}
+
+/// C_USESKILL: only recall and escape skills are handled so far.
+async fn handle_use_skill(session: &mut Session, data: &[u8]) -> Result<()> {
+    let req = crate::protocol::client::skill::parse_use_skill(data);
+    if !session.learned_skills.contains(&req.skill_id) {
+        warn!("Skill {} used but not learned by {:?}", req.skill_id, session.char_name);
+        return Ok(());
+    }
+    if crate::ecs::recall::recall_rule(req.skill_id).is_some() {
+        handle_recall_skill(session, req.skill_id, &req.char_name).await?;
+        return Ok(());
+    }
+    if crate::ecs::recall::is_escape(req.skill_id, req.bookmark_id) {
+        let town = crate::ecs::recall::escape_destination(session.char_map, session.char_x, session.char_y);
+        teleport_to(session, town.x, town.y, town.map_id).await?;
+        return Ok(());
+    }
+    debug!("Skill use received (not fully handled yet)");
+    Ok(())
+}
+
+/// C_ATTR: yes/no answer to a confirmation dialog.
+async fn handle_attr(session: &mut Session, data: &[u8]) -> Result<()> {
+    let reply = crate::protocol::client::action::parse_attr(data);
+    if reply.message_id == crate::ecs::recall::MSG_CALL_CLAN_CONFIRM {
+        let mv = session.world.recalls.lock().await
+            .respond(session.char_objid, reply.yes_no_id, reply.accepted);
+        if let Some(mv) = mv {
+            teleport_to(session, mv.x, mv.y, mv.map_id).await?;
         }
-        opcodes::client::C_QUITGAME => {
-            info!("Client requested quit");
-            // Send disconnect packet before closing
-            let pkt = crate::protocol::packet::PacketBuilder::new(
-                crate::protocol::opcodes::server::S_OPCODE_DISCONNECT
-            ).build();
-            let _ = session.send_packet(&pkt).await;
-            return Err(anyhow::anyhow!("Client quit"));
+    } else if reply.message_id == crate::ecs::party::MSG_PARTY_INVITE {
+        let join = session.world.parties.lock().await
+            .respond(session.char_objid, reply.yes_no_id, reply.accepted);
+        if let Some(join) = join {
+            join_party(session, join).await;
         }
-        opcodes::client::C_CHANGECHAR => {
-            // ESC menu → "重新開始" / return to character select
-            info!("Client returning to character select");
-            save_character(&session).await;
-            session.state = SessionState::Authenticated;
-            send_char_list(session).await?;
-            info!("State -> Authenticated (restart)");
-        }
-        opcodes::client::C_RESTARTMENU => {
-            // This handles clan ranks, survival cry, etc. (not the ESC menu)
-            // For now, just ignore silently
-        }
-        opcodes::client::C_RESTART => {
-            // Restart after death - respawn at saved location
-            info!("Client restarting after death");
-            // Re-send game init packets at current position
-            if let Some(pool) = &session.db {
-                if let Some(name) = &session.char_name {
-                    let account = session.account_name.as_ref().unwrap();
-                    if let Ok(Some(ch)) = crate::db::character::load_character(pool, name, account).await {
-                        session.char_x = ch.loc_x;
-                        session.char_y = ch.loc_y;
-                        session.char_map = ch.map_id;
-                        let init_packets = crate::protocol::server::game_init::build_all_game_init_packets(&ch, 4);
-                        for pkt in &init_packets {
-                            session.send_packet(pkt).await?;
-                        }
-                    }
-                }
-            }
+    } else if reply.message_id == crate::ecs::trade::MSG_TRADE_REQUEST {
+        let requester = session.world.trades.lock().await
+            .respond(session.char_objid, reply.yes_no_id, reply.accepted);
+        if let Some(requester_id) = requester {
+            open_trade(session, requester_id).await?;
         }
-        _ => {
-            // Silently ignore unhandled opcodes to reduce log spam
+    }
+    Ok(())
+}
+
+/// C_CREATEPARTY: invite a player into our party.
+async fn handle_create_party(session: &mut Session, data: &[u8]) -> Result<()> {
+    let req = crate::protocol::client::party::parse_create_party(data);
+    let Some(target_id) = req.target_id else {
+        debug!("Party invite type {} not handled yet", req.party_type);
+        return Ok(());
+    };
+    let Some(target) = session.world.find_by_id(target_id).await else { return Ok(()) };
+    let result = session.world.parties.lock().await.invite(session.char_objid, target.object_id);
+    match result {
+        Ok(yes_no_id) => {
+            let name = session.char_name.as_deref().unwrap_or("");
+            let pkt = crate::protocol::server::npc_dialog::build_yes_no_with_args(
+                crate::ecs::party::MSG_PARTY_INVITE, yes_no_id, &[name],
+            );
+            let _ = target.packet_tx.send(pkt);
         }
+        Err(e) => debug!("Party invite of {} rejected: {:?}", target.name, e),
     }
     Ok(())
 }
 
-/// Save character position to database.
-async fn save_character(session: &Session) {
-    if let (Some(pool), Some(name)) = (&session.db, &session.char_name) {
-        let result = sqlx::query(
-            "UPDATE characters SET LocX=?, LocY=?, MapID=?, Heading=? WHERE char_name=?"
-        )
-        .bind(session.char_x)
-        .bind(session.char_y)
-        .bind(session.char_map)
-        .bind(session.char_heading)
-        .bind(name)
-        .execute(pool)
-        .await;
+/// C_LEAVEPARTY: leave our party.
+async fn handle_leave_party(session: &mut Session, _data: &[u8]) -> Result<()> {
+    let (party, left) = {
+        let mut parties = session.world.parties.lock().await;
+        let Some(party) = parties.party_of(session.char_objid) else { return Ok(()) };
+        (party, parties.leave(session.char_objid))
+    };
+    // Hide the leaver's HP bar from those who stay
+    let pkt = crate::protocol::server::combat::build_hp_meter_hidden(session.char_objid);
+    session.world.broadcast_to_party(party, session.char_objid, &pkt).await;
+    let mut groups = session.world.groups.lock().await;
+    for id in left {
+        groups.leave_party(id);
+    }
+    Ok(())
+}
 
-        match result {
-            Ok(_) => info!("Character saved: {} at ({},{} map={})",
-                name, session.char_x, session.char_y, session.char_map),
-            Err(e) => warn!("Failed to save character: {}", e),
+/// List an accepted invitation's members in the group index and show
+/// each member's HP bar to the rest of the party.
+async fn join_party(session: &mut Session, join: crate::ecs::party::PartyJoin) {
+    let inviter = match join.founded {
+        true => session.world.find_by_id(join.inviter_id).await,
+        false => None,
+    };
+    {
+        let mut groups = session.world.groups.lock().await;
+        if let Some(inviter) = &inviter {
+            groups.join_party(inviter.object_id, join.party, inviter.packet_tx.clone());
         }
+        groups.join_party(join.member_id, join.party, session.packet_tx.clone());
     }
-}
 
-/// Cleanup when session ends: remove from world, save character, set account offline.
-async fn cleanup_session(session: &Session) {
-    // Remove from shared world + broadcast removal to nearby players
-    if session.state == SessionState::InGame && session.char_objid != 0 {
-        let remove_pkt = crate::protocol::server::npc_pack::build_remove_object(session.char_objid as u32);
-        let mut world = session.world.lock().await;
-        world.broadcast_to_nearby(
-            session.char_map, session.char_x, session.char_y,
-            session.char_objid, &remove_pkt,
-        );
-        world.remove_player(session.char_objid);
-        drop(world);
+    let members = session.world.parties.lock().await.get(join.party).map(|p| p.members.clone()).unwrap_or_default();
 
-        save_character(session).await;
+    for id in members {
+        let Some(member) = session.world.find_by_id(id as i32).await else { continue };
+        let pkt = crate::protocol::server::combat::build_hp_meter(member.object_id, member.cur_hp, member.max_hp);
+        session.world.broadcast_to_party(join.party, member.object_id, &pkt).await;
     }
+}
 
-    // Set account offline
-    if let (Some(pool), Some(account)) = (&session.db, &session.account_name) {
-        let _ = crate::db::account::set_offline(pool, account).await;
-        info!("Account set offline: {}", account);
+/// C_TRADE: ask the player standing in front of us to trade.
+async fn handle_trade(session: &mut Session, _data: &[u8]) -> Result<()> {
+    let (dx, dy) = crate::ecs::components::position::heading_delta(session.char_heading);
+    let (x, y) = (session.char_x + dx, session.char_y + dy);
+    let target = session.world.lock_map(session.char_map).await
+        .get_nearby_players(session.char_map, x, y, session.char_objid)
+        .into_iter()
+        .find(|p| p.x == x && p.y == y && !p.is_dead);
+    let Some(target) = target else { return Ok(()) };
+    let result = session.world.trades.lock().await.request(session.char_objid, target.object_id);
+    match result {
+        Ok(yes_no_id) => {
+            let name = session.char_name.as_deref().unwrap_or("");
+            let pkt = crate::protocol::server::npc_dialog::build_yes_no_with_args(
+                crate::ecs::trade::MSG_TRADE_REQUEST, yes_no_id, &[name],
+            );
+            let _ = target.packet_tx.send(pkt);
+        }
+        Err(e) => debug!("Trade request to {} rejected: {:?}", target.name, e),
     }
+    Ok(())
 }
 
-/// Build S_CHARPACK for a player (so other players can see them).
-fn build_player_charpack(p: &OnlinePlayer) -> Vec<u8> {
-    use crate::protocol::packet::PacketBuilder;
-    use crate::protocol::opcodes::server;
+/// Open the trade window on both sides once the target accepted.
+async fn open_trade(session: &mut Session, requester_id: i32) -> Result<()> {
+    use crate::protocol::server::trade::build_trade;
 
-    PacketBuilder::new(server::S_OPCODE_CHARPACK)
-        .write_h(p.x)
-        .write_h(p.y)
-        .write_d(p.object_id)
-        .write_h(p.gfx_id)
-        .write_c(0)              // weapon
-        .write_c(p.heading)
-        .write_c(0)              // light
+    let Some(requester) = session.world.find_by_id(requester_id).await else {
+        session.world.trades.lock().await.close(session.char_objid);
+        return Ok(());
+    };
+    let _ = requester.packet_tx.send(build_trade(session.char_name.as_deref().unwrap_or("")));
+    session.send_packet(&build_trade(&requester.name)).await
+}
+
+/// C_TRADEADDITEM: offer an item from the stored inventory.
+async fn handle_trade_add_item(session: &mut Session, data: &[u8]) -> Result<()> {
+    use crate::ecs::components::item::Inventory;
+    use crate::protocol::server::trade::build_trade_add_item;
+
+    let req = crate::protocol::client::trade::parse_trade_add_item(data);
+    let Some(pool) = session.db.clone() else { return Ok(()) };
+    let mut inv = Inventory::new();
+    inv.items = crate::db::inventory::load_items(&pool, session.char_objid).await?;
+    let Some(item) = inv.get_item(req.object_id).cloned() else { return Ok(()) };
+    let templates = crate::data::item_table::load_item_templates_for(&pool, &[item.item_id]).await?;
+    let Some(template) = templates.get(&item.item_id) else { return Ok(()) };
+
+    let partner = {
+        let mut trades = session.world.trades.lock().await;
+        let Some(trade) = trades.get_mut(session.char_objid) else { return Ok(()) };
+        if let Err(e) = trade.offer(session.char_objid, &inv, &templates, req.object_id, req.count) {
+            debug!("Trade offer by {:?} rejected: {:?}", session.char_name, e);
+            return Ok(());
+        }
+        trade.partner_of(session.char_objid)
+    };
+    session.send_packet(&build_trade_add_item(&item, req.count, template, false)).await?;
+    if let Some(partner) = partner {
+        if let Some(p) = session.world.find_by_id(partner).await {
+            let _ = p.packet_tx.send(build_trade_add_item(&item, req.count, template, true));
+        }
+    }
+    Ok(())
+}
+
+/// C_TRADEADDOK: confirm our side; the second confirmation swaps.
+async fn handle_trade_ok(session: &mut Session, _data: &[u8]) -> Result<()> {
+    let trade = {
+        let mut trades = session.world.trades.lock().await;
+        let Some(trade) = trades.get_mut(session.char_objid) else { return Ok(()) };
+        match trade.confirm(session.char_objid) {
+            Ok(true) => trades.close(session.char_objid),
+            _ => None,
+        }
+    };
+    match trade {
+        Some(trade) => complete_trade(session, trade).await,
+        None => Ok(()),
+    }
+}
+
+/// C_TRADEADDCANCEL: close the trade without swapping.
+async fn handle_trade_cancel(session: &mut Session, _data: &[u8]) -> Result<()> {
+    let trade = session.world.trades.lock().await.close(session.char_objid);
+    match trade {
+        Some(trade) => end_trade(session, &trade, crate::protocol::server::trade::TRADE_CANCEL).await,
+        None => Ok(()),
+    }
+}
+
+/// Close both sides' trade windows with `status`.
+async fn end_trade(session: &mut Session, trade: &crate::ecs::trade::Trade, status: i32) -> Result<()> {
+    let pkt = crate::protocol::server::trade::build_trade_status(status);
+    if let Some(partner) = trade.partner_of(session.char_objid) {
+        if let Some(p) = session.world.find_by_id(partner).await {
+            let _ = p.packet_tx.send(pkt.clone());
+        }
+    }
+    session.send_packet(&pkt).await
+}
+
+/// Swap a confirmed trade's items in the stored inventories, both sides
+/// in one transaction, and audit it. If anything changed since the
+/// offers were made the trade is cancelled and nothing moves.
+async fn complete_trade(session: &mut Session, trade: crate::ecs::trade::Trade) -> Result<()> {
+    use crate::db::inventory as inv;
+    use crate::ecs::components::item::Inventory;
+    use crate::protocol::server::trade::{TRADE_CANCEL, TRADE_COMPLETE};
+
+    let Some(pool) = session.db.clone() else {
+        return end_trade(session, &trade, TRADE_CANCEL).await;
+    };
+    let (mut inv_a, mut inv_b) = (Inventory::new(), Inventory::new());
+    inv_a.items = inv::load_items(&pool, trade.a.char_id).await?;
+    inv_b.items = inv::load_items(&pool, trade.b.char_id).await?;
+    let (before_a, before_b) = (inv_a.clone(), inv_b.clone());
+    let item_ids: Vec<i32> = trade.a.offers.iter().chain(&trade.b.offers).map(|o| o.item_id).collect();
+    let templates = crate::data::item_table::load_item_templates_for(&pool, &item_ids).await?;
+    if let Err(e) = trade.complete(&mut inv_a, &mut inv_b, &templates) {
+        debug!("Trade between {} and {} aborted: {:?}", trade.a.char_id, trade.b.char_id, e);
+        return end_trade(session, &trade, TRADE_CANCEL).await;
+    }
+
+    let mut changes_a = inv_a.changes_since(&before_a);
+    let mut changes_b = inv_b.changes_since(&before_b);
+    // Part of a stack arrives under the giver's object ID; the giver
+    // keeps the rest, so the new stack needs an ID of its own
+    for (changes, giver) in [(&mut changes_a, &inv_b), (&mut changes_b, &inv_a)] {
+        for item in &mut changes.added {
+            if giver.get_item(item.object_id).is_some() {
+                item.object_id = inv::next_item_id(&pool).await? as u32;
+            }
+        }
+    }
+    let written = inv::apply_changes_for(
+        &pool, &[(trade.a.char_id, &changes_a), (trade.b.char_id, &changes_b)], &templates,
+    ).await?;
+    if !written {
+        // The stored inventories moved under us; nothing was written
+        return end_trade(session, &trade, TRADE_CANCEL).await;
+    }
+
+    let me = session.char_objid;
+    let partner = session.world.find_by_id(trade.partner_of(me).unwrap_or(0)).await;
+    let (mine, theirs) = if trade.a.char_id == me { (&changes_a, &changes_b) } else { (&changes_b, &changes_a) };
+    let packets = crate::protocol::server::inventory::build_inventory_changes(mine, &templates);
+    session.send_packets(&packets).await?;
+    if let Some(p) = &partner {
+        for pkt in crate::protocol::server::inventory::build_inventory_changes(theirs, &templates) {
+            let _ = p.packet_tx.send(pkt);
+        }
+    }
+    end_trade(session, &trade, TRADE_COMPLETE).await?;
+
+    let my_name = session.char_name.clone().unwrap_or_default();
+    let their_name = partner.map(|p| p.name).unwrap_or_default();
+    let names = if trade.a.char_id == me { (my_name.as_str(), their_name.as_str()) } else { (their_name.as_str(), my_name.as_str()) };
+    let account = session.account_name.clone().unwrap_or_default();
+    audit::record(&account, AuditAction::Trade, &trade_audit_details(&trade, names, &templates));
+    Ok(())
+}
+
+/// Audit details for a completed trade: what each side handed over.
+/// `names` are the characters on sides a and b.
+fn trade_audit_details(
+    trade: &crate::ecs::trade::Trade,
+    names: (&str, &str),
+    templates: &HashMap<i32, crate::ecs::components::item::ItemTemplate>,
+) -> String {
+    let side = |from: &crate::ecs::trade::TradeSide, from_name: &str, to_name: &str| {
+        let items: Vec<String> = from
+            .offers
+            .iter()
+            .map(|o| {
+                let name = templates.get(&o.item_id).map_or("", |t| t.name.as_str());
+                format!("{}({}) x{} #{}", name, o.item_id, o.count, o.object_id)
+            })
+            .collect();
+        format!("{}({}) -> {}: [{}]", from_name, from.char_id, to_name, items.join(", "))
+    };
+    format!("{}; {}", side(&trade.a, names.0, names.1), side(&trade.b, names.1, names.0))
+}
+
+/// C_LEAVECLAN: leave our clan. Only members can leave; a leader
+/// leaving disbands the clan, which is not handled yet.
+async fn handle_leave_clan(session: &mut Session, _data: &[u8]) -> Result<()> {
+    let Some(pool) = session.db.clone() else { return Ok(()) };
+    let Some(clan) = session.world.groups.lock().await.clan_of(session.char_objid).map(str::to_string) else {
+        return Ok(());
+    };
+    if crate::db::clan::leader_of(&pool, session.char_clan_id).await? == Some(session.char_objid) {
+        debug!("Clan leader {:?} leaving {} (disband not handled yet)", session.char_name, clan);
+        return Ok(());
+    }
+
+    crate::db::clan::delete_member(&pool, session.char_objid).await?;
+    crate::db::clan::clear_character_clan(&pool, session.char_objid).await?;
+    session.char_clan_id = 0;
+    session.world.groups.lock().await.leave_clan(session.char_objid);
+    session.world.lock_map(session.char_map).await.set_clan(session.char_objid, "");
+
+    let name = session.char_name.as_deref().unwrap_or("");
+    let pkt = crate::protocol::server::chat::build_server_message(&format!("{} 已退出 {} 血盟。", name, clan));
+    session.world.broadcast_to_clan(&clan, 0, &pkt).await;
+    session.send_packet(&pkt).await
+}
+
+/// C_USEITEM: put on or take off a weapon or armour. Other items are
+/// not handled yet.
+async fn handle_use_item(session: &mut Session, data: &[u8]) -> Result<()> {
+    use crate::ecs::components::item::{Inventory, ItemType2};
+
+    let Some(pool) = session.db.clone() else { return Ok(()) };
+    let req = crate::protocol::client::action::parse_use_item(data);
+    let mut inv = Inventory::new();
+    inv.items = crate::db::inventory::load_items(&pool, session.char_objid).await?;
+    let Some(item) = inv.items.iter().find(|i| i.object_id == req.item_obj_id as u32).cloned() else {
+        return Ok(());
+    };
+    let item_ids: Vec<i32> = inv.items.iter().map(|i| i.item_id).collect();
+    let templates = crate::data::item_table::load_item_templates_for(&pool, &item_ids).await?;
+    let Some(template) = templates.get(&item.item_id) else { return Ok(()) };
+    if template.type2 == ItemType2::EtcItem {
+        debug!("Item use received (not fully handled yet)");
+        return Ok(());
+    }
+
+    let before = inv.items.clone();
+    let result = if item.is_equipped {
+        inv.unequip(item.object_id)
+    } else {
+        // Take off whatever is in the same slot first
+        let slot: Vec<u32> = inv.get_equipped().iter()
+            .filter(|i| templates.get(&i.item_id).is_some_and(|t| {
+                t.type2 == template.type2 && (t.type2 == ItemType2::Weapon || t.item_type == template.item_type)
+            }))
+            .map(|i| i.object_id)
+            .collect();
+        slot.into_iter()
+            .try_for_each(|id| inv.unequip(id))
+            .and_then(|_| inv.equip(item.object_id, template, session.char_type))
+    };
+    if let Err(e) = result {
+        let pkt = crate::protocol::server::chat::build_server_message(e.message());
+        return session.send_packet(&pkt).await;
+    }
+
+    for (old, new) in before.iter().zip(&inv.items) {
+        if old.is_equipped != new.is_equipped {
+            crate::db::inventory::set_equipped(&pool, session.char_objid, new.object_id as i32, new.is_equipped).await?;
+        }
+    }
+    send_equipped_status(session, &pool).await
+}
+
+/// C_KEEPALIVE: heartbeat, no response needed.
+async fn handle_keepalive(_session: &mut Session, _data: &[u8]) -> Result<()> {
+    Ok(())
+}
+
+/// C_QUITGAME: clean quit, ends the session.
+async fn handle_quit_game(session: &mut Session, _data: &[u8]) -> Result<()> {
+    info!("Client requested quit");
+    // Send disconnect packet before closing
+    let pkt = crate::protocol::packet::PacketBuilder::new(
+        crate::protocol::opcodes::server::S_OPCODE_DISCONNECT
+    ).build();
+    let _ = session.send_packet(&pkt).await;
+    session.quitting = true;
+    Err(anyhow::anyhow!("Client quit"))
+}
+
+/// C_CHANGECHAR: ESC menu → "重新開始" / return to character select.
+async fn handle_change_char(session: &mut Session, _data: &[u8]) -> Result<()> {
+    info!("Client returning to character select");
+    save_character(session).await;
+    session.play_timer.stop(std::time::Instant::now());
+    let parked = ParkedSkills::park(
+        std::mem::replace(&mut session.skill_cooldowns, SkillCooldowns::new()),
+        std::mem::replace(&mut session.skill_effects, SkillEffects::new()),
+    );
+    session.parked_skills.insert(session.char_objid, parked);
+    // Same as a logout: off the map, out of the name index, clan and party
+    leave_world(&session.world, session.char_map, session.char_x, session.char_y, session.char_objid).await;
+    session.state = SessionState::Authenticated;
+    send_char_list(session).await?;
+    info!("State -> Authenticated (restart)");
+    Ok(())
+}
+
+/// C_RESTARTMENU: clan ranks, survival cry, etc. (not the ESC menu).
+async fn handle_restart_menu_request(session: &mut Session, data: &[u8]) -> Result<()> {
+    let action = crate::protocol::client::action::parse_restart_menu(data);
+    handle_restart_menu(session, action).await
+}
+
+/// C_RESTART: restart after death, respawning at the saved location.
+async fn handle_restart(session: &mut Session, _data: &[u8]) -> Result<()> {
+    info!("Client restarting after death");
+    // Re-send game init packets at current position
+    if let Some(pool) = &session.db {
+        if let Some(name) = &session.char_name {
+            let account = session.account_name.as_ref().unwrap();
+            if let Ok(Some(ch)) = crate::db::character::load_character(pool, name, account).await {
+                session.char_x = ch.loc_x;
+                session.char_y = ch.loc_y;
+                session.char_map = ch.map_id;
+                let init_packets = crate::protocol::server::game_init::build_all_game_init_packets(&ch, 4);
+                session.send_packets(&init_packets).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Teleport this session's character (spell effect) and update visibility.
+async fn teleport_to(session: &mut Session, x: i32, y: i32, map_id: i32) -> Result<()> {
+    let me = match session.world.lock_map(session.char_map).await.get_player(session.char_objid) {
+        Some(p) => p.clone(),
+        None => return Ok(()),
+    };
+    let action = crate::protocol::server::teleport::build_effect_teleport(
+        me.object_id, x, y, map_id, session.char_heading, me.gfx_id,
+        &me.name, &me.clan_name, me.lawful, false,
+    );
+
+    relocate_in_world(session, &me, x, y, map_id).await;
+
+    session.char_x = x;
+    session.char_y = y;
+    session.char_map = map_id;
+    for pkt in &action.player_packets {
+        session.send_packet(pkt).await?;
+    }
+    info!("{} teleported to ({},{}) map {}", me.name, x, y, map_id);
+    Ok(())
+}
+
+/// Teleport through the portal at (x, y) on the current map, if any,
+/// once its level and key requirements are met.
+async fn enter_portal(session: &mut Session, x: i32, y: i32) -> Result<()> {
+    use crate::data::dungeon_table::PortalError;
+
+    let entry = session.world.dungeons.read().unwrap().find_portal(session.char_map, x, y).cloned();
+    let Some(entry) = entry else { return Ok(()) };
+    let has_key = match (&session.db, entry.key_item_id) {
+        (_, 0) => true,
+        (Some(pool), key) => crate::db::inventory::count_item(pool, session.char_objid, key).await? > 0,
+        (None, _) => false,
+    };
+    match entry.check_gate(session.char_level, has_key) {
+        Ok(()) => {
+            session.char_heading = entry.new_heading;
+            teleport_to(session, entry.new_x, entry.new_y, entry.new_map_id).await
+        }
+        Err(e) => {
+            let msg = match e {
+                PortalError::UnderLevel { needed } => format!("等級 {} 以上才能進入。", needed),
+                PortalError::MissingKey { .. } => "缺少進入所需的物品。".to_string(),
+            };
+            let pkt = crate::protocol::server::chat::build_server_message(&msg);
+            session.send_packet(&pkt).await
+        }
+    }
+}
+
+/// Survival cry effect (Ctrl+E).
+const SURVIVAL_CRY_GFX: i32 = 8683;
+
+/// C_RESTARTMENU sub-actions. Unknown ones are ignored.
+async fn handle_restart_menu(
+    session: &mut Session,
+    action: crate::protocol::client::action::RestartMenuAction,
+) -> Result<()> {
+    use crate::protocol::client::action::RestartMenuAction;
+
+    match action {
+        RestartMenuAction::SurvivalCry => {
+            let pkt = crate::protocol::server::skill_effect::build_skill_sound(session.char_objid, SURVIVAL_CRY_GFX);
+            session.send_packet(&pkt).await?;
+            session.world.lock_map(session.char_map).await.broadcast_to_nearby(
+                session.char_map, session.char_x, session.char_y,
+                session.char_objid, broadcast_range::SCREEN, &pkt,
+            );
+        }
+        RestartMenuAction::ClanRankList => {
+            let Some(pool) = session.db.clone() else { return Ok(()) };
+            if session.char_clan_id == 0 {
+                return Ok(());
+            }
+            let members = crate::db::clan::load_clan_members(&pool, session.char_clan_id).await?;
+            let names: Vec<&str> = members.iter().map(|m| m.char_name.as_str()).collect();
+            let pkt = crate::protocol::server::chat::build_server_message(
+                &format!("血盟成員 ({}): {}", names.len(), names.join(", ")),
+            );
+            session.send_packet(&pkt).await?;
+        }
+        RestartMenuAction::SetRank { rank, name } => {
+            debug!("Clan rank change {} -> {} (not handled yet)", name, rank);
+        }
+        RestartMenuAction::Unknown(code) => {
+            debug!("C_RESTARTMENU sub-action {} ignored", code);
+        }
+    }
+    Ok(())
+}
+
+/// Run a `.command` typed by a GM. Every attempt is audited.
+async fn handle_gm_command(session: &mut Session, name: &str, args: &str) -> Result<()> {
+    use crate::network::gm::{self, GmError};
+
+    let account = session.account_name.clone().unwrap_or_default();
+    let reply = match gm::authorize(session.access_level, name) {
+        Err(GmError::UnknownCommand) => format!("Unknown command: .{}", name),
+        Err(GmError::NotPermitted) => {
+            audit::record(&account, AuditAction::GmCommand, &format!("denied .{} {}", name, args));
+            format!("Not permitted: .{}", name)
+        }
+        Ok(()) => {
+            audit::record(&account, AuditAction::GmCommand, &format!(".{} {}", name, args));
+            match name {
+                "announce" => {
+                    let sent = crate::network::shared_state::announce(&session.world, args).await;
+                    format!("Announced to {} players", sent)
+                }
+                "access" => {
+                    let mut it = args.split_whitespace();
+                    match (it.next(), it.next().and_then(|l| l.parse::<i32>().ok()), &session.db) {
+                        (Some(login), Some(level), Some(pool)) => {
+                            crate::db::account::set_access_level(pool, login, level).await?;
+                            format!("{} access level set to {}", login, level)
+                        }
+                        _ => "Usage: .access <account> <level>".to_string(),
+                    }
+                }
+                "reload" => {
+                    let path = &session.config.paths.game_data;
+                    match session.world.game_data.reload_file(path).await {
+                        Ok(()) => format!("Reloaded {}", path),
+                        Err(e) => format!("Reload failed, keeping current data: {:#}", e),
+                    }
+                }
+                "who" => {
+                    let online = session.world.online_players().await;
+                    let rows: Vec<String> = online
+                        .iter()
+                        .map(|p| match p.clan_name.as_str() {
+                            "" => format!("{} Lv{} map {}", p.name, p.level, p.map_id),
+                            clan => format!("{} Lv{} map {} [{}]", p.name, p.level, p.map_id, clan),
+                        })
+                        .collect();
+                    format!("{} online: {}", online.len(), rows.join(", "))
+                }
+                "confirmip" => {
+                    let mut it = args.split_whitespace();
+                    match (it.next(), it.next(), &session.db) {
+                        (Some(login), Some(ip), Some(pool)) => {
+                            let n = crate::db::account::confirm_ip(pool, login, ip).await?;
+                            format!("Confirmed {} for {} ({} attempts)", ip, login, n)
+                        }
+                        _ => "Usage: .confirmip <account> <ip>".to_string(),
+                    }
+                }
+                _ => format!("Unknown command: .{}", name),
+            }
+        }
+    };
+    let pkt = crate::protocol::server::chat::build_server_message(&reply);
+    session.send_packet(&pkt).await
+}
+
+/// Move a player's world entry to a new location, switching map shards if
+/// needed, and update what nearby players see on both ends.
+async fn relocate_in_world(session: &Session, me: &OnlinePlayer, x: i32, y: i32, map_id: i32) {
+    {
+        // Old and new map locked together, in map ID order
+        let (mut from, to) = session.world.lock_pair(me.map_id, map_id).await;
+        let remove_pkt = crate::protocol::server::npc_pack::build_remove_object(me.object_id as u32);
+        from.broadcast_to_nearby(
+            me.map_id, me.x, me.y, me.object_id, broadcast_range::SCREEN, &remove_pkt,
+        );
+        let mut moved = me.clone();
+        moved.x = x;
+        moved.y = y;
+        moved.map_id = map_id;
+        let dest = match to {
+            Some(mut to) => {
+                from.remove_player(me.object_id);
+                to.add_player(moved.clone());
+                to
+            }
+            None => {
+                from.move_player(me.object_id, x, y);
+                from
+            }
+        };
+        let pack = build_player_charpack(&moved);
+        dest.broadcast_to_nearby(map_id, x, y, me.object_id, broadcast_range::SCREEN, &pack);
+    }
+}
+
+/// Cast a recall skill on a clan member named `target_name`.
+async fn handle_recall_skill(session: &mut Session, skill_id: i32, target_name: &str) -> Result<()> {
+    let me = session.world.lock_map(session.char_map).await.get_player(session.char_objid).cloned();
+    let target = session.world.find_by_name(target_name).await;
+    let (me, target) = match (me, target) {
+        (Some(me), Some(t)) => (me, t),
+        _ => return Ok(()),
+    };
+    if me.clan_name.is_empty() || me.clan_name != target.clan_name {
+        debug!("Recall target {} is not in {}'s clan", target.name, me.name);
+        return Ok(());
+    }
+
+    let result = {
+        let mut recalls = session.world.recalls.lock().await;
+        recalls
+            .request(
+                skill_id,
+                (me.object_id, me.x, me.y, me.map_id),
+                (target.object_id, target.x, target.y, target.map_id),
+            )
+            .map(|mv| (mv, target))
+    };
+
+    match result {
+        Ok((Some(mv), _)) => teleport_to(session, mv.x, mv.y, mv.map_id).await?,
+        Ok((None, target)) => {
+            // Ask the target to accept the summon
+            let recalls = session.world.recalls.lock().await;
+            if let Some(req) = recalls.pending_for(target.object_id) {
+                let pkt = crate::protocol::server::npc_dialog::build_yes_no(
+                    crate::ecs::recall::MSG_CALL_CLAN_CONFIRM, req.yes_no_id,
+                );
+                let _ = target.packet_tx.send(pkt);
+            }
+        }
+        Err(e) => debug!("Recall skill {} rejected: {:?}", skill_id, e),
+    }
+    Ok(())
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// C_DELETECHAR: delete (or schedule deletion of) a character, or restore
+/// one already pending deletion.
+async fn handle_delete_char(session: &mut Session, char_name: &str) -> Result<()> {
+    use crate::db::char_delete::{self, DeleteDecision};
+    use crate::protocol::server::char_list::{build_delete_char_ok, DELETE_CHAR_AFTER_7DAYS, DELETE_CHAR_NOW};
+
+    let (Some(pool), Some(account)) = (session.db.clone(), session.account_name.clone()) else {
+        return Ok(());
+    };
+    let Some(state) = char_delete::load_delete_state(&pool, &account, char_name).await? else {
+        warn!("Delete for unknown character {} on {}", char_name, account);
+        return Ok(());
+    };
+
+    if state.deadline.is_some() {
+        return handle_restore_char(session, &pool, &state).await;
+    }
+
+    let code = match char_delete::plan_delete(state.level, unix_now()) {
+        DeleteDecision::Immediate => {
+            char_delete::delete_character(&pool, state.objid).await?;
+            info!("Character deleted: {}", char_name);
+            DELETE_CHAR_NOW
+        }
+        DeleteDecision::Scheduled { deadline } => {
+            char_delete::set_delete_time(&pool, state.objid, Some(deadline)).await?;
+            info!("Character {} scheduled for deletion", char_name);
+            DELETE_CHAR_AFTER_7DAYS
+        }
+    };
+    session.send_packet(&build_delete_char_ok(code)).await
+}
+
+/// Restore a character pending deletion (C_RESTORECHAR). The 3.80c client
+/// sends this as a second C_DELETECHAR on the pending character.
+async fn handle_restore_char(
+    session: &mut Session,
+    pool: &MySqlPool,
+    state: &crate::db::char_delete::DeleteState,
+) -> Result<()> {
+    use crate::db::char_delete;
+
+    // A restore past the deadline is refused and changes nothing; the
+    // character goes with the next `purge_expired`
+    if let Err(e) = char_delete::check_restore(state.deadline, unix_now()) {
+        warn!("Restore of {} rejected: {:?}", state.objid, e);
+        return Ok(());
+    }
+    char_delete::set_delete_time(pool, state.objid, None).await?;
+    info!("Character {} restored", state.objid);
+    send_char_list(session).await
+}
+
+/// Queue the character for the next batched autosave (`network::autosave`)
+/// if it moved since it was last queued or saved. Play time stays on the
+/// timer until then, or until logout.
+async fn queue_autosave(session: &mut Session) {
+    if session.db.is_none() || session.char_name.is_none() {
+        return;
+    }
+    let now = session.snapshot();
+    if session.last_saved == Some(now) || session.last_queued == Some(now) {
+        return;
+    }
+    let save = crate::db::character::CharSave {
+        objid: session.char_objid,
+        x: session.char_x,
+        y: session.char_y,
+        map_id: session.char_map,
+        heading: session.char_heading,
+        played_secs: session.play_timer.take_elapsed(std::time::Instant::now()),
+    };
+    session.world.autosaves.mark(save).await;
+    session.last_queued = Some(now);
+    // The batch writes behind our back (or may fail), so what the DB
+    // holds is unknown until the next direct save
+    session.last_saved = None;
+}
+
+/// Save the character right away (logout, character change), taking
+/// over anything it still had queued for the batched autosave.
+///
+/// Position and play time are separate writes; either can fail without
+/// losing the other.
+async fn save_character(session: &mut Session) {
+    let world = session.world.clone();
+    let (_writing, queued) = world.autosaves.begin_direct_save(session.char_objid).await;
+    session.last_queued = None;
+    let played = session.play_timer.take_elapsed(std::time::Instant::now())
+        + queued.map_or(0, |q| q.played_secs);
+    let (Some(pool), Some(name)) = (session.db.clone(), session.char_name.clone()) else { return };
+    let now = session.snapshot();
+    match crate::db::character::save_position(&pool, session.char_objid, &now, session.last_saved.as_ref()).await {
+        Ok(wrote) => {
+            session.last_saved = Some(now);
+            if wrote {
+                info!("Character saved: {} at ({},{} map={})",
+                    name, session.char_x, session.char_y, session.char_map);
+            }
+        }
+        Err(e) => warn!("Failed to save character {}: {}", name, e),
+    }
+    if played > 0 {
+        if let Err(e) = crate::db::character::add_played_seconds(&pool, session.char_objid, played).await {
+            warn!("Failed to add play time for {}: {}", name, e);
+        }
+    }
+}
+
+/// Save the character's persistent buffs at logout (`game.persist_buffs`).
+async fn save_buffs(session: &Session) {
+    if !session.config.game.persist_buffs {
+        return;
+    }
+    let Some(pool) = &session.db else { return };
+    let buffs = crate::db::buff::buffs_to_save(&session.skill_effects);
+    if let Err(e) = crate::db::buff::save_buffs(pool, session.char_objid, &buffs, unix_now()).await {
+        warn!("Failed to save buffs for {}: {}", session.char_objid, e);
+    }
+}
+
+/// Speed icons for haste/brave carried over from the last session, so
+/// the client runs at the restored speed.
+fn speed_buff_packets(objid: i32, effects: &SkillEffects) -> Vec<Vec<u8>> {
+    use crate::ecs::components::skill::TICK_MS;
+    use crate::protocol::server::skill::{build_skill_brave, build_skill_haste};
+
+    let secs = |skill_id| {
+        effects.effects.get(&skill_id).map(|e| (e.remaining_ticks as u128 * TICK_MS / 1000).min(u16::MAX as u128) as i32)
+    };
+    let mut packets = Vec::new();
+    if let Some(s) = secs(skill_ids::STATUS_HASTE).filter(|&s| s > 0) {
+        packets.push(build_skill_haste(objid, 1, s));
+    }
+    if let Some(s) = secs(skill_ids::STATUS_BRAVE).filter(|&s| s > 0) {
+        packets.push(build_skill_brave(objid, 1, s));
+    }
+    packets
+}
+
+/// Buffs saved at the character's last logout, minus the time away.
+async fn load_saved_buffs(session: &Session, objid: i32) -> SkillEffects {
+    let Some(pool) = session.db.as_ref().filter(|_| session.config.game.persist_buffs) else {
+        return SkillEffects::new();
+    };
+    match crate::db::buff::take_buffs(pool, objid, unix_now()).await {
+        Ok((buffs, offline_secs)) => crate::db::buff::restore_buffs(&buffs, offline_secs),
+        Err(e) => {
+            warn!("Failed to load saved buffs for {}: {}", objid, e);
+            SkillEffects::new()
+        }
+    }
+}
+
+/// Cleanup when session ends: remove from world, save character, set account offline.
+async fn cleanup_session(session: &mut Session) {
+    let in_world = session.state == SessionState::InGame && session.char_objid != 0;
+    let grace = session.config.server.reconnect_grace_secs;
+
+    // Dropped (not quit): keep the character in the world for a while
+    if in_world && !session.quitting && grace > 0 && session.account_name.is_some() {
+        save_character(session).await;
+        park_for_reconnect(session, std::time::Duration::from_secs(grace)).await;
+        // The reconnect timer clears the flag if nobody comes back
+        session.marked_online = false;
+        return;
+    }
+
+    // Remove from shared world + broadcast removal to nearby players
+    if in_world {
+        leave_world(&session.world, session.char_map, session.char_x, session.char_y, session.char_objid).await;
+        save_character(session).await;
+        save_buffs(session).await;
+    }
+
+    // Set account offline
+    if let (Some(pool), Some(account)) = (&session.db, &session.account_name) {
+        let _ = crate::db::account::set_offline(pool, account).await;
+        info!("Account set offline: {}", account);
+    }
+    session.marked_online = false;
+}
+
+/// Remove a character from the world and tell nearby players.
+async fn leave_world(world: &SharedWorld, map_id: i32, x: i32, y: i32, objid: i32) {
+    let remove_pkt = crate::protocol::server::npc_pack::build_remove_object(objid as u32);
+    world.lock_map(map_id).await.broadcast_to_nearby(
+        map_id, x, y, objid, broadcast_range::SCREEN, &remove_pkt,
+    );
+    world.remove_player(map_id, objid).await;
+    metrics::global().player_left();
+}
+
+/// Park a dropped session and schedule the real logout for when the
+/// reconnect window closes.
+async fn park_for_reconnect(session: &mut Session, grace: std::time::Duration) {
+    use crate::network::reconnect::ResumeState;
+
+    let Some(account) = session.account_name.clone() else { return };
+    let state = ResumeState {
+        char_name: session.char_name.clone().unwrap_or_default(),
+        objid: session.char_objid,
+        map_id: session.char_map,
+        x: session.char_x,
+        y: session.char_y,
+        heading: session.char_heading,
+        skills: ParkedSkills::park(
+            std::mem::replace(&mut session.skill_cooldowns, SkillCooldowns::new()),
+            std::mem::replace(&mut session.skill_effects, SkillEffects::new()),
+        ),
+    };
+    let token = session.world.reconnects.lock().await
+        .park(&account, state, std::time::Instant::now(), grace);
+    info!("{} dropped, holding for {:?}", account, grace);
+
+    let world = session.world.clone();
+    let db = session.db.clone();
+    let persist_buffs = session.config.game.persist_buffs;
+    tokio::spawn(async move {
+        tokio::time::sleep(grace).await;
+        let expired = world.reconnects.lock().await.expire(&account, token);
+        if let Some(st) = expired {
+            leave_world(&world, st.map_id, st.x, st.y, st.objid).await;
+            if let Some(pool) = &db {
+                if persist_buffs {
+                    let (_, effects) = st.skills.restore();
+                    let buffs = crate::db::buff::buffs_to_save(&effects);
+                    if let Err(e) = crate::db::buff::save_buffs(pool, st.objid, &buffs, unix_now()).await {
+                        warn!("Failed to save buffs for {}: {}", st.objid, e);
+                    }
+                }
+                let _ = crate::db::account::set_offline(pool, &account).await;
+            }
+            info!("Reconnect window closed, {} logged out", account);
+        }
+    });
+}
+
+/// Build S_CHARPACK for a player (so other players can see them).
+pub(crate) fn build_player_charpack(p: &OnlinePlayer) -> Vec<u8> {
+    use crate::protocol::packet::PacketBuilder;
+    use crate::protocol::opcodes::server;
+
+    PacketBuilder::new(server::S_OPCODE_CHARPACK)
+        .write_h(p.x)
+        .write_h(p.y)
+        .write_d(p.object_id)
+        .write_h(p.gfx_id)
+        .write_c(0)              // weapon
+        .write_c(p.heading)
+        .write_c(0)              // light
         .write_c(0)              // speed
         .write_d(1)              // exp
         .write_h(p.lawful)
@@ -655,6 +1817,231 @@ fn build_player_charpack(p: &OnlinePlayer) -> Vec<u8> {
         .build()
 }
 
+/// Learn skills bought from a magic NPC (common magic, paid in adena).
+async fn handle_learn_skill(session: &mut Session, data: &[u8]) -> Result<()> {
+    use crate::ecs::skill_learning::{check_learn, LearnCost, LearnRequest};
+
+    let pool = match &session.db {
+        Some(p) => p.clone(),
+        None => return Ok(()),
+    };
+    let adena_id = crate::ecs::components::clan::ADENA_ITEM_ID;
+
+    for skill_id in crate::protocol::client::skill::parse_learn_skill(data) {
+        let adena = crate::db::inventory::count_item(&pool, session.char_objid, adena_id).await?;
+        let req = LearnRequest {
+            skill_id,
+            class_type: session.char_type,
+            level: session.char_level,
+            learned: &session.learned_skills,
+            adena,
+            has_spellbook: false,
+        };
+        match check_learn(&req) {
+            Ok(LearnCost::Adena(price)) => {
+                let Some((name, skill_number)) =
+                    crate::data::skill_table::load_skill_entry(&pool, skill_id).await?
+                else {
+                    warn!("Skill {} has no row in the skills table", skill_id);
+                    continue;
+                };
+                if !crate::db::skill::buy_skill(&pool, session.char_objid, skill_id, &name, adena_id, price).await? {
+                    continue;
+                }
+                session.learned_skills.push(skill_id);
+                let pkt = crate::protocol::server::skill::build_add_skill(skill_number);
+                session.send_packet(&pkt).await?;
+                info!("{:?} learned skill {} for {} adena", session.char_name, skill_id, price);
+            }
+            Ok(LearnCost::Spellbook) => {
+                // Class skills are learned by using the spellbook item
+            }
+            Err(e) => {
+                debug!("Skill {} learn rejected: {:?}", skill_id, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Seconds between checks for expired (event/rental) items.
+const ITEM_EXPIRY_SWEEP_SECS: u64 = 60;
+
+/// Remove the character's expired items and tell the player.
+async fn sweep_expired_items(session: &mut Session) -> Result<()> {
+    let Some(pool) = session.db.clone() else { return Ok(()) };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let expired = crate::db::inventory::remove_expired(&pool, session.char_objid, now).await?;
+    for (item, name) in &expired {
+        let pkt = crate::protocol::server::inventory::build_delete_inventory_item(item.object_id);
+        session.send_packet(&pkt).await?;
+        let pkt = crate::protocol::server::chat::build_server_message(
+            &format!("物品使用期限已到，已自動消失。({})", name),
+        );
+        session.send_packet(&pkt).await?;
+    }
+    if expired.iter().any(|(item, _)| item.is_equipped) {
+        send_equipped_status(session, &pool).await?;
+    }
+    if !expired.is_empty() {
+        info!("{:?}: {} expired item(s) removed", session.char_name, expired.len());
+    }
+    Ok(())
+}
+
+/// Re-send the character's status with the bonuses of what is still
+/// equipped, after equipment was taken away.
+async fn send_equipped_status(session: &mut Session, pool: &MySqlPool) -> Result<()> {
+    use crate::ecs::components::item::Inventory;
+
+    let (Some(name), Some(account)) = (session.char_name.clone(), session.account_name.clone()) else {
+        return Ok(());
+    };
+    let Some(ch) = crate::db::character::load_character(pool, &name, &account).await? else {
+        return Ok(());
+    };
+    let mut inv = Inventory::new();
+    inv.items = crate::db::inventory::load_items(pool, ch.objid).await?;
+    let equipped: Vec<i32> = inv.get_equipped().iter().map(|i| i.item_id).collect();
+    let templates = crate::data::item_table::load_item_templates_for(pool, &equipped).await?;
+    let bonus = inv.equipment_stats(&templates);
+    session.weapon_type = wielded_weapon_type(&equipped, &templates);
+
+    let status = crate::protocol::server::char_list::build_own_char_status(&with_equipment(ch, &bonus));
+    let spmr = crate::protocol::server::game_init::build_spmr(bonus.sp, bonus.mr);
+    session.send_packets(&[status, spmr]).await
+}
+
+/// Weapon `type` of the wielded weapon, if any.
+async fn load_weapon_type(pool: &MySqlPool, char_id: i32) -> Result<Option<i32>> {
+    let items = crate::db::inventory::load_items(pool, char_id).await?;
+    let equipped: Vec<i32> = items.iter().filter(|i| i.is_equipped).map(|i| i.item_id).collect();
+    let templates = crate::data::item_table::load_item_templates_for(pool, &equipped).await?;
+    Ok(wielded_weapon_type(&equipped, &templates))
+}
+
+fn wielded_weapon_type(
+    equipped: &[i32],
+    templates: &HashMap<i32, crate::ecs::components::item::ItemTemplate>,
+) -> Option<i32> {
+    equipped
+        .iter()
+        .filter_map(|id| templates.get(id))
+        .find(|t| t.type2 == crate::ecs::components::item::ItemType2::Weapon)
+        .map(|t| t.item_type)
+}
+
+/// Base character stats plus equipment bonuses, as S_OWNCHARSTATUS shows them.
+fn with_equipment(
+    mut ch: crate::db::character::CharacterFullData,
+    bonus: &crate::ecs::components::item::EquipmentStats,
+) -> crate::db::character::CharacterFullData {
+    ch.ac += bonus.ac;
+    ch.str_stat += bonus.str_stat;
+    ch.dex_stat += bonus.dex_stat;
+    ch.con_stat += bonus.con_stat;
+    ch.int_stat += bonus.int_stat;
+    ch.wis_stat += bonus.wis_stat;
+    ch.cha_stat += bonus.cha_stat;
+    ch.max_hp += bonus.hp;
+    ch.max_mp += bonus.mp;
+    ch.cur_hp = ch.cur_hp.min(ch.max_hp);
+    ch.cur_mp = ch.cur_mp.min(ch.max_mp);
+    ch
+}
+
+/// Open an NPC's dialogue. Only the vulcan smith has one so far.
+async fn handle_npc_talk(session: &mut Session, data: &[u8]) -> Result<()> {
+    let objid = crate::protocol::client::action::parse_npc_talk(data);
+    if !crate::ecs::vulcan::is_near_vulcan(session.char_map, session.char_x, session.char_y) {
+        return Ok(());
+    }
+    let pool = match &session.db {
+        Some(p) => p.clone(),
+        None => return Ok(()),
+    };
+    let items = crate::db::inventory::load_items(&pool, session.char_objid).await?;
+    let data = session.world.game_data.get();
+    let html = crate::ecs::vulcan::menu_html(&items, &data.vulcan_recipes);
+    let pkt = crate::protocol::server::npc_dialog::build_show_html(objid, &html);
+    session.send_packet(&pkt).await
+}
+
+/// A link picked in an NPC dialogue.
+async fn handle_npc_action(session: &mut Session, data: &[u8]) -> Result<()> {
+    let req = crate::protocol::client::action::parse_npc_action(data);
+    if !crate::ecs::vulcan::is_near_vulcan(session.char_map, session.char_x, session.char_y) {
+        return Ok(());
+    }
+    match crate::ecs::vulcan::parse_action(&req.action) {
+        Some(action) => handle_vulcan_action(session, action).await,
+        None => {
+            debug!("Unknown NPC action {:?} from {:?}", req.action, session.char_name);
+            Ok(())
+        }
+    }
+}
+
+/// Smelt or craft at the vulcan smith, against the stored inventory.
+///
+/// The action runs on a copy of the stored inventory; the difference is
+/// written in one transaction and then shown to the client.
+async fn handle_vulcan_action(session: &mut Session, action: crate::ecs::vulcan::VulcanAction) -> Result<()> {
+    use crate::db::inventory as inv;
+    use crate::ecs::components::item::Inventory;
+    use crate::ecs::vulcan::{self, VulcanAction};
+
+    let pool = match &session.db {
+        Some(p) => p.clone(),
+        None => return Ok(()),
+    };
+    let char_id = session.char_objid;
+    let now = unix_now();
+
+    if let VulcanAction::Craft(_) = action {
+        let limits = session.config.game.craft_limits();
+        let allowed = session.world.crafts.lock().await.check(char_id, now, limits);
+        if let Err(e) = allowed {
+            let pkt = crate::protocol::server::chat::build_server_message(e.message());
+            return session.send_packet(&pkt).await;
+        }
+    }
+
+    let mut before = Inventory::new();
+    before.items = inv::load_items(&pool, char_id).await?;
+    let mut after = before.clone();
+    // No luck stat or blessing buff is tracked on the session yet
+    let luck = 0;
+    let data = session.world.game_data.get();
+    let reply = match vulcan::run_action(&mut after, action, &data.vulcan_recipes, vulcan::PENDING_OBJECT_ID, luck) {
+        Ok(reply) => {
+            let mut changes = after.changes_since(&before);
+            // Only an action that opens a new stack needs an object ID
+            for item in &mut changes.added {
+                item.object_id = inv::next_item_id(&pool).await? as u32;
+            }
+            let templates = crate::data::item_table::load_item_templates_for(&pool, &changes.item_ids()).await?;
+            if !inv::apply_changes(&pool, char_id, &changes, &templates).await? {
+                // The stored inventory moved under us; nothing was written
+                return Ok(());
+            }
+            if let VulcanAction::Craft(_) = action {
+                session.world.crafts.lock().await.record(char_id, now);
+            }
+            let packets = crate::protocol::server::inventory::build_inventory_changes(&changes, &templates);
+            session.send_packets(&packets).await?;
+            info!("{:?} at the vulcan smith: {}", session.char_name, reply);
+            reply
+        }
+        Err(e) => e.message().to_string(),
+    };
+    let pkt = crate::protocol::server::chat::build_server_message(&reply);
+    session.send_packet(&pkt).await
+}
+
 async fn handle_create_char(session: &mut Session, data: &[u8]) -> Result<()> {
     let nc = crate::protocol::client::char_create::parse_new_char(data);
     info!("Creating character: name={}, type={}, sex={}", nc.name, nc.char_type, nc.sex);
@@ -668,8 +2055,24 @@ async fn handle_create_char(session: &mut Session, data: &[u8]) -> Result<()> {
         None => return Ok(()),
     };
 
+    // Check slot limit (default + purchased slots)
+    let existing = crate::db::character::count_characters(pool, &account).await?;
+    let extra_slots = crate::db::account::load_account(pool, &account)
+        .await?
+        .map(|a| a.character_slot)
+        .unwrap_or(0);
+    if !crate::protocol::client::char_create::has_free_slot(existing, extra_slots) {
+        info!("Character slots full for {}: {} existing", account, existing);
+        let pkt = crate::protocol::server::char_create::build_char_create_status(
+            crate::protocol::server::char_create::REASON_WRONG_AMOUNT,
+        );
+        session.send_packet(&pkt).await?;
+        return Ok(());
+    }
+
     // Validate name
-    if nc.name.is_empty() || nc.name.len() > 16 {
+    if !crate::protocol::client::char_create::validate_name(&nc.name) {
+        info!("Invalid character name: {}", nc.name);
         let pkt = crate::protocol::server::char_create::build_char_create_status(
             crate::protocol::server::char_create::REASON_INVALID_NAME,
         );
@@ -704,9 +2107,11 @@ async fn handle_create_char(session: &mut Session, data: &[u8]) -> Result<()> {
         .as_millis() & 0x7FFFFFFF) as i32;
 
     // Create in database
-    match crate::db::char_create::create_character(pool, &account, &nc, objid).await {
+    let start = session.config.start_locations.for_class(nc.char_type);
+    match crate::db::char_create::create_character(pool, &account, &nc, objid, start).await {
         Ok(_) => {
             info!("Character created: {} (objid={})", nc.name, objid);
+            audit::record(&account, AuditAction::ItemGrant, &format!("starter kit to {}", nc.name));
 
             let pkt = crate::protocol::server::char_create::build_char_create_status(
                 crate::protocol::server::char_create::REASON_OK,
@@ -725,12 +2130,325 @@ async fn handle_create_char(session: &mut Session, data: &[u8]) -> Result<()> {
             send_char_list(session).await?;
         }
         Err(e) => {
-            warn!("Failed to create character: {}", e);
-            let pkt = crate::protocol::server::char_create::build_char_create_status(
-                crate::protocol::server::char_create::REASON_INVALID_NAME,
-            );
+            warn!("Failed to create character {}: {}", nc.name, e);
+            let pkt = crate::protocol::server::char_create::build_char_create_status(e.status());
             session.send_packet(&pkt).await?;
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::shared_state::create_shared_world;
+    use crate::protocol::packet::PacketBuilder;
+    use crate::protocol::server::login;
+    use tokio::io::DuplexStream;
+
+    fn test_config(server_extra: &str) -> ServerConfig {
+        toml::from_str(&format!(
+            r#"
+            [server]
+            host = "127.0.0.1"
+            port = 7000
+            max_online_users = 10
+            {server_extra}
+
+            [database]
+            url = "mysql://root@localhost/l1jtw"
+            max_connections = 1
+
+            [game]
+            tick_interval_ms = 200
+            npc_ai_sleep_range = 30
+            packet_batch_flush = true
+            "#
+        ))
+        .unwrap()
+    }
+
+    /// A session on one end of an in-memory pipe; the test plays the
+    /// client on the other end.
+    fn mock_session(server_extra: &str) -> (Session, DuplexStream) {
+        let (server, client) = tokio::io::duplex(64 * 1024);
+        let session = Session::new(
+            server, test_config(server_extra), None, "127.0.0.1".to_string(), create_shared_world(),
+        );
+        (session, client)
+    }
+
+    /// Send `packet` from the client, then read and dispatch it like the
+    /// session loop does.
+    async fn feed(session: &mut Session, client: &mut DuplexStream, packet: &[u8]) -> Option<Result<()>> {
+        client.write_all(&codec::encode_frame(packet)).await.unwrap();
+        let data = session.read_packet().await.unwrap();
+        assert_eq!(data, packet);
+        handlers(session.state).dispatch(session, &data).await
+    }
+
+    /// Everything the session wrote, once it is gone.
+    async fn outbound(session: Session, mut client: DuplexStream) -> Vec<u8> {
+        drop(session);
+        let mut sent = Vec::new();
+        client.read_to_end(&mut sent).await.unwrap();
+        sent
+    }
+
+    fn version_packet(language: i32, version: u32) -> Vec<u8> {
+        PacketBuilder::new(opcodes::client::C_CLIENTVERSION)
+            .write_h(0)
+            .write_c(0)
+            .write_d(language)
+            .write_h(0)
+            .write_h(0)
+            .write_d(version as i32)
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_frames_of_any_length_decrypt() {
+        let (mut session, mut client) = mock_session("");
+        session.cipher = Some(Cipher::new(0x1234_5678));
+        let mut client_cipher = Cipher::new(0x1234_5678);
+
+        // 8 bytes, then 6 (not a multiple of 4)
+        for packet in [
+            vec![opcodes::client::C_CLIENTVERSION, 1, 2, 3, 4, 5, 6, 7],
+            vec![opcodes::client::C_CLIENTVERSION, 1, 2, 3, 4, 5],
+        ] {
+            let mut body = packet.clone();
+            client_cipher.encrypt(&mut body);
+            client.write_all(&codec::encode_frame(&body)).await.unwrap();
+            assert_eq!(session.read_packet().await.unwrap(), packet);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_short_encrypted_frame_rejected() {
+        let (mut session, mut client) = mock_session("");
+        session.cipher = Some(Cipher::new(0x1234_5678));
+
+        client.write_all(&codec::encode_frame(&[1, 2])).await.unwrap();
+        let err = session.read_packet().await.unwrap_err();
+        assert!(err.to_string().contains("too short"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_client_version_accepted() {
+        let (mut session, mut client) = mock_session("");
+        feed(&mut session, &mut client, &version_packet(3, 0x07cb_f4dd)).await.unwrap().unwrap();
+        assert_eq!(session.state, SessionState::VersionVerified);
+
+        let expected = codec::seal_frame(&login::build_server_version(session.server_start_time), None);
+        assert_eq!(outbound(session, client).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_client_version_rejected() {
+        let (mut session, mut client) = mock_session("allowed_client_versions = [1]");
+        assert!(feed(&mut session, &mut client, &version_packet(3, 0x07cb_f4dd)).await.unwrap().is_err());
+        assert_eq!(session.state, SessionState::Connected);
+
+        let expected = codec::seal_frame(&login::build_server_version_rejected(), None);
+        assert_eq!(outbound(session, client).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_login_without_database() {
+        let (mut session, mut client) = mock_session("");
+        session.state = SessionState::VersionVerified;
+        let packet = PacketBuilder::new(opcodes::client::C_LOGINPACKET)
+            .write_s(Some("Alice"))
+            .write_s(Some("secret"))
+            .build();
+        feed(&mut session, &mut client, &packet).await.unwrap().unwrap();
+        assert_eq!(session.state, SessionState::VersionVerified);
+        assert!(session.account_name.is_none());
+
+        let expected = codec::seal_frame(&login::build_login_result(login::REASON_ACCESS_FAILED), None);
+        assert_eq!(outbound(session, client).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_unexpected_opcode_is_ignored() {
+        let (mut session, mut client) = mock_session("");
+        let packet = PacketBuilder::new(opcodes::client::C_MOVECHAR).write_h(0).build();
+        assert!(feed(&mut session, &mut client, &packet).await.is_none());
+        assert_eq!(session.state, SessionState::Connected);
+        assert!(outbound(session, client).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_uses_configured_version() {
+        use crate::protocol::server::init::{build_init_packet, first_packet};
+
+        let (mut session, client) = mock_session(r#"protocol_version = "3.80c-tw""#);
+        let key = session.send_handshake().await.unwrap();
+        let expected = codec::encode_frame(&build_init_packet(key, first_packet("3.80c-tw").unwrap()));
+        assert_eq!(outbound(session, client).await, expected);
+
+        let (mut session, _client) = mock_session(r#"protocol_version = "9.99-xx""#);
+        assert!(session.send_handshake().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_change_char_leaves_groups() {
+        use crate::network::shared_state::OnlinePlayer;
+
+        let (mut session, mut client) = mock_session("");
+        session.state = SessionState::InGame;
+        (session.char_objid, session.char_map, session.char_x, session.char_y) = (1, 4, 32800, 32800);
+        let me = OnlinePlayer {
+            object_id: 1, name: "Alice".into(), x: 32800, y: 32800, map_id: 4, heading: 0,
+            gfx_id: 61, level: 1, lawful: 0, char_type: 1, sex: 0,
+            clan_name: "Knights".into(), title: String::new(), cur_hp: 50, max_hp: 50,
+            is_dead: false, move_seq: 0, packet_tx: session.packet_tx.clone(),
+        };
+        session.world.lock_map(4).await.add_player(me);
+        session.world.groups.lock().await.join_clan(1, "Knights", session.packet_tx.clone());
+        assert_eq!(session.world.broadcast_to_clan("Knights", 0, b"hi").await, 1);
+
+        let packet = PacketBuilder::new(opcodes::client::C_CHANGECHAR).build();
+        feed(&mut session, &mut client, &packet).await.unwrap().unwrap();
+        assert_eq!(session.state, SessionState::Authenticated);
+        assert_eq!(session.world.broadcast_to_clan("Knights", 0, b"hi").await, 0);
+        assert!(session.world.find_by_name("Alice").await.is_none());
+        assert!(session.world.find_by_id(1).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_escape_skill_returns_to_town() {
+        use crate::network::shared_state::OnlinePlayer;
+        use crate::protocol::client::skill::SKILL_TELEPORT;
+
+        let (mut session, mut client) = mock_session("");
+        session.state = SessionState::InGame;
+        (session.char_objid, session.char_map, session.char_x, session.char_y) = (1, 4, 33060, 32790);
+        session.learned_skills.push(SKILL_TELEPORT);
+        let me = OnlinePlayer {
+            object_id: 1, name: "Alice".into(), x: 33060, y: 32790, map_id: 4, heading: 0,
+            gfx_id: 61, level: 1, lawful: 0, char_type: 1, sex: 0,
+            clan_name: String::new(), title: String::new(), cur_hp: 50, max_hp: 50,
+            is_dead: false, move_seq: 0, packet_tx: session.packet_tx.clone(),
+        };
+        session.world.lock_map(4).await.add_player(me);
+
+        // Teleport with no bookmark selected: row 0, column 4, map 0, bookmark 0
+        let packet = PacketBuilder::new(opcodes::client::C_USESKILL)
+            .write_c(0)
+            .write_c(4)
+            .write_h(0)
+            .write_d(0)
+            .build();
+        feed(&mut session, &mut client, &packet).await.unwrap().unwrap();
+        let town = crate::ecs::recall::escape_destination(4, 33060, 32790);
+        assert_eq!(town.name, "肯特村");
+        assert_eq!((session.char_x, session.char_y, session.char_map), (town.x, town.y, town.map_id));
+        let moved = session.world.find_by_id(1).await.unwrap();
+        assert_eq!((moved.x, moved.y), (town.x, town.y));
+    }
+
+    #[tokio::test]
+    async fn test_trade_request_and_cancel() {
+        use crate::ecs::trade::MSG_TRADE_REQUEST;
+        use crate::network::shared_state::OnlinePlayer;
+        use crate::protocol::server::npc_dialog::build_yes_no_with_args;
+        use crate::protocol::server::trade::{build_trade, build_trade_status, TRADE_CANCEL};
+
+        let (mut session, mut client) = mock_session("");
+        session.state = SessionState::InGame;
+        session.char_name = Some("Bob".into());
+        (session.char_objid, session.char_map, session.char_x, session.char_y, session.char_heading) =
+            (2, 4, 32800, 32800, 2);
+        let (alice_tx, mut alice_rx) = tokio::sync::mpsc::unbounded_channel();
+        let player = |object_id, name: &str, x, packet_tx| OnlinePlayer {
+            object_id, name: name.into(), x, y: 32800, map_id: 4, heading: 6,
+            gfx_id: 61, level: 1, lawful: 0, char_type: 1, sex: 0,
+            clan_name: String::new(), title: String::new(), cur_hp: 50, max_hp: 50,
+            is_dead: false, move_seq: 0, packet_tx,
+        };
+        {
+            let mut map = session.world.lock_map(4).await;
+            map.add_player(player(1, "Alice", 32801, alice_tx));
+            map.add_player(player(2, "Bob", 32800, session.packet_tx.clone()));
+        }
+
+        // Facing east, Bob asks Alice, who declines
+        let packet = PacketBuilder::new(opcodes::client::C_TRADE).build();
+        feed(&mut session, &mut client, &packet).await.unwrap().unwrap();
+        let asked = alice_rx.try_recv().unwrap();
+        assert_eq!(asked, build_yes_no_with_args(MSG_TRADE_REQUEST, 1, &["Bob"]));
+        assert_eq!(session.world.trades.lock().await.respond(1, 1, false), None);
+
+        // Alice asks Bob, who accepts: both windows open
+        let yes_no_id = session.world.trades.lock().await.request(1, 2).unwrap();
+        let packet = PacketBuilder::new(opcodes::client::C_ATTR)
+            .write_h(yes_no_id)
+            .write_d(MSG_TRADE_REQUEST)
+            .write_c(1)
+            .build();
+        feed(&mut session, &mut client, &packet).await.unwrap().unwrap();
+        assert_eq!(alice_rx.try_recv().unwrap(), build_trade("Bob"));
+        assert!(session.world.trades.lock().await.get_mut(2).is_some());
+
+        // Cancelling closes both windows
+        let packet = PacketBuilder::new(opcodes::client::C_TRADEADDCANCEL).build();
+        feed(&mut session, &mut client, &packet).await.unwrap().unwrap();
+        assert_eq!(alice_rx.try_recv().unwrap(), build_trade_status(TRADE_CANCEL));
+        assert!(session.world.trades.lock().await.get_mut(1).is_none());
+
+        let mut expected = codec::seal_frame(&build_trade("Alice"), None);
+        expected.extend(codec::seal_frame(&build_trade_status(TRADE_CANCEL), None));
+        assert_eq!(outbound(session, client).await, expected);
+    }
+
+    #[test]
+    fn test_trade_audit_lists_both_sides() {
+        use crate::ecs::components::item::ItemTemplate;
+        use crate::ecs::trade::{Trade, TradeOffer};
+
+        let mut trade = Trade::new(1, 2);
+        trade.a.offers.push(TradeOffer { object_id: 10, item_id: 48, count: 1 });
+        trade.b.offers.push(TradeOffer { object_id: 20, item_id: 40308, count: 600 });
+        trade.b.offers.push(TradeOffer { object_id: 21, item_id: 40010, count: 3 });
+        let templates = HashMap::from([
+            (48, ItemTemplate { item_id: 48, name: "雙手劍".into(), ..Default::default() }),
+            (40308, ItemTemplate { item_id: 40308, name: "金幣".into(), ..Default::default() }),
+        ]);
+
+        assert_eq!(
+            trade_audit_details(&trade, ("Alice", "Bob"), &templates),
+            "Alice(1) -> Bob: [雙手劍(48) x1 #10]; Bob(2) -> Alice: [金幣(40308) x600 #20, (40010) x3 #21]",
+        );
+    }
+
+    #[test]
+    fn test_status_adds_remaining_equipment() {
+        use crate::db::character::CharacterFullData;
+        use crate::ecs::components::item::EquipmentStats;
+
+        // Saved while a +20 HP rental was still worn; only -2 AC gear is left
+        let ch = CharacterFullData { ac: 10, str_stat: 12, cur_hp: 70, max_hp: 50, ..Default::default() };
+        let shown = with_equipment(ch, &EquipmentStats { ac: -2, str_stat: 1, ..Default::default() });
+        assert_eq!((shown.ac, shown.str_stat, shown.max_hp, shown.cur_hp), (8, 13, 50, 50));
+    }
+
+    #[test]
+    fn test_restored_speed_buffs_are_shown() {
+        use crate::ecs::components::skill::TICK_MS;
+        use crate::protocol::opcodes::server;
+
+        let ticks = |secs: u128| (secs * 1000 / TICK_MS) as u32;
+        let mut effects = SkillEffects::new();
+        effects.add_effect(skill_ids::STATUS_HASTE, ticks(600), 0);
+        effects.add_effect(skill_ids::STATUS_BRAVE, ticks(120), 0);
+        effects.add_effect(skill_ids::SHIELD, ticks(60), 2);
+
+        let packets = speed_buff_packets(7, &effects);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0], crate::protocol::server::skill::build_skill_haste(7, 1, 600));
+        assert_eq!(packets[1][0], server::S_OPCODE_SKILLBRAVE);
+        assert!(speed_buff_packets(7, &SkillEffects::new()).is_empty());
+    }
+}
@@ -5,8 +5,20 @@
 /// and queries other players for visibility.
 
 use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::sync::{Arc, RwLock};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::data::dungeon_table::DungeonTable;
+use crate::data::game_data::GameDataStore;
+use crate::ecs::party::Parties;
+use crate::ecs::recall::RecallRequests;
+use crate::ecs::trade::Trades;
+use crate::ecs::vulcan::CraftLog;
+use crate::network::autosave::Autosaves;
+use crate::network::groups::{GroupIndex, PartyId};
+use crate::network::reconnect::ReconnectGrace;
+use crate::world::persist::PersistentState;
+use crate::world::grid::WorldGrid;
 
 /// A connected player visible in the game world.
 #[derive(Debug, Clone)]
@@ -24,71 +36,773 @@ pub struct OnlinePlayer {
     pub sex: i32,
     pub clan_name: String,
     pub title: String,
+    /// HP as of login, for party members' HP bars.
+    pub cur_hp: i32,
+    pub max_hp: i32,
+    /// Dead players wait for resurrection or restart.
+    pub is_dead: bool,
+    /// Sequence number of the last applied move.
+    pub move_seq: u32,
     /// Channel to send packets to this player's session.
     pub packet_tx: tokio::sync::mpsc::UnboundedSender<Vec<u8>>,
 }
 
-/// Shared state wrapped in Arc<Mutex> for cross-session access.
-pub type SharedWorld = Arc<Mutex<WorldState>>;
+/// One row of the online-player directory (`.who`, GM tools).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayerListing {
+    pub name: String,
+    pub level: i32,
+    pub map_id: i32,
+    pub clan_name: String,
+}
+
+impl From<&OnlinePlayer> for PlayerListing {
+    fn from(p: &OnlinePlayer) -> Self {
+        PlayerListing {
+            name: p.name.clone(),
+            level: p.level,
+            map_id: p.map_id,
+            clan_name: p.clan_name.clone(),
+        }
+    }
+}
+
+/// Broadcast radii (in tiles) for effects with different audible/visible ranges.
+pub mod broadcast_range {
+    /// Quiet effects (small sounds, emotes) only reach players right next to the source.
+    pub const QUIET: i32 = 8;
+    /// Normal screen range - movement, chat, appearance packets.
+    pub const SCREEN: i32 = 18;
+    /// Loud effects (explosions, shouts) carry past the edge of the screen.
+    pub const LOUD: i32 = 30;
+}
+
+/// Shared world handle for cross-session access.
+pub type SharedWorld = Arc<World>;
+
+/// Guard over one map's shard.
+pub type MapGuard = OwnedMutexGuard<WorldState>;
+
+/// The game world, sharded by map ID.
+///
+/// Each map has its own lock, so movement and chat on different maps
+/// never contend. Operations that touch two maps (teleport) must use
+/// `lock_pair`, which always locks the lower map ID first to avoid
+/// deadlock. Never hold a map guard while locking another map directly.
+pub struct World {
+    shards: RwLock<HashMap<i32, Arc<Mutex<WorldState>>>>,
+    /// Cell size, in tiles, of each map's player grid.
+    grid_cell_size: i32,
+    /// Summons waiting for the target's consent (cross-map).
+    pub recalls: Mutex<RecallRequests>,
+    /// Dropped sessions inside their reconnect window.
+    pub reconnects: Mutex<ReconnectGrace>,
+    /// Recent vulcan crafts per character, for the pace limits.
+    pub crafts: Mutex<CraftLog>,
+    /// Gameplay data swapped in by `.reload`.
+    pub game_data: GameDataStore,
+    /// Portal tiles (from the `dungeon` table).
+    pub dungeons: std::sync::RwLock<DungeonTable>,
+    /// Characters queued for the next batched autosave.
+    pub autosaves: Autosaves,
+    /// Castle door states kept across restarts.
+    pub persistent: std::sync::Mutex<PersistentState>,
+    /// Online clan and party members, for cross-map group broadcasts.
+    pub groups: Mutex<GroupIndex>,
+    /// Party rosters and invitations waiting for an answer.
+    pub parties: Mutex<Parties>,
+    /// Open trade windows and requests waiting for an answer.
+    pub trades: Mutex<Trades>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::with_grid_cell_size(crate::world::grid::REGION_SIZE)
+    }
+
+    /// World whose map grids use `cell_size` x `cell_size` tile cells
+    /// (`game.grid_cell_size`).
+    pub fn with_grid_cell_size(cell_size: i32) -> Self {
+        World {
+            shards: RwLock::new(HashMap::new()),
+            grid_cell_size: cell_size,
+            recalls: Mutex::new(RecallRequests::new()),
+            reconnects: Mutex::new(ReconnectGrace::new()),
+            crafts: Mutex::new(CraftLog::new()),
+            game_data: GameDataStore::default(),
+            dungeons: std::sync::RwLock::new(DungeonTable::default()),
+            autosaves: Autosaves::new(),
+            persistent: std::sync::Mutex::new(PersistentState::new()),
+            groups: Mutex::new(GroupIndex::new()),
+            parties: Mutex::new(Parties::new()),
+            trades: Mutex::new(Trades::new()),
+        }
+    }
+
+    /// Get (or create) the shard for a map.
+    pub fn shard(&self, map_id: i32) -> Arc<Mutex<WorldState>> {
+        if let Some(shard) = self.shards.read().unwrap().get(&map_id) {
+            return shard.clone();
+        }
+        self.shards
+            .write()
+            .unwrap()
+            .entry(map_id)
+            .or_insert_with(|| Arc::new(Mutex::new(WorldState::with_grid_cell_size(self.grid_cell_size))))
+            .clone()
+    }
+
+    /// Snapshot of every shard, in map ID order.
+    pub fn shards(&self) -> Vec<Arc<Mutex<WorldState>>> {
+        let shards = self.shards.read().unwrap();
+        let mut ids: Vec<i32> = shards.keys().copied().collect();
+        ids.sort_unstable();
+        ids.iter().map(|id| shards[id].clone()).collect()
+    }
+
+    /// Lock one map.
+    pub async fn lock_map(&self, map_id: i32) -> MapGuard {
+        self.shard(map_id).lock_owned().await
+    }
+
+    /// Lock two maps in a consistent (ascending map ID) order.
+    ///
+    /// Returns guards in argument order; the second is `None` when both
+    /// IDs are the same map.
+    pub async fn lock_pair(&self, a: i32, b: i32) -> (MapGuard, Option<MapGuard>) {
+        if a == b {
+            return (self.lock_map(a).await, None);
+        }
+        let (lo, hi) = (a.min(b), a.max(b));
+        let lo_guard = self.lock_map(lo).await;
+        let hi_guard = self.lock_map(hi).await;
+        if a == lo {
+            (lo_guard, Some(hi_guard))
+        } else {
+            (hi_guard, Some(lo_guard))
+        }
+    }
+
+    /// Find an online player on any map by character name (any case).
+    ///
+    /// Locks shards one at a time; don't call while holding a map guard.
+    pub async fn find_by_name(&self, name: &str) -> Option<OnlinePlayer> {
+        for shard in self.shards() {
+            if let Some(p) = shard.lock().await.find_by_name(name) {
+                return Some(p.clone());
+            }
+        }
+        None
+    }
+
+    /// Find an online player on any map by object ID.
+    ///
+    /// Locks shards one at a time; don't call while holding a map guard.
+    pub async fn find_by_id(&self, object_id: i32) -> Option<OnlinePlayer> {
+        for shard in self.shards() {
+            if let Some(p) = shard.lock().await.get_player(object_id) {
+                return Some(p.clone());
+            }
+        }
+        None
+    }
+
+    /// Every online player on every map, sorted by name.
+    ///
+    /// Each shard is locked only long enough to copy its rows out, so
+    /// the caller can format and send the result with no lock held.
+    pub async fn online_players(&self) -> Vec<PlayerListing> {
+        let mut listings = Vec::new();
+        for shard in self.shards() {
+            listings.extend(shard.lock().await.listings());
+        }
+        listings.sort_by(|a, b| a.name.cmp(&b.name));
+        listings
+    }
+
+    /// Snapshot the packet channels of every online player on every map.
+    pub async fn all_senders(&self) -> Vec<tokio::sync::mpsc::UnboundedSender<Vec<u8>>> {
+        let mut senders = Vec::new();
+        for shard in self.shards() {
+            senders.extend(shard.lock().await.all_senders());
+        }
+        senders
+    }
+
+    /// Remove a player from a map and drop their pending recalls, trade,
+    /// party and group listings. A party left with one member is
+    /// dissolved; a trade partner sees the window cancelled.
+    pub async fn remove_player(&self, map_id: i32, object_id: i32) {
+        self.lock_map(map_id).await.remove_player(object_id);
+        self.recalls.lock().await.remove_player(object_id);
+        let trade = self.trades.lock().await.remove_player(object_id);
+        if let Some(partner) = trade.and_then(|t| t.partner_of(object_id)) {
+            if let Some(p) = self.find_by_id(partner).await {
+                use crate::protocol::server::trade::{build_trade_status, TRADE_CANCEL};
+                let _ = p.packet_tx.send(build_trade_status(TRADE_CANCEL));
+            }
+        }
+        let left_party = self.parties.lock().await.remove_player(object_id);
+        let mut groups = self.groups.lock().await;
+        for id in left_party {
+            groups.leave_party(id);
+        }
+        groups.remove_player(object_id);
+    }
+
+    /// Send `packet` to every online member of `clan` except `exclude_id`,
+    /// on any map. Returns how many it was queued for.
+    pub async fn broadcast_to_clan(&self, clan: &str, exclude_id: i32, packet: &[u8]) -> usize {
+        let senders = self.groups.lock().await.clan_senders(clan, exclude_id);
+        send_all(&senders, packet)
+    }
 
+    /// Send `packet` to every online member of `party` except `exclude_id`.
+    pub async fn broadcast_to_party(&self, party: PartyId, exclude_id: i32, packet: &[u8]) -> usize {
+        let senders = self.groups.lock().await.party_senders(party, exclude_id);
+        send_all(&senders, packet)
+    }
+}
+
+/// One map's players.
+///
+/// Players are indexed by region in a `WorldGrid` so nearby lookups only
+/// touch the surrounding cells. All position changes must go through the
+/// methods below to keep the grid in sync.
 pub struct WorldState {
-    /// All online players keyed by object_id.
-    pub players: HashMap<i32, OnlinePlayer>,
+    /// All online players on this map keyed by object_id.
+    players: HashMap<i32, OnlinePlayer>,
+    /// Region index over `players`.
+    grid: WorldGrid,
+    /// Lowercased character name -> object_id, for whisper/trade/party
+    /// lookups. Kept in step with `players` by the methods below.
+    by_name: HashMap<String, i32>,
+}
+
+/// Key for `WorldState::by_name`; character names match case-insensitively.
+fn name_key(name: &str) -> String {
+    name.to_lowercase()
 }
 
 impl WorldState {
     pub fn new() -> Self {
+        Self::with_grid_cell_size(crate::world::grid::REGION_SIZE)
+    }
+
+    pub fn with_grid_cell_size(cell_size: i32) -> Self {
         WorldState {
             players: HashMap::new(),
+            grid: WorldGrid::with_cell_size(cell_size),
+            by_name: HashMap::new(),
         }
     }
 
+    /// Look up an online player on this map.
+    pub fn get_player(&self, object_id: i32) -> Option<&OnlinePlayer> {
+        self.players.get(&object_id)
+    }
+
     /// Register a player when they enter the game.
     pub fn add_player(&mut self, player: OnlinePlayer) {
+        self.remove_player(player.object_id);
+        self.grid.add(player.object_id as u32, player.map_id, player.x, player.y);
+        self.by_name.insert(name_key(&player.name), player.object_id);
         self.players.insert(player.object_id, player);
     }
 
-    /// Remove a player when they leave.
-    pub fn remove_player(&mut self, object_id: i32) {
-        self.players.remove(&object_id);
+    /// Remove a player when they leave (or change maps).
+    pub fn remove_player(&mut self, object_id: i32) -> Option<OnlinePlayer> {
+        let p = self.players.remove(&object_id)?;
+        self.grid.remove(p.object_id as u32, p.map_id, p.x, p.y);
+        self.unindex_name(&p.name, object_id);
+        Some(p)
     }
 
-    /// Update a player's position after movement.
-    pub fn update_position(&mut self, object_id: i32, x: i32, y: i32, heading: i32) {
+    /// Change an online player's name (rename item, GM rename), keeping
+    /// the name index in step. Returns false if the player is unknown.
+    pub fn rename_player(&mut self, object_id: i32, new_name: &str) -> bool {
+        let Some(p) = self.players.get_mut(&object_id) else { return false };
+        let old = std::mem::replace(&mut p.name, new_name.to_string());
+        self.unindex_name(&old, object_id);
+        self.by_name.insert(name_key(new_name), object_id);
+        true
+    }
+
+    /// Drop `name`'s index entry if it still points at `object_id`.
+    fn unindex_name(&mut self, name: &str, object_id: i32) {
+        let key = name_key(name);
+        if self.by_name.get(&key) == Some(&object_id) {
+            self.by_name.remove(&key);
+        }
+    }
+
+    /// Find an online player by character name, ignoring case.
+    pub fn find_by_name(&self, name: &str) -> Option<&OnlinePlayer> {
+        self.by_name.get(&name_key(name)).and_then(|id| self.players.get(id))
+    }
+
+    /// Move a player within this map (teleport).
+    pub fn move_player(&mut self, object_id: i32, x: i32, y: i32) {
         if let Some(p) = self.players.get_mut(&object_id) {
+            self.grid.move_object(object_id as u32, p.map_id, p.x, p.y, x, y);
             p.x = x;
             p.y = y;
-            p.heading = heading;
         }
     }
 
+    /// Apply a move with sequence number `seq`.
+    ///
+    /// Moves older than the last applied one are discarded so a late
+    /// packet can't drag the player back. Returns whether it was applied.
+    pub fn update_position(&mut self, object_id: i32, x: i32, y: i32, heading: i32, seq: u32) -> bool {
+        let Some(p) = self.players.get_mut(&object_id) else { return false };
+        if !crate::ecs::components::movement::seq_is_newer(seq, p.move_seq) {
+            return false;
+        }
+        self.grid.move_object(object_id as u32, p.map_id, p.x, p.y, x, y);
+        p.x = x;
+        p.y = y;
+        p.heading = heading;
+        p.move_seq = seq;
+        true
+    }
+
+    /// Change an online player's clan (join, leave).
+    pub fn set_clan(&mut self, object_id: i32, clan_name: &str) {
+        if let Some(p) = self.players.get_mut(&object_id) {
+            p.clan_name = clan_name.to_string();
+        }
+    }
+
+    /// Mark a player dead.
+    pub fn set_dead(&mut self, object_id: i32) {
+        if let Some(p) = self.players.get_mut(&object_id) {
+            p.is_dead = true;
+        }
+    }
+
+    /// Clear a dead player's death state and re-send their appearance
+    /// to everyone on screen, the player included.
+    ///
+    /// Returns false if the player is unknown or not dead.
+    pub fn resurrect_player(&mut self, object_id: i32) -> bool {
+        let p = match self.players.get_mut(&object_id) {
+            Some(p) if p.is_dead => p,
+            _ => return false,
+        };
+        p.is_dead = false;
+        let (map_id, x, y) = (p.map_id, p.x, p.y);
+        let pkt = crate::network::session::build_player_charpack(p);
+        self.broadcast_to_nearby(map_id, x, y, 0, broadcast_range::SCREEN, &pkt);
+        true
+    }
+
+    /// Players within `radius` tiles of a point, answered from the grid.
+    fn players_within(&self, map_id: i32, x: i32, y: i32, radius: i32) -> Vec<&OnlinePlayer> {
+        self.grid.objects_in_range(map_id, x, y, radius)
+            .into_iter()
+            .filter_map(|id| self.players.get(&(id as i32)))
+            .collect()
+    }
+
     /// Get all players on the same map within screen range (18 tiles).
     pub fn get_nearby_players(&self, map_id: i32, x: i32, y: i32, exclude_id: i32) -> Vec<OnlinePlayer> {
-        self.players.values()
-            .filter(|p| {
-                p.object_id != exclude_id
-                    && p.map_id == map_id
-                    && (p.x - x).abs() <= 18
-                    && (p.y - y).abs() <= 18
-            })
+        self.players_within(map_id, x, y, broadcast_range::SCREEN)
+            .into_iter()
+            .filter(|p| p.object_id != exclude_id)
             .cloned()
             .collect()
     }
 
-    /// Send a packet to all nearby players (broadcast).
-    pub fn broadcast_to_nearby(&self, map_id: i32, x: i32, y: i32, exclude_id: i32, packet: &[u8]) {
-        for p in self.players.values() {
-            if p.object_id != exclude_id
-                && p.map_id == map_id
-                && (p.x - x).abs() <= 18
-                && (p.y - y).abs() <= 18
-            {
+    /// Directory rows for the players on this map.
+    pub fn listings(&self) -> Vec<PlayerListing> {
+        self.players.values().map(PlayerListing::from).collect()
+    }
+
+    /// Snapshot the packet channels of every online player.
+    pub fn all_senders(&self) -> Vec<tokio::sync::mpsc::UnboundedSender<Vec<u8>>> {
+        self.players.values().map(|p| p.packet_tx.clone()).collect()
+    }
+
+    /// Send a packet to all players within `radius` tiles (broadcast).
+    ///
+    /// Use the `broadcast_range` constants so quiet effects only reach
+    /// close players and loud ones carry farther.
+    pub fn broadcast_to_nearby(
+        &self,
+        map_id: i32,
+        x: i32,
+        y: i32,
+        exclude_id: i32,
+        radius: i32,
+        packet: &[u8],
+    ) {
+        for p in self.players_within(map_id, x, y, radius) {
+            if p.object_id != exclude_id {
                 let _ = p.packet_tx.send(packet.to_vec());
             }
         }
     }
 }
 
+/// Queue `packet` on each channel; returns how many accepted it.
+fn send_all(senders: &[tokio::sync::mpsc::UnboundedSender<Vec<u8>>], packet: &[u8]) -> usize {
+    senders.iter().filter(|tx| tx.send(packet.to_vec()).is_ok()).count()
+}
+
+/// Send a system announcement to every online player, regardless of map.
+///
+/// Used for boss spawns, shutdown notices and GM broadcasts. The sender
+/// list is snapshotted under the lock and the lock is released before any
+/// packet is queued, so callers never hold the world while fanning out.
+/// Returns the number of players the announcement was queued for.
+pub async fn announce(world: &SharedWorld, message: &str) -> usize {
+    let pkt = crate::protocol::server::chat::build_server_message(message);
+    let senders = world.all_senders().await;
+
+    senders
+        .iter()
+        .filter(|tx| tx.send(pkt.clone()).is_ok())
+        .count()
+}
+
 pub fn create_shared_world() -> SharedWorld {
-    Arc::new(Mutex::new(WorldState::new()))
+    Arc::new(World::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    fn make_player(
+        object_id: i32,
+        x: i32,
+        y: i32,
+    ) -> (OnlinePlayer, mpsc::UnboundedReceiver<Vec<u8>>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let player = OnlinePlayer {
+            object_id,
+            name: format!("Player{}", object_id),
+            x,
+            y,
+            map_id: 4,
+            heading: 0,
+            gfx_id: 61,
+            level: 1,
+            lawful: 0,
+            char_type: 1,
+            sex: 0,
+            clan_name: String::new(),
+            title: String::new(),
+            cur_hp: 50,
+            max_hp: 50,
+            is_dead: false,
+            move_seq: 0,
+            packet_tx: tx,
+        };
+        (player, rx)
+    }
+
+    #[test]
+    fn test_broadcast_radius_limits_recipients() {
+        let mut world = WorldState::new();
+        let (near, mut near_rx) = make_player(1, 32805, 32800);
+        let (far, mut far_rx) = make_player(2, 32820, 32800); // 20 tiles away
+        world.add_player(near);
+        world.add_player(far);
+
+        // Quiet effect: only the close player hears it
+        world.broadcast_to_nearby(4, 32800, 32800, 0, broadcast_range::QUIET, &[1]);
+        assert_eq!(near_rx.try_recv().unwrap(), vec![1]);
+        assert!(far_rx.try_recv().is_err());
+
+        // Loud effect: reaches past the screen edge
+        world.broadcast_to_nearby(4, 32800, 32800, 0, broadcast_range::LOUD, &[2]);
+        assert_eq!(near_rx.try_recv().unwrap(), vec![2]);
+        assert_eq!(far_rx.try_recv().unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn test_broadcast_uses_grid_cells() {
+        let mut world = WorldState::new();
+        let mut receivers = Vec::new();
+        // 10x10 players spaced 40 tiles apart - each in its own region
+        for i in 0..100 {
+            let (p, rx) = make_player(i + 1, 32000 + (i % 10) * 40, 32000 + (i / 10) * 40);
+            world.add_player(p);
+            receivers.push(rx);
+        }
+
+        // Centre on player 45 at (32160, 32160); only it is within screen range
+        world.broadcast_to_nearby(4, 32160, 32160, 0, broadcast_range::SCREEN, &[7]);
+        for (i, rx) in receivers.iter_mut().enumerate() {
+            assert_eq!(rx.try_recv().is_ok(), i == 44, "player {}", i + 1);
+        }
+
+        // Walking next to player 45 updates the grid index
+        world.update_position(1, 32165, 32160, 0, 1);
+        assert_eq!(world.get_nearby_players(4, 32160, 32160, 45).len(), 1);
+        world.update_position(1, 32000, 32000, 0, 2);
+        assert!(world.get_nearby_players(4, 32160, 32160, 45).is_empty());
+
+        // Removed players stop receiving
+        world.remove_player(45);
+        world.broadcast_to_nearby(4, 32160, 32160, 0, broadcast_range::SCREEN, &[8]);
+        assert!(receivers[44].try_recv().is_err());
+    }
+
+    #[test]
+    fn test_out_of_order_move_discarded() {
+        let mut world = WorldState::new();
+        let (p, _rx) = make_player(1, 32800, 32800);
+        world.add_player(p);
+
+        assert!(world.update_position(1, 32801, 32800, 6, 1));
+        assert!(world.update_position(1, 32802, 32800, 6, 3));
+        // Seq 2 arrives late - ignored, position stays at the newer move
+        assert!(!world.update_position(1, 32801, 32800, 6, 2));
+        // Duplicate of the applied move is ignored too
+        assert!(!world.update_position(1, 32802, 32800, 6, 3));
+        let p = world.get_player(1).unwrap();
+        assert_eq!((p.x, p.y, p.move_seq), (32802, 32800, 3));
+    }
+
+    #[tokio::test]
+    async fn test_announce_reaches_every_map() {
+        let world = create_shared_world();
+        let mut receivers = Vec::new();
+        for (i, map_id) in [4, 4, 0, 304].into_iter().enumerate() {
+            let (mut p, rx) = make_player(i as i32 + 1, 32800 + i as i32 * 500, 32800);
+            p.map_id = map_id;
+            world.lock_map(map_id).await.add_player(p);
+            receivers.push(rx);
+        }
+
+        let sent = announce(&world, "Server shutting down in 5 minutes").await;
+        assert_eq!(sent, 4);
+
+        let expected = crate::protocol::server::chat::build_server_message(
+            "Server shutting down in 5 minutes",
+        );
+        for rx in &mut receivers {
+            assert_eq!(rx.try_recv().unwrap(), expected);
+        }
+
+        // Locks must be free again after the announcement
+        for shard in world.shards() {
+            assert!(shard.try_lock().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_find_by_name_ignores_case() {
+        let mut world = WorldState::new();
+        let (mut p, _rx) = make_player(1, 32800, 32800);
+        p.name = "Alice".to_string();
+        world.add_player(p);
+
+        assert_eq!(world.find_by_name("Alice").unwrap().object_id, 1);
+        assert_eq!(world.find_by_name("aLICE").unwrap().object_id, 1);
+        assert!(world.find_by_name("Bob").is_none());
+        assert!(world.find_by_name("Alic").is_none());
+    }
+
+    #[test]
+    fn test_name_index_follows_remove_and_rename() {
+        let mut world = WorldState::new();
+        let (mut p, _rx) = make_player(1, 32800, 32800);
+        p.name = "Alice".to_string();
+        world.add_player(p);
+
+        assert!(world.rename_player(1, "Alicia"));
+        assert!(world.find_by_name("alice").is_none());
+        assert_eq!(world.find_by_name("ALICIA").unwrap().name, "Alicia");
+        assert!(!world.rename_player(2, "Ghost"));
+
+        // Re-adding the same object under a new name drops the old entry
+        let (mut again, _rx2) = make_player(1, 32801, 32800);
+        again.name = "Ally".to_string();
+        world.add_player(again);
+        assert!(world.find_by_name("Alicia").is_none());
+        assert_eq!(world.find_by_name("ally").unwrap().object_id, 1);
+
+        world.remove_player(1);
+        assert!(world.find_by_name("Ally").is_none());
+        assert!(world.by_name.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_world_find_by_name_across_maps() {
+        let world = create_shared_world();
+        let (mut p, _rx) = make_player(7, 32800, 32800);
+        p.name = "Bob".to_string();
+        p.map_id = 304;
+        world.lock_map(304).await.add_player(p);
+
+        assert_eq!(world.find_by_name("bob").await.unwrap().map_id, 304);
+        world.remove_player(304, 7).await;
+        assert!(world.find_by_name("Bob").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_clan_broadcast_reaches_only_members() {
+        let world = create_shared_world();
+        let mut receivers = Vec::new();
+        for (id, clan, map_id) in [(1, "Knights", 4), (2, "Knights", 304), (3, "Rogues", 4), (4, "", 4)] {
+            let (mut p, rx) = make_player(id, 32800 + id, 32800);
+            p.map_id = map_id;
+            p.clan_name = clan.to_string();
+            world.groups.lock().await.join_clan(id, clan, p.packet_tx.clone());
+            world.lock_map(map_id).await.add_player(p);
+            receivers.push(rx);
+        }
+
+        assert_eq!(world.broadcast_to_clan("Knights", 1, b"clan chat").await, 1);
+        assert!(receivers[0].try_recv().is_err());
+        assert_eq!(receivers[1].try_recv().unwrap(), b"clan chat");
+        assert!(receivers[2].try_recv().is_err());
+        assert!(receivers[3].try_recv().is_err());
+
+        // A member who logs out no longer receives clan messages
+        world.remove_player(304, 2).await;
+        assert_eq!(world.broadcast_to_clan("Knights", 0, b"again").await, 1);
+        assert_eq!(receivers[0].try_recv().unwrap(), b"again");
+        assert!(receivers[1].try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_logout_dissolves_two_member_party() {
+        let world = create_shared_world();
+        let mut receivers = Vec::new();
+        for id in [1, 2] {
+            let (p, rx) = make_player(id, 32800 + id, 32800);
+            world.lock_map(4).await.add_player(p);
+            receivers.push(rx);
+        }
+        let yes_no_id = world.parties.lock().await.invite(1, 2).unwrap();
+        let party = world.parties.lock().await.respond(2, yes_no_id, true).unwrap().party;
+        for id in [1, 2] {
+            let tx = world.find_by_id(id).await.unwrap().packet_tx;
+            world.groups.lock().await.join_party(id, party, tx);
+        }
+        assert_eq!(world.broadcast_to_party(party, 1, b"hp").await, 1);
+        assert_eq!(receivers[1].try_recv().unwrap(), b"hp");
+
+        world.remove_player(4, 2).await;
+        assert!(world.parties.lock().await.party_of(1).is_none());
+        assert!(world.groups.lock().await.party_of(1).is_none());
+        assert_eq!(world.broadcast_to_party(party, 0, b"hp").await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_online_players_tracks_add_and_remove() {
+        let world = create_shared_world();
+        assert!(world.online_players().await.is_empty());
+
+        for (id, name, map_id) in [(1, "Cecil", 4), (2, "Alice", 0), (3, "Bob", 4)] {
+            let (mut p, _rx) = make_player(id, 32800 + id, 32800);
+            p.name = name.to_string();
+            p.map_id = map_id;
+            p.level = 10 * id;
+            if id == 2 {
+                p.clan_name = "Knights".to_string();
+            }
+            world.lock_map(map_id).await.add_player(p);
+        }
+
+        let listed = world.online_players().await;
+        let names: Vec<&str> = listed.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Alice", "Bob", "Cecil"]);
+        assert_eq!(
+            listed[0],
+            PlayerListing { name: "Alice".into(), level: 20, map_id: 0, clan_name: "Knights".into() }
+        );
+
+        world.remove_player(4, 3).await;
+        let names: Vec<String> = world.online_players().await.into_iter().map(|p| p.name).collect();
+        assert_eq!(names, ["Alice", "Cecil"]);
+
+        // No shard stays locked after the snapshot
+        for shard in world.shards() {
+            assert!(shard.try_lock().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_resurrect_rebroadcasts_appearance() {
+        let mut world = WorldState::new();
+        let (dead, mut dead_rx) = make_player(1, 32800, 32800);
+        let (watcher, mut watcher_rx) = make_player(2, 32805, 32800);
+        world.add_player(dead);
+        world.add_player(watcher);
+
+        // Living players can't be resurrected
+        assert!(!world.resurrect_player(1));
+        assert!(watcher_rx.try_recv().is_err());
+
+        world.set_dead(1);
+        assert!(world.resurrect_player(1));
+        assert!(!world.players[&1].is_dead);
+
+        let pack = crate::network::session::build_player_charpack(&world.players[&1]);
+        assert_eq!(dead_rx.try_recv().unwrap(), pack);
+        assert_eq!(watcher_rx.try_recv().unwrap(), pack);
+    }
+
+    #[tokio::test]
+    async fn test_maps_lock_independently() {
+        let world = create_shared_world();
+        let (p, _rx) = make_player(1, 32800, 32800);
+        world.lock_map(4).await.add_player(p);
+
+        // Hold map 4 while working on map 0: must not block
+        let _held = world.lock_map(4).await;
+        let other = tokio::time::timeout(
+            std::time::Duration::from_millis(100),
+            world.lock_map(0),
+        )
+        .await;
+        assert!(other.is_ok(), "Map 0 blocked by a lock on map 4");
+
+        // Map 4 itself is still exclusive
+        let same = tokio::time::timeout(
+            std::time::Duration::from_millis(20),
+            world.lock_map(4),
+        )
+        .await;
+        assert!(same.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lock_pair_orders_consistently() {
+        let world = create_shared_world();
+        // Opposite argument orders from many tasks must not deadlock
+        let mut tasks = Vec::new();
+        for i in 0..50 {
+            let w = world.clone();
+            tasks.push(tokio::spawn(async move {
+                let (a, b) = if i % 2 == 0 { (4, 0) } else { (0, 4) };
+                let (ga, gb) = w.lock_pair(a, b).await;
+                assert!(gb.is_some());
+                drop((ga, gb));
+            }));
+        }
+        let all = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            for t in tasks {
+                t.await.unwrap();
+            }
+        })
+        .await;
+        assert!(all.is_ok(), "lock_pair deadlocked");
+
+        let (_g, none) = world.lock_pair(4, 4).await;
+        assert!(none.is_none());
+    }
 }
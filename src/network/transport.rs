@@ -0,0 +1,13 @@
+//! Byte transport under a session.
+//!
+//! A session only needs an ordered byte stream in both directions, so it
+//! holds a boxed `Transport` rather than a `TcpStream`. The server hands
+//! it the accepted socket; tests hand it one end of an in-memory pipe
+//! (`tokio::io::duplex`) and play the client on the other end.
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// A bidirectional byte stream a session can run on.
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send + Sync {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + Sync> Transport for T {}
@@ -57,3 +57,111 @@ pub fn parse_use_item(data: &[u8]) -> UseItem {
     let item_obj_id = r.read_d();
     UseItem { item_obj_id }
 }
+
+/// Parsed C_NPCTALK - the player clicked an NPC.
+pub fn parse_npc_talk(data: &[u8]) -> i32 {
+    PacketReader::after_opcode(data).read_d()
+}
+
+/// Parsed C_NPCACTION - a link chosen in an NPC dialogue.
+pub struct NpcAction {
+    pub object_id: i32,
+    pub action: String,
+}
+
+pub fn parse_npc_action(data: &[u8]) -> NpcAction {
+    let mut r = PacketReader::after_opcode(data);
+    let object_id = r.read_d();
+    let action = r.read_s();
+    NpcAction { object_id, action }
+}
+
+/// Parsed C_ATTR - answer to an S_YES_NO dialog.
+///
+/// The client echoes the dialog's fields in S_YES_NO order, so the reply
+/// can be matched to the prompt it answers.
+#[derive(Debug, PartialEq)]
+pub struct AttrReply {
+    pub yes_no_id: i32,
+    pub message_id: i32,
+    pub accepted: bool,
+}
+
+pub fn parse_attr(data: &[u8]) -> AttrReply {
+    let mut r = PacketReader::after_opcode(data);
+    let yes_no_id = r.read_h() as i32;
+    let message_id = r.read_d();
+    let accepted = r.read_c() != 0;
+    AttrReply { yes_no_id, message_id, accepted }
+}
+
+/// C_RESTARTMENU (Java C_Rank) sub-action codes.
+pub mod restart_menu {
+    /// Grant a clan rank to a member.
+    pub const SET_RANK: u8 = 1;
+    /// Survival cry (Ctrl+E).
+    pub const SURVIVAL_CRY: u8 = 5;
+    /// List clan members and their ranks.
+    pub const CLAN_RANK_LIST: u8 = 9;
+}
+
+/// What a C_RESTARTMENU packet asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RestartMenuAction {
+    SetRank { rank: u8, name: String },
+    SurvivalCry,
+    ClanRankList,
+    Unknown(u8),
+}
+
+/// Parse C_RESTARTMENU: sub-action byte, then rank byte and name
+/// (only meaningful for rank changes).
+pub fn parse_restart_menu(data: &[u8]) -> RestartMenuAction {
+    let mut r = PacketReader::after_opcode(data);
+    let action = r.read_c();
+    match action {
+        restart_menu::SET_RANK => {
+            let rank = r.read_c();
+            let name = r.read_s();
+            RestartMenuAction::SetRank { rank, name }
+        }
+        restart_menu::SURVIVAL_CRY => RestartMenuAction::SurvivalCry,
+        restart_menu::CLAN_RANK_LIST => RestartMenuAction::ClanRankList,
+        other => RestartMenuAction::Unknown(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::opcodes::client::{C_ATTR, C_RESTARTMENU};
+    use crate::protocol::packet::PacketBuilder;
+
+    #[test]
+    fn test_parse_restart_menu_sub_actions() {
+        let cry = PacketBuilder::new(C_RESTARTMENU).write_c(5).write_c(0).build();
+        assert_eq!(parse_restart_menu(&cry), RestartMenuAction::SurvivalCry);
+
+        let list = PacketBuilder::new(C_RESTARTMENU).write_c(9).build();
+        assert_eq!(parse_restart_menu(&list), RestartMenuAction::ClanRankList);
+
+        let rank = PacketBuilder::new(C_RESTARTMENU).write_c(1).write_c(9).write_s(Some("Alice")).build();
+        assert_eq!(
+            parse_restart_menu(&rank),
+            RestartMenuAction::SetRank { rank: 9, name: "Alice".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_unknown_sub_action_is_ignored() {
+        let pkt = PacketBuilder::new(C_RESTARTMENU).write_c(42).build();
+        assert_eq!(parse_restart_menu(&pkt), RestartMenuAction::Unknown(42));
+        assert_eq!(parse_restart_menu(&[C_RESTARTMENU]), RestartMenuAction::Unknown(0));
+    }
+
+    #[test]
+    fn test_parse_attr_reply() {
+        let pkt = PacketBuilder::new(C_ATTR).write_h(7).write_d(729).write_c(1).build();
+        assert_eq!(parse_attr(&pkt), AttrReply { yes_no_id: 7, message_id: 729, accepted: true });
+    }
+}
@@ -56,24 +56,94 @@ const CLASS_GFX: [[i32; 2]; 7] = [
     [6671, 6650], // Illusionist
 ];
 
-/// Starting location (all classes).
+/// Default starting location (classes without a configured start town).
 pub const START_X: i32 = 32689;
 pub const START_Y: i32 = 32842;
 pub const START_MAP: i32 = 2005;
 
+/// Maximum character name length, in client-visible characters.
+pub const MAX_NAME_CHARS: usize = 12;
+
+/// Names reserved for staff/system use (case-insensitive prefix match).
+const RESERVED_PREFIXES: &[&str] = &[
+    "gm", "admin", "operator", "system", "server", "管理員", "客服", "官方",
+];
+
+/// Words rejected anywhere in a name (case-insensitive substring match).
+const PROFANITY: &[&str] = &["fuck", "shit", "bitch", "幹你", "操你", "屁眼"];
+
+/// Check a single name character: ASCII letters/digits or CJK ideographs.
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric()
+        || ('\u{4E00}'..='\u{9FFF}').contains(&c)
+        || ('\u{3400}'..='\u{4DBF}').contains(&c)
+}
+
+/// Validate a new character name.
+/// Length is counted in characters as the client displays them, not bytes.
+pub fn validate_name(name: &str) -> bool {
+    if name.is_empty() || name.trim() != name {
+        return false;
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        return false;
+    }
+    if !name.chars().all(is_name_char) {
+        return false;
+    }
+    let lower = name.to_lowercase();
+    if RESERVED_PREFIXES.iter().any(|w| lower.starts_with(w)) {
+        return false;
+    }
+    if PROFANITY.iter().any(|w| lower.contains(w)) {
+        return false;
+    }
+    true
+}
+
+/// Total character slots for an account: the server default plus purchased slots.
+pub fn max_character_slots(extra_slots: i32) -> i32 {
+    crate::DEFAULT_CHARACTER_SLOT + extra_slots.max(0)
+}
+
+/// Check whether an account with `existing` characters may create another one.
+pub fn has_free_slot(existing: i64, extra_slots: i32) -> bool {
+    existing < max_character_slots(extra_slots) as i64
+}
+
+/// Highest value any single stat may start at (client creation screen cap).
+pub const MAX_INIT_STAT: i32 = 18;
+
+/// Official point-buy total for a class: base stats plus bonus points.
+pub fn class_stat_total(char_type: i32) -> Option<i32> {
+    if !(0..=6).contains(&char_type) { return None; }
+    let base = &BASE_STATS[char_type as usize];
+    Some(base.iter().sum())
+}
+
+/// Allowed (min, max) for each stat of a class, in STR DEX CON WIS CHA INT order.
+pub fn class_stat_ranges(char_type: i32) -> Option<[(i32, i32); 6]> {
+    if !(0..=6).contains(&char_type) { return None; }
+    let base = &BASE_STATS[char_type as usize];
+    let bonus = base[6];
+    let mut ranges = [(0, 0); 6];
+    for (i, r) in ranges.iter_mut().enumerate() {
+        *r = (base[i], (base[i] + bonus).min(MAX_INIT_STAT));
+    }
+    Some(ranges)
+}
+
 /// Validate character creation stats.
+///
+/// Each stat must lie within the class's base..cap range, and the total
+/// must match the class's base stats plus bonus points.
 pub fn validate_stats(nc: &NewChar) -> bool {
-    if nc.char_type < 0 || nc.char_type > 6 { return false; }
-    let base = &BASE_STATS[nc.char_type as usize];
-    let total = nc.str_stat + nc.dex_stat + nc.con_stat + nc.wis_stat + nc.cha_stat + nc.int_stat;
-    if total != 75 { return false; }
-    if nc.str_stat < base[0] { return false; }
-    if nc.dex_stat < base[1] { return false; }
-    if nc.con_stat < base[2] { return false; }
-    if nc.wis_stat < base[3] { return false; }
-    if nc.cha_stat < base[4] { return false; }
-    if nc.int_stat < base[5] { return false; }
-    true
+    let (Some(total), Some(ranges)) = (class_stat_total(nc.char_type), class_stat_ranges(nc.char_type)) else {
+        return false;
+    };
+    let stats = [nc.str_stat, nc.dex_stat, nc.con_stat, nc.wis_stat, nc.cha_stat, nc.int_stat];
+    if stats.iter().sum::<i32>() != total { return false; }
+    stats.iter().zip(ranges.iter()).all(|(&v, &(min, max))| v >= min && v <= max)
 }
 
 /// Calculate initial MP based on class and WIS.
@@ -113,3 +183,86 @@ pub fn get_init_hp(char_type: i32) -> i32 {
     if char_type < 0 || char_type > 6 { return 14; }
     INIT_HP[char_type as usize]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_char(char_type: i32, stats: [i32; 6]) -> NewChar {
+        NewChar {
+            name: "Test".into(), char_type, sex: 0,
+            str_stat: stats[0], dex_stat: stats[1], con_stat: stats[2],
+            wis_stat: stats[3], cha_stat: stats[4], int_stat: stats[5],
+        }
+    }
+
+    #[test]
+    fn test_valid_elf_stats() {
+        // Elf base 11/12/12/12/9/12 + 7 bonus
+        assert!(validate_stats(&make_char(2, [12, 12, 12, 12, 9, 18])));
+        assert!(validate_stats(&make_char(2, [14, 14, 12, 12, 9, 14])));
+        assert_eq!(class_stat_total(2), Some(75));
+    }
+
+    #[test]
+    fn test_over_capped_stat_rejected() {
+        // Mage base INT 12 + 16 bonus would exceed the cap of 18
+        assert!(!validate_stats(&make_char(3, [8, 7, 12, 12, 8, 28])));
+        // Below racial base
+        assert!(!validate_stats(&make_char(1, [15, 13, 14, 9, 12, 12])));
+    }
+
+    #[test]
+    fn test_wrong_total_rejected() {
+        assert!(!validate_stats(&make_char(1, [16, 12, 14, 9, 12, 8])));
+        assert!(!validate_stats(&make_char(1, [18, 14, 16, 9, 12, 8])));
+        assert!(!validate_stats(&make_char(7, [13, 10, 10, 11, 13, 18])));
+    }
+
+    #[test]
+    fn test_reserved_name_rejected() {
+        assert!(!validate_name("GM"));
+        assert!(!validate_name("gmHelper"));
+        assert!(!validate_name("Admin01"));
+        assert!(!validate_name("管理員"));
+        assert!(!validate_name("xFuCkx"));
+    }
+
+    #[test]
+    fn test_invalid_charset_rejected() {
+        assert!(!validate_name(""));
+        assert!(!validate_name(" Knight"));
+        assert!(!validate_name("Kni ght"));
+        assert!(!validate_name("Knight!"));
+        assert!(!validate_name("ナイト"));
+    }
+
+    #[test]
+    fn test_valid_name_accepted() {
+        assert!(validate_name("Knight01"));
+        assert!(validate_name("妖精"));
+        // Length is counted in characters, not bytes
+        assert!(validate_name("龍騎士龍騎士龍騎士龍騎士"));
+        assert!(!validate_name("龍騎士龍騎士龍騎士龍騎士龍"));
+        assert!(!validate_name("abcdefghijklm"));
+    }
+
+    #[test]
+    fn test_slot_cap_rejects_creation() {
+        let max = crate::DEFAULT_CHARACTER_SLOT as i64;
+        assert!(has_free_slot(0, 0));
+        assert!(has_free_slot(max - 1, 0));
+        assert!(!has_free_slot(max, 0));
+        assert!(!has_free_slot(max + 3, 0));
+    }
+
+    #[test]
+    fn test_purchased_slots_extend_cap() {
+        let max = crate::DEFAULT_CHARACTER_SLOT as i64;
+        assert_eq!(max_character_slots(2), crate::DEFAULT_CHARACTER_SLOT + 2);
+        assert!(has_free_slot(max, 2));
+        assert!(!has_free_slot(max + 2, 2));
+        // Negative slot values from the DB never shrink the default
+        assert_eq!(max_character_slots(-5), crate::DEFAULT_CHARACTER_SLOT);
+    }
+}
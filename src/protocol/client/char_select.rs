@@ -13,3 +13,9 @@ pub fn parse_login_to_server(data: &[u8]) -> LoginToServer {
     let char_name = r.read_s();
     LoginToServer { char_name }
 }
+
+/// Parse the C_DELETECHAR packet (character name to delete or restore).
+pub fn parse_delete_char(data: &[u8]) -> String {
+    let mut r = PacketReader::after_opcode(data);
+    r.read_s()
+}
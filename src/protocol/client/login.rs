@@ -21,6 +21,14 @@ pub fn parse_client_version(data: &[u8]) -> ClientVersion {
     }
 }
 
+impl ClientVersion {
+    /// Check against the configured allowlists. An empty list allows anything.
+    pub fn is_supported(&self, versions: &[u32], languages: &[i32]) -> bool {
+        (versions.is_empty() || versions.contains(&(self.client_version as u32)))
+            && (languages.is_empty() || languages.contains(&self.client_language))
+    }
+}
+
 /// Login action codes from C_AuthLogin (opcode 210).
 pub const LOGIN_ACTION_LOGIN: u8 = 0x06;
 pub const LOGIN_ACTION_RETURN_CHARSELECT: u8 = 0x0b;
@@ -65,3 +73,37 @@ pub fn parse_login_packet(data: &[u8]) -> AuthLogin {
         password,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::opcodes::client::C_CLIENTVERSION;
+    use crate::protocol::packet::PacketBuilder;
+
+    fn version_packet(language: i32, version: u32) -> Vec<u8> {
+        PacketBuilder::new(C_CLIENTVERSION)
+            .write_h(0)
+            .write_c(0)
+            .write_d(language)
+            .write_h(0)
+            .write_h(0)
+            .write_d(version as i32)
+            .build()
+    }
+
+    #[test]
+    fn test_allowed_client_version() {
+        let cv = parse_client_version(&version_packet(3, 0x07cbf4dd));
+        assert!(cv.is_supported(&[0x07cbf4dd], &[3]));
+        // Empty allowlists accept anything
+        assert!(cv.is_supported(&[], &[]));
+    }
+
+    #[test]
+    fn test_disallowed_client_rejected() {
+        let old = parse_client_version(&version_packet(3, 0x07cbf4d0));
+        assert!(!old.is_supported(&[0x07cbf4dd], &[]));
+        let foreign = parse_client_version(&version_packet(0, 0x07cbf4dd));
+        assert!(!foreign.is_supported(&[0x07cbf4dd], &[3]));
+    }
+}
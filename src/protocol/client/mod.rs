@@ -5,5 +5,7 @@ pub mod chat;
 pub mod clan;
 pub mod login;
 pub mod movement;
+pub mod party;
 pub mod skill;
 pub mod teleport;
+pub mod trade;
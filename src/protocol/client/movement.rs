@@ -2,6 +2,9 @@ use crate::protocol::packet::PacketReader;
 
 /// Parsed C_MOVECHAR packet.
 pub struct MoveChar {
+    /// Tile the client says it is moving from.
+    pub x: i32,
+    pub y: i32,
     pub heading: i32,
 }
 
@@ -9,12 +12,12 @@ pub struct MoveChar {
 /// Taiwan client (3.80c): heading is XOR'd with 0x49.
 pub fn parse_move_char(data: &[u8]) -> MoveChar {
     let mut r = PacketReader::after_opcode(data);
-    let _locx = r.read_h();  // client's reported X (we use server-side pos)
-    let _locy = r.read_h();  // client's reported Y
+    let x = r.read_h() as i32;  // client's reported origin (duplicate detection only;
+    let y = r.read_h() as i32;  // the server-side position stays authoritative)
     let raw_heading = r.read_c() as i32;
     let heading = (raw_heading ^ 0x49) & 7; // Taiwan client XOR decode
 
-    MoveChar { heading }
+    MoveChar { x, y, heading }
 }
 
 /// Parsed C_CHANGEHEADING packet.
@@ -0,0 +1,30 @@
+//! Party-related client packet parsers.
+//!
+//! Ported from Java C_CreateParty.
+
+use crate::protocol::packet::PacketReader;
+
+/// C_CREATEPARTY invite types: a normal party and one that shares loot
+/// automatically (the server treats both as a normal party for now).
+pub const PARTY_NORMAL: u8 = 0;
+pub const PARTY_AUTO_SHARE: u8 = 1;
+
+/// Parsed C_CREATEPARTY packet (invite a player).
+///
+/// Only the party invite types carry a target object ID; chat-party
+/// invites (by name) are not parsed.
+#[derive(Debug, PartialEq)]
+pub struct CreateParty {
+    pub party_type: u8,
+    pub target_id: Option<i32>,
+}
+
+pub fn parse_create_party(data: &[u8]) -> CreateParty {
+    let mut r = PacketReader::after_opcode(data);
+    let party_type = r.read_c();
+    let target_id = match party_type {
+        PARTY_NORMAL | PARTY_AUTO_SHARE => Some(r.read_d()),
+        _ => None,
+    };
+    CreateParty { party_type, target_id }
+}
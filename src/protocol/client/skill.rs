@@ -22,6 +22,9 @@ pub const SKILL_LIFE_STREAM: i32 = 50;
 pub const SKILL_CALL_CLAN: i32 = 68;
 pub const SKILL_RUN_CLAN: i32 = 69;
 pub const SKILL_TRUE_TARGET: i32 = 70;
+/// Prince recall skills (呼喚盟友 / 援護盟友); both name their target.
+pub const SKILL_CALL_ALLY: i32 = 116;
+pub const SKILL_RUN_ALLY: i32 = 118;
 
 pub fn parse_use_skill(data: &[u8]) -> UseSkill {
     let mut r = PacketReader::after_opcode(data);
@@ -44,7 +47,7 @@ pub fn parse_use_skill(data: &[u8]) -> UseSkill {
     }
 
     match skill_id {
-        SKILL_CALL_CLAN | SKILL_RUN_CLAN => {
+        SKILL_CALL_CLAN | SKILL_RUN_CLAN | SKILL_CALL_ALLY | SKILL_RUN_ALLY => {
             result.char_name = r.read_s();
         }
         SKILL_TRUE_TARGET => {
@@ -72,8 +75,37 @@ pub fn parse_use_skill(data: &[u8]) -> UseSkill {
     result
 }
 
+/// Parse a skill learn request (C_SKILLBUY): count, then that many skill IDs.
+pub fn parse_learn_skill(data: &[u8]) -> Vec<i32> {
+    let mut r = PacketReader::after_opcode(data);
+    let count = r.read_h() as usize;
+    let mut ids = Vec::with_capacity(count.min(64));
+    for _ in 0..count {
+        if !r.has_remaining() {
+            break;
+        }
+        ids.push(r.read_d());
+    }
+    ids
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::protocol::opcodes::client::C_USESKILL;
+    use crate::protocol::packet::PacketBuilder;
+
+    #[test]
+    fn test_recall_skills_carry_target_name() {
+        // 116 = row 14, column 3; 118 = row 14, column 5
+        for (column, skill_id) in [(3, SKILL_CALL_ALLY), (5, SKILL_RUN_ALLY)] {
+            let pkt = PacketBuilder::new(C_USESKILL).write_c(14).write_c(column).write_s(Some("Alice")).build();
+            let req = parse_use_skill(&pkt);
+            assert_eq!(req.skill_id, skill_id);
+            assert_eq!(req.char_name, "Alice");
+        }
+    }
+
     #[test]
     fn test_skill_id_calculation() {
         // Row 0, Column 0 → skill 1 (Energy Bolt)
@@ -0,0 +1,20 @@
+//! Trade client packet parsers.
+//!
+//! Ported from Java C_TradeAddItem. C_TRADE, C_TRADEADDOK and
+//! C_TRADEADDCANCEL carry no data.
+
+use crate::protocol::packet::PacketReader;
+
+/// Parsed C_TRADEADDITEM packet (offer an item).
+#[derive(Debug, PartialEq)]
+pub struct TradeAddItem {
+    pub object_id: u32,
+    pub count: i32,
+}
+
+pub fn parse_trade_add_item(data: &[u8]) -> TradeAddItem {
+    let mut r = PacketReader::after_opcode(data);
+    let object_id = r.read_d() as u32;
+    let count = r.read_d();
+    TradeAddItem { object_id, count }
+}
@@ -50,6 +50,8 @@ pub mod client {
     pub const C_PICKUPITEM: u8 = 112;
     pub const C_BOARDREAD: u8 = 114;
     pub const C_FIX_WEAPON_LIST: u8 = 118;
+    /// Login with account + password directly (no BeanFun action byte).
+    pub const C_LOGINPACKET: u8 = 119;
     pub const C_EXTCOMMAND: u8 = 120;
     pub const C_ATTR: u8 = 121;
     pub const C_QUITGAME: u8 = 122;
@@ -12,6 +12,17 @@ pub fn build_char_amount(count: i32, max_slots: i32) -> Vec<u8> {
         .build()
 }
 
+/// S_DeleteCharOK result codes.
+pub const DELETE_CHAR_NOW: u8 = 0x05;
+pub const DELETE_CHAR_AFTER_7DAYS: u8 = 0x51;
+
+/// Build S_DELETECHAROK - character deleted, scheduled or restored.
+pub fn build_delete_char_ok(code: u8) -> Vec<u8> {
+    PacketBuilder::new(server::S_OPCODE_DELETECHAROK)
+        .write_c(code as i32)
+        .build()
+}
+
 /// Build S_CHARSYNACK (SYN) packet - marks start of character list.
 pub fn build_char_syn() -> Vec<u8> {
     PacketBuilder::new(server::S_OPCODE_CHARSYNACK)
@@ -37,6 +48,9 @@ pub fn build_char_ack() -> Vec<u8> {
 /// Build S_CHARLIST packet for a single character.
 ///
 /// One of these is sent per character in the account's character list.
+/// The 3.80c packet has no hair, face or weapon fields: the selection
+/// screen draws the model from class (`char_type`) and `sex` alone, so
+/// those two are all the appearance `load_char_list` has to supply.
 pub fn build_char_pack(ch: &CharacterListData) -> Vec<u8> {
     let checksum = (ch.level ^ ch.str_stat ^ ch.dex_stat ^ ch.con_stat
         ^ ch.wis_stat ^ ch.cha_stat ^ ch.int_stat)
@@ -153,3 +167,59 @@ pub fn build_own_char_pack(ch: &crate::db::character::CharacterFullData) -> Vec<
         .write_c(0)              // padding
         .build()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::client::char_create::max_character_slots;
+
+    #[test]
+    fn test_char_amount_includes_purchased_slots() {
+        let pkt = build_char_amount(4, max_character_slots(2));
+        assert_eq!(pkt, vec![server::S_OPCODE_CHARAMOUNT, 4, crate::DEFAULT_CHARACTER_SLOT as u8 + 2]);
+
+        let pkt = build_char_amount(1, max_character_slots(0));
+        assert_eq!(pkt[2], crate::DEFAULT_CHARACTER_SLOT as u8);
+    }
+
+    #[test]
+    fn test_char_pack_carries_appearance() {
+        use crate::protocol::packet::PacketReader;
+
+        let ch = CharacterListData {
+            objid: 1,
+            char_name: "Galadriel".to_string(),
+            clanname: String::new(),
+            char_type: 2, // elf
+            sex: 1,
+            lawful: -500,
+            cur_hp: 120,
+            cur_mp: 60,
+            ac: -12,
+            level: 45,
+            str_stat: 12,
+            dex_stat: 18,
+            con_stat: 12,
+            wis_stat: 12,
+            cha_stat: 9,
+            int_stat: 12,
+            access_level: 0,
+            birthday: 20240315,
+        };
+        let pkt = build_char_pack(&ch);
+        let mut r = PacketReader::new(&pkt);
+        assert_eq!(r.read_c(), server::S_OPCODE_CHARLIST);
+        assert_eq!(r.read_s(), "Galadriel");
+        assert_eq!(r.read_s(), "");
+        // Class and sex pick the female elf model on the selection screen
+        assert_eq!((r.read_c(), r.read_c()), (2, 1));
+        assert_eq!(r.read_h() as i16, -500);
+        assert_eq!((r.read_h(), r.read_h()), (120, 60));
+        assert_eq!((r.read_c() as i8, r.read_c()), (-12, 45));
+        let stats: Vec<u8> = (0..6).map(|_| r.read_c()).collect();
+        assert_eq!(stats, [12, 18, 12, 12, 9, 12]);
+        assert_eq!(r.read_c(), 0);
+        assert_eq!(r.read_d(), 20240315);
+        assert_eq!(r.read_c() as i32, (45 ^ 12 ^ 18 ^ 12 ^ 12 ^ 9 ^ 12) & 0xFF);
+    }
+}
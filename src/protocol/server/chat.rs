@@ -31,7 +31,7 @@ pub fn build_normal_chat(
     pb.build()
 }
 
-/// Build S_GLOBALCHAT (world/clan/alliance chat).
+/// Build S_GLOBALCHAT (world/clan/party chat).
 pub fn build_global_chat(
     chat_type: i32,
     name: &str,
@@ -40,6 +40,7 @@ pub fn build_global_chat(
     let text = match chat_type {
         3 => format!("[{}] {}", name, message),   // World
         4 => format!("{{{}}} {}", name, message), // Clan
+        11 => format!("({}) {}", name, message),  // Party
         _ => format!("{}: {}", name, message),
     };
 
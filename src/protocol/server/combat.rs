@@ -73,3 +73,11 @@ pub fn build_hp_meter(object_id: i32, cur_hp: i32, max_hp: i32) -> Vec<u8> {
         .write_h(ratio)
         .build()
 }
+
+/// Build S_HPMETER that hides an entity's HP bar (a member left the party).
+pub fn build_hp_meter_hidden(object_id: i32) -> Vec<u8> {
+    PacketBuilder::new(server::S_OPCODE_HPMETER)
+        .write_d(object_id)
+        .write_h(0xff)
+        .build()
+}
@@ -8,6 +8,30 @@
 
 use crate::protocol::opcodes::server;
 
+/// Protocol version used when the config names none.
+pub const DEFAULT_PROTOCOL_VERSION: &str = "3.80c-tw";
+
+/// S_INITPACKET payload (after opcode + key) for each client build the
+/// server can talk to, keyed by the `protocol_version` config name.
+const FIRST_PACKETS: &[(&str, &[u8])] = &[
+    // 3.80c Taiwan
+    ("3.80c-tw", &[0x9d, 0xd1, 0xd6, 0x7a, 0xf4, 0x62, 0xe7, 0xa0, 0x66, 0x02, 0xfa]),
+];
+
+/// The handshake payload for `version`, if it is a known protocol.
+pub fn first_packet(version: &str) -> Option<&'static [u8]> {
+    FIRST_PACKETS.iter().find(|(v, _)| *v == version).map(|(_, p)| *p)
+}
+
+/// Build S_INITPACKET: opcode, cipher key (LE) and the version's payload.
+pub fn build_init_packet(key: u32, first_packet: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(1 + 4 + first_packet.len());
+    payload.push(server::S_OPCODE_INITPACKET);
+    payload.extend_from_slice(&key.to_le_bytes());
+    payload.extend_from_slice(first_packet);
+    payload
+}
+
 /// Build the S_SERVERVERSION packet.
 ///
 /// Sent in response to C_CLIENTVERSION.
@@ -37,3 +61,19 @@ pub fn build_login_result(reason: u8) -> Vec<u8> {
     buf.push(0);
     buf
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_packet_for_version() {
+        let payload = first_packet(DEFAULT_PROTOCOL_VERSION).unwrap();
+        assert_eq!(payload.len(), 11);
+        assert!(first_packet("9.99-xx").is_none());
+
+        let pkt = build_init_packet(0x1234_5678, payload);
+        assert_eq!(&pkt[..5], &[server::S_OPCODE_INITPACKET, 0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(&pkt[5..], payload);
+    }
+}
@@ -1,6 +1,8 @@
 /// Inventory-related server packets: S_AddItem, S_DeleteInventoryItem, S_InvList, S_ItemStatus.
 
-use crate::ecs::components::item::{ItemInstance, ItemTemplate};
+use std::collections::HashMap;
+
+use crate::ecs::components::item::{InventoryChanges, ItemInstance, ItemTemplate};
 use crate::protocol::opcodes::server;
 use crate::protocol::packet::PacketBuilder;
 
@@ -14,7 +16,7 @@ fn write_item_entry(pb: PacketBuilder, item: &ItemInstance, template: &ItemTempl
         .write_c(template.use_type)             // use type
         .write_c(item.charge_count)             // charge count
         .write_h(template.inv_gfx_id)           // inventory GFX
-        .write_c(item.bless)                    // bless
+        .write_c(item.view_bless())             // bless (hidden until identified)
         .write_d(item.count)                    // count
         .write_c(0)                             // item status X
         .write_s(Some(&view_name))              // display name
@@ -23,7 +25,7 @@ fn write_item_entry(pb: PacketBuilder, item: &ItemInstance, template: &ItemTempl
         .write_c(0)                             // padding
         .write_h(0)                             // padding
         .write_h(0)                             // padding
-        .write_c(item.enchant_level)            // enchant level
+        .write_c(item.view_enchant())           // enchant level
         .write_d(item.object_id as i32)         // world serial
         .write_d(0)                             // padding
         .write_d(0)                             // padding
@@ -67,3 +69,42 @@ pub fn build_item_status(item: &ItemInstance, template: &ItemTemplate) -> Vec<u8
         .write_c(0)  // status bytes length (simplified)
         .build()
 }
+
+/// Packets that bring the client's inventory in line with `changes`:
+/// S_DELETEINVENTORYITEM for removed items, S_ITEMSTATUS for changed
+/// stacks and S_ADDITEM for new items. Items without a template in
+/// `templates` are shown with a blank one.
+pub fn build_inventory_changes(changes: &InventoryChanges, templates: &HashMap<i32, ItemTemplate>) -> Vec<Vec<u8>> {
+    let template = |item: &ItemInstance| {
+        templates
+            .get(&item.item_id)
+            .cloned()
+            .unwrap_or_else(|| ItemTemplate { item_id: item.item_id, ..Default::default() })
+    };
+    let removed = changes.removed.iter().map(|&id| build_delete_inventory_item(id));
+    let updated = changes.updated.iter().map(|i| build_item_status(i, &template(i)));
+    let added = changes.added.iter().map(|i| build_add_item(i, &template(i)));
+    removed.chain(updated).chain(added).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inventory_change_packets() {
+        let changes = InventoryChanges {
+            removed: vec![10],
+            updated: vec![ItemInstance { count: 18, ..ItemInstance::new(11, 40308) }],
+            added: vec![ItemInstance::new(12, 80)],
+        };
+        let opcodes: Vec<u8> = build_inventory_changes(&changes, &HashMap::new())
+            .iter()
+            .map(|p| p[0])
+            .collect();
+        assert_eq!(
+            opcodes,
+            [server::S_OPCODE_DELETEINVENTORYITEM, server::S_OPCODE_ITEMSTATUS, server::S_OPCODE_ADDITEM]
+        );
+    }
+}
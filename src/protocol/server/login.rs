@@ -5,6 +5,20 @@ use crate::protocol::packet::PacketBuilder;
 pub const REASON_LOGIN_OK: u8 = 0x00;
 pub const REASON_ACCOUNT_IN_USE: u8 = 0x16;
 pub const REASON_ACCESS_FAILED: u8 = 0x08;
+/// Unknown account or wrong password (client shows the "check ID/password" dialog).
+pub const REASON_USER_OR_PASS_WRONG: u8 = 0x0a;
+
+/// Decide what to do when a login names an account that doesn't exist.
+///
+/// Returns `None` if the account should be created, or the login
+/// result reason to reject with.
+pub fn unknown_account_result(auto_create_account: bool) -> Option<u8> {
+    if auto_create_account {
+        None
+    } else {
+        Some(REASON_USER_OR_PASS_WRONG)
+    }
+}
 
 /// Build S_SERVERVERSION packet.
 ///
@@ -34,6 +48,17 @@ pub fn build_server_version(server_start_time: i32) -> Vec<u8> {
         .build()
 }
 
+/// Build S_SERVERVERSION rejecting an unsupported client.
+///
+/// A non-zero first byte makes the client report a version mismatch
+/// and close the connection.
+pub fn build_server_version_rejected() -> Vec<u8> {
+    PacketBuilder::new(server::S_OPCODE_SERVERVERSION)
+        .write_c(0x01)          // auth failed: unsupported client
+        .write_c(0x01)          // server ID
+        .build()
+}
+
 /// Build S_LOGINRESULT packet.
 ///
 /// Sent after account authentication attempt.
@@ -71,3 +96,20 @@ pub fn build_login_to_game(clanid: i32, clan_member_id: i32) -> Vec<u8> {
             .build()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_account_created_when_enabled() {
+        assert_eq!(unknown_account_result(true), None);
+    }
+
+    #[test]
+    fn test_unknown_account_rejected_when_disabled() {
+        assert_eq!(unknown_account_result(false), Some(REASON_USER_OR_PASS_WRONG));
+        // Distinct from the generic refusal, so the client says why
+        assert_ne!(REASON_USER_OR_PASS_WRONG, REASON_ACCESS_FAILED);
+    }
+}
@@ -13,3 +13,4 @@ pub mod npc_pack;
 pub mod skill;
 pub mod skill_effect;
 pub mod teleport;
+pub mod trade;
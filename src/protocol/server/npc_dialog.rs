@@ -71,6 +71,18 @@ pub fn build_yes_no(message_id: i32, yes_no_id: i32) -> Vec<u8> {
         .build()
 }
 
+/// Build S_YES_NO whose message has `%0`, `%1`... placeholders, filled
+/// in from `args` in order.
+pub fn build_yes_no_with_args(message_id: i32, yes_no_id: i32, args: &[&str]) -> Vec<u8> {
+    let mut pb = PacketBuilder::new(server::S_OPCODE_YES_NO)
+        .write_h(yes_no_id)
+        .write_d(message_id);
+    for arg in args {
+        pb = pb.write_s(Some(arg));
+    }
+    pb.build()
+}
+
 /// Build a simple NPC chat HTML page.
 ///
 /// This is a convenience function for common dialogue patterns.
@@ -0,0 +1,38 @@
+//! Trade window server packets.
+//!
+//! Ported from Java S_Trade, S_TradeAddItem, S_TradeStatus.
+
+use crate::ecs::components::item::{ItemInstance, ItemTemplate};
+use crate::protocol::opcodes::server;
+use crate::protocol::packet::PacketBuilder;
+
+/// S_TRADESTATUS outcomes.
+pub const TRADE_COMPLETE: i32 = 0;
+pub const TRADE_CANCEL: i32 = 1;
+
+/// Build S_TRADE - opens the trade window with `partner_name`.
+pub fn build_trade(partner_name: &str) -> Vec<u8> {
+    PacketBuilder::new(server::S_OPCODE_TRADE)
+        .write_s(Some(partner_name))
+        .build()
+}
+
+/// Build S_TRADEADDITEM - shows `count` of an offered item in the trade
+/// window; `theirs` puts it in the partner's (lower) half.
+pub fn build_trade_add_item(item: &ItemInstance, count: i32, template: &ItemTemplate, theirs: bool) -> Vec<u8> {
+    let name = item.get_view_name(template);
+    let name = if count > 1 { format!("{} ({})", name, count) } else { name };
+    PacketBuilder::new(server::S_OPCODE_TRADEADDITEM)
+        .write_h(template.inv_gfx_id)
+        .write_s(Some(&name))
+        .write_c(theirs as i32)
+        .build()
+}
+
+/// Build S_TRADESTATUS - closes the trade window (`TRADE_COMPLETE` or
+/// `TRADE_CANCEL`).
+pub fn build_trade_status(status: i32) -> Vec<u8> {
+    PacketBuilder::new(server::S_OPCODE_TRADESTATUS)
+        .write_c(status)
+        .build()
+}
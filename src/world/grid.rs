@@ -4,7 +4,8 @@
 /// objects, only the current region and its 8 neighbors are checked,
 /// reducing complexity from O(n) to O(k) where k = objects in nearby regions.
 
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::HashMap;
 
 /// Region size in tiles. Each region covers 32x32 tiles.
 pub const REGION_SIZE: i32 = 32;
@@ -24,10 +25,15 @@ pub struct RegionKey {
 impl RegionKey {
     /// Compute the region key for a world position.
     pub fn from_world(map_id: i32, x: i32, y: i32) -> Self {
+        Self::for_cell_size(map_id, x, y, REGION_SIZE)
+    }
+
+    /// Region key for a world position on a grid of `cell_size` tiles.
+    pub fn for_cell_size(map_id: i32, x: i32, y: i32, cell_size: i32) -> Self {
         RegionKey {
             map_id,
-            rx: x.div_euclid(REGION_SIZE),
-            ry: y.div_euclid(REGION_SIZE),
+            rx: x.div_euclid(cell_size),
+            ry: y.div_euclid(cell_size),
         }
     }
 
@@ -50,42 +56,166 @@ impl RegionKey {
 /// Object ID type.
 pub type ObjectId = u32;
 
-/// The grid index: maps RegionKey -> set of object IDs in that region.
+/// The grid index: maps RegionKey -> objects (and their tile) in that region.
 ///
 /// This is the core spatial index that makes 10,000 NPC visibility
 /// queries fast. Instead of iterating all objects on the map,
 /// we only iterate objects in the 9 surrounding regions.
+///
+/// The cell size defaults to `REGION_SIZE`. Smaller cells make range
+/// queries scan fewer far-away objects but cost more map entries; use
+/// `occupancy` to see how full the cells are and `rebalance` to change it.
 pub struct WorldGrid {
-    regions: HashMap<RegionKey, HashSet<ObjectId>>,
+    cell_size: i32,
+    regions: HashMap<RegionKey, HashMap<ObjectId, Placement>>,
+    /// Objects with a footprint bigger than one tile.
+    large_objects: usize,
+}
+
+/// Where an object stands: its center tile and footprint radius.
+///
+/// A size of 0 is a single tile; a large NPC (size 1) covers the 3x3
+/// block around its center. The object is filed in every cell its
+/// footprint overlaps, so queries from any side see it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub x: i32,
+    pub y: i32,
+    pub size: i32,
+}
+
+impl Placement {
+    /// Tiles (square distance) from `(x, y)` to the nearest footprint tile;
+    /// 0 when the tile is covered.
+    pub fn distance_to(&self, x: i32, y: i32) -> i32 {
+        let dx = ((self.x - x).abs() - self.size).max(0);
+        let dy = ((self.y - y).abs() - self.size).max(0);
+        dx.max(dy)
+    }
+
+    pub fn covers(&self, x: i32, y: i32) -> bool {
+        self.distance_to(x, y) == 0
+    }
+
+    /// Do two footprints share a tile?
+    pub fn overlaps(&self, other: &Placement) -> bool {
+        (self.x - other.x).abs() <= self.size + other.size
+            && (self.y - other.y).abs() <= self.size + other.size
+    }
+}
+
+/// Summary of how objects are spread over grid cells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridOccupancy {
+    pub cell_size: i32,
+    /// Non-empty cells.
+    pub cells: usize,
+    pub objects: usize,
+    pub max_per_cell: usize,
+    pub mean_per_cell: f64,
+}
+
+impl Default for WorldGrid {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WorldGrid {
     pub fn new() -> Self {
+        Self::with_cell_size(REGION_SIZE)
+    }
+
+    /// Grid with `cell_size` x `cell_size` tile cells.
+    pub fn with_cell_size(cell_size: i32) -> Self {
+        assert!(cell_size > 0, "grid cell size must be positive");
         WorldGrid {
+            cell_size,
             regions: HashMap::new(),
+            large_objects: 0,
         }
     }
 
+    pub fn cell_size(&self) -> i32 {
+        self.cell_size
+    }
+
+    fn key(&self, map_id: i32, x: i32, y: i32) -> RegionKey {
+        RegionKey::for_cell_size(map_id, x, y, self.cell_size)
+    }
+
+    /// First and last cell a footprint overlaps.
+    fn footprint_cells(&self, map_id: i32, p: &Placement) -> (RegionKey, RegionKey) {
+        (
+            self.key(map_id, p.x - p.size, p.y - p.size),
+            self.key(map_id, p.x + p.size, p.y + p.size),
+        )
+    }
+
+    /// The cell an object is counted in: the one holding its center tile.
+    fn is_home(&self, key: &RegionKey, p: &Placement) -> bool {
+        *key == self.key(key.map_id, p.x, p.y)
+    }
+
+    /// Placement of the object whose center is at `(x, y)`.
+    fn placement_at(&self, id: ObjectId, map_id: i32, x: i32, y: i32) -> Option<Placement> {
+        self.regions.get(&self.key(map_id, x, y))?.get(&id).copied()
+    }
+
     /// Add an object to the grid at the given world position.
     pub fn add(&mut self, id: ObjectId, map_id: i32, x: i32, y: i32) {
-        let key = RegionKey::from_world(map_id, x, y);
-        self.regions.entry(key).or_default().insert(id);
+        self.add_sized(id, map_id, x, y, 0);
+    }
+
+    /// Add an object whose footprint reaches `size` tiles out from `(x, y)`.
+    pub fn add_sized(&mut self, id: ObjectId, map_id: i32, x: i32, y: i32, size: i32) {
+        assert!(size >= 0, "footprint size must not be negative");
+        let p = Placement { x, y, size };
+        if size > 0 {
+            self.large_objects += 1;
+        }
+        let (lo, hi) = self.footprint_cells(map_id, &p);
+        for rx in lo.rx..=hi.rx {
+            for ry in lo.ry..=hi.ry {
+                self.regions.entry(RegionKey { map_id, rx, ry }).or_default().insert(id, p);
+            }
+        }
+    }
+
+    /// Add many `(id, map_id, x, y)` objects at once.
+    pub fn add_batch(&mut self, objects: &[(ObjectId, i32, i32, i32)]) {
+        for &(id, map_id, x, y) in objects {
+            self.add(id, map_id, x, y);
+        }
+    }
+
+    fn remove_from_cell(&mut self, key: RegionKey, id: ObjectId) {
+        if let Some(cell) = self.regions.get_mut(&key) {
+            cell.remove(&id);
+            if cell.is_empty() {
+                self.regions.remove(&key);
+            }
+        }
     }
 
     /// Remove an object from the grid at its current world position.
     pub fn remove(&mut self, id: ObjectId, map_id: i32, x: i32, y: i32) {
-        let key = RegionKey::from_world(map_id, x, y);
-        if let Some(set) = self.regions.get_mut(&key) {
-            set.remove(&id);
-            if set.is_empty() {
-                self.regions.remove(&key);
+        let Some(p) = self.placement_at(id, map_id, x, y) else { return };
+        if p.size > 0 {
+            self.large_objects -= 1;
+        }
+        let (lo, hi) = self.footprint_cells(map_id, &p);
+        for rx in lo.rx..=hi.rx {
+            for ry in lo.ry..=hi.ry {
+                self.remove_from_cell(RegionKey { map_id, rx, ry }, id);
             }
         }
     }
 
     /// Move an object from one position to another.
     ///
-    /// Only updates the grid if the object moved to a different region.
+    /// The footprint keeps its size. Only the cells it leaves or enters
+    /// change membership; the rest just get the new position.
     pub fn move_object(
         &mut self,
         id: ObjectId,
@@ -95,47 +225,239 @@ impl WorldGrid {
         new_x: i32,
         new_y: i32,
     ) {
-        let old_key = RegionKey::from_world(map_id, old_x, old_y);
-        let new_key = RegionKey::from_world(map_id, new_x, new_y);
-
-        if old_key != new_key {
-            // Remove from old region
-            if let Some(set) = self.regions.get_mut(&old_key) {
-                set.remove(&id);
-                if set.is_empty() {
-                    self.regions.remove(&old_key);
+        // A single tile moving within its cell: update it in place
+        let old_key = self.key(map_id, old_x, old_y);
+        let same_cell = old_key == self.key(map_id, new_x, new_y);
+        let size = match self.regions.get_mut(&old_key).and_then(|cell| cell.get_mut(&id)) {
+            Some(p) if same_cell && p.size == 0 => {
+                p.x = new_x;
+                p.y = new_y;
+                return;
+            }
+            Some(p) => p.size,
+            None => 0,
+        };
+
+        let old = Placement { x: old_x, y: old_y, size };
+        let new = Placement { x: new_x, y: new_y, size };
+        let (old_lo, old_hi) = self.footprint_cells(map_id, &old);
+        let (new_lo, new_hi) = self.footprint_cells(map_id, &new);
+
+        for rx in old_lo.rx..=old_hi.rx {
+            for ry in old_lo.ry..=old_hi.ry {
+                let still_covered = (new_lo.rx..=new_hi.rx).contains(&rx)
+                    && (new_lo.ry..=new_hi.ry).contains(&ry);
+                if !still_covered {
+                    self.remove_from_cell(RegionKey { map_id, rx, ry }, id);
                 }
             }
-            // Add to new region
-            self.regions.entry(new_key).or_default().insert(id);
         }
+        for rx in new_lo.rx..=new_hi.rx {
+            for ry in new_lo.ry..=new_hi.ry {
+                self.regions.entry(RegionKey { map_id, rx, ry }).or_default().insert(id, new);
+            }
+        }
+    }
+
+    /// Should the entry for `p` in cell `key` be reported by a scan over
+    /// the cells `lo..=hi`? Only in the first scanned cell it overlaps,
+    /// so a multi-cell footprint is returned once.
+    fn first_in_scan(&self, key: &RegionKey, p: &Placement, lo: &RegionKey) -> bool {
+        if p.size == 0 {
+            // A single tile is only ever filed in one cell
+            return true;
+        }
+        let (fp_lo, _) = self.footprint_cells(key.map_id, p);
+        key.rx == fp_lo.rx.max(lo.rx) && key.ry == fp_lo.ry.max(lo.ry)
     }
 
     /// Get all object IDs in the 9 surrounding regions of a position.
     ///
     /// This is the main visibility query - O(k) where k = nearby objects.
     pub fn get_nearby(&self, map_id: i32, x: i32, y: i32) -> Vec<ObjectId> {
-        let center = RegionKey::from_world(map_id, x, y);
+        let center = self.key(map_id, x, y);
+        let lo = RegionKey { map_id, rx: center.rx - 1, ry: center.ry - 1 };
         let mut result = Vec::new();
 
         for key in &center.neighbors() {
-            if let Some(set) = self.regions.get(key) {
-                result.extend(set.iter());
+            if let Some(cell) = self.regions.get(key) {
+                if self.large_objects == 0 {
+                    // Every object sits in exactly one cell
+                    result.extend(cell.keys().copied());
+                } else {
+                    result.extend(
+                        cell.iter().filter(|(_, p)| self.first_in_scan(key, p, &lo)).map(|(id, _)| *id),
+                    );
+                }
             }
         }
 
         result
     }
 
+    /// Objects within `range` tiles (square distance) of a position.
+    ///
+    /// Exact for any cell size and range: every cell overlapping the
+    /// square is visited and each object's footprint is checked, so a
+    /// large NPC is in range as soon as its nearest edge is.
+    pub fn objects_in_range(&self, map_id: i32, x: i32, y: i32, range: i32) -> Vec<ObjectId> {
+        let lo = self.key(map_id, x - range, y - range);
+        let hi = self.key(map_id, x + range, y + range);
+        let in_range = |p: &Placement| p.distance_to(x, y) <= range;
+
+        let span = (hi.rx - lo.rx + 1) as usize * (hi.ry - lo.ry + 1) as usize;
+        let mut result = Vec::new();
+        if span > self.regions.len() {
+            // Huge range: cheaper to walk the occupied cells
+            for (key, cell) in &self.regions {
+                if key.map_id == map_id {
+                    result.extend(
+                        cell.iter()
+                            .filter(|(_, p)| self.is_home(key, p) && in_range(p))
+                            .map(|(id, _)| *id),
+                    );
+                }
+            }
+            return result;
+        }
+        for rx in lo.rx..=hi.rx {
+            for ry in lo.ry..=hi.ry {
+                let key = RegionKey { map_id, rx, ry };
+                if let Some(cell) = self.regions.get(&key) {
+                    result.extend(
+                        cell.iter()
+                            .filter(|(_, p)| self.first_in_scan(&key, p, &lo) && in_range(p))
+                            .map(|(id, _)| *id),
+                    );
+                }
+            }
+        }
+        result
+    }
+
+    /// An object whose footprint covers tile `(x, y)`, if any.
+    pub fn occupant_at(&self, map_id: i32, x: i32, y: i32) -> Option<ObjectId> {
+        let cell = self.regions.get(&self.key(map_id, x, y))?;
+        cell.iter().find(|(_, p)| p.covers(x, y)).map(|(id, _)| *id)
+    }
+
+    /// Would `id` with footprint `size` centered on `(x, y)` overlap any
+    /// other object's footprint?
+    ///
+    /// Only large footprints block: single-tile objects may still share a
+    /// tile with each other, but not step into (or onto) a large NPC.
+    pub fn is_blocked(&self, id: ObjectId, map_id: i32, x: i32, y: i32, size: i32) -> bool {
+        if size == 0 && self.large_objects == 0 {
+            return false;
+        }
+        let p = Placement { x, y, size };
+        let (lo, hi) = self.footprint_cells(map_id, &p);
+        for rx in lo.rx..=hi.rx {
+            for ry in lo.ry..=hi.ry {
+                if let Some(cell) = self.regions.get(&RegionKey { map_id, rx, ry }) {
+                    if cell.iter().any(|(&other, q)| other != id && (size > 0 || q.size > 0) && p.overlaps(q)) {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
     /// Get total number of tracked objects.
     pub fn total_objects(&self) -> usize {
-        self.regions.values().map(|s| s.len()).sum()
+        self.regions
+            .iter()
+            .map(|(key, cell)| cell.values().filter(|p| self.is_home(key, p)).count())
+            .sum()
     }
 
     /// Get number of active regions.
     pub fn active_regions(&self) -> usize {
         self.regions.len()
     }
+
+    /// Object count of every non-empty cell, busiest first. A large
+    /// object counts toward every cell its footprint overlaps.
+    pub fn cell_occupancy(&self) -> Vec<(RegionKey, usize)> {
+        let mut cells: Vec<_> = self.regions.iter().map(|(k, c)| (*k, c.len())).collect();
+        cells.sort_by_key(|c| Reverse(c.1));
+        cells
+    }
+
+    /// Summary statistics for tuning the cell size.
+    pub fn occupancy(&self) -> GridOccupancy {
+        let objects = self.total_objects();
+        let cells = self.regions.len();
+        GridOccupancy {
+            cell_size: self.cell_size,
+            cells,
+            objects,
+            max_per_cell: self.regions.values().map(|c| c.len()).max().unwrap_or(0),
+            mean_per_cell: if cells == 0 { 0.0 } else { objects as f64 / cells as f64 },
+        }
+    }
+
+    /// Every tracked object as `(id, map_id, x, y)`, once each.
+    pub fn entries(&self) -> impl Iterator<Item = (ObjectId, i32, i32, i32)> + '_ {
+        self.placements().map(|(id, map_id, p)| (id, map_id, p.x, p.y))
+    }
+
+    /// Every tracked object with its footprint, once each.
+    pub fn placements(&self) -> impl Iterator<Item = (ObjectId, i32, Placement)> + '_ {
+        self.regions.iter().flat_map(move |(key, cell)| {
+            cell.iter()
+                .filter(move |(_, p)| self.is_home(key, p))
+                .map(move |(&id, &p)| (id, key.map_id, p))
+        })
+    }
+
+    /// Panic if any object sits in a cell its footprint doesn't overlap,
+    /// is missing from one it does, or is tracked twice. For tests and
+    /// debug checks; walks the whole grid, so release builds skip it.
+    pub fn validate_consistency(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let mut seen = HashMap::new();
+        for (key, cell) in &self.regions {
+            assert!(!cell.is_empty(), "empty cell {key:?} left in the grid");
+            for (&id, p) in cell {
+                let (lo, hi) = self.footprint_cells(key.map_id, p);
+                assert!(
+                    (lo.rx..=hi.rx).contains(&key.rx) && (lo.ry..=hi.ry).contains(&key.ry),
+                    "object {id} at ({},{}) filed under the wrong cell",
+                    p.x,
+                    p.y,
+                );
+                if !self.is_home(key, p) {
+                    continue;
+                }
+                if let Some(other) = seen.insert(id, *key) {
+                    panic!("object {id} tracked in both {other:?} and {key:?}");
+                }
+                for rx in lo.rx..=hi.rx {
+                    for ry in lo.ry..=hi.ry {
+                        let cell = RegionKey { map_id: key.map_id, rx, ry };
+                        assert_eq!(
+                            self.regions.get(&cell).and_then(|c| c.get(&id)),
+                            Some(p),
+                            "object {id} missing from footprint cell {cell:?}",
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Re-bucket every object into cells of `cell_size` tiles.
+    pub fn rebalance(&mut self, cell_size: i32) {
+        let mut grid = WorldGrid::with_cell_size(cell_size);
+        for (id, map_id, p) in self.placements() {
+            grid.add_sized(id, map_id, p.x, p.y, p.size);
+        }
+        *self = grid;
+    }
 }
 
 #[cfg(test)]
@@ -202,6 +524,11 @@ mod tests {
 
         assert!(!nearby_old.contains(&1));
         assert!(nearby_new.contains(&1));
+
+        // A step inside the cell still moves the tracked position
+        grid.move_object(1, 4, 500, 500, 501, 500);
+        assert_eq!(grid.entries().collect::<Vec<_>>(), vec![(1, 4, 501, 500)]);
+        assert_eq!(grid.objects_in_range(4, 502, 500, 1), vec![1]);
     }
 
     #[test]
@@ -234,4 +561,132 @@ mod tests {
         assert!(nearby.len() < 10_000);
         assert!(nearby.len() > 0);
     }
+
+    #[test]
+    fn test_objects_in_range_any_cell_size() {
+        let spawn: Vec<(ObjectId, i32, i32)> = (0..2_000u32)
+            .map(|i| (i, 32000 + (i as i32 * 37) % 300, 32000 + (i as i32 * 91) % 300))
+            .collect();
+        // Object 0 steps within its cell, object 1 jumps across cells
+        let mut moved = spawn.clone();
+        moved[0] = (0, 32001, 32000);
+        moved[1] = (1, 32150, 32150);
+
+        let brute = |x: i32, y: i32, r: i32| {
+            let mut ids: Vec<ObjectId> = moved.iter()
+                .filter(|(_, ox, oy)| (ox - x).abs() <= r && (oy - y).abs() <= r)
+                .map(|(id, _, _)| *id)
+                .collect();
+            ids.sort_unstable();
+            ids
+        };
+
+        for cell_size in [1, 8, 13, 32, 64, 500] {
+            let mut grid = WorldGrid::with_cell_size(cell_size);
+            for &(id, x, y) in &spawn {
+                grid.add(id, 4, x, y);
+            }
+            grid.add(9999, 5, 32100, 32100); // other map, never returned
+            for (&(id, ox, oy), &(_, nx, ny)) in spawn.iter().zip(&moved).take(2) {
+                grid.move_object(id, 4, ox, oy, nx, ny);
+            }
+
+            for (x, y, r) in [(32100, 32100, 0), (32100, 32100, 18), (32000, 32000, 5), (32150, 32150, 40), (32150, 32150, 1000)] {
+                let mut got = grid.objects_in_range(4, x, y, r);
+                got.sort_unstable();
+                assert_eq!(got, brute(x, y, r), "cell {cell_size}, ({x},{y}) r={r}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_rebalance_and_occupancy() {
+        let mut grid = WorldGrid::new();
+        for i in 0..64u32 {
+            grid.add(i, 4, 32000 + (i % 8) as i32, 32000 + (i / 8) as i32);
+        }
+        let occ = grid.occupancy();
+        assert_eq!((occ.cell_size, occ.cells, occ.objects, occ.max_per_cell), (32, 1, 64, 64));
+
+        grid.rebalance(4);
+        let occ = grid.occupancy();
+        assert_eq!((occ.cell_size, occ.cells, occ.objects, occ.max_per_cell), (4, 4, 64, 16));
+        assert_eq!(occ.mean_per_cell, 16.0);
+        assert_eq!(grid.cell_occupancy().len(), 4);
+        assert_eq!(grid.objects_in_range(4, 32000, 32000, 1).len(), 4);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "tracked in both")]
+    fn test_validate_catches_duplicate() {
+        let mut grid = WorldGrid::new();
+        grid.add(1, 4, 100, 100);
+        grid.add(1, 4, 500, 500); // added again instead of moved
+        grid.validate_consistency();
+    }
+
+    #[test]
+    fn test_large_npc_occupies_footprint_cells() {
+        // Centered on a cell corner, a 3x3 footprint straddles four cells
+        let mut grid = WorldGrid::with_cell_size(8);
+        grid.add_sized(1, 4, 32, 32, 1);
+        grid.add(2, 4, 40, 40);
+
+        let mut cells: Vec<_> = grid.cell_occupancy().into_iter()
+            .filter(|(k, _)| k.rx < 5)
+            .map(|(k, _)| (k.rx, k.ry))
+            .collect();
+        cells.sort_unstable();
+        assert_eq!(cells, vec![(3, 3), (3, 4), (4, 3), (4, 4)]);
+        assert_eq!(grid.total_objects(), 2);
+        grid.validate_consistency();
+
+        for (x, y) in [(31, 31), (32, 32), (33, 31), (31, 33)] {
+            assert_eq!(grid.occupant_at(4, x, y), Some(1), "({x},{y})");
+        }
+        assert_eq!(grid.occupant_at(4, 34, 32), None);
+
+        // A move drops the cells the footprint left
+        grid.move_object(1, 4, 32, 32, 36, 36);
+        assert_eq!(grid.active_regions(), 2);
+        assert_eq!(grid.occupant_at(4, 31, 31), None);
+        grid.validate_consistency();
+        grid.remove(1, 4, 36, 36);
+        assert_eq!(grid.total_objects(), 1);
+        grid.validate_consistency();
+    }
+
+    #[test]
+    fn test_large_npc_in_range_from_any_edge() {
+        for cell_size in [1, 2, 8, 32] {
+            let mut grid = WorldGrid::with_cell_size(cell_size);
+            grid.add_sized(1, 4, 100, 100, 1);
+
+            // One tile out from each edge and corner of the 3x3 block
+            for (x, y) in [(98, 100), (102, 100), (100, 98), (100, 102), (98, 98), (102, 102)] {
+                assert_eq!(grid.objects_in_range(4, x, y, 1), vec![1], "cell {cell_size}, ({x},{y})");
+                assert!(grid.objects_in_range(4, x, y, 0).is_empty());
+            }
+            assert_eq!(grid.objects_in_range(4, 100, 100, 0), vec![1]);
+            assert_eq!(grid.objects_in_range(4, 100, 100, 1000), vec![1]);
+            assert_eq!(grid.get_nearby(4, 100, 100), vec![1]);
+        }
+    }
+
+    #[test]
+    fn test_large_footprint_blocks() {
+        let mut grid = WorldGrid::new();
+        grid.add_sized(1, 4, 100, 100, 1);
+        grid.add(2, 4, 110, 110);
+
+        // Small objects can't step onto the footprint but may stack otherwise
+        assert!(grid.is_blocked(3, 4, 101, 101, 0));
+        assert!(!grid.is_blocked(3, 4, 102, 101, 0));
+        assert!(!grid.is_blocked(3, 4, 110, 110, 0));
+        // Two large footprints may not overlap; moving in place is fine
+        assert!(grid.is_blocked(3, 4, 102, 102, 1));
+        assert!(!grid.is_blocked(3, 4, 103, 103, 1));
+        assert!(!grid.is_blocked(1, 4, 101, 100, 1));
+    }
 }
@@ -129,6 +129,40 @@ impl MapTileData {
         }
     }
 
+    /// Parse a V1 text map (`maps/{id}.txt`): one line per row, each a
+    /// comma-separated list of tile bytes. The origin comes from the
+    /// `mapids` bounds in `props`.
+    pub fn parse_v1_text(text: &str, props: MapProperties) -> anyhow::Result<Self> {
+        let mut tiles = Vec::new();
+        let mut width = 0;
+        let mut height = 0;
+        for (row, line) in text.lines().map(str::trim).filter(|l| !l.is_empty()).enumerate() {
+            let before = tiles.len();
+            for cell in line.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+                let value: i32 = cell
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("row {}: bad tile {:?}: {}", row, cell, e))?;
+                tiles.push(value as u8);
+            }
+            let len = (tiles.len() - before) as i32;
+            if row == 0 {
+                width = len;
+            } else if len != width {
+                anyhow::bail!("row {} has {} tiles, expected {}", row, len, width);
+            }
+            height += 1;
+        }
+        Ok(Self::from_v1(props.map_id, props.startx, props.starty, width, height, tiles, props))
+    }
+
+    /// Read `{maps_dir}/{map_id}.txt` as a V1 map.
+    pub fn load_v1_text(maps_dir: &str, props: MapProperties) -> anyhow::Result<Self> {
+        let path = std::path::Path::new(maps_dir).join(format!("{}.txt", props.map_id));
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        Self::parse_v1_text(&text, props)
+    }
+
     /// Create a null/empty map (returned when map not found).
     pub fn null_map(map_id: i32) -> Self {
         MapTileData {
@@ -244,6 +278,20 @@ mod tests {
         assert!(map.is_combat_zone(101, 201)); // 8 - combat
     }
 
+    #[test]
+    fn test_parse_v1_text() {
+        let props = MapProperties { map_id: 4, startx: 100, starty: 200, ..Default::default() };
+        let map = MapTileData::parse_v1_text("0,128\n16,3\n", props.clone()).unwrap();
+
+        assert_eq!((map.x_loc, map.y_loc, map.width, map.height), (100, 200, 2, 2));
+        assert!(map.is_passable(100, 200));
+        assert!(!map.is_passable(101, 200));
+        assert!(map.is_safety_zone(100, 201));
+
+        assert!(MapTileData::parse_v1_text("0,0\n0\n", props.clone()).is_err());
+        assert!(MapTileData::parse_v1_text("0,x\n", props).is_err());
+    }
+
     #[test]
     fn test_null_map() {
         let map = MapTileData::null_map(999);
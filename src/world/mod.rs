@@ -1,2 +1,3 @@
 pub mod grid;
 pub mod map_data;
+pub mod persist;
@@ -0,0 +1,151 @@
+//! Castle door states that survive a restart.
+//!
+//! Optional (`game.world_state_save_secs`): the state is written to a
+//! TOML file on a timer and at shutdown, and read back on boot.
+//! `SiegeManager` records every door change here and restores a door's
+//! saved state when it spawns.
+//!
+//! Ground items are not persisted: the server has no ground-item
+//! system yet (drops and picks are not implemented), so there is
+//! nothing to save.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::ecs::siege::DoorState;
+
+/// The part of a door that changes in play. A destroyed door has
+/// `cur_hp` 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DoorSnapshot {
+    pub object_id: u32,
+    pub cur_hp: i32,
+    pub is_open: bool,
+}
+
+/// Door states, keyed by object id.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PersistentState {
+    #[serde(default, with = "by_id")]
+    doors: BTreeMap<u32, DoorSnapshot>,
+}
+
+impl PersistentState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `door`'s current state.
+    pub fn record_door(&mut self, door: &DoorState) {
+        self.doors.insert(
+            door.object_id,
+            DoorSnapshot { object_id: door.object_id, cur_hp: door.cur_hp, is_open: door.is_open },
+        );
+    }
+
+    /// Put a recorded state back on a freshly spawned door. False if
+    /// nothing was recorded for it.
+    pub fn restore_door(&self, door: &mut DoorState) -> bool {
+        match self.doors.get(&door.object_id) {
+            Some(s) => {
+                door.cur_hp = s.cur_hp.min(door.max_hp);
+                door.is_open = s.is_open;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Write to `path`, via a temporary file so a crash mid-write
+    /// leaves the previous save intact.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let text = toml::to_string(self)?;
+        if let Some(dir) = Path::new(path).parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        }
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, text).with_context(|| format!("writing {}", tmp))?;
+        std::fs::rename(&tmp, path).with_context(|| format!("replacing {}", path))?;
+        Ok(())
+    }
+
+    /// Read a save from `path`. A missing file is an empty state
+    /// (first boot).
+    pub fn load(path: &str) -> Result<Self> {
+        if !Path::new(path).exists() {
+            return Ok(Self::new());
+        }
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+        toml::from_str(&text).with_context(|| format!("parsing {}", path))
+    }
+}
+
+/// Maps keyed by object id are stored as plain arrays of tables.
+mod by_id {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(map: &BTreeMap<u32, DoorSnapshot>, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(map.values())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<BTreeMap<u32, DoorSnapshot>, D::Error> {
+        let list = Vec::<DoorSnapshot>::deserialize(d)?;
+        Ok(list.into_iter().map(|v| (v.object_id, v)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn door(object_id: u32) -> DoorState {
+        DoorState {
+            object_id,
+            castle_id: 1,
+            max_hp: 3000,
+            cur_hp: 3000,
+            is_open: false,
+            direction: 0,
+            x: 33630,
+            y: 32675,
+            map_id: 4,
+        }
+    }
+
+    fn temp_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("l1j-persist-{}-{}", std::process::id(), name));
+        dir.join("world_state.toml").to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let mut state = PersistentState::new();
+        let mut open = door(10);
+        open.is_open = true;
+        let mut broken = door(11);
+        broken.receive_damage(5000);
+        state.record_door(&open);
+        state.record_door(&broken);
+
+        let path = temp_path("round_trip");
+        state.save(&path).unwrap();
+        let loaded = PersistentState::load(&path).unwrap();
+        assert_eq!(loaded, state);
+
+        let mut fresh = door(11);
+        assert!(loaded.restore_door(&mut fresh));
+        assert_eq!(fresh.cur_hp, 0);
+        assert!(!loaded.restore_door(&mut door(12)));
+        let _ = std::fs::remove_dir_all(Path::new(&path).parent().unwrap());
+    }
+
+    #[test]
+    fn test_missing_file_is_empty() {
+        let state = PersistentState::load(&temp_path("missing")).unwrap();
+        assert_eq!(state, PersistentState::new());
+    }
+}
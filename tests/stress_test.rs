@@ -10,7 +10,8 @@ use std::time::Instant;
 
 use l1j_rust::ecs::components::npc::NpcTemplate;
 use l1j_rust::ecs::components::position::Position;
-use l1j_rust::ecs::game_engine::GameWorld;
+use l1j_rust::ecs::game_engine::{AiRanges, GameWorld};
+use l1j_rust::world::grid::WorldGrid;
 
 fn make_monster_template(npc_id: i32) -> NpcTemplate {
     NpcTemplate {
@@ -76,7 +77,7 @@ fn stress_10000_npcs_100_ticks() {
     let total_start = Instant::now();
     for tick in 0..100 {
         let tick_start = Instant::now();
-        let movements = world.tick(30);
+        let movements = world.tick(AiRanges::new(30, 35));
         let tick_time = tick_start.elapsed();
 
         total_movements += movements.len();
@@ -145,3 +146,60 @@ fn stress_visibility_query_10000() {
         elapsed
     );
 }
+
+#[test]
+fn stress_grid_cell_size_comparison() {
+    println!("\n=== STRESS TEST: Range Query vs Grid Cell Size (50,000 Objects) ===\n");
+
+    let objects: Vec<(u32, i32, i32)> = (0..50_000u32)
+        .map(|i| (i, 32000 + (i % 500) as i32, 32000 + (i / 500) as i32 * 5))
+        .collect();
+
+    for cell_size in [8, 16, 32, 64] {
+        let mut grid = WorldGrid::with_cell_size(cell_size);
+        for &(id, x, y) in &objects {
+            grid.add(id, 4, x, y);
+        }
+
+        let start = Instant::now();
+        let mut total = 0usize;
+        for &(_, x, y) in objects.iter().step_by(10) {
+            total += grid.objects_in_range(4, x, y, 18).len();
+        }
+        let elapsed = start.elapsed();
+
+        let occ = grid.occupancy();
+        println!(
+            "cell {:2}: 5,000 queries in {:?} ({} hits), {} cells, max {} / mean {:.1} per cell",
+            cell_size, elapsed, total, occ.cells, occ.max_per_cell, occ.mean_per_cell
+        );
+        assert_eq!(occ.objects, 50_000);
+    }
+}
+
+#[test]
+fn stress_spawn_many_vs_spawn_npc() {
+    println!("\n=== STRESS TEST: Bulk Spawn vs One-by-One (50,000 NPCs) ===\n");
+
+    let spots: Vec<(i32, i32, i32)> = (0..50_000i32)
+        .map(|i| (32000 + (i % 250), 32000 + (i / 250), 4))
+        .collect();
+    let templates = || HashMap::from([(45000, make_monster_template(45000))]);
+
+    let mut world = GameWorld::new(templates());
+    let start = Instant::now();
+    for &(x, y, map) in &spots {
+        world.spawn_npc(45000, x, y, map);
+    }
+    let single = start.elapsed();
+
+    let mut bulk = GameWorld::new(templates());
+    let start = Instant::now();
+    bulk.spawn_many(45000, &spots);
+    let batched = start.elapsed();
+
+    println!("spawn_npc x 50,000: {:?}", single);
+    println!("spawn_many(50,000): {:?}", batched);
+    assert_eq!(world.npcs.len(), bulk.npcs.len());
+    assert_eq!(world.grid.total_objects(), bulk.grid.total_objects());
+}
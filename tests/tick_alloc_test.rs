@@ -0,0 +1,89 @@
+/// Allocation benchmark for GameWorld::tick.
+///
+/// Run with: cargo test --release --test tick_alloc_test -- --nocapture
+///
+/// Counts heap allocations made by ticks over 50,000 NPCs. Sleeping NPCs
+/// produce no movements, so once the tick's handle buffer has grown a tick
+/// should not allocate at all.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use l1j_rust::ecs::components::npc::NpcTemplate;
+use l1j_rust::ecs::game_engine::{AiRanges, GameWorld};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn template(npc_id: i32) -> NpcTemplate {
+    NpcTemplate {
+        npc_id,
+        name: "Monster".to_string(),
+        nameid: "monster".to_string(),
+        impl_type: "L1Monster".to_string(),
+        gfxid: 100,
+        level: 10,
+        hp: 500,
+        mp: 100,
+        ac: 5,
+        str_stat: 15, con_stat: 12, dex_stat: 14,
+        wis_stat: 10, int_stat: 10, mr: 20,
+        exp: 1000, lawful: -100,
+        size: "small".to_string(),
+        undead: 0, poison_atk: 0, paralysis_atk: 0,
+        agro: true, agrososc: true, agrocoi: false,
+        family: 0, agrofamily: 0, pickup_item: false,
+        brave_speed: 0, passispeed: 640, atkspeed: 1020,
+        atk_magic_speed: 0, tamable: false, teleport: false,
+        doppel: false, hpr_interval: 12000, hpr: 3,
+        mpr_interval: 12000, mpr: 2, ranged: 0, light_size: 0,
+        change_head: false, damage_reduction: 0, hard: false,
+        karma: 0, transform_id: 0, transform_gfxid: 0,
+        cant_resurrect: false,
+    }
+}
+
+#[test]
+fn stress_tick_allocations_50000_npcs() {
+    println!("\n=== STRESS TEST: Tick Allocations with 50,000 NPCs ===\n");
+
+    let mut world = GameWorld::new(HashMap::from([(45000, template(45000))]));
+    let spots: Vec<(i32, i32, i32)> = (0..50_000i32)
+        .map(|i| (32000 + (i % 250), 32000 + (i / 250), 4))
+        .collect();
+    world.spawn_many(45000, &spots).unwrap();
+    let ranges = AiRanges::new(30, 35);
+
+    // First tick grows the handle buffer
+    world.tick(ranges);
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..100 {
+        world.tick(ranges);
+    }
+    let allocs = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+    println!("Allocations over 100 ticks: {}", allocs);
+    assert_eq!(allocs, 0, "idle ticks should reuse the handle buffer");
+}